@@ -0,0 +1,43 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use chimpanzee::utils::{compile_source, run_bytecode};
+
+const MAP_SUM: &str = r"
+        let map = fn(arr, f) {
+            let iter = fn(arr, acc) {
+                if (len(arr) == 0) {
+                    acc
+                } else {
+                    iter(rest(arr), push(acc, f(first(arr))))
+                }
+            };
+            iter(arr, [])
+        };
+        let sum = fn(arr) {
+            let i = 0;
+            let total = 0;
+            while (i < len(arr)) {
+                let total = total + arr[i];
+                let i = i + 1;
+            }
+            total
+        };
+        let numbers = [9, 3, 7, 1, 8, 2, 6, 4, 5, 0, 19, 13, 17, 11, 18, 12, 16, 14, 15, 10];
+        sum(map(numbers, fn(x) { x * x }));
+        ";
+
+pub fn compile_benchmark(c: &mut Criterion) {
+    c.bench_function("map and sum compile", |b| {
+        b.iter(|| compile_source(black_box(MAP_SUM)).unwrap());
+    });
+}
+
+pub fn run_benchmark(c: &mut Criterion) {
+    let bytecode = compile_source(MAP_SUM).unwrap();
+    c.bench_function("map and sum run", |b| {
+        b.iter(|| run_bytecode(black_box(bytecode.clone())).unwrap());
+    });
+}
+
+criterion_group!(benches, compile_benchmark, run_benchmark);
+criterion_main!(benches);
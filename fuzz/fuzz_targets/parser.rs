@@ -0,0 +1,8 @@
+#![no_main]
+
+use chimpanzee::parser::try_parse;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = try_parse(data);
+});
@@ -2,7 +2,7 @@ use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
 use enum_stringify::EnumStringify;
 use num_derive::{FromPrimitive, ToPrimitive};
 use num_traits::FromPrimitive;
-use std::fmt::Display;
+use std::{fmt::Display, ops::Range};
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Instructions {
@@ -15,21 +15,13 @@ impl Default for Instructions {
     }
 }
 
+/// Length of the longest mnemonic, `OpJumpNotTruthyOrPop`.
+const MNEMONIC_WIDTH: usize = 20;
+
 impl Display for Instructions {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut i = 0;
-        while i < self.data.len() {
-            let op = Opcode::from_u8(self.data[i])
-                .unwrap_or_else(|| panic!("ERROR: Unknown opcode: {}", self.data[i]));
-            let widths = op.lookup_widths();
-            let (operands, read) = Opcode::read_operands(&widths, &self.data[i + 1..]);
-            writeln!(
-                f,
-                "{:04} {}",
-                i,
-                self.format_instruction(op, &widths, &operands)
-            )?;
-            i += 1 + read as usize;
+        for (i, op, operands) in self.iter_decoded() {
+            writeln!(f, "{:04} {}", i, self.format_instruction(op, &operands))?;
         }
         Ok(())
     }
@@ -40,8 +32,10 @@ impl Instructions {
         Instructions { data }
     }
 
-    pub fn format_instruction(&self, operand: Opcode, widths: &[u32], operands: &[i32]) -> String {
-        let operand_count = widths.len();
+    /// Formats an instruction as its mnemonic followed by its operands, the mnemonics being
+    /// padded so that the operands of consecutive instructions are aligned.
+    pub fn format_instruction(&self, operand: Opcode, operands: &[i32]) -> String {
+        let operand_count = operand.definition().operand_widths.len();
         if operands.len() != operand_count {
             return format!(
                 "ERROR: operand len {} does not match defined {}",
@@ -50,17 +44,97 @@ impl Instructions {
             );
         }
 
-        match operand_count {
-            2 => format!("{operand} {} {}", operands[0], operands[1]),
-            1 => format!("{operand} {}", operands[0]),
-            0 => format!("{operand}"),
-            _ => format!("Unhandeled operand_count for {operand}"),
+        if operands.is_empty() {
+            return operand.to_string();
         }
+        let operands = operands
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(" ");
+        format!("{:<MNEMONIC_WIDTH$} {operands}", operand.to_string())
     }
 
     pub fn append(&mut self, mut new_instructions: Instructions) {
         self.data.append(&mut new_instructions.data);
     }
+
+    /// Number of bytes of the instructions.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Replaces the bytes in `range` with `replacement`.
+    ///
+    /// Absolute jump targets are not updated, and the range is expected to cover whole
+    /// instructions. `JumpRel` jumps stay correct as long as their target moves with them.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error, leaving the instructions untouched, if the range is out of bounds.
+    pub fn splice(&mut self, range: Range<usize>, replacement: Instructions) -> Result<(), String> {
+        if range.start > range.end || range.end > self.data.len() {
+            return Err(format!(
+                "splice range {range:?} is out of bounds ({} bytes)",
+                self.data.len()
+            ));
+        }
+        self.data.splice(range, replacement.data);
+        Ok(())
+    }
+
+    /// The opcode of the instruction starting at `offset`, or `None` if there is no complete
+    /// instruction there: the offset is out of bounds, the byte is not an opcode, or its
+    /// operands are truncated.
+    pub fn opcode_at(&self, offset: usize) -> Option<Opcode> {
+        let op = Opcode::from_u8(*self.data.get(offset)?)?;
+        (offset + op.definition().operands_length() < self.data.len()).then_some(op)
+    }
+
+    /// Iterates over the instructions, yielding the offset, opcode and decoded operands
+    /// of each one.
+    ///
+    /// # Panics
+    ///
+    /// The iterator panics on an unknown opcode or on truncated operands, run the
+    /// verifier first on untrusted bytecode.
+    pub fn iter_decoded(&self) -> DecodedInstructions<'_> {
+        DecodedInstructions::new(&self.data)
+    }
+}
+
+/// Iterator over the instructions of a byte buffer, see `Instructions::iter_decoded`.
+pub struct DecodedInstructions<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> DecodedInstructions<'a> {
+    /// Decodes raw instructions, such as the ones of a `CompiledFunction`.
+    pub fn new(data: &'a [u8]) -> Self {
+        DecodedInstructions { data, position: 0 }
+    }
+}
+
+impl Iterator for DecodedInstructions<'_> {
+    type Item = (usize, Opcode, Vec<i32>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let position = self.position;
+        let byte = *self.data.get(position)?;
+        let op = Opcode::from_u8(byte).unwrap_or_else(|| panic!("ERROR: Unknown opcode: {byte}"));
+        assert!(
+            position + op.definition().operands_length() < self.data.len(),
+            "ERROR: truncated operands for {op} at {position}"
+        );
+        let (operands, read) = op.read_operands(&self.data[position + 1..]);
+        self.position += 1 + read;
+        Some((position, op, operands))
+    }
 }
 
 #[derive(Debug, PartialEq, FromPrimitive, ToPrimitive, Clone, Copy, EnumStringify)]
@@ -74,16 +148,17 @@ pub enum Opcode {
     Sub,
     Mul,
     Div,
+    FloorDiv,
 
     // Boolean
     True,
     False,
     GreaterThan,
     GreaterEqualThan,
+    LessThan,
+    LessEqualThan,
     Equal,
     NotEqual,
-    Or,
-    And,
     Modulo,
 
     // Prefix operators
@@ -93,6 +168,20 @@ pub enum Opcode {
     // Jump
     JumpNotTruthy,
     Jump,
+    // Used by `&&` and `||`: jump and keep the value on the stack, or pop it and continue
+    JumpNotTruthyOrPop,
+    JumpTruthyOrPop,
+    // Used by `??`: jump and keep the value on the stack unless it is null
+    JumpNotNullOrPop,
+    // Used by `?[` and `?.(`: jump if the value on the stack is null, keeping it
+    JumpNull,
+
+    // Error handling: an error raised after `Try` and before `EndTry` jumps to the operand
+    // of `Try` with the error on the stack
+    Try,
+    EndTry,
+    // Raises the value on top of the stack as an error
+    Throw,
 
     // Null
     Null,
@@ -111,9 +200,13 @@ pub enum Opcode {
     Array,
     HashMap,
     Index,
+    // Checks that the value on top of the stack is an array that can be spread
+    Spread,
 
     // Functions
     Call,
+    // Calls the function below the array on top of the stack with its elements
+    CallSpread,
     ReturnValue,
     Return,
     GetBuiltin,
@@ -121,37 +214,92 @@ pub enum Opcode {
 
     // Stack
     Pop,
+
+    // Jumps by the signed operand, counted from the end of the instruction, so that the
+    // code can be moved without updating it
+    JumpRel,
+
+    // Discards the number of values given by the operand, without making the last one the
+    // result of the program like `Pop` does
+    PopN,
 }
 
+/// Describes how an opcode is encoded.
+#[derive(Debug, PartialEq)]
+pub struct Definition {
+    /// Size in bytes of each operand, in order. Operands are big endian.
+    pub operand_widths: &'static [usize],
+    /// Whether the operands are signed, in two's complement.
+    pub signed: bool,
+}
+
+impl Definition {
+    /// Total size in bytes of the operands.
+    pub fn operands_length(&self) -> usize {
+        self.operand_widths.iter().sum()
+    }
+}
+
+static NO_OPERANDS: Definition = Definition {
+    operand_widths: &[],
+    signed: false,
+};
+static ONE_BYTE_OPERAND: Definition = Definition {
+    operand_widths: &[1],
+    signed: false,
+};
+static TWO_BYTES_OPERAND: Definition = Definition {
+    operand_widths: &[2],
+    signed: false,
+};
+static SIGNED_BYTE_OPERAND: Definition = Definition {
+    operand_widths: &[1],
+    signed: true,
+};
+// Constant index of the function and number of free variables
+static CLOSURE_OPERANDS: Definition = Definition {
+    operand_widths: &[2, 1],
+    signed: false,
+};
+
 impl Opcode {
-    pub fn lookup_widths(&self) -> Vec<u32> {
+    /// The operand layout of the opcode, used by everything that encodes or decodes
+    /// instructions.
+    pub fn definition(&self) -> &'static Definition {
         match self {
             Opcode::Constant
             | Opcode::Jump
             | Opcode::JumpNotTruthy
+            | Opcode::JumpNotTruthyOrPop
+            | Opcode::JumpTruthyOrPop
+            | Opcode::JumpNotNullOrPop
+            | Opcode::JumpNull
+            | Opcode::Try
             | Opcode::SetGlobal
             | Opcode::GetGlobal
             | Opcode::Array
-            | Opcode::HashMap => vec![2],
+            | Opcode::HashMap => &TWO_BYTES_OPERAND,
 
             Opcode::Call
             | Opcode::SetLocal
             | Opcode::GetLocal
             | Opcode::GetBuiltin
-            | Opcode::GetFree => vec![1],
-            Opcode::Closure => vec![2, 1],
+            | Opcode::GetFree
+            | Opcode::PopN => &ONE_BYTE_OPERAND,
+            Opcode::JumpRel => &SIGNED_BYTE_OPERAND,
+            Opcode::Closure => &CLOSURE_OPERANDS,
 
-            _ => vec![],
+            _ => &NO_OPERANDS,
         }
     }
 
     #[allow(clippy::needless_pass_by_value)]
     pub fn make(&self, operands: Vec<i32>) -> Instructions {
-        let widths = self.lookup_widths();
-        let mut instructions: Vec<u8> = Vec::new();
+        let definition = self.definition();
+        let mut instructions: Vec<u8> = Vec::with_capacity(1 + definition.operands_length());
         instructions.push(*self as u8);
 
-        for (operand, width) in operands.iter().zip(widths) {
+        for (operand, width) in operands.iter().zip(definition.operand_widths) {
             match width {
                 2 => instructions
                     .write_u16::<BigEndian>(*operand as u16)
@@ -164,16 +312,24 @@ impl Opcode {
         Instructions::new(instructions)
     }
 
-    fn read_operands(widths: &Vec<u32>, ins: &[u8]) -> (Vec<i32>, i32) {
+    /// Decodes the operands of the opcode from `ins`, which starts right after the opcode.
+    ///
+    /// Returns the operands and the number of bytes read.
+    pub fn read_operands(&self, ins: &[u8]) -> (Vec<i32>, usize) {
         let mut operands: Vec<i32> = Vec::new();
         let mut offset = 0;
 
-        for width in widths {
+        let definition = self.definition();
+        for width in definition.operand_widths {
             match width {
                 2 => {
                     operands.push(i32::from(read_u16(&ins[offset..offset + 2])));
                     offset += 2;
                 }
+                1 if definition.signed => {
+                    operands.push(i32::from(i8::from_be_bytes([ins[offset]])));
+                    offset += 1;
+                }
                 1 => {
                     operands.push(i32::from(ins[offset]));
                     offset += 1;
@@ -182,8 +338,25 @@ impl Opcode {
             }
         }
 
-        (operands, offset as i32)
+        (operands, offset)
+    }
+}
+
+/// Disassembles the single instruction starting at `position`, prefixed by its position
+/// in the same format as the `Display` implementation of `Instructions`.
+///
+/// Returns `None` if there is no valid instruction at that position.
+pub fn format_instruction_at(data: &[u8], position: usize) -> Option<String> {
+    let op = Opcode::from_u8(*data.get(position)?)?;
+    if position + op.definition().operands_length() >= data.len() {
+        return None;
     }
+    let (operands, _) = op.read_operands(&data[position + 1..]);
+    let instructions = Instructions::default();
+    Some(format!(
+        "{position:04} {}",
+        instructions.format_instruction(op, &operands)
+    ))
 }
 
 /// This is a helper function to read a u16 from a byte slice, using
@@ -236,6 +409,8 @@ mod tests {
                 vec![65534, 255],
                 vec![Opcode::Closure as u8, 255, 254, 255],
             ),
+            (Opcode::JumpRel, vec![-3], vec![Opcode::JumpRel as u8, 253]),
+            (Opcode::JumpRel, vec![127], vec![Opcode::JumpRel as u8, 127]),
         ];
 
         for (op, operands, expected) in tests {
@@ -263,6 +438,7 @@ mod tests {
             Opcode::Constant.make(vec![2]),
             Opcode::Constant.make(vec![65535]),
             Opcode::Closure.make(vec![65535, 255]),
+            Opcode::JumpRel.make(vec![-13]),
         ];
 
         let mut test_instruction = Instructions::default();
@@ -270,27 +446,225 @@ mod tests {
             test_instruction.append(instruction);
         }
 
-        let expected = "0000 OpAdd\n0001 OpGetLocal 1\n0003 OpConstant 2\n0006 OpConstant 65535\n0009 OpClosure 65535 255\n";
+        let expected = "\
+0000 OpAdd
+0001 OpGetLocal           1
+0003 OpConstant           2
+0006 OpConstant           65535
+0009 OpClosure            65535 255
+0013 OpJumpRel            -13
+";
 
         assert_eq!(test_instruction.to_string(), expected);
     }
 
+    #[test]
+    fn test_mnemonic_width() {
+        let widest = (0..=u8::MAX)
+            .filter_map(Opcode::from_u8)
+            .map(|op| op.to_string().len())
+            .max();
+        assert_eq!(widest, Some(MNEMONIC_WIDTH));
+    }
+
+    #[test]
+    fn test_iter_decoded() {
+        let mut instructions = Instructions::default();
+        instructions.append(Opcode::Constant.make(vec![65535]));
+        instructions.append(Opcode::GetLocal.make(vec![3]));
+        instructions.append(Opcode::Add.make(vec![]));
+        instructions.append(Opcode::Closure.make(vec![2, 1]));
+        instructions.append(Opcode::Pop.make(vec![]));
+
+        let decoded = instructions.iter_decoded().collect::<Vec<_>>();
+        assert_eq!(
+            decoded,
+            vec![
+                (0, Opcode::Constant, vec![65535]),
+                (3, Opcode::GetLocal, vec![3]),
+                (5, Opcode::Add, vec![]),
+                (6, Opcode::Closure, vec![2, 1]),
+                (10, Opcode::Pop, vec![]),
+            ]
+        );
+
+        assert_eq!(Instructions::default().iter_decoded().next(), None);
+    }
+
+    #[test]
+    fn test_splice() {
+        let mut instructions = Instructions::default();
+        instructions.append(Opcode::Constant.make(vec![0]));
+        instructions.append(Opcode::Constant.make(vec![1]));
+        instructions.append(Opcode::Add.make(vec![]));
+        instructions.append(Opcode::Pop.make(vec![]));
+        assert_eq!(instructions.len(), 8);
+
+        // The second constant and the addition are replaced by a subtraction of the first one
+        let mut replacement = Opcode::GetLocal.make(vec![2]);
+        replacement.append(Opcode::Sub.make(vec![]));
+        instructions.splice(3..7, replacement).unwrap();
+
+        let mut expected = Opcode::Constant.make(vec![0]);
+        expected.append(Opcode::GetLocal.make(vec![2]));
+        expected.append(Opcode::Sub.make(vec![]));
+        expected.append(Opcode::Pop.make(vec![]));
+        assert_eq!(instructions, expected);
+
+        // Removing instructions and inserting at the end
+        instructions.splice(3..6, Instructions::default()).unwrap();
+        instructions
+            .splice(4..4, Opcode::Null.make(vec![]))
+            .unwrap();
+        let mut expected = Opcode::Constant.make(vec![0]);
+        expected.append(Opcode::Pop.make(vec![]));
+        expected.append(Opcode::Null.make(vec![]));
+        assert_eq!(instructions, expected);
+
+        for range in [4..6, 6..6, Range { start: 3, end: 2 }] {
+            assert_eq!(
+                instructions.splice(range.clone(), Opcode::Add.make(vec![])),
+                Err(format!("splice range {range:?} is out of bounds (5 bytes)"))
+            );
+        }
+        assert_eq!(instructions, expected);
+    }
+
+    #[test]
+    fn test_opcode_at() {
+        let mut instructions = Instructions::default();
+        instructions.append(Opcode::Add.make(vec![]));
+        instructions.append(Opcode::Constant.make(vec![1]));
+        instructions.append(Opcode::GetLocal.make(vec![1]));
+
+        assert_eq!(instructions.opcode_at(0), Some(Opcode::Add));
+        assert_eq!(instructions.opcode_at(1), Some(Opcode::Constant));
+        assert_eq!(instructions.opcode_at(4), Some(Opcode::GetLocal));
+        assert_eq!(instructions.opcode_at(6), None);
+        assert_eq!(instructions.opcode_at(100), None);
+
+        // The last operand byte is missing
+        instructions.data.pop();
+        assert_eq!(instructions.opcode_at(4), None);
+        assert_eq!(instructions.opcode_at(1), Some(Opcode::Constant));
+
+        assert_eq!(Instructions::new(vec![255]).opcode_at(0), None);
+        assert_eq!(Instructions::default().opcode_at(0), None);
+    }
+
     #[test]
     fn test_read_operands() {
         let tests = vec![
             (Opcode::Constant, vec![65535], 2),
             (Opcode::GetLocal, vec![255], 1),
             (Opcode::Closure, vec![65535, 255], 3),
+            (Opcode::JumpRel, vec![-128], 1),
+            (Opcode::JumpRel, vec![5], 1),
         ];
 
         for (op, operands, bytes_read) in tests {
             let instructions = op.make(operands.clone());
-            let widths = op.lookup_widths();
 
-            let (got_operands, offset) = Opcode::read_operands(&widths, &instructions.data[1..]);
+            let (got_operands, offset) = op.read_operands(&instructions.data[1..]);
             assert_eq!(offset, bytes_read, "offset wrong");
             assert!(got_operands.len() == operands.len(), "operands len wrong");
             assert_eq!(got_operands, operands, "operands wrong");
         }
     }
+
+    #[test]
+    fn test_definitions() {
+        let tests = vec![
+            (Opcode::Add, vec![]),
+            (Opcode::Constant, vec![2]),
+            (Opcode::GetLocal, vec![1]),
+            (Opcode::Closure, vec![2, 1]),
+        ];
+
+        for (op, widths) in tests {
+            let definition = op.definition();
+            assert_eq!(definition.operand_widths, widths.as_slice());
+            assert_eq!(definition.operands_length(), widths.iter().sum());
+            assert_eq!(
+                op.make(vec![0; widths.len()]).data.len(),
+                1 + definition.operands_length()
+            );
+        }
+    }
+
+    #[test]
+    fn test_round_trip_of_all_opcodes() {
+        // xorshift, so that the operands are random but the same on every run
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut random = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        // Opcodes are numbered from 0, without gaps
+        let opcodes: Vec<Opcode> = (0..=u8::MAX).map_while(Opcode::from_u8).collect();
+        assert!(opcodes.contains(&Opcode::Pop));
+
+        for op in opcodes {
+            let definition = op.definition();
+            let widths = definition.operand_widths;
+            let min = |width: usize| {
+                if definition.signed {
+                    -(1_i64 << (8 * width - 1))
+                } else {
+                    0
+                }
+            };
+            let max = |width: usize| min(width) + (1_i64 << (8 * width)) - 1;
+            let mut cases: Vec<Vec<i32>> = vec![
+                widths.iter().map(|w| min(*w) as i32).collect(),
+                widths.iter().map(|w| max(*w) as i32).collect(),
+            ];
+            for _ in 0..100 {
+                cases.push(
+                    widths
+                        .iter()
+                        .map(|w| (min(*w) + (random() % (1_u64 << (8 * w))) as i64) as i32)
+                        .collect(),
+                );
+            }
+
+            let mut stream = Instructions::default();
+            for operands in &cases {
+                let instructions = op.make(operands.clone());
+                assert_eq!(instructions.data.len(), 1 + widths.iter().sum::<usize>());
+                let (decoded, read) = op.read_operands(&instructions.data[1..]);
+                assert_eq!(&decoded, operands, "{op}");
+                assert_eq!(read, instructions.data.len() - 1, "{op}");
+                stream.append(instructions);
+            }
+
+            let decoded: Vec<Vec<i32>> = stream
+                .iter_decoded()
+                .map(|(_, decoded_op, operands)| {
+                    assert_eq!(decoded_op, op);
+                    operands
+                })
+                .collect();
+            assert_eq!(decoded, cases, "{op}");
+        }
+    }
+
+    #[test]
+    fn test_two_operands_round_trip() {
+        let tests = vec![vec![0, 0], vec![1, 2], vec![65535, 255], vec![256, 128]];
+
+        for operands in tests {
+            let instructions = Opcode::Closure.make(operands.clone());
+            let op = Opcode::from_u8(instructions.data[0]).unwrap();
+            assert_eq!(op, Opcode::Closure);
+
+            let (decoded, read) = op.read_operands(&instructions.data[1..]);
+            assert_eq!(decoded, operands);
+            assert_eq!(read, instructions.data.len() - 1);
+            assert_eq!(op.make(decoded), instructions);
+        }
+    }
 }
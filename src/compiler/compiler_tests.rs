@@ -2,12 +2,23 @@
 #[cfg(test)]
 pub mod tests {
 
+    use std::{cell::RefCell, rc::Rc};
+
     use crate::{
         compiler::{
             code::Opcode,
+            symbol_table::SymbolScope,
             test_utils::{flatten_instructions, run_compiler, CompilerTestCase},
+            Compiler,
         },
+        diagnostics::{Diagnostic, DiagnosticKind},
+        lexer::{token::Token, Position},
         object::Object,
+        parser::{
+            ast::{Expression, InfixOperator, PrefixOperator, Primitive, Program, Statement},
+            parse,
+        },
+        vm::VM,
     };
 
     #[test]
@@ -24,12 +35,20 @@ pub mod tests {
                 ]),
             },
             CompilerTestCase {
-                input: "1; 2".to_string(),
+                input: "1 // 2".to_string(),
                 expected_constants: vec![Object::INTEGER(1), Object::INTEGER(2)],
                 expected_instructions: flatten_instructions(vec![
                     Opcode::Constant.make(vec![0]),
-                    Opcode::Pop.make(vec![]),
                     Opcode::Constant.make(vec![1]),
+                    Opcode::FloorDiv.make(vec![]),
+                    Opcode::Pop.make(vec![]),
+                ]),
+            },
+            CompilerTestCase {
+                input: "1; 2".to_string(),
+                expected_constants: vec![Object::INTEGER(2)],
+                expected_instructions: flatten_instructions(vec![
+                    Opcode::Constant.make(vec![0]),
                     Opcode::Pop.make(vec![]),
                 ]),
             },
@@ -65,10 +84,9 @@ pub mod tests {
             },
             CompilerTestCase {
                 input: "-1".to_string(),
-                expected_constants: vec![Object::INTEGER(1)],
+                expected_constants: vec![Object::INTEGER(-1)],
                 expected_instructions: flatten_instructions(vec![
                     Opcode::Constant.make(vec![0]),
-                    Opcode::Minus.make(vec![]),
                     Opcode::Pop.make(vec![]),
                 ]),
             },
@@ -87,6 +105,78 @@ pub mod tests {
         run_compiler(tests);
     }
 
+    #[test]
+    fn test_negative_integer_literals() {
+        let tests = vec![
+            CompilerTestCase {
+                input: "-5".to_string(),
+                expected_constants: vec![Object::INTEGER(-5)],
+                expected_instructions: flatten_instructions(vec![
+                    Opcode::Constant.make(vec![0]),
+                    Opcode::Pop.make(vec![]),
+                ]),
+            },
+            CompilerTestCase {
+                input: "10 - 5".to_string(),
+                expected_constants: vec![Object::INTEGER(10), Object::INTEGER(5)],
+                expected_instructions: flatten_instructions(vec![
+                    Opcode::Constant.make(vec![0]),
+                    Opcode::Constant.make(vec![1]),
+                    Opcode::Sub.make(vec![]),
+                    Opcode::Pop.make(vec![]),
+                ]),
+            },
+            CompilerTestCase {
+                input: "10 - -5".to_string(),
+                expected_constants: vec![Object::INTEGER(10), Object::INTEGER(-5)],
+                expected_instructions: flatten_instructions(vec![
+                    Opcode::Constant.make(vec![0]),
+                    Opcode::Constant.make(vec![1]),
+                    Opcode::Sub.make(vec![]),
+                    Opcode::Pop.make(vec![]),
+                ]),
+            },
+            CompilerTestCase {
+                input: "let a = 5; a - 5; -a".to_string(),
+                expected_constants: vec![Object::INTEGER(5), Object::INTEGER(5)],
+                expected_instructions: flatten_instructions(vec![
+                    Opcode::Constant.make(vec![0]),
+                    Opcode::SetGlobal.make(vec![0]),
+                    Opcode::GetGlobal.make(vec![0]),
+                    Opcode::Constant.make(vec![1]),
+                    Opcode::Sub.make(vec![]),
+                    Opcode::Pop.make(vec![]),
+                    Opcode::GetGlobal.make(vec![0]),
+                    Opcode::Minus.make(vec![]),
+                    Opcode::Pop.make(vec![]),
+                ]),
+            },
+            CompilerTestCase {
+                // Only a literal is folded, not an expression that starts with one
+                input: "-(2 * 3)".to_string(),
+                expected_constants: vec![Object::INTEGER(2), Object::INTEGER(3)],
+                expected_instructions: flatten_instructions(vec![
+                    Opcode::Constant.make(vec![0]),
+                    Opcode::Constant.make(vec![1]),
+                    Opcode::Mul.make(vec![]),
+                    Opcode::Minus.make(vec![]),
+                    Opcode::Pop.make(vec![]),
+                ]),
+            },
+            CompilerTestCase {
+                // The smallest integer, whose magnitude is not an integer by itself
+                input: "-9223372036854775808".to_string(),
+                expected_constants: vec![Object::INTEGER(i64::MIN)],
+                expected_instructions: flatten_instructions(vec![
+                    Opcode::Constant.make(vec![0]),
+                    Opcode::Pop.make(vec![]),
+                ]),
+            },
+        ];
+
+        run_compiler(tests);
+    }
+
     #[test]
     fn test_boolean_expression() {
         let tests = vec![
@@ -136,21 +226,21 @@ pub mod tests {
             },
             CompilerTestCase {
                 input: "1 < 2".to_string(),
-                expected_constants: vec![Object::INTEGER(2), Object::INTEGER(1)],
+                expected_constants: vec![Object::INTEGER(1), Object::INTEGER(2)],
                 expected_instructions: flatten_instructions(vec![
                     Opcode::Constant.make(vec![0]),
                     Opcode::Constant.make(vec![1]),
-                    Opcode::GreaterThan.make(vec![]),
+                    Opcode::LessThan.make(vec![]),
                     Opcode::Pop.make(vec![]),
                 ]),
             },
             CompilerTestCase {
                 input: "1 <= 2".to_string(),
-                expected_constants: vec![Object::INTEGER(2), Object::INTEGER(1)],
+                expected_constants: vec![Object::INTEGER(1), Object::INTEGER(2)],
                 expected_instructions: flatten_instructions(vec![
                     Opcode::Constant.make(vec![0]),
                     Opcode::Constant.make(vec![1]),
-                    Opcode::GreaterEqualThan.make(vec![]),
+                    Opcode::LessEqualThan.make(vec![]),
                     Opcode::Pop.make(vec![]),
                 ]),
             },
@@ -218,32 +308,124 @@ pub mod tests {
     }
 
     #[test]
-    fn test_conditionals() {
+    fn test_logical_operators() {
         let tests = vec![
             CompilerTestCase {
-                input: "if (true) { 10 }; 3333;".to_string(),
-                expected_constants: vec![Object::INTEGER(10), Object::INTEGER(3333)],
+                input: "true && false".to_string(),
+                expected_constants: vec![],
                 expected_instructions: flatten_instructions(vec![
                     // 0000
                     Opcode::True.make(vec![]),
                     // 0001
-                    Opcode::JumpNotTruthy.make(vec![10]),
+                    Opcode::JumpNotTruthyOrPop.make(vec![5]),
                     // 0004
+                    Opcode::False.make(vec![]),
+                    // 0005
+                    Opcode::Pop.make(vec![]),
+                ]),
+            },
+            CompilerTestCase {
+                input: "1 || 2 || 3".to_string(),
+                expected_constants: vec![
+                    Object::INTEGER(1),
+                    Object::INTEGER(2),
+                    Object::INTEGER(3),
+                ],
+                expected_instructions: flatten_instructions(vec![
+                    // 0000
                     Opcode::Constant.make(vec![0]),
-                    // 0007
-                    Opcode::Jump.make(vec![11]),
+                    // 0003
+                    Opcode::JumpTruthyOrPop.make(vec![9]),
+                    // 0006
+                    Opcode::Constant.make(vec![1]),
+                    // 0009
+                    Opcode::JumpTruthyOrPop.make(vec![15]),
+                    // 0012
+                    Opcode::Constant.make(vec![2]),
+                    // 0015
+                    Opcode::Pop.make(vec![]),
+                ]),
+            },
+            CompilerTestCase {
+                input: "[]?[0]".to_string(),
+                expected_constants: vec![Object::INTEGER(0)],
+                expected_instructions: flatten_instructions(vec![
+                    // 0000
+                    Opcode::Array.make(vec![0]),
+                    // 0003
+                    Opcode::JumpNull.make(vec![10]),
+                    // 0006
+                    Opcode::Constant.make(vec![0]),
+                    // 0009
+                    Opcode::Index.make(vec![]),
                     // 0010
-                    Opcode::Null.make(vec![]),
-                    // 0011
                     Opcode::Pop.make(vec![]),
+                ]),
+            },
+            CompilerTestCase {
+                input: "len?.([])".to_string(),
+                expected_constants: vec![],
+                expected_instructions: flatten_instructions(vec![
+                    // 0000
+                    Opcode::GetBuiltin.make(vec![0]),
+                    // 0002
+                    Opcode::JumpNull.make(vec![10]),
+                    // 0005
+                    Opcode::Array.make(vec![0]),
+                    // 0008
+                    Opcode::Call.make(vec![1]),
+                    // 0010
+                    Opcode::Pop.make(vec![]),
+                ]),
+            },
+            CompilerTestCase {
+                input: "1 ?? 2".to_string(),
+                expected_constants: vec![Object::INTEGER(1), Object::INTEGER(2)],
+                expected_instructions: flatten_instructions(vec![
+                    // 0000
+                    Opcode::Constant.make(vec![0]),
+                    // 0003
+                    Opcode::JumpNotNullOrPop.make(vec![9]),
+                    // 0006
+                    Opcode::Constant.make(vec![1]),
+                    // 0009
+                    Opcode::Pop.make(vec![]),
+                ]),
+            },
+        ];
+
+        run_compiler(tests);
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let tests = vec![
+            CompilerTestCase {
+                input: "if (!false) { 10 }; 3333;".to_string(),
+                expected_constants: vec![Object::INTEGER(10), Object::INTEGER(3333)],
+                expected_instructions: flatten_instructions(vec![
+                    // 0000
+                    Opcode::False.make(vec![]),
+                    // 0001
+                    Opcode::Bang.make(vec![]),
+                    // 0002
+                    Opcode::JumpNotTruthy.make(vec![11]),
+                    // 0005
+                    Opcode::Constant.make(vec![0]),
+                    // 0008
+                    Opcode::Jump.make(vec![12]),
+                    // 0011
+                    Opcode::Null.make(vec![]),
                     // 0012
+                    Opcode::Pop.make(vec![]),
+                    // 0013
                     Opcode::Constant.make(vec![1]),
-                    // 0015
+                    // 0016
                     Opcode::Pop.make(vec![]),
                 ]),
             },
             CompilerTestCase {
-                input: "if (true) { 10 } else { 20 }; 3333;".to_string(),
+                input: "if (!false) { 10 } else { 20 }; 3333;".to_string(),
                 expected_constants: vec![
                     Object::INTEGER(10),
                     Object::INTEGER(20),
@@ -251,23 +433,102 @@ pub mod tests {
                 ],
                 expected_instructions: flatten_instructions(vec![
                     // 0000
-                    Opcode::True.make(vec![]),
+                    Opcode::False.make(vec![]),
                     // 0001
-                    Opcode::JumpNotTruthy.make(vec![10]),
-                    // 0004
+                    Opcode::Bang.make(vec![]),
+                    // 0002
+                    Opcode::JumpNotTruthy.make(vec![11]),
+                    // 0005
                     Opcode::Constant.make(vec![0]),
-                    // 0007
-                    Opcode::Jump.make(vec![13]),
-                    // 0010
+                    // 0008
+                    Opcode::Jump.make(vec![14]),
+                    // 0011
                     Opcode::Constant.make(vec![1]),
-                    // 0013
-                    Opcode::Pop.make(vec![]),
                     // 0014
+                    Opcode::Pop.make(vec![]),
+                    // 0015
+                    Opcode::Constant.make(vec![2]),
+                    // 0018
+                    Opcode::Pop.make(vec![]),
+                ]),
+            },
+            CompilerTestCase {
+                input: "if (!true) { 10 } else if (!false) { 20 } else { 30 }".to_string(),
+                expected_constants: vec![
+                    Object::INTEGER(10),
+                    Object::INTEGER(20),
+                    Object::INTEGER(30),
+                ],
+                expected_instructions: flatten_instructions(vec![
+                    // 0000
+                    Opcode::True.make(vec![]),
+                    // 0001
+                    Opcode::Bang.make(vec![]),
+                    // 0002
+                    Opcode::JumpNotTruthy.make(vec![11]),
+                    // 0005
+                    Opcode::Constant.make(vec![0]),
+                    // 0008
+                    Opcode::Jump.make(vec![25]),
+                    // 0011
+                    Opcode::False.make(vec![]),
+                    // 0012
+                    Opcode::Bang.make(vec![]),
+                    // 0013
+                    Opcode::JumpNotTruthy.make(vec![22]),
+                    // 0016
+                    Opcode::Constant.make(vec![1]),
+                    // 0019
+                    Opcode::Jump.make(vec![25]),
+                    // 0022
                     Opcode::Constant.make(vec![2]),
-                    // 0017
+                    // 0025
                     Opcode::Pop.make(vec![]),
                 ]),
             },
+            CompilerTestCase {
+                input: "if (true) { 10 } else { 20 }".to_string(),
+                expected_constants: vec![Object::INTEGER(10)],
+                expected_instructions: flatten_instructions(vec![
+                    Opcode::Constant.make(vec![0]),
+                    Opcode::Pop.make(vec![]),
+                ]),
+            },
+            CompilerTestCase {
+                // A branch that does not end with an expression has the value `null`
+                input: "let x = 1; if (x) { x = 2; }".to_string(),
+                expected_constants: vec![Object::INTEGER(1), Object::INTEGER(2)],
+                expected_instructions: flatten_instructions(vec![
+                    // 0000
+                    Opcode::Constant.make(vec![0]),
+                    // 0003
+                    Opcode::SetGlobal.make(vec![0]),
+                    // 0006
+                    Opcode::GetGlobal.make(vec![0]),
+                    // 0009
+                    Opcode::JumpNotTruthy.make(vec![22]),
+                    // 0012
+                    Opcode::Constant.make(vec![1]),
+                    // 0015
+                    Opcode::SetGlobal.make(vec![0]),
+                    // 0018
+                    Opcode::Null.make(vec![]),
+                    // 0019
+                    Opcode::Jump.make(vec![23]),
+                    // 0022
+                    Opcode::Null.make(vec![]),
+                    // 0023
+                    Opcode::Pop.make(vec![]),
+                ]),
+            },
+            CompilerTestCase {
+                input: "let a = if (false) { 10 } else { 20 };".to_string(),
+                expected_constants: vec![Object::INTEGER(20)],
+                expected_instructions: flatten_instructions(vec![
+                    Opcode::Constant.make(vec![0]),
+                    Opcode::SetGlobal.make(vec![0]),
+                ]),
+            },
         ];
 
         run_compiler(tests);
@@ -411,6 +672,40 @@ pub mod tests {
                     Opcode::Pop.make(vec![]),
                 ]),
             },
+            CompilerTestCase {
+                input: "[1, ...[], 2, 3, ...[]]".to_string(),
+                expected_constants: vec![
+                    Object::INTEGER(1),
+                    Object::INTEGER(2),
+                    Object::INTEGER(3),
+                ],
+                expected_instructions: flatten_instructions(vec![
+                    Opcode::Constant.make(vec![0]),
+                    Opcode::Array.make(vec![1]),
+                    Opcode::Array.make(vec![0]),
+                    Opcode::Spread.make(vec![]),
+                    Opcode::Add.make(vec![]),
+                    Opcode::Constant.make(vec![1]),
+                    Opcode::Constant.make(vec![2]),
+                    Opcode::Array.make(vec![2]),
+                    Opcode::Add.make(vec![]),
+                    Opcode::Array.make(vec![0]),
+                    Opcode::Spread.make(vec![]),
+                    Opcode::Add.make(vec![]),
+                    Opcode::Pop.make(vec![]),
+                ]),
+            },
+            CompilerTestCase {
+                input: "len(...[])".to_string(),
+                expected_constants: vec![],
+                expected_instructions: flatten_instructions(vec![
+                    Opcode::GetBuiltin.make(vec![0]),
+                    Opcode::Array.make(vec![0]),
+                    Opcode::Spread.make(vec![]),
+                    Opcode::CallSpread.make(vec![]),
+                    Opcode::Pop.make(vec![]),
+                ]),
+            },
         ];
 
         run_compiler(tests);
@@ -545,4 +840,496 @@ pub mod tests {
 
         run_compiler(tests);
     }
+
+    #[test]
+    fn test_try_catch_and_throw() {
+        let tests = vec![
+            CompilerTestCase {
+                input: "throw 1;".to_string(),
+                expected_constants: vec![Object::INTEGER(1)],
+                expected_instructions: flatten_instructions(vec![
+                    Opcode::Constant.make(vec![0]),
+                    Opcode::Throw.make(vec![]),
+                ]),
+            },
+            CompilerTestCase {
+                input: "try { 1 } catch (e) { e }".to_string(),
+                expected_constants: vec![Object::INTEGER(1)],
+                expected_instructions: flatten_instructions(vec![
+                    // 0000
+                    Opcode::Try.make(vec![10]),
+                    // 0003
+                    Opcode::Constant.make(vec![0]),
+                    // 0006
+                    Opcode::EndTry.make(vec![]),
+                    // 0007
+                    Opcode::Jump.make(vec![16]),
+                    // 0010
+                    Opcode::SetGlobal.make(vec![0]),
+                    // 0013
+                    Opcode::GetGlobal.make(vec![0]),
+                    // 0016
+                    Opcode::Pop.make(vec![]),
+                ]),
+            },
+        ];
+
+        run_compiler(tests);
+    }
+
+    #[test]
+    fn test_error_positions() {
+        let tests = vec![
+            ("foo", "Undefined variable: foo at 1:1"),
+            (
+                "let a = 1;\nlet b = fn(x) {\n    x + a * c\n};",
+                "Undefined variable: c at 3:13",
+            ),
+            (
+                "let a = 1;\n\n  if (a > 0) {\n\tlet b = -missing;\n  }",
+                "Undefined variable: missing at 4:11",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let mut compiler = Compiler::new();
+            assert_eq!(
+                compiler.compile(parse(input)),
+                Err(expected.to_string()),
+                "input: {input}"
+            );
+        }
+
+        // The parser never produces unknown operators, so the AST is built by hand
+        let mut infix = InfixOperator::new(
+            Token::Assign,
+            Expression::Primitive(Primitive::IntegerLiteral(1)),
+            Expression::Primitive(Primitive::IntegerLiteral(2)),
+        );
+        infix.position = Position { line: 2, column: 3 };
+        let mut prefix = PrefixOperator::new(
+            Token::Plus,
+            Expression::Primitive(Primitive::IntegerLiteral(1)),
+        );
+        prefix.position = Position { line: 5, column: 8 };
+
+        let tests = vec![
+            (Expression::Infix(infix), "Unknown operator: = at 2:3"),
+            (Expression::Prefix(prefix), "Unknown operator: + at 5:8"),
+        ];
+
+        for (expression, expected) in tests {
+            let mut compiler = Compiler::new();
+            let program = Program {
+                statements: vec![Statement::Expression(expression)],
+            };
+            assert_eq!(compiler.compile(program), Err(expected.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_return_outside_of_function() {
+        let invalid = [
+            "return 5;",
+            "let a = 1; return a;",
+            "if (true) { return 1; }",
+            "while (true) { return 1; }",
+            "{ return 1; }",
+        ];
+        for input in invalid {
+            let mut compiler = Compiler::new();
+            assert_eq!(
+                compiler.compile(parse(input)),
+                Err("return outside of function".to_string()),
+                "input: {input}"
+            );
+        }
+
+        let valid = [
+            "fn() { return 5; }",
+            "let f = fn(x) { if (x) { return 1; } 2 };",
+            "let f = fn() { { return 1; } };",
+        ];
+        for input in valid {
+            let mut compiler = Compiler::new();
+            assert_eq!(compiler.compile(parse(input)), Ok(()), "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_block_expressions() {
+        let tests = vec![
+            // The variables of a block take the next global slots, and it can assign to the
+            // variables around it
+            CompilerTestCase {
+                input: "let a = 1; let b = { let c = 3; a = 2; c }; b".to_string(),
+                expected_constants: vec![
+                    Object::INTEGER(1),
+                    Object::INTEGER(3),
+                    Object::INTEGER(2),
+                ],
+                expected_instructions: flatten_instructions(vec![
+                    Opcode::Constant.make(vec![0]),
+                    Opcode::SetGlobal.make(vec![0]),
+                    Opcode::Constant.make(vec![1]),
+                    Opcode::SetGlobal.make(vec![2]),
+                    Opcode::Constant.make(vec![2]),
+                    Opcode::SetGlobal.make(vec![0]),
+                    Opcode::GetGlobal.make(vec![2]),
+                    Opcode::SetGlobal.make(vec![1]),
+                    Opcode::GetGlobal.make(vec![1]),
+                    Opcode::Pop.make(vec![]),
+                ]),
+            },
+            CompilerTestCase {
+                input: "while (true) { { break; } }".to_string(),
+                expected_constants: vec![],
+                expected_instructions: flatten_instructions(vec![
+                    Opcode::True.make(vec![]),
+                    Opcode::JumpNotTruthy.make(vec![11]),
+                    Opcode::Jump.make(vec![11]),
+                    Opcode::Null.make(vec![]),
+                    Opcode::Pop.make(vec![]),
+                    Opcode::JumpRel.make(vec![-11]),
+                ]),
+            },
+        ];
+
+        run_compiler(tests);
+
+        let invalid = [
+            ("let a = { break; };", "break outside of loop"),
+            ("{ continue; }", "continue outside of loop"),
+            (
+                "while (true) { let f = fn() { break; }; }",
+                "break outside of loop",
+            ),
+        ];
+        for (input, expected) in invalid {
+            let mut compiler = Compiler::new();
+            assert_eq!(
+                compiler.compile(parse(input)),
+                Err(expected.to_string()),
+                "input: {input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_break_and_continue_pop_the_operands() {
+        let tests = vec![CompilerTestCase {
+            input: "while (true) { [1, 2, { continue; }] }".to_string(),
+            expected_constants: vec![Object::INTEGER(1), Object::INTEGER(2)],
+            expected_instructions: flatten_instructions(vec![
+                Opcode::True.make(vec![]),
+                Opcode::JumpNotTruthy.make(vec![21]),
+                Opcode::Constant.make(vec![0]),
+                Opcode::Constant.make(vec![1]),
+                Opcode::PopN.make(vec![2]),
+                Opcode::JumpRel.make(vec![-14]),
+                Opcode::Null.make(vec![]),
+                Opcode::Array.make(vec![3]),
+                Opcode::Pop.make(vec![]),
+                Opcode::JumpRel.make(vec![-21]),
+            ]),
+        }];
+
+        run_compiler(tests);
+
+        // Unlike `Pop`, `PopN` does not make the discarded value the result of the program
+        let mut compiler = Compiler::new();
+        compiler
+            .compile(parse("1; while (true) { 2 + { break; } }"))
+            .unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        vm.run().unwrap();
+        assert_eq!(vm.stack_top(), None);
+        assert_eq!(vm.result().as_ref(), &Object::INTEGER(1));
+
+        // More operands than the operand of `PopN` can count
+        let elements = vec!["0"; 300].join(", ");
+        let input =
+            format!("let i = 0; while (i < 10) {{ i = i + 1; [{elements}, {{ continue; }}] }} i");
+        let mut compiler = Compiler::new();
+        compiler.compile(parse(&input)).unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        vm.run().unwrap();
+        assert_eq!(vm.stack_top(), None);
+        assert_eq!(
+            vm.last_popped_stack_element().unwrap().as_ref(),
+            &Object::INTEGER(10)
+        );
+    }
+
+    #[test]
+    fn test_side_effect_free_statements_are_skipped() {
+        let tests = vec![
+            CompilerTestCase {
+                input: "5; -1.5 * 2 <= 3; !\"a\"; puts(5);".to_string(),
+                expected_constants: vec![Object::INTEGER(5)],
+                expected_instructions: flatten_instructions(vec![
+                    Opcode::GetBuiltin.make(vec![5]),
+                    Opcode::Constant.make(vec![0]),
+                    Opcode::Call.make(vec![1]),
+                    Opcode::Pop.make(vec![]),
+                ]),
+            },
+            // Divisions can fail, and the last expression is the result of the program
+            CompilerTestCase {
+                input: "1 / 0; 5; let a = 1;".to_string(),
+                expected_constants: vec![
+                    Object::INTEGER(1),
+                    Object::INTEGER(0),
+                    Object::INTEGER(5),
+                    Object::INTEGER(1),
+                ],
+                expected_instructions: flatten_instructions(vec![
+                    Opcode::Constant.make(vec![0]),
+                    Opcode::Constant.make(vec![1]),
+                    Opcode::Div.make(vec![]),
+                    Opcode::Pop.make(vec![]),
+                    Opcode::Constant.make(vec![2]),
+                    Opcode::Pop.make(vec![]),
+                    Opcode::Constant.make(vec![3]),
+                    Opcode::SetGlobal.make(vec![0]),
+                ]),
+            },
+            // So can integer operations that overflow, unlike the ones on floats
+            CompilerTestCase {
+                input: "9223372036854775807 + 1 > 0; 9223372036854775807.0 * 2; 5".to_string(),
+                expected_constants: vec![
+                    Object::INTEGER(9_223_372_036_854_775_807),
+                    Object::INTEGER(1),
+                    Object::INTEGER(0),
+                    Object::INTEGER(5),
+                ],
+                expected_instructions: flatten_instructions(vec![
+                    Opcode::Constant.make(vec![0]),
+                    Opcode::Constant.make(vec![1]),
+                    Opcode::Add.make(vec![]),
+                    Opcode::Constant.make(vec![2]),
+                    Opcode::GreaterThan.make(vec![]),
+                    Opcode::Pop.make(vec![]),
+                    Opcode::Constant.make(vec![3]),
+                    Opcode::Pop.make(vec![]),
+                ]),
+            },
+            CompilerTestCase {
+                input: "-(-9223372036854775808); 5".to_string(),
+                expected_constants: vec![Object::INTEGER(i64::MIN), Object::INTEGER(5)],
+                expected_instructions: flatten_instructions(vec![
+                    Opcode::Constant.make(vec![0]),
+                    Opcode::Minus.make(vec![]),
+                    Opcode::Pop.make(vec![]),
+                    Opcode::Constant.make(vec![1]),
+                    Opcode::Pop.make(vec![]),
+                ]),
+            },
+        ];
+
+        run_compiler(tests);
+    }
+
+    #[test]
+    fn test_builtin_arity_errors() {
+        let tests = vec![
+            (
+                "len(1, 2)",
+                "Wrong number of arguments for len: want=1, got=2 at 1:1",
+            ),
+            (
+                "let f = fn() {\n  push([1])\n};",
+                "Wrong number of arguments for push: want=2, got=1 at 2:3",
+            ),
+            (
+                "read_line(\"prompt\")",
+                "Wrong number of arguments for read_line: want=0, got=1 at 1:1",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let mut compiler = Compiler::new();
+            assert_eq!(
+                compiler.compile(parse(input)),
+                Err(expected.to_string()),
+                "input: {input}"
+            );
+        }
+
+        let valid = [
+            "len([1])",
+            "puts()",
+            "puts(1, 2, 3)",
+            // Only calls that resolve to the builtin itself are checked
+            "let len = fn(a, b) { a }; len(1, 2)",
+            "let f = fn(len) { len(1, 2) };",
+            "let length = len; length(1, 2)",
+        ];
+        for input in valid {
+            let mut compiler = Compiler::new();
+            assert_eq!(compiler.compile(parse(input)), Ok(()), "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_unused_variable_warnings() {
+        let tests = vec![
+            ("let a = 1;", vec!["Unused variable: a at 1:5"]),
+            ("let a = 1; a", vec![]),
+            ("let a = 1; let b = a;", vec!["Unused variable: b at 1:16"]),
+            // Variables that are only read by a function are used
+            ("let a = 1; let f = fn() { a }; f()", vec![]),
+            (
+                "let f = fn(x) {\n  let y = x;\n  let z = 2;\n  y\n}; f(1)",
+                vec!["Unused variable: z at 3:7"],
+            ),
+            ("let f = fn() { let a = 1; fn() { a } }; f()", vec![]),
+            ("{ let a = 1; 2 }", vec!["Unused variable: a at 1:7"]),
+            ("let _ignored = 1;", vec![]),
+        ];
+
+        for (input, expected) in tests {
+            let mut compiler = Compiler::new();
+            compiler.compile(parse(input)).unwrap();
+            let warnings = compiler
+                .warnings
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            assert_eq!(warnings, expected, "input: {input}");
+        }
+
+        // Later lines of a REPL session may still use the globals
+        let mut compiler = Compiler::new_with_state(Compiler::new().symbol_table, vec![]);
+        compiler.compile(parse("let a = 1;")).unwrap();
+        assert!(compiler.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_diagnostics_callback() {
+        let received = Rc::new(RefCell::new(vec![]));
+        let sink = Rc::clone(&received);
+        let mut compiler = Compiler::new();
+        compiler.set_diagnostics(Box::new(move |diagnostic| {
+            sink.borrow_mut().push(diagnostic)
+        }));
+        compiler
+            .compile(parse("let f = fn() { let y = 2; 3 }; f()"))
+            .unwrap();
+
+        let expected = Diagnostic {
+            kind: DiagnosticKind::Warning,
+            message: "Unused variable: y".to_string(),
+            position: Some(Position {
+                line: 1,
+                column: 20,
+            }),
+        };
+        assert_eq!(*received.borrow(), vec![expected.clone()]);
+        assert_eq!(expected.to_string(), "Warning: Unused variable: y at 1:20");
+        // The warnings are still collected
+        assert_eq!(compiler.warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_redefinition_warnings() {
+        let redefined =
+            |line, column| format!("Variable x is redefined in the same scope at {line}:{column}");
+        let tests = vec![
+            ("let x = 1; let x = 2; x", vec![redefined(1, 16)]),
+            (
+                "let f = fn() { let x = 1; let x = 2; x }; f()",
+                vec![redefined(1, 31)],
+            ),
+            ("{ let x = 1; let x = 2; x }", vec![redefined(1, 18)]),
+            // Shadowing in an inner scope is not a redefinition
+            ("let x = 1; { let x = 2; x } + x", vec![]),
+            ("let x = 1; let f = fn() { let x = 2; x }; f() + x", vec![]),
+            ("let x = 1; let f = fn(x) { x }; f(x)", vec![]),
+            // Neither is updating the value
+            ("let x = 1; let x = x + 1; x", vec![]),
+            ("let x = 0; while (x < 3) { let x = x + 1; } x", vec![]),
+        ];
+
+        for (input, expected) in tests {
+            let mut compiler = Compiler::new();
+            compiler.compile(parse(input)).unwrap();
+            let warnings = compiler
+                .warnings
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            assert_eq!(warnings, expected, "input: {input}");
+        }
+
+        let mut compiler = Compiler::new();
+        compiler.warn_on_redefinition(false);
+        compiler.compile(parse("let x = 1; let x = 2; x")).unwrap();
+        assert!(compiler.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_reset_scopes() {
+        let mut compiler = Compiler::new_with_state(Compiler::new().symbol_table, vec![]);
+        compiler
+            .compile(parse("let a = 40; let f = fn(x) { x + a };"))
+            .unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        vm.run().unwrap();
+
+        compiler.reset_scopes();
+        compiler.compile(parse("f(2)")).unwrap();
+        let bytecode = compiler.bytecode();
+        // Only the instructions of the second line are left, the constants are kept
+        assert_eq!(
+            bytecode.instructions,
+            flatten_instructions(vec![
+                Opcode::GetGlobal.make(vec![1]),
+                Opcode::Constant.make(vec![2]),
+                Opcode::Call.make(vec![1]),
+                Opcode::Pop.make(vec![]),
+            ])
+        );
+        assert_eq!(bytecode.constants.len(), 3);
+
+        let mut vm = VM::new_with_global_store(bytecode, vm.globals);
+        vm.run().unwrap();
+        assert_eq!(
+            vm.last_popped_stack_element().unwrap().as_ref(),
+            &Object::INTEGER(42)
+        );
+
+        // A failure inside a function does not leave its scope behind
+        assert!(compiler.compile(parse("let g = fn() { y };")).is_err());
+        compiler.reset_scopes();
+        compiler.compile(parse("let b = a;")).unwrap();
+        assert_eq!(
+            compiler
+                .symbol_table
+                .resolve("b")
+                .map(|symbol| symbol.scope),
+            Some(SymbolScope::Global)
+        );
+    }
+
+    #[test]
+    fn test_compiling_a_large_program() {
+        let statements = 10_000;
+        let input = format!("let a = 1;\n{}", "a;\n".repeat(statements));
+        let program = parse(&input);
+        let mut compiler = Compiler::new();
+        // `Constant` and `SetGlobal`, then `GetGlobal` and `Pop` for each statement
+        let length = 6 + statements * 4;
+        compiler.scopes[0].instructions.data.reserve(length);
+        let buffer = compiler.current_instructions().data.as_ptr();
+        compiler.compile(program).unwrap();
+
+        assert_eq!(compiler.current_instructions_len(), length);
+        assert!(std::ptr::eq(
+            compiler.current_instructions(),
+            &compiler.scopes[0].instructions
+        ));
+        // Every emit appends to the buffer of the scope, none copies it to a new one
+        assert_eq!(compiler.current_instructions().data.as_ptr(), buffer);
+    }
 }
@@ -29,6 +29,7 @@ pub mod tests {
                         ]),
                         num_locals: 0,
                         num_parameters: 0,
+                        name: None,
                     }),
                 ],
                 expected_instructions: flatten_instructions(vec![
@@ -50,6 +51,7 @@ pub mod tests {
                         ]),
                         num_locals: 0,
                         num_parameters: 0,
+                        name: None,
                     }),
                 ],
                 expected_instructions: flatten_instructions(vec![
@@ -60,21 +62,19 @@ pub mod tests {
             CompilerTestCase {
                 input: "fn() { 1; 2 }".to_string(),
                 expected_constants: vec![
-                    Object::INTEGER(1),
                     Object::INTEGER(2),
                     Object::COMPILEDFUNCTION(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![
                             Opcode::Constant.make(vec![0]),
-                            Opcode::Pop.make(vec![]),
-                            Opcode::Constant.make(vec![1]),
                             Opcode::ReturnValue.make(vec![]),
                         ]),
                         num_locals: 0,
                         num_parameters: 0,
+                        name: None,
                     }),
                 ],
                 expected_instructions: flatten_instructions(vec![
-                    Opcode::Closure.make(vec![2, 0]),
+                    Opcode::Closure.make(vec![1, 0]),
                     Opcode::Pop.make(vec![]),
                 ]),
             },
@@ -89,6 +89,7 @@ pub mod tests {
                         ]),
                         num_locals: 0,
                         num_parameters: 0,
+                        name: None,
                     }),
                 ],
                 expected_instructions: flatten_instructions(vec![
@@ -108,6 +109,7 @@ pub mod tests {
                         ]),
                         num_locals: 0,
                         num_parameters: 0,
+                        name: Some("noArg".to_string()),
                     }),
                 ],
                 expected_instructions: flatten_instructions(vec![
@@ -125,6 +127,7 @@ pub mod tests {
                         instructions: flatten_u8_instructions(vec![Opcode::Return.make(vec![0])]),
                         num_locals: 1,
                         num_parameters: 1,
+                        name: Some("oneArg".to_string()),
                     }),
                     Object::INTEGER(24),
                 ],
@@ -144,6 +147,7 @@ pub mod tests {
                         instructions: flatten_u8_instructions(vec![Opcode::Return.make(vec![0])]),
                         num_locals: 3,
                         num_parameters: 3,
+                        name: Some("manyArg".to_string()),
                     }),
                     Object::INTEGER(24),
                     Object::INTEGER(25),
@@ -170,6 +174,7 @@ pub mod tests {
                         ]),
                         num_locals: 1,
                         num_parameters: 1,
+                        name: Some("oneArg".to_string()),
                     }),
                     Object::INTEGER(24),
                 ],
@@ -196,6 +201,7 @@ pub mod tests {
                         ]),
                         num_locals: 3,
                         num_parameters: 3,
+                        name: Some("manyArg".to_string()),
                     }),
                     Object::INTEGER(24),
                     Object::INTEGER(25),
@@ -225,6 +231,7 @@ pub mod tests {
                 instructions: flatten_u8_instructions(vec![Opcode::Return.make(vec![])]),
                 num_locals: 0,
                 num_parameters: 0,
+                name: None,
             })],
             expected_instructions: flatten_instructions(vec![
                 Opcode::Closure.make(vec![0, 0]),
@@ -235,6 +242,74 @@ pub mod tests {
         run_compiler(tests);
     }
 
+    #[test]
+    fn test_conditional_returning_on_every_branch() {
+        let tests = vec![
+            CompilerTestCase {
+                input: "fn(x) { if (x) { return 1; } else { return 2; } }".to_string(),
+                expected_constants: vec![
+                    Object::INTEGER(1),
+                    Object::INTEGER(2),
+                    Object::COMPILEDFUNCTION(CompiledFunction {
+                        instructions: flatten_u8_instructions(vec![
+                            // 0000
+                            Opcode::GetLocal.make(vec![0]),
+                            // 0002
+                            Opcode::JumpNotTruthy.make(vec![9]),
+                            // 0005
+                            Opcode::Constant.make(vec![0]),
+                            // 0008
+                            Opcode::ReturnValue.make(vec![]),
+                            // 0009
+                            Opcode::Constant.make(vec![1]),
+                            // 0012
+                            Opcode::ReturnValue.make(vec![]),
+                        ]),
+                        num_locals: 1,
+                        num_parameters: 1,
+                        name: None,
+                    }),
+                ],
+                expected_instructions: flatten_instructions(vec![
+                    Opcode::Closure.make(vec![2, 0]),
+                    Opcode::Pop.make(vec![]),
+                ]),
+            },
+            CompilerTestCase {
+                // The `null` of the missing `else` is still needed
+                input: "fn(x) { if (x) { return 1; } }".to_string(),
+                expected_constants: vec![
+                    Object::INTEGER(1),
+                    Object::COMPILEDFUNCTION(CompiledFunction {
+                        instructions: flatten_u8_instructions(vec![
+                            // 0000
+                            Opcode::GetLocal.make(vec![0]),
+                            // 0002
+                            Opcode::JumpNotTruthy.make(vec![9]),
+                            // 0005
+                            Opcode::Constant.make(vec![0]),
+                            // 0008
+                            Opcode::ReturnValue.make(vec![]),
+                            // 0009
+                            Opcode::Null.make(vec![]),
+                            // 0010
+                            Opcode::ReturnValue.make(vec![]),
+                        ]),
+                        num_locals: 1,
+                        num_parameters: 1,
+                        name: None,
+                    }),
+                ],
+                expected_instructions: flatten_instructions(vec![
+                    Opcode::Closure.make(vec![1, 0]),
+                    Opcode::Pop.make(vec![]),
+                ]),
+            },
+        ];
+
+        run_compiler(tests);
+    }
+
     #[test]
     fn test_let_statements_scope() {
         let tests = vec![
@@ -252,6 +327,7 @@ pub mod tests {
                         ]),
                         num_locals: 0,
                         num_parameters: 0,
+                        name: None,
                     }),
                 ],
                 expected_instructions: flatten_instructions(vec![
@@ -279,6 +355,7 @@ pub mod tests {
                         ]),
                         num_locals: 1,
                         num_parameters: 0,
+                        name: None,
                     }),
                 ],
                 expected_instructions: flatten_instructions(vec![
@@ -310,6 +387,7 @@ pub mod tests {
                         ]),
                         num_locals: 2,
                         num_parameters: 0,
+                        name: None,
                     }),
                 ],
                 expected_instructions: flatten_instructions(vec![
@@ -350,6 +428,7 @@ pub mod tests {
                     ]),
                     num_locals: 0,
                     num_parameters: 0,
+                    name: None,
                 })],
                 expected_instructions: flatten_instructions(vec![
                     Opcode::Closure.make(vec![0, 0]),
@@ -383,6 +462,7 @@ pub mod tests {
                         ]),
                         num_locals: 1,
                         num_parameters: 1,
+                        name: None,
                     }),
                     Object::COMPILEDFUNCTION(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![
@@ -392,6 +472,7 @@ pub mod tests {
                         ]),
                         num_locals: 1,
                         num_parameters: 1,
+                        name: None,
                     }),
                 ],
                 expected_instructions: flatten_instructions(vec![
@@ -422,6 +503,7 @@ pub mod tests {
                         ]),
                         num_locals: 1,
                         num_parameters: 1,
+                        name: None,
                     }),
                     Object::COMPILEDFUNCTION(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![
@@ -432,6 +514,7 @@ pub mod tests {
                         ]),
                         num_locals: 1,
                         num_parameters: 1,
+                        name: None,
                     }),
                     Object::COMPILEDFUNCTION(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![
@@ -441,6 +524,7 @@ pub mod tests {
                         ]),
                         num_locals: 1,
                         num_parameters: 1,
+                        name: None,
                     }),
                 ],
                 expected_instructions: flatten_instructions(vec![
@@ -483,6 +567,7 @@ pub mod tests {
                         ]),
                         num_locals: 1,
                         num_parameters: 0,
+                        name: None,
                     }),
                     Object::COMPILEDFUNCTION(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![
@@ -495,6 +580,7 @@ pub mod tests {
                         ]),
                         num_locals: 1,
                         num_parameters: 0,
+                        name: None,
                     }),
                     Object::COMPILEDFUNCTION(CompiledFunction {
                         instructions: flatten_u8_instructions(vec![
@@ -506,6 +592,7 @@ pub mod tests {
                         ]),
                         num_locals: 1,
                         num_parameters: 0,
+                        name: None,
                     }),
                 ],
                 expected_instructions: flatten_instructions(vec![
@@ -543,6 +630,7 @@ pub mod tests {
                         ]),
                         num_locals: 1,
                         num_parameters: 1,
+                        name: Some("countDown".to_string()),
                     }),
                     Object::INTEGER(1),
                 ],
@@ -579,6 +667,7 @@ pub mod tests {
                         ]),
                         num_locals: 1,
                         num_parameters: 1,
+                        name: Some("countDown".to_string()),
                     }),
                     Object::INTEGER(1),
                     Object::COMPILEDFUNCTION(CompiledFunction {
@@ -592,6 +681,7 @@ pub mod tests {
                         ]),
                         num_locals: 1,
                         num_parameters: 0,
+                        name: Some("wrapper".to_string()),
                     }),
                 ],
                 expected_instructions: flatten_instructions(vec![
@@ -1,29 +1,36 @@
 pub mod code;
 mod compiler_tests;
 mod function_tests;
+mod optimizer;
 pub mod symbol_table;
 mod test_utils;
+pub mod verifier;
 mod while_tests;
 
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, collections::HashMap, fmt::Display, rc::Rc};
 
 use crate::{
     compiler::{
-        code::{Instructions, Opcode},
+        code::{DecodedInstructions, Instructions, Opcode},
         symbol_table::{Symbol, SymbolScope, SymbolTable},
     },
-    lexer::token::Token,
+    diagnostics::{Diagnostic, DiagnosticsCallback},
+    lexer::{token::Token, Position},
     object::{
-        builtins::BuiltinFunction,
+        builtins::{BuiltinFunction, BuiltinRegistry},
         {CompiledFunction, Object},
     },
-    parser::ast::{
-        BlockStatement, Conditional, Expression, FunctionLiteral, InfixOperator, LetStatement,
-        LoopStatement, Primitive, Program, Statement, WhileStatement,
+    parser::{
+        ast::{
+            AssignStatement, BlockStatement, Conditional, Expression, FunctionCall,
+            FunctionLiteral, Identifier, InfixOperator, InterpolatedString, LetStatement,
+            LoopStatement, Primitive, Program, Statement, StringPart, TryCatch, WhileStatement,
+        },
+        visitor::{walk_expression, Visitor},
     },
 };
 
-use num_traits::FromPrimitive;
+use num_traits::{FromPrimitive, ToPrimitive};
 
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
@@ -37,6 +44,15 @@ struct CompilerScope {
     last_instruction: Option<EmittedInstruction>,
     previous_instruction: Option<EmittedInstruction>,
     loop_scope: Option<Rc<RefCell<LoopScope>>>,
+    // Number of `try` blocks being compiled, which `break` and `continue` have to leave
+    try_depth: usize,
+    // Number of values that the expressions being compiled have pushed and not consumed
+    // yet, which `break` and `continue` have to pop
+    operands: usize,
+    // Variables defined by a `let` in this scope, checked for uses when it is left
+    definitions: Vec<Identifier>,
+    // Parameters and local variables of the function, including the ones of its blocks
+    locals: Vec<Symbol>,
 }
 
 impl Default for CompilerScope {
@@ -52,11 +68,20 @@ impl CompilerScope {
             last_instruction: None,
             previous_instruction: None,
             loop_scope: None,
+            try_depth: 0,
+            operands: 0,
+            definitions: vec![],
+            locals: vec![],
         }
     }
 
     fn enter_loop_scope(&mut self, start_position: usize) {
-        let loop_scope = LoopScope::new_enclosed(self.loop_scope.clone(), start_position);
+        let loop_scope = LoopScope::new_enclosed(
+            self.loop_scope.clone(),
+            start_position,
+            self.try_depth,
+            self.operands,
+        );
         self.loop_scope = Some(Rc::new(RefCell::new(loop_scope)));
     }
 
@@ -72,14 +97,25 @@ struct LoopScope {
     outer: Option<Rc<RefCell<LoopScope>>>,
     start_position: usize,
     breaks: Vec<usize>,
+    // Number of `try` blocks around the loop
+    try_depth: usize,
+    // Number of operands on the stack when the loop starts
+    operands: usize,
 }
 
 impl LoopScope {
-    pub fn new_enclosed(outer: Option<Rc<RefCell<LoopScope>>>, start_position: usize) -> Self {
+    pub fn new_enclosed(
+        outer: Option<Rc<RefCell<LoopScope>>>,
+        start_position: usize,
+        try_depth: usize,
+        operands: usize,
+    ) -> Self {
         Self {
             outer,
             start_position,
             breaks: vec![],
+            try_depth,
+            operands,
         }
     }
 
@@ -99,6 +135,19 @@ pub struct Compiler {
 
     scopes: Vec<CompilerScope>,
     scope_index: usize,
+
+    emit_final_pop: bool,
+
+    /// Problems found while compiling that do not make it fail, such as unused variables.
+    pub warnings: Vec<Warning>,
+    check_unused_globals: bool,
+    warn_on_redefinition: bool,
+    // Receives the warnings as they are found, in addition to `warnings`
+    diagnostics: Option<Box<DiagnosticsCallback>>,
+
+    /// Local variables of each compiled function, by index of the function in the constant
+    /// pool, so that the debugger can show them.
+    pub function_locals: HashMap<usize, Vec<Symbol>>,
 }
 
 impl Default for Compiler {
@@ -122,26 +171,131 @@ impl Compiler {
 
             scopes: vec![main_scope],
             scope_index: 0,
+
+            emit_final_pop: true,
+
+            warnings: vec![],
+            check_unused_globals: true,
+            warn_on_redefinition: true,
+            diagnostics: None,
+
+            function_locals: HashMap::new(),
         }
     }
 
+    /// Creates a compiler that also resolves the functions of `registry` as builtins.
+    pub fn new_with_registry(registry: &BuiltinRegistry) -> Self {
+        let mut compiler = Compiler::new();
+        for (i, name) in registry.names().enumerate() {
+            compiler
+                .symbol_table
+                .define_builtin(BuiltinRegistry::native_count() + i, name.to_string());
+        }
+        compiler
+    }
+
+    /// Creates a compiler that carries on from the state of a previous one, like the REPL
+    /// does between lines. Later compilations may use its globals, so unused ones are not
+    /// reported.
     pub fn new_with_state(symbol_table: SymbolTable, constants: Vec<Object>) -> Self {
         let mut compiler = Compiler::new();
         compiler.symbol_table = symbol_table;
         compiler.constants = constants;
+        compiler.check_unused_globals = false;
         compiler
     }
 
-    pub fn compile(&mut self, program: Program) -> Result<(), String> {
-        self.compile_statements(program.statements)
+    /// Hands the warnings to `callback` as they are found, as structured data. They are
+    /// still collected in `warnings`.
+    pub fn set_diagnostics(&mut self, callback: Box<DiagnosticsCallback>) {
+        self.diagnostics = Some(callback);
+    }
+
+    /// Prepares the compiler for another program, which sees the globals and constants of
+    /// the ones compiled before, as the lines of a REPL do. The instructions and warnings of
+    /// the previous program are discarded, but their buffers are kept for the next one.
+    ///
+    /// The symbol table is brought back to the global scope, in case the previous program
+    /// failed to compile in the middle of a function.
+    pub fn reset_scopes(&mut self) {
+        while let Some(outer) = self.symbol_table.outer.take() {
+            self.symbol_table = outer.as_ref().clone().into_inner();
+        }
+
+        self.scopes.truncate(1);
+        self.scope_index = 0;
+        let scope = &mut self.scopes[0];
+        scope.instructions.data.clear();
+        scope.last_instruction = None;
+        scope.previous_instruction = None;
+        scope.loop_scope = None;
+        scope.try_depth = 0;
+        scope.operands = 0;
+        scope.definitions.clear();
+
+        self.warnings.clear();
+    }
+
+    /// Sets whether the last statement of a program, when it is an expression, is followed
+    /// by a `Pop` like every other expression statement (the default).
+    ///
+    /// Without it the value of the program stays on the stack once the bytecode has run,
+    /// and can be read with `VM::stack_top` instead of `VM::last_popped_stack_element`.
+    pub fn emit_final_pop(&mut self, emit: bool) {
+        self.emit_final_pop = emit;
+    }
+
+    /// Sets whether defining a variable again in the same scope is reported as a warning
+    /// (the default). Shadowing a variable in an inner block or function never is, nor is
+    /// a redefinition that reads the previous value, like `let i = i + 1;`.
+    pub fn warn_on_redefinition(&mut self, warn: bool) {
+        self.warn_on_redefinition = warn;
+    }
+
+    pub fn compile(&mut self, mut program: Program) -> Result<(), String> {
+        optimizer::fold_constant_conditionals(&mut program);
+        let mut statements = program.statements;
+        match statements.pop() {
+            Some(Statement::Expression(last)) if !self.emit_final_pop => {
+                self.compile_statements(statements)?;
+                self.compile_expression(last)?;
+            }
+            last => {
+                statements.extend(last);
+                self.compile_statements(statements)?;
+            }
+        }
+
+        if self.check_unused_globals {
+            self.report_unused_variables();
+        }
+        Ok(())
+    }
+
+    /// Compiles a single expression, leaving its value on the stack instead of popping it
+    /// as an expression statement would. Once the bytecode has run, the value is the top
+    /// of the VM's stack (see `VM::stack_top`), which is how the REPL echoes expressions.
+    pub fn compile_expression_public(&mut self, expression: Expression) -> Result<(), String> {
+        self.compile_expression(expression)
     }
 
     fn compile_block_statement(&mut self, block: BlockStatement) -> Result<(), String> {
         self.compile_statements(block.statements)
     }
 
+    /// Expression statements without side effects are skipped, as their value is popped
+    /// right away. The last expression statement is always compiled: its value may be the
+    /// one of the block, or the result of the program (see `VM::result`).
     fn compile_statements(&mut self, statements: Vec<Statement>) -> Result<(), String> {
-        for statement in statements {
+        let last_expression = statements
+            .iter()
+            .rposition(|statement| matches!(statement, Statement::Expression(_)));
+        for (i, statement) in statements.into_iter().enumerate() {
+            if last_expression.is_some_and(|last| i < last)
+                && matches!(&statement, Statement::Expression(e) if is_side_effect_free(e))
+            {
+                continue;
+            }
             self.compile_statement(statement)?;
         }
 
@@ -151,56 +305,105 @@ impl Compiler {
     fn compile_statement(&mut self, statement: Statement) -> Result<(), String> {
         match statement {
             Statement::Expression(s) => {
+                // Such a conditional pushes nothing, as none of its branches reaches the end
+                let exits = matches!(&s, Expression::Conditional(c) if always_exits_conditional(c));
                 self.compile_expression(s)?;
-                self.emit(Opcode::Pop, vec![]);
+                if !exits {
+                    self.emit(Opcode::Pop, vec![]);
+                }
             }
             Statement::Let(s) => {
                 self.compiler_let_statement(s)?;
             }
+            Statement::Assign(s) => {
+                self.compile_assign_statement(s)?;
+            }
             Statement::Return(r) => {
+                if self.scope_index == 0 {
+                    return Err("return outside of function".to_string());
+                }
                 self.compile_expression(r.return_value)?;
                 self.emit(Opcode::ReturnValue, vec![]);
             }
+            Statement::Throw(t) => {
+                self.compile_expression(t.value)?;
+                self.emit(Opcode::Throw, vec![]);
+            }
             Statement::While(wh) => {
                 self.compile_while_statement(wh)?;
             }
 
-            Statement::LoopStatements(smt) => self.compile_loop_statement(&smt),
+            Statement::LoopStatements(smt) => self.compile_loop_statement(&smt)?,
+            Statement::Enum(enum_stmt) => {
+                for variant in enum_stmt.into_let_statements() {
+                    let name = variant.name.value.clone();
+                    self.compiler_let_statement(variant)?;
+                    // Unused variants are not worth a warning
+                    self.symbol_table.mark_used(&name);
+                }
+            }
+            Statement::Comment(_) => {}
         }
 
         Ok(())
     }
 
     fn compiler_let_statement(&mut self, s: LetStatement) -> Result<(), String> {
+        let symbol = self.define_variable(&s.name.value);
+
+        let definitions = &mut self.scopes[self.scope_index].definitions;
+        if !definitions.iter().any(|name| name.value == s.name.value) {
+            definitions.push(s.name);
+        } else if self.warn_on_redefinition
+            && !s
+                .value
+                .as_ref()
+                .is_some_and(|value| reads_variable(value, &s.name.value))
+        {
+            self.warn(
+                format!("Variable {} is redefined in the same scope", s.name.value),
+                s.name.position,
+            );
+        }
+
+        match s.value {
+            Some(value) => self.compile_expression(value)?,
+            None => {
+                self.emit(Opcode::Null, vec![]);
+            }
+        }
+
+        self.store_variable(&symbol);
+
+        Ok(())
+    }
+
+    /// The symbol a `let` of `name` defines in the current scope.
+    fn define_variable(&mut self, name: &str) -> Symbol {
         // This step is extremely important. If it is not done then when shadowing variables
         // and using the previous value we get an error. Because we would have assigned
         // a new index to the symbol and the GetGlobal instruction would get a NULL
         // value instead of the previous value. (corresponds to issue #8)
-        let symbol = match self.symbol_table.resolve(&s.name.value) {
-            Some(symbol) => match symbol.scope {
-                SymbolScope::Global => {
-                    // A Local variable should never replace a global one
-                    if self.symbol_table.has_outer() {
-                        // This means that the symbol will
-                        // be local and not global, and thus not
-                        // replace the global one
-                        self.symbol_table.define(s.name.value)
-                    } else {
-                        symbol
-                    }
-                }
-                SymbolScope::Local => symbol,
-
-                // We only want to do in in the case of "normal" variable assignation.
-                // The special cases should not be touched, since the program should not
-                // have access to them, only the compiler/vm
-                _ => self.symbol_table.define(s.name.value),
-            },
-            None => self.symbol_table.define(s.name.value),
-        };
+        //
+        // Only the variables of the current scope are reused: a `let` in a function or a
+        // block shadows the variables of the enclosing scopes, and the builtins.
+        match self.symbol_table.own_variable(name) {
+            Some(symbol) => symbol,
+            None => self.define_symbol(name.to_string()),
+        }
+    }
 
-        self.compile_expression(s.value)?;
+    /// Defines `name` in the symbol table, keeping track of the locals of the function.
+    fn define_symbol(&mut self, name: String) -> Symbol {
+        let symbol = self.symbol_table.define(name);
+        if symbol.scope == SymbolScope::Local {
+            self.scopes[self.scope_index].locals.push(symbol.clone());
+        }
+        symbol
+    }
 
+    /// Stores the value on top of the stack in a variable returned by `define_variable`.
+    fn store_variable(&mut self, symbol: &Symbol) {
         match symbol.scope {
             SymbolScope::Global => {
                 self.emit(Opcode::SetGlobal, vec![symbol.index as i32]);
@@ -224,76 +427,274 @@ impl Compiler {
                 )
             }
         }
+    }
 
+    fn compile_assign_statement(&mut self, s: AssignStatement) -> Result<(), String> {
+        let name = &s.name;
+        let Some(symbol) = self.symbol_table.resolve(&name.value) else {
+            return Err(format!(
+                "Undefined variable: {} at {}",
+                name.value, name.position
+            ));
+        };
+        let opcode = match symbol.scope {
+            SymbolScope::Global => Opcode::SetGlobal,
+            SymbolScope::Local => Opcode::SetLocal,
+            // Closures hold a copy of the variables of the enclosing functions, assigning
+            // to it would not change the original
+            SymbolScope::Free => {
+                return Err(format!(
+                    "Cannot assign to {}, a variable of an enclosing function, at {}",
+                    name.value, name.position
+                ));
+            }
+            SymbolScope::Builtin | SymbolScope::Function => {
+                return Err(format!(
+                    "Cannot assign to the function {} at {}",
+                    name.value, name.position
+                ));
+            }
+        };
+
+        self.compile_expression(s.value)?;
+        self.emit(opcode, vec![symbol.index as i32]);
         Ok(())
     }
 
     fn compile_expression(&mut self, expression: Expression) -> Result<(), String> {
         match expression {
             Expression::Infix(infix) => match infix.token {
-                Token::LT | Token::LTE => self.compile_lt_and_lte(infix)?,
+                Token::And | Token::Or | Token::NullCoalesce => {
+                    self.compile_logical_operator(infix)?;
+                }
                 _ => {
                     self.compile_expression(*infix.left)?;
-                    self.compile_expression(*infix.right)?;
-                    self.compile_infix_operator(&infix.token)?;
+                    self.compile_operand(*infix.right, 1)?;
+                    self.compile_infix_operator(&infix.token, infix.position)?;
+                }
+            },
+            Expression::Prefix(prefix) => match (prefix.token, *prefix.right) {
+                // A minus right before an integer literal is a negative literal, it is
+                // compiled to a single constant instead of the literal followed by `Minus`.
+                (Token::Minus, Expression::Primitive(Primitive::IntegerLiteral(i)))
+                    if i.checked_neg().is_some() =>
+                {
+                    self.compile_primitive(Primitive::IntegerLiteral(-i))?;
+                }
+                (token, right) => {
+                    self.compile_expression(right)?;
+                    self.compile_prefix_operator(&token, prefix.position)?;
                 }
             },
-            Expression::Prefix(prefix) => {
-                self.compile_expression(*prefix.right)?;
-                self.compile_prefix_operator(&prefix.token)?;
-            }
             Expression::Primitive(primitive) => self.compile_primitive(primitive)?,
             Expression::Conditional(conditional) => self.compile_conditional(conditional)?,
+            Expression::TryCatch(try_catch) => self.compile_try_catch(try_catch)?,
             Expression::Identifier(ident) => {
                 let symbol = self.symbol_table.resolve(&ident.value);
                 match symbol {
-                    Some(symbol) => self.load_symbol(&symbol),
+                    Some(symbol) => {
+                        self.symbol_table.mark_used(&ident.value);
+                        self.load_symbol(&symbol);
+                    }
                     None => {
-                        return Err(format!("Undefined variable: {}", ident.value));
+                        return Err(format!(
+                            "Undefined variable: {} at {}",
+                            ident.value, ident.position
+                        ));
                     }
                 }
             }
-            Expression::ArrayLiteral(array) => {
-                let len = i32::from_usize(array.elements.len()).ok_or("Invalid array length")?;
-                for element in array.elements {
-                    self.compile_expression(element)?;
-                }
-                self.emit(Opcode::Array, vec![len]);
-            }
+            Expression::ArrayLiteral(array) => self.compile_elements(array.elements)?,
 
             Expression::HashMapLiteral(hasmap) => {
                 let len = i32::from_usize(hasmap.pairs.len()).ok_or("Invalid hashmap length")?;
-                for (key, value) in hasmap.pairs {
-                    self.compile_expression(key)?;
-                    self.compile_expression(value)?;
+                for (i, (key, value)) in hasmap.pairs.into_iter().enumerate() {
+                    self.compile_operand(key, 2 * i)?;
+                    self.compile_operand(value, 2 * i + 1)?;
                 }
                 self.emit(Opcode::HashMap, vec![len * 2]);
             }
             Expression::IndexExpression(index) => {
                 self.compile_expression(*index.left)?;
-                self.compile_expression(*index.index)?;
+                let null_jump = index
+                    .optional
+                    .then(|| self.emit(Opcode::JumpNull, vec![9999]));
+                self.compile_operand(*index.index, 1)?;
                 self.emit(Opcode::Index, vec![]);
+                self.patch_null_jump(null_jump)?;
             }
             Expression::FunctionLiteral(fun) => {
                 self.compile_function_literal(fun)?;
             }
-            Expression::FunctionCall(call) => {
-                self.compile_expression(*call.function)?;
+            Expression::FunctionCall(call) => self.compile_function_call(call)?,
+            Expression::Block(block) => self.compile_block_expression(block)?,
+            Expression::InterpolatedString(string) => self.compile_interpolated_string(string)?,
+            Expression::Spread(_) => {
+                return Err(
+                    "Spreading is only allowed in array literals and call arguments".to_string(),
+                );
+            }
+        }
 
-                let args_length =
-                    i32::from_usize(call.arguments.len()).ok_or("Invalid argument length")?;
+        Ok(())
+    }
+
+    /// Compiles `expression` while `below` values pushed for the enclosing expression are
+    /// on the stack, so that a `break` or `continue` inside of it pops them.
+    fn compile_operand(&mut self, expression: Expression, below: usize) -> Result<(), String> {
+        self.scopes[self.scope_index].operands += below;
+        let compiled = self.compile_expression(expression);
+        self.scopes[self.scope_index].operands -= below;
+        compiled
+    }
 
-                for argument in call.arguments {
-                    self.compile_expression(argument)?;
+    /// Compiles the elements of an array literal, or the spread arguments of a call, to a
+    /// single array. The elements between two spreads are gathered in arrays, which are
+    /// concatenated with the spread arrays, from left to right.
+    fn compile_elements(&mut self, elements: Vec<Expression>) -> Result<(), String> {
+        let mut arrays = 0;
+        let mut pending = 0;
+        for element in elements {
+            // The arrays gathered so far are concatenated as soon as there are two
+            let below = arrays.min(1) + pending;
+            if let Expression::Spread(spread) = element {
+                if pending > 0 {
+                    self.emit_array(pending, &mut arrays)?;
+                    pending = 0;
                 }
+                self.compile_operand(*spread, arrays.min(1))?;
+                self.emit(Opcode::Spread, vec![]);
+                self.concat_array(&mut arrays);
+            } else {
+                self.compile_operand(element, below)?;
+                pending += 1;
+            }
+        }
+        if pending > 0 || arrays == 0 {
+            self.emit_array(pending, &mut arrays)?;
+        }
+        Ok(())
+    }
+
+    /// Gathers the last `len` values of the stack in an array, see `compile_elements`.
+    fn emit_array(&mut self, len: usize, arrays: &mut usize) -> Result<(), String> {
+        let len = i32::from_usize(len).ok_or("Invalid array length")?;
+        self.emit(Opcode::Array, vec![len]);
+        self.concat_array(arrays);
+        Ok(())
+    }
+
+    fn concat_array(&mut self, arrays: &mut usize) {
+        *arrays += 1;
+        if *arrays > 1 {
+            self.emit(Opcode::Add, vec![]);
+        }
+    }
 
-                self.emit(Opcode::Call, vec![args_length]);
+    /// Concatenates the parts of an interpolated string with `Add`, the expressions being
+    /// converted by the `str` builtin, which is loaded directly so that a variable named
+    /// `str` does not replace it.
+    fn compile_interpolated_string(&mut self, string: InterpolatedString) -> Result<(), String> {
+        if string.parts.is_empty() {
+            return self.compile_primitive(Primitive::StringLiteral(String::new()));
+        }
+        let str_index = BuiltinFunction::STR
+            .to_i32()
+            .ok_or("Invalid builtin index")?;
+        for (i, part) in string.parts.into_iter().enumerate() {
+            match part {
+                StringPart::Text(text) => {
+                    self.compile_primitive(Primitive::StringLiteral(text))?;
+                }
+                StringPart::Expression(expression) => {
+                    self.emit(Opcode::GetBuiltin, vec![str_index]);
+                    self.compile_operand(expression, usize::from(i > 0) + 1)?;
+                    self.emit(Opcode::Call, vec![1]);
+                }
+            }
+            if i > 0 {
+                self.emit(Opcode::Add, vec![]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Compiles a call, a method being the builtin of that name called on its receiver.
+    fn compile_function_call(&mut self, call: FunctionCall) -> Result<(), String> {
+        let spread = call.arguments.iter().any(Expression::is_spread);
+        let builtin = self.called_builtin(&call);
+        if let (Some(builtin), Expression::Identifier(ident), false) =
+            (&builtin, call.function.as_ref(), spread)
+        {
+            Self::check_builtin_arity(builtin, ident, call.arguments.len())?;
+        }
+        match builtin {
+            // A method is the builtin of that name, whatever the variables in scope
+            Some(builtin) if call.method => {
+                let index = builtin.to_i32().ok_or("Invalid builtin index")?;
+                self.emit(Opcode::GetBuiltin, vec![index]);
+            }
+            _ => self.compile_expression(*call.function)?,
+        }
+        let null_jump = call
+            .optional
+            .then(|| self.emit(Opcode::JumpNull, vec![9999]));
+
+        if spread {
+            // The number of arguments is only known at runtime
+            self.scopes[self.scope_index].operands += 1;
+            let compiled = self.compile_elements(call.arguments);
+            self.scopes[self.scope_index].operands -= 1;
+            compiled?;
+            self.emit(Opcode::CallSpread, vec![]);
+        } else {
+            let args_length =
+                i32::from_usize(call.arguments.len()).ok_or("Invalid argument length")?;
+
+            for (i, argument) in call.arguments.into_iter().enumerate() {
+                self.compile_operand(argument, i + 1)?;
             }
+
+            self.emit(Opcode::Call, vec![args_length]);
         }
+        self.patch_null_jump(null_jump)?;
 
         Ok(())
     }
 
+    /// The builtin that `call` calls, when it is known at compile time.
+    fn called_builtin(&mut self, call: &FunctionCall) -> Option<BuiltinFunction> {
+        let Expression::Identifier(ident) = call.function.as_ref() else {
+            return None;
+        };
+        if call.method {
+            return BuiltinFunction::try_from(ident.value.as_str()).ok();
+        }
+        let symbol = self.symbol_table.resolve(&ident.value)?;
+        if symbol.scope != SymbolScope::Builtin {
+            return None;
+        }
+        BuiltinFunction::from_usize(symbol.index)
+    }
+
+    /// Reports calls to native builtins with a wrong number of arguments, which would
+    /// otherwise only fail at runtime. Host functions declare no arity and are not checked.
+    fn check_builtin_arity(
+        builtin: &BuiltinFunction,
+        ident: &Identifier,
+        count: usize,
+    ) -> Result<(), String> {
+        let arity = builtin.arity();
+        if arity.accepts(count) {
+            Ok(())
+        } else {
+            Err(format!(
+                "Wrong number of arguments for {}: want={arity}, got={count} at {}",
+                ident.value, ident.position
+            ))
+        }
+    }
+
     fn compile_primitive(&mut self, primitive: Primitive) -> Result<(), String> {
         match primitive {
             Primitive::IntegerLiteral(i) => {
@@ -302,6 +703,11 @@ impl Compiler {
                 let pos = i32::from_usize(pos).ok_or("Invalid constant position")?;
                 self.emit(Opcode::Constant, vec![pos]);
             }
+            Primitive::FloatLiteral(x) => {
+                let pos = self.add_constant(Object::FLOAT(x));
+                let pos = i32::from_usize(pos).ok_or("Invalid constant position")?;
+                self.emit(Opcode::Constant, vec![pos]);
+            }
             Primitive::BooleanLiteral(true) => {
                 self.emit(Opcode::True, vec![]);
             }
@@ -314,91 +720,196 @@ impl Compiler {
                 let pos = i32::from_usize(pos).ok_or("Invalid constant position")?;
                 self.emit(Opcode::Constant, vec![pos]);
             }
+            Primitive::CharLiteral(c) => {
+                let pos = self.add_constant(Object::CHAR(c));
+                let pos = i32::from_usize(pos).ok_or("Invalid constant position")?;
+                self.emit(Opcode::Constant, vec![pos]);
+            }
         }
 
         Ok(())
     }
 
-    fn compile_infix_operator(&mut self, operator: &Token) -> Result<(), String> {
+    fn compile_infix_operator(
+        &mut self,
+        operator: &Token,
+        position: Position,
+    ) -> Result<(), String> {
         match operator {
             Token::Plus => self.emit(Opcode::Add, vec![]),
             Token::Minus => self.emit(Opcode::Sub, vec![]),
             Token::Asterisk => self.emit(Opcode::Mul, vec![]),
             Token::Slash => self.emit(Opcode::Div, vec![]),
+            Token::DoubleSlash => self.emit(Opcode::FloorDiv, vec![]),
             Token::GT => self.emit(Opcode::GreaterThan, vec![]),
             Token::GTE => self.emit(Opcode::GreaterEqualThan, vec![]),
+            Token::LT => self.emit(Opcode::LessThan, vec![]),
+            Token::LTE => self.emit(Opcode::LessEqualThan, vec![]),
             Token::Equal => self.emit(Opcode::Equal, vec![]),
             Token::NotEqual => self.emit(Opcode::NotEqual, vec![]),
-            Token::Or => self.emit(Opcode::Or, vec![]),
-            Token::And => self.emit(Opcode::And, vec![]),
             Token::Modulo => self.emit(Opcode::Modulo, vec![]),
-            _ => return Err(format!("Unknown operator: {operator}")),
+            _ => return Err(format!("Unknown operator: {operator} at {position}")),
         };
         Ok(())
     }
 
-    fn compile_lt_and_lte(&mut self, infix: InfixOperator) -> Result<(), String> {
-        self.compile_expression(*infix.right)?;
-        self.compile_expression(*infix.left)?;
-        match infix.token {
-            Token::LT => self.emit(Opcode::GreaterThan, vec![]),
-            Token::LTE => self.emit(Opcode::GreaterEqualThan, vec![]),
-            tk => return Err(format!("Unknown operator: {tk}")),
+    /// Points the `JumpNull` of an optional index or call, if any, right after the
+    /// operation, so that a `null` left side is its result.
+    fn patch_null_jump(&mut self, jump_pos: Option<usize>) -> Result<(), String> {
+        if let Some(jump_pos) = jump_pos {
+            let after_pos = self.current_instructions_len();
+            self.change_operand(jump_pos, after_pos as i32)?;
+        }
+        Ok(())
+    }
+
+    /// `a && b` and `a || b` evaluate to one of their operands, and `b` is only evaluated
+    /// when `a` does not already decide the result: `a && b` is `a` if it is falsy and `b`
+    /// otherwise, `a || b` is `a` if it is truthy and `b` otherwise. `a ?? b` is `a` unless
+    /// it is null.
+    fn compile_logical_operator(&mut self, infix: InfixOperator) -> Result<(), String> {
+        let jump = match infix.token {
+            Token::And => Opcode::JumpNotTruthyOrPop,
+            Token::Or => Opcode::JumpTruthyOrPop,
+            Token::NullCoalesce => Opcode::JumpNotNullOrPop,
+            tk => return Err(format!("Unknown operator: {tk} at {}", infix.position)),
         };
+        self.compile_expression(*infix.left)?;
+        let jump_pos = self.emit(jump, vec![9999]); // We emit a dummy value for the jump offset
+                                                    // and we will fix it later
+        self.compile_expression(*infix.right)?;
+        let after_right_pos = self.current_instructions_len();
+        self.change_operand(jump_pos, after_right_pos as i32)?;
         Ok(())
     }
 
-    fn compile_prefix_operator(&mut self, operator: &Token) -> Result<(), String> {
+    fn compile_prefix_operator(
+        &mut self,
+        operator: &Token,
+        position: Position,
+    ) -> Result<(), String> {
         match operator {
             Token::Bang => self.emit(Opcode::Bang, vec![]),
             Token::Minus => self.emit(Opcode::Minus, vec![]),
-            _ => return Err(format!("Unknown operator: {operator}")),
+            _ => return Err(format!("Unknown operator: {operator} at {position}")),
         };
         Ok(())
     }
 
     fn compile_conditional(&mut self, conditional: Conditional) -> Result<(), String> {
-        self.compile_expression(*conditional.condition)?;
+        // Every block jumps to the end once done, the positions are fixed at the end
+        let mut jump_positions = Vec::with_capacity(conditional.branches.len());
+        for (condition, consequence) in conditional.branches {
+            self.compile_expression(condition)?;
+
+            let jump_not_truthy_pos = self.emit(Opcode::JumpNotTruthy, vec![9999]); // We emit a dummy value for the jump offset
+                                                                                    // and we will fix it later
+            if always_exits(&consequence) {
+                self.compile_block_statement(consequence)?;
+            } else {
+                self.compile_block_value(consequence)?;
+                jump_positions.push(self.emit(Opcode::Jump, vec![9999]));
+            }
 
-        let jump_not_truthy_pos = self.emit(Opcode::JumpNotTruthy, vec![9999]); // We emit a dummy value for the jump offset
-                                                                                // and we will fix it later
-        self.compile_block_statement(conditional.consequence)?;
-        if self.last_instruction_is(Opcode::Pop) {
-            self.remove_last_instruction();
+            let after_consequence_pos = self.current_instructions_len();
+            self.change_operand(jump_not_truthy_pos, after_consequence_pos as i32)?;
         }
 
-        let jump_pos = self.emit(Opcode::Jump, vec![9999]); // We emit a dummy value for the jump offset
-                                                            // and we will fix it later
-
-        let after_consequence_pos = self.current_instructions().data.len();
-        self.change_operand(jump_not_truthy_pos, after_consequence_pos as i32)?;
-
         if let Some(alternative) = conditional.alternative {
-            self.compile_block_statement(alternative)?;
-            if self.last_instruction_is(Opcode::Pop) {
-                self.remove_last_instruction();
+            if always_exits(&alternative) {
+                self.compile_block_statement(alternative)?;
+            } else {
+                self.compile_block_value(alternative)?;
             }
         } else {
             self.emit(Opcode::Null, vec![]);
         }
 
-        let after_alternative_pos = self.current_instructions().data.len();
-        self.change_operand(jump_pos, after_alternative_pos as i32)?;
+        let after_alternative_pos = self.current_instructions_len();
+        for jump_pos in jump_positions {
+            self.change_operand(jump_pos, after_alternative_pos as i32)?;
+        }
+
+        Ok(())
+    }
+
+    fn compile_try_catch(&mut self, try_catch: TryCatch) -> Result<(), String> {
+        let try_pos = self.emit(Opcode::Try, vec![9999]); // Fixed once the catch is reached
+        self.scopes[self.scope_index].try_depth += 1;
+        self.compile_block_value(try_catch.body)?;
+        self.scopes[self.scope_index].try_depth -= 1;
+        self.emit(Opcode::EndTry, vec![]);
+        let jump_pos = self.emit(Opcode::Jump, vec![9999]);
+
+        let catch_pos = self.current_instructions_len();
+        self.change_operand(try_pos, catch_pos as i32)?;
+
+        // The VM pushes the error before jumping to the catch. Not using it is fine, so
+        // the variable is never reported as unused.
+        let name = try_catch.error.value;
+        let symbol = self.define_variable(&name);
+        self.store_variable(&symbol);
+        self.symbol_table.mark_used(&name);
+        self.compile_block_value(try_catch.handler)?;
+
+        let after_catch_pos = self.current_instructions_len();
+        self.change_operand(jump_pos, after_catch_pos as i32)?;
 
         Ok(())
     }
 
+    /// Compiles a block whose value is left on the stack: the one of its last statement if
+    /// it is an expression, `null` otherwise.
+    fn compile_block_value(&mut self, block: BlockStatement) -> Result<(), String> {
+        let ends_with_expression =
+            matches!(block.statements.last(), Some(Statement::Expression(_)));
+        self.compile_block_statement(block)?;
+        if !ends_with_expression {
+            self.emit(Opcode::Null, vec![]);
+        } else if self.last_instruction_is(Opcode::Pop) {
+            // A conditional whose branches all exit pushes nothing, and is not popped
+            self.remove_last_instruction();
+        }
+        Ok(())
+    }
+
+    /// Compiles a block expression in place, with its own symbol table so that its
+    /// variables are not visible after it.
+    fn compile_block_expression(&mut self, block: BlockStatement) -> Result<(), String> {
+        let outer_definitions = std::mem::take(&mut self.scopes[self.scope_index].definitions);
+        self.symbol_table = SymbolTable::new_block(Rc::new(RefCell::new(std::mem::take(
+            &mut self.symbol_table,
+        ))));
+
+        let compiled = self.compile_block_value(block);
+        if compiled.is_ok() {
+            self.report_unused_variables();
+        }
+
+        self.symbol_table = self
+            .symbol_table
+            .outer
+            .take()
+            .unwrap()
+            .as_ref()
+            .clone()
+            .into_inner();
+        self.scopes[self.scope_index].definitions = outer_definitions;
+
+        compiled
+    }
+
     fn compile_function_literal(&mut self, fun: FunctionLiteral) -> Result<(), String> {
         self.enter_scope();
 
-        if let Some(name) = fun.name {
-            self.symbol_table.define_function_name(name);
+        if let Some(name) = &fun.name {
+            self.symbol_table.define_function_name(name.clone());
         }
 
         let num_parameters = fun.parameters.len();
 
         for param in fun.parameters {
-            self.symbol_table.define(param.value);
+            self.define_symbol(param.value);
         }
 
         self.compile_block_statement(fun.body)?;
@@ -414,6 +925,7 @@ impl Compiler {
         let free_symbols_len = free_symbols.len();
 
         let num_locals = self.symbol_table.num_definitions;
+        let locals = std::mem::take(&mut self.scopes[self.scope_index].locals);
         let instructions = self.leave_scope().data;
 
         for symbol in free_symbols {
@@ -426,10 +938,12 @@ impl Compiler {
             instructions,
             num_locals,
             num_parameters,
+            name: fun.name,
         });
 
-        let operands =
-            i32::from_usize(self.add_constant(compiled_function)).ok_or("Invalid integer type")?;
+        let constant_index = self.add_constant(compiled_function);
+        self.function_locals.insert(constant_index, locals);
+        let operands = i32::from_usize(constant_index).ok_or("Invalid integer type")?;
 
         self.emit(Opcode::Closure, vec![operands, free_symbols_len as i32]);
 
@@ -437,7 +951,7 @@ impl Compiler {
     }
 
     fn compile_while_statement(&mut self, wh: WhileStatement) -> Result<(), String> {
-        let condition_pos = self.current_instructions().data.len();
+        let condition_pos = self.current_instructions_len();
         self.scopes[self.scope_index].enter_loop_scope(condition_pos);
 
         self.compile_expression(wh.condition)?;
@@ -446,10 +960,9 @@ impl Compiler {
                                                                                 // and we will fix it later
         self.compile_block_statement(wh.body)?;
 
-        self.emit(Opcode::Jump, vec![condition_pos as i32]); // We emit a dummy value for the jump offset
-                                                             // and we will fix it later
+        self.emit_jump_back(condition_pos);
 
-        let after_body_pos = self.current_instructions().data.len();
+        let after_body_pos = self.current_instructions_len();
         self.change_operand(jump_not_truthy_pos, after_body_pos as i32)?;
 
         for break_pos in self.scopes[self.scope_index]
@@ -468,7 +981,20 @@ impl Compiler {
         Ok(())
     }
 
-    fn compile_loop_statement(&mut self, smt: &LoopStatement) {
+    fn compile_loop_statement(&mut self, smt: &LoopStatement) -> Result<(), String> {
+        // Jumping out of a `try` leaves it
+        let scope = &self.scopes[self.scope_index];
+        let Some(loop_scope) = &scope.loop_scope else {
+            return Err(format!("{smt} outside of loop"));
+        };
+        let loop_try_depth = loop_scope.borrow().try_depth;
+        // The operands pushed since the loop started are left behind by the jump
+        let operands = scope.operands - loop_scope.borrow().operands;
+        for _ in loop_try_depth..scope.try_depth {
+            self.emit(Opcode::EndTry, vec![]);
+        }
+        self.emit_pops(operands);
+
         match smt {
             LoopStatement::Break => {
                 let pos = self.emit(Opcode::Jump, vec![9999]); // We emit a dummy value for the jump offset
@@ -489,9 +1015,21 @@ impl Compiler {
                     .borrow()
                     .start_position;
 
-                self.emit(Opcode::Jump, vec![while_initial_pos as i32]);
+                self.emit_jump_back(while_initial_pos);
             }
         }
+
+        Ok(())
+    }
+
+    /// Jumps back to `target`, with a `JumpRel` when the distance fits in its operand.
+    fn emit_jump_back(&mut self, target: usize) {
+        // The offset is counted from the end of the `JumpRel`, which is 2 bytes long
+        let distance = self.current_instructions_len() + 2 - target;
+        match i32::try_from(distance) {
+            Ok(distance) if distance <= 128 => self.emit(Opcode::JumpRel, vec![-distance]),
+            _ => self.emit(Opcode::Jump, vec![target as i32]),
+        };
     }
 
     fn last_instruction_is(&self, opcode: Opcode) -> bool {
@@ -505,10 +1043,10 @@ impl Compiler {
         if let Some(last) = self.scopes[self.scope_index].last_instruction.clone() {
             let previous = self.scopes[self.scope_index].previous_instruction.clone();
 
-            let old = self.current_instructions().data;
-            let new = old[..last.position].to_vec();
-
-            self.scopes[self.scope_index].instructions.data = new;
+            self.scopes[self.scope_index]
+                .instructions
+                .data
+                .truncate(last.position);
             self.scopes[self.scope_index].last_instruction = previous;
         }
     }
@@ -525,8 +1063,18 @@ impl Compiler {
         pos
     }
 
+    /// Discards `count` values from the stack with `PopN`, split in several instructions
+    /// when `count` does not fit in its operand.
+    fn emit_pops(&mut self, mut count: usize) {
+        while count > 0 {
+            let popped = count.min(usize::from(u8::MAX));
+            self.emit(Opcode::PopN, vec![popped as i32]);
+            count -= popped;
+        }
+    }
+
     fn add_instruction(&mut self, instruction: Instructions) -> usize {
-        let pos_new_instruction = self.current_instructions().data.len();
+        let pos_new_instruction = self.current_instructions_len();
         self.scopes[self.scope_index]
             .instructions
             .append(instruction);
@@ -544,10 +1092,13 @@ impl Compiler {
     }
 
     fn change_operand(&mut self, pos: usize, operand: i32) -> Result<(), String> {
-        let op = Opcode::from_u8(self.current_instructions().data[pos]).ok_or(format!(
-            "Unknown opcode: {opcode}",
-            opcode = self.current_instructions().data[pos]
-        ))?;
+        let opcode = self.current_instructions().data[pos];
+        let op = Opcode::from_u8(opcode).ok_or(format!("Unknown opcode: {opcode}"))?;
+        if op.definition().operand_widths.len() != 1 {
+            return Err(format!(
+                "Cannot change the operand of {op}, it does not have one"
+            ));
+        }
         let new_instruction = op.make(vec![operand]);
         self.replace_instruction(pos, &new_instruction);
         Ok(())
@@ -560,8 +1111,13 @@ impl Compiler {
         }
     }
 
-    fn current_instructions(&self) -> Instructions {
-        self.scopes[self.scope_index].instructions.clone()
+    fn current_instructions(&self) -> &Instructions {
+        &self.scopes[self.scope_index].instructions
+    }
+
+    /// Position of the next instruction emitted in the current scope.
+    fn current_instructions_len(&self) -> usize {
+        self.current_instructions().data.len()
     }
 
     fn enter_scope(&mut self) {
@@ -573,7 +1129,8 @@ impl Compiler {
     }
 
     fn leave_scope(&mut self) -> Instructions {
-        let instructions = self.current_instructions();
+        self.report_unused_variables();
+        let instructions = std::mem::take(&mut self.scopes[self.scope_index].instructions);
 
         self.symbol_table = self
             .symbol_table
@@ -590,6 +1147,25 @@ impl Compiler {
         instructions
     }
 
+    /// Warns about the variables of the current scope that are never read. Names starting
+    /// with an underscore are meant to be unused and are skipped.
+    fn report_unused_variables(&mut self) {
+        let definitions = std::mem::take(&mut self.scopes[self.scope_index].definitions);
+        for name in definitions {
+            if !name.value.starts_with('_') && !self.symbol_table.is_used(&name.value) {
+                self.warn(format!("Unused variable: {}", name.value), name.position);
+            }
+        }
+    }
+
+    /// Records a warning, and hands it to the diagnostics callback if there is one.
+    fn warn(&mut self, message: String, position: Position) {
+        if let Some(report) = &self.diagnostics {
+            report(Diagnostic::warning(message.clone(), position));
+        }
+        self.warnings.push(Warning { message, position });
+    }
+
     fn replace_last_pop_with_return(&mut self) {
         let last_pos = self.scopes[self.scope_index]
             .last_instruction
@@ -617,22 +1193,187 @@ impl Compiler {
     }
 
     pub fn bytecode(&self) -> Bytecode {
-        Bytecode::new(self.current_instructions(), self.constants.clone())
+        Bytecode::new(
+            self.current_instructions().clone(),
+            self.constants.clone(),
+            self.symbol_table.global_indices(),
+        )
+    }
+}
+
+/// Whether `expression` reads the variable `name`, which makes a redefinition of `name`
+/// an update of its value rather than a likely mistake.
+fn reads_variable(expression: &Expression, name: &str) -> bool {
+    struct Reads<'a> {
+        name: &'a str,
+        found: bool,
+    }
+
+    impl Visitor for Reads<'_> {
+        fn visit_expression(&mut self, expression: &Expression) {
+            match expression {
+                Expression::Identifier(ident) if ident.value == self.name => self.found = true,
+                _ => walk_expression(self, expression),
+            }
+        }
+    }
+
+    let mut reads = Reads { name, found: false };
+    reads.visit_expression(expression);
+    reads.found
+}
+
+/// Whether control never reaches the end of `block`, as it returns or throws on every path.
+fn always_exits(block: &BlockStatement) -> bool {
+    block.statements.iter().any(|statement| match statement {
+        Statement::Return(_) | Statement::Throw(_) => true,
+        Statement::Expression(Expression::Conditional(conditional)) => {
+            always_exits_conditional(conditional)
+        }
+        _ => false,
+    })
+}
+
+fn always_exits_conditional(conditional: &Conditional) -> bool {
+    conditional.alternative.as_ref().is_some_and(always_exits)
+        && conditional
+            .branches
+            .iter()
+            .all(|(_, block)| always_exits(block))
+}
+
+/// Whether evaluating `expression` can neither fail nor have any effect, so that its value
+/// can be dropped without evaluating it: literals, negations and number comparisons.
+fn is_side_effect_free(expression: &Expression) -> bool {
+    match expression {
+        Expression::Primitive(_) => true,
+        Expression::Prefix(prefix) if prefix.token == Token::Bang => {
+            is_side_effect_free(&prefix.right)
+        }
+        Expression::Infix(infix)
+            if matches!(
+                infix.token,
+                Token::LT | Token::GT | Token::LTE | Token::GTE | Token::Equal | Token::NotEqual
+            ) =>
+        {
+            constant_number(&infix.left).is_some() && constant_number(&infix.right).is_some()
+        }
+        _ => constant_number(expression).is_some(),
+    }
+}
+
+/// The value of `expression` when it is computed from number literals only, without
+/// failing. Divisions are left out, as they fail when dividing by zero, and so are the
+/// integer operations that overflow.
+fn constant_number(expression: &Expression) -> Option<Object> {
+    match expression {
+        Expression::Primitive(Primitive::IntegerLiteral(i)) => Some(Object::INTEGER(*i)),
+        Expression::Primitive(Primitive::FloatLiteral(x)) => Some(Object::FLOAT(*x)),
+        Expression::Prefix(prefix) if prefix.token == Token::Minus => {
+            match constant_number(&prefix.right)? {
+                Object::INTEGER(i) => i.checked_neg().map(Object::INTEGER),
+                value => Some(Object::FLOAT(-value.as_float()?)),
+            }
+        }
+        Expression::Infix(infix) => {
+            let left = constant_number(&infix.left)?;
+            let right = constant_number(&infix.right)?;
+            if let (Object::INTEGER(left), Object::INTEGER(right)) = (&left, &right) {
+                let result = match infix.token {
+                    Token::Plus => left.checked_add(*right),
+                    Token::Minus => left.checked_sub(*right),
+                    Token::Asterisk => left.checked_mul(*right),
+                    _ => None,
+                };
+                return result.map(Object::INTEGER);
+            }
+            let (left, right) = (left.as_float()?, right.as_float()?);
+            let result = match infix.token {
+                Token::Plus => left + right,
+                Token::Minus => left - right,
+                Token::Asterisk => left * right,
+                _ => return None,
+            };
+            Some(Object::FLOAT(result))
+        }
+        _ => None,
     }
 }
 
+/// A problem in a program that does not prevent it from compiling.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub message: String,
+    pub position: Position,
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at {}", self.message, self.position)
+    }
+}
+
+#[derive(Clone)]
 pub struct Bytecode {
     pub instructions: Instructions,
     pub constants: Vec<Object>,
+    /// Index of each global variable, by name, so that the host program can find them
+    /// once the bytecode has run (see `VM::call_function`).
+    pub globals: HashMap<String, usize>,
 }
 
 impl Bytecode {
-    fn new(instructions: Instructions, constants: Vec<Object>) -> Self {
+    fn new(
+        instructions: Instructions,
+        constants: Vec<Object>,
+        globals: HashMap<String, usize>,
+    ) -> Self {
         Bytecode {
             instructions,
             constants,
+            globals,
         }
     }
+
+    /// Measures the bytecode. The instructions of the functions of the constant pool are
+    /// counted along with the ones of the main program.
+    pub fn stats(&self) -> BytecodeStats {
+        let functions = self.constants.iter().filter_map(|constant| match constant {
+            Object::COMPILEDFUNCTION(function) => Some(function.instructions.as_slice()),
+            _ => None,
+        });
+
+        let mut stats = BytecodeStats {
+            instruction_count: 0,
+            byte_length: 0,
+            constant_count: self.constants.len(),
+        };
+        for instructions in std::iter::once(self.instructions.data.as_slice()).chain(functions) {
+            stats.instruction_count += DecodedInstructions::new(instructions).count();
+            stats.byte_length += instructions.len();
+        }
+        stats
+    }
+}
+
+/// Size of a `Bytecode`, see `Bytecode::stats`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct BytecodeStats {
+    /// Number of instructions, whatever the size of their operands
+    pub instruction_count: usize,
+    /// Size of the instructions in bytes
+    pub byte_length: usize,
+    pub constant_count: usize,
+}
+
+impl Display for BytecodeStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} instructions, {} bytes, {} constants",
+            self.instruction_count, self.byte_length, self.constant_count
+        )
+    }
 }
 
 #[cfg(test)]
@@ -706,4 +1447,36 @@ pub mod tests {
             .unwrap();
         assert_eq!(previous.opcode, Opcode::Mul);
     }
+
+    #[test]
+    fn test_bytecode_stats() {
+        let mut compiler = Compiler::new();
+        compiler
+            .compile(crate::parser::parse(
+                "let add = fn(a, b) { a + b }; add(1, 2);",
+            ))
+            .unwrap();
+        let stats = compiler.bytecode().stats();
+
+        // Main program: Closure, SetGlobal, GetGlobal, Constant, Constant, Call, Pop
+        // Function: GetLocal, GetLocal, Add, ReturnValue
+        assert_eq!(
+            stats,
+            BytecodeStats {
+                instruction_count: 11,
+                byte_length: 25,
+                constant_count: 3,
+            }
+        );
+        assert_eq!(stats.to_string(), "11 instructions, 25 bytes, 3 constants");
+
+        assert_eq!(
+            Compiler::new().bytecode().stats(),
+            BytecodeStats {
+                instruction_count: 0,
+                byte_length: 0,
+                constant_count: 0,
+            }
+        );
+    }
 }
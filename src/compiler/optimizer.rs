@@ -0,0 +1,239 @@
+use crate::parser::ast::{
+    BlockStatement, Conditional, Expression, Primitive, Program, Statement, StringPart,
+};
+
+/// Replaces the conditionals whose condition is `true` or `false` by the branch they take,
+/// so that neither the condition nor the jumps are compiled. In an `else if` chain, the
+/// branches whose condition is `false` are dropped, as are the ones after a `true` one.
+///
+/// The branches of a conditional are compiled in the scope around it, so the taken branch
+/// is inlined rather than turned into a block, which would get its own scope:
+///
+/// * a conditional used as a statement is replaced by the statements of the branch,
+/// * a conditional used as a value is replaced by the expression of the branch, when the
+///   branch holds nothing else.
+///
+/// Conditionals whose value would change are kept, such as the ones without an `else`
+/// whose condition is `false`, which result in `null`.
+pub fn fold_constant_conditionals(program: &mut Program) {
+    fold_statements(&mut program.statements);
+}
+
+fn fold_statements(statements: &mut Vec<Statement>) {
+    for statement in statements.iter_mut() {
+        fold_statement(statement);
+    }
+
+    let mut folded = Vec::with_capacity(statements.len());
+    for statement in statements.drain(..) {
+        match statement {
+            Statement::Expression(Expression::Conditional(conditional)) => {
+                match inline_branch(conditional) {
+                    Ok(statements) => folded.extend(statements),
+                    Err(conditional) => {
+                        folded.push(Statement::Expression(Expression::Conditional(conditional)));
+                    }
+                }
+            }
+            statement => folded.push(statement),
+        }
+    }
+    *statements = folded;
+}
+
+/// The statements of the branch taken by `conditional`, or the conditional itself if it
+/// must be kept.
+fn inline_branch(mut conditional: Conditional) -> Result<Vec<Statement>, Conditional> {
+    match taken_block(&mut conditional) {
+        Some(block) if ends_with_expression(block) => Ok(std::mem::take(&mut block.statements)),
+        _ => Err(conditional),
+    }
+}
+
+fn fold_block(block: &mut BlockStatement) {
+    fold_statements(&mut block.statements);
+}
+
+fn fold_statement(statement: &mut Statement) {
+    match statement {
+        Statement::Let(statement) => {
+            if let Some(value) = &mut statement.value {
+                fold_expression(value);
+            }
+        }
+        Statement::Assign(statement) => fold_expression(&mut statement.value),
+        Statement::Return(statement) => fold_expression(&mut statement.return_value),
+        Statement::Throw(statement) => fold_expression(&mut statement.value),
+        Statement::Expression(expression) => fold_expression(expression),
+        Statement::While(statement) => {
+            fold_expression(&mut statement.condition);
+            fold_block(&mut statement.body);
+        }
+        Statement::LoopStatements(_) | Statement::Enum(_) | Statement::Comment(_) => {}
+    }
+}
+
+fn fold_expression(expression: &mut Expression) {
+    match expression {
+        Expression::Identifier(_) | Expression::Primitive(_) => {}
+        Expression::Prefix(prefix) => fold_expression(&mut prefix.right),
+        Expression::Spread(spread) => fold_expression(spread),
+        Expression::Infix(infix) => {
+            fold_expression(&mut infix.left);
+            fold_expression(&mut infix.right);
+        }
+        Expression::Conditional(conditional) => {
+            for (condition, consequence) in &mut conditional.branches {
+                fold_expression(condition);
+                fold_block(consequence);
+            }
+            if let Some(alternative) = &mut conditional.alternative {
+                fold_block(alternative);
+            }
+
+            if let Some(value) = taken_block(conditional).and_then(single_expression) {
+                *expression = value;
+            }
+        }
+        Expression::TryCatch(try_catch) => {
+            fold_block(&mut try_catch.body);
+            fold_block(&mut try_catch.handler);
+        }
+        Expression::FunctionLiteral(function) => fold_block(&mut function.body),
+        Expression::FunctionCall(call) => {
+            fold_expression(&mut call.function);
+            call.arguments.iter_mut().for_each(fold_expression);
+        }
+        Expression::ArrayLiteral(array) => array.elements.iter_mut().for_each(fold_expression),
+        Expression::HashMapLiteral(hashmap) => {
+            for (key, value) in &mut hashmap.pairs {
+                fold_expression(key);
+                fold_expression(value);
+            }
+        }
+        Expression::IndexExpression(index) => {
+            fold_expression(&mut index.left);
+            fold_expression(&mut index.index);
+        }
+        Expression::Block(block) => fold_block(block),
+        Expression::InterpolatedString(string) => {
+            for part in &mut string.parts {
+                if let StringPart::Expression(expression) = part {
+                    fold_expression(expression);
+                }
+            }
+        }
+    }
+}
+
+/// The block a conditional always takes, if any, after dropping the branches that are
+/// never taken. A conditional whose conditions are all `false` and without an `else` is
+/// left with its first branch, as it results in `null` rather than in a block.
+fn taken_block(conditional: &mut Conditional) -> Option<&mut BlockStatement> {
+    let branches = &mut conditional.branches;
+    if let Some(i) = branches
+        .iter()
+        .position(|(condition, _)| is_boolean(condition, true))
+    {
+        branches.truncate(i + 1);
+        conditional.alternative = None;
+    }
+
+    if branches
+        .iter()
+        .all(|(condition, _)| is_boolean(condition, false))
+    {
+        match conditional.alternative.take() {
+            Some(alternative) => *branches = vec![(true_literal(), alternative)],
+            None => branches.truncate(1),
+        }
+    } else {
+        branches.retain(|(condition, _)| !is_boolean(condition, false));
+    }
+
+    match branches.as_mut_slice() {
+        [(condition, block)] if is_boolean(condition, true) => Some(block),
+        _ => None,
+    }
+}
+
+fn is_boolean(expression: &Expression, value: bool) -> bool {
+    matches!(expression, Expression::Primitive(Primitive::BooleanLiteral(b)) if *b == value)
+}
+
+fn true_literal() -> Expression {
+    Expression::Primitive(Primitive::BooleanLiteral(true))
+}
+
+/// Whether the value of the block is the one of its last statement. Otherwise, the block
+/// results in `null`, which inlining its statements would lose.
+fn ends_with_expression(block: &BlockStatement) -> bool {
+    matches!(block.statements.last(), Some(Statement::Expression(_)))
+}
+
+/// The expression of a block holding a single expression statement.
+fn single_expression(block: &mut BlockStatement) -> Option<Expression> {
+    if !matches!(block.statements.as_slice(), [Statement::Expression(_)]) {
+        return None;
+    }
+    match block.statements.pop() {
+        Some(Statement::Expression(expression)) => Some(expression),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn fold(input: &str) -> String {
+        let mut program = parse(input);
+        fold_constant_conditionals(&mut program);
+        program.to_string()
+    }
+
+    #[test]
+    fn test_fold_constant_conditionals() {
+        let tests = [
+            ("if (true) { 1 } else { 2 }", "1\n"),
+            ("if (false) { 1 } else { 2; 3 }", "2\n3\n"),
+            ("let a = if (true) { 1 } else { 2 };", "let a = 1;\n"),
+            ("let a = if (false) { 1 } else { 2 };", "let a = 2;\n"),
+            (
+                "let f = fn() { if (true) { if (false) { 1 } else { 2 } } };",
+                "let f = fn(){\n2\n};\n",
+            ),
+            // Kept, as their value would change
+            ("if (false) { 1 }", "if false{\n1\n}\n"),
+            ("if (true) { let a = 1; }", "if true{\nlet a = 1;\n}\n"),
+            (
+                "let a = if (true) { 1; 2 };",
+                "let a = if true{\n1\n2\n};\n",
+            ),
+            // Chains lose the branches that are never taken
+            ("if (false) { 1 } else if (true) { 2 } else { 3 }", "2\n"),
+            (
+                "if (false) { 1 } else if (false) { 2 }",
+                "if false{\n1\n}\n",
+            ),
+            (
+                "if (x) { 1 } else if (false) { 2 } else if (true) { 3 } else { 4 }",
+                "if x{\n1\n} else if true{\n3\n}\n",
+            ),
+            (
+                "let a = if (false) { 1 } else { 2; 3 };",
+                "let a = if true{\n2\n3\n};\n",
+            ),
+            // Not a literal
+            (
+                "if (!false) { 1 } else { 2 }",
+                "if (!false){\n1\n} else {\n2\n}\n",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(fold(input), expected, "input: {input}");
+        }
+    }
+}
@@ -1,4 +1,8 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum SymbolScope {
@@ -24,6 +28,17 @@ pub struct SymbolTable {
     pub num_definitions: usize,
 
     pub free_symbols: Vec<Symbol>,
+
+    // Global and builtin symbols already resolved through the outer tables. Outer tables
+    // cannot define anything while this one is in use, so entries never become stale.
+    resolved_outer: HashMap<String, Symbol>,
+
+    // Names of the global and local symbols of this table that have been read.
+    used: HashSet<String>,
+
+    // Whether the table is the one of a block expression, whose variables are stored with
+    // the ones of the enclosing function (or with the globals at the top level).
+    block: bool,
 }
 
 impl SymbolTable {
@@ -35,6 +50,12 @@ impl SymbolTable {
             num_definitions: 0,
 
             free_symbols: vec![],
+
+            resolved_outer: HashMap::new(),
+
+            used: HashSet::new(),
+
+            block: false,
         }
     }
 
@@ -44,24 +65,62 @@ impl SymbolTable {
         new
     }
 
-    pub fn define(&mut self, name: String) -> Symbol {
-        let scope = match self.outer {
-            Some(_) => SymbolScope::Local,
-            None => SymbolScope::Global,
-        };
+    /// A table for the variables of a block expression, which are only visible inside of
+    /// the block but take slots of the enclosing table.
+    pub fn new_block(enclosing: Rc<RefCell<Self>>) -> Self {
+        let mut new = Self::new_enclosed(enclosing);
+        new.block = true;
+        new
+    }
 
+    pub fn define(&mut self, name: String) -> Symbol {
+        let (scope, index) = self.allocate();
         let symbol = Symbol {
             name: name.clone(),
             scope,
-            index: self.num_definitions,
+            index,
         };
 
         self.store.insert(name, symbol.clone());
-        self.num_definitions += 1;
 
         symbol
     }
 
+    /// Reserves the slot of a new variable, in the table of the enclosing function when
+    /// this one is a block.
+    fn allocate(&mut self) -> (SymbolScope, usize) {
+        if self.block {
+            if let Some(outer) = &self.outer {
+                return outer.borrow_mut().allocate();
+            }
+        }
+
+        let scope = match self.outer {
+            Some(_) => SymbolScope::Local,
+            None => SymbolScope::Global,
+        };
+        self.num_definitions += 1;
+        (scope, self.num_definitions - 1)
+    }
+
+    /// The global or local variable `name` defined by this table itself, not by an outer
+    /// one.
+    pub fn own_variable(&self, name: &str) -> Option<Symbol> {
+        self.store
+            .get(name)
+            .filter(|symbol| matches!(symbol.scope, SymbolScope::Global | SymbolScope::Local))
+            .cloned()
+    }
+
+    /// Index of each global variable of the table, by name.
+    pub fn global_indices(&self) -> HashMap<String, usize> {
+        self.store
+            .values()
+            .filter(|symbol| symbol.scope == SymbolScope::Global)
+            .map(|symbol| (symbol.name.clone(), symbol.index))
+            .collect()
+    }
+
     pub fn define_builtin(&mut self, index: usize, name: String) -> Symbol {
         let sym = Symbol {
             name: name.clone(),
@@ -77,11 +136,21 @@ impl SymbolTable {
             return Some(obj.clone());
         }
 
+        if let Some(obj) = self.resolved_outer.get(name) {
+            return Some(obj.clone());
+        }
+
         if let Some(outer) = self.outer.clone() {
+            if self.block {
+                // The variables of the enclosing function are in the same frame
+                return outer.borrow_mut().resolve(name);
+            }
+
             //TODO: Change this
             match outer.borrow_mut().resolve(name) {
                 Some(sym) => {
                     if sym.scope == SymbolScope::Global || sym.scope == SymbolScope::Builtin {
+                        self.resolved_outer.insert(name.to_string(), sym.clone());
                         Some(sym)
                     } else {
                         let free = self.define_free(sym);
@@ -94,6 +163,27 @@ impl SymbolTable {
             None // If there's no outer or if it is None, return None
         }
     }
+
+    /// Records that `name` is read, in the table that defines it. Symbols resolved through
+    /// outer tables are marked there.
+    pub fn mark_used(&mut self, name: &str) {
+        match self.store.get(name).map(|symbol| &symbol.scope) {
+            Some(SymbolScope::Global | SymbolScope::Local) => {
+                self.used.insert(name.to_string());
+            }
+            Some(SymbolScope::Builtin | SymbolScope::Function) => {}
+            Some(SymbolScope::Free) | None => {
+                if let Some(outer) = &self.outer {
+                    outer.borrow_mut().mark_used(name);
+                }
+            }
+        }
+    }
+
+    pub fn is_used(&self, name: &str) -> bool {
+        self.used.contains(name)
+    }
+
     fn define_free(&mut self, original: Symbol) -> Symbol {
         let name = original.name.clone();
         let sym = Symbol {
@@ -594,4 +684,95 @@ mod tests {
         assert!(result.is_some());
         assert_eq!(result.unwrap(), expected);
     }
+
+    #[test]
+    fn test_block_variables() {
+        let global = Rc::new(RefCell::new(SymbolTable::new()));
+        global.borrow_mut().define("a".to_string());
+        let mut block = SymbolTable::new_block(Rc::clone(&global));
+        assert_eq!(
+            block.define("b".to_string()),
+            Symbol {
+                name: "b".to_string(),
+                scope: SymbolScope::Global,
+                index: 1,
+            }
+        );
+        assert_eq!(block.own_variable("a"), None);
+        assert_eq!(block.resolve("a").unwrap().scope, SymbolScope::Global);
+        assert_eq!(global.borrow().num_definitions, 2);
+        assert_eq!(global.borrow_mut().resolve("b"), None);
+
+        // In a function, the variables of a block are locals of the function, not free ones
+        let local = Rc::new(RefCell::new(SymbolTable::new_enclosed(Rc::clone(&global))));
+        local.borrow_mut().define("c".to_string());
+        let mut block = SymbolTable::new_block(Rc::clone(&local));
+        assert_eq!(
+            block.resolve("c"),
+            Some(Symbol {
+                name: "c".to_string(),
+                scope: SymbolScope::Local,
+                index: 0,
+            })
+        );
+        assert_eq!(block.define("d".to_string()).index, 1);
+        assert!(block.free_symbols.is_empty());
+        assert_eq!(local.borrow().num_definitions, 2);
+    }
+
+    #[test]
+    fn test_resolve_caches_outer_globals() {
+        let global = Rc::new(RefCell::new(SymbolTable::new()));
+        global.borrow_mut().define("a".to_string());
+        global.borrow_mut().define_builtin(0, "len".to_string());
+        let first_local = Rc::new(RefCell::new(SymbolTable::new_enclosed(Rc::clone(&global))));
+        first_local.borrow_mut().define("b".to_string());
+        let mut second_local = SymbolTable::new_enclosed(Rc::clone(&first_local));
+
+        for _ in 0..3 {
+            for name in ["a", "len", "b"] {
+                second_local.resolve(name).unwrap();
+            }
+        }
+
+        let mut cached = second_local.resolved_outer.keys().collect::<Vec<_>>();
+        cached.sort();
+        assert_eq!(cached, vec!["a", "len"]);
+        // `b` is a local of the enclosing function, it becomes a free variable instead
+        assert_eq!(second_local.free_symbols.len(), 1);
+
+        // A local defined after a global was resolved shadows it
+        second_local.define("a".to_string());
+        assert_eq!(
+            second_local.resolve("a"),
+            Some(Symbol {
+                name: "a".to_string(),
+                scope: SymbolScope::Local,
+                index: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn test_mark_used() {
+        let global = Rc::new(RefCell::new(SymbolTable::new()));
+        global.borrow_mut().define("a".to_string());
+        global.borrow_mut().define("unused".to_string());
+        let first_local = Rc::new(RefCell::new(SymbolTable::new_enclosed(Rc::clone(&global))));
+        first_local.borrow_mut().define("b".to_string());
+        let mut second_local = SymbolTable::new_enclosed(Rc::clone(&first_local));
+        second_local.define("c".to_string());
+
+        for name in ["a", "b", "c"] {
+            second_local.resolve(name).unwrap();
+            second_local.mark_used(name);
+        }
+
+        assert!(global.borrow().is_used("a"));
+        assert!(!global.borrow().is_used("unused"));
+        assert!(first_local.borrow().is_used("b"));
+        assert!(second_local.is_used("c"));
+        // Free symbols are marked in the table that defines them
+        assert!(!second_local.is_used("b"));
+    }
 }
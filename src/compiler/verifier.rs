@@ -0,0 +1,271 @@
+use num_traits::FromPrimitive;
+
+use crate::{
+    compiler::{code::Opcode, Bytecode},
+    object::{builtins::BuiltinRegistry, Object},
+};
+
+/// Checks that a `Bytecode` is well formed before handing it to the VM.
+///
+/// The main program and every compiled function in the constant pool are walked
+/// instruction by instruction, and the following is ensured:
+///
+/// * every opcode is known and is followed by all of its operands,
+/// * jump targets land on an instruction boundary (or right after the last instruction),
+/// * constant indices point inside the constant pool, and `OpClosure` points to a function,
+/// * builtin indices refer to an existing builtin.
+///
+/// # Errors
+///
+/// Returns a message describing the first problem found.
+pub fn verify(bytecode: &Bytecode) -> Result<(), String> {
+    verify_with_registry(bytecode, &BuiltinRegistry::default())
+}
+
+/// Same as `verify`, for bytecode compiled with `Compiler::new_with_registry`: the
+/// functions of `registry` are accepted as builtins.
+///
+/// # Errors
+///
+/// Returns a message describing the first problem found.
+pub fn verify_with_registry(bytecode: &Bytecode, registry: &BuiltinRegistry) -> Result<(), String> {
+    verify_instructions(&bytecode.instructions.data, &bytecode.constants, registry)
+        .map_err(|err| format!("main program: {err}"))?;
+
+    for (index, constant) in bytecode.constants.iter().enumerate() {
+        if let Object::COMPILEDFUNCTION(function) = constant {
+            verify_instructions(&function.instructions, &bytecode.constants, registry)
+                .map_err(|err| format!("function at constant {index}: {err}"))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn verify_instructions(
+    instructions: &[u8],
+    constants: &[Object],
+    registry: &BuiltinRegistry,
+) -> Result<(), String> {
+    let mut boundaries = Vec::new();
+    let mut jumps = Vec::new();
+
+    let mut ip = 0;
+    while ip < instructions.len() {
+        boundaries.push(ip);
+
+        let op = Opcode::from_u8(instructions[ip])
+            .ok_or(format!("unknown opcode {} at {ip}", instructions[ip]))?;
+        let operands_length = op.definition().operands_length();
+        if ip + operands_length >= instructions.len() {
+            return Err(format!(
+                "{op} at {ip} expects {} operand bytes, got {}",
+                operands_length,
+                instructions.len() - ip - 1
+            ));
+        }
+
+        let (operands, _) = op.read_operands(&instructions[ip + 1..]);
+        match op {
+            Opcode::Jump
+            | Opcode::JumpNotTruthy
+            | Opcode::JumpNotTruthyOrPop
+            | Opcode::JumpTruthyOrPop
+            | Opcode::JumpNotNullOrPop
+            | Opcode::JumpNull
+            | Opcode::Try => jumps.push((ip, operands[0] as usize)),
+            Opcode::JumpRel => {
+                let target = (ip + 1 + operands_length)
+                    .checked_add_signed(operands[0] as isize)
+                    .ok_or(format!("jump at {ip} targets before the start"))?;
+                jumps.push((ip, target));
+            }
+            Opcode::Constant => check_constant_index(operands[0] as usize, constants, ip)?,
+            Opcode::Closure => {
+                let index = operands[0] as usize;
+                check_constant_index(index, constants, ip)?;
+                if !matches!(constants[index], Object::COMPILEDFUNCTION(_)) {
+                    return Err(format!(
+                        "{op} at {ip} expects a function constant, got {}",
+                        constants[index].get_type()
+                    ));
+                }
+            }
+            Opcode::GetBuiltin => {
+                let index = operands[0] as usize;
+                if registry.get_builtin_by_id(index).is_none() {
+                    return Err(format!("unknown builtin {index} at {ip}"));
+                }
+            }
+            _ => {}
+        }
+
+        ip += 1 + operands_length;
+    }
+
+    for (ip, target) in jumps {
+        if target != instructions.len() && boundaries.binary_search(&target).is_err() {
+            return Err(format!(
+                "jump at {ip} targets {target}, which is not an instruction boundary"
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn check_constant_index(index: usize, constants: &[Object], ip: usize) -> Result<(), String> {
+    if index >= constants.len() {
+        return Err(format!(
+            "constant index {index} at {ip} is out of bounds ({} constants)",
+            constants.len()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        compiler::{code::Instructions, Compiler},
+        object::CompiledFunction,
+        parser::parse,
+    };
+
+    use super::*;
+
+    fn compile(input: &str) -> Bytecode {
+        let program = parse(input);
+        let mut compiler = Compiler::new();
+        compiler.compile(program).unwrap();
+        compiler.bytecode()
+    }
+
+    fn bytecode(instructions: Vec<Instructions>, constants: Vec<Object>) -> Bytecode {
+        let mut concatted = Instructions::default();
+        for instruction in instructions {
+            concatted.append(instruction);
+        }
+        Bytecode {
+            instructions: concatted,
+            constants,
+            globals: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_verify_valid_programs() {
+        let inputs = vec![
+            "1 + 2; \"monkey\"",
+            "if (1 > 2) { 10 } else { 20 }",
+            "let i = 0; while (i < 10) { if (i == 5) { break; } let i = i + 1; }",
+            "let add = fn(a, b) { a + b }; add(1, len([1, 2]))",
+            "let outer = fn(a) { fn(b) { a + b } }; outer(1)(2)",
+            "{1: true, \"two\": [3]}[1]",
+            "let i = 0; while (i < 3) { let i = i + 1; if (i == 1) { continue; } }",
+        ];
+
+        for input in inputs {
+            let bytecode = compile(input);
+            assert_eq!(verify(&bytecode), Ok(()), "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_verify_corrupted_jump() {
+        let mut bytecode = compile("if (!false) { 10 }; 3333;");
+        assert_eq!(verify(&bytecode), Ok(()));
+
+        // 0000 OpFalse
+        // 0001 OpBang
+        // 0002 OpJumpNotTruthy 11
+        // The target is moved to the middle of the OpConstant at 0005
+        bytecode.instructions.data[4] = 6;
+        assert_eq!(
+            verify(&bytecode),
+            Err(
+                "main program: jump at 2 targets 6, which is not an instruction boundary"
+                    .to_string()
+            )
+        );
+
+        bytecode.instructions.data[4] = 200;
+        assert_eq!(
+            verify(&bytecode),
+            Err(
+                "main program: jump at 2 targets 200, which is not an instruction boundary"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_verify_errors() {
+        let tests = vec![
+            (
+                bytecode(vec![Instructions::new(vec![255])], vec![]),
+                "main program: unknown opcode 255 at 0",
+            ),
+            (
+                bytecode(
+                    vec![
+                        Opcode::Constant.make(vec![0]),
+                        Instructions::new(vec![Opcode::Constant as u8, 0]),
+                    ],
+                    vec![Object::INTEGER(1)],
+                ),
+                "main program: OpConstant at 3 expects 2 operand bytes, got 1",
+            ),
+            (
+                bytecode(
+                    vec![Opcode::Constant.make(vec![1])],
+                    vec![Object::INTEGER(1)],
+                ),
+                "main program: constant index 1 at 0 is out of bounds (1 constants)",
+            ),
+            (
+                bytecode(
+                    vec![Opcode::Closure.make(vec![0, 0])],
+                    vec![Object::INTEGER(1)],
+                ),
+                "main program: OpClosure at 0 expects a function constant, got INTEGER",
+            ),
+            (
+                bytecode(vec![Opcode::GetBuiltin.make(vec![200])], vec![]),
+                "main program: unknown builtin 200 at 0",
+            ),
+            (
+                bytecode(vec![Opcode::JumpRel.make(vec![-3])], vec![]),
+                "main program: jump at 0 targets before the start",
+            ),
+            (
+                bytecode(
+                    vec![
+                        Opcode::Constant.make(vec![0]),
+                        Opcode::JumpRel.make(vec![-4]),
+                    ],
+                    vec![Object::INTEGER(1)],
+                ),
+                "main program: jump at 3 targets 1, which is not an instruction boundary",
+            ),
+            (
+                bytecode(
+                    vec![Opcode::Closure.make(vec![0, 0])],
+                    vec![Object::COMPILEDFUNCTION(CompiledFunction {
+                        instructions: vec![255],
+                        num_locals: 0,
+                        num_parameters: 0,
+                        name: None,
+                    })],
+                ),
+                "function at constant 0: unknown opcode 255 at 0",
+            ),
+        ];
+
+        for (bytecode, expected) in tests {
+            assert_eq!(verify(&bytecode), Err(expected.to_string()));
+        }
+    }
+}
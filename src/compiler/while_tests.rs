@@ -6,8 +6,10 @@ pub mod tests {
         compiler::{
             code::Opcode,
             test_utils::{flatten_instructions, run_compiler, CompilerTestCase},
+            Compiler,
         },
         object::Object,
+        parser::parse,
     };
 
     #[test]
@@ -22,13 +24,13 @@ pub mod tests {
             expected_constants: vec![Object::STRING("yes".to_string())],
             expected_instructions: flatten_instructions(vec![
                 Opcode::True.make(vec![]),            // 000
-                Opcode::JumpNotTruthy.make(vec![15]), // 001
+                Opcode::JumpNotTruthy.make(vec![14]), // 001
                 Opcode::GetBuiltin.make(vec![5]),     // 004
                 Opcode::Constant.make(vec![0]),       // 006
                 Opcode::Call.make(vec![1]),           // 009
                 Opcode::Pop.make(vec![]),             // 011
-                Opcode::Jump.make(vec![0]),           // 012
-                                                      // 015
+                Opcode::JumpRel.make(vec![-14]),      // 012
+                                                      // 014
             ]),
         }];
 
@@ -46,11 +48,11 @@ pub mod tests {
             .to_string(),
             expected_constants: vec![],
             expected_instructions: flatten_instructions(vec![
-                Opcode::True.make(vec![]),            // 000
-                Opcode::JumpNotTruthy.make(vec![10]), // 001
-                Opcode::Jump.make(vec![10]),          // 004
-                Opcode::Jump.make(vec![0]),           // 007
-                                                      // 010
+                Opcode::True.make(vec![]),           // 000
+                Opcode::JumpNotTruthy.make(vec![9]), // 001
+                Opcode::Jump.make(vec![9]),          // 004
+                Opcode::JumpRel.make(vec![-9]),      // 007
+                                                     // 009
             ]),
         }];
 
@@ -72,14 +74,14 @@ pub mod tests {
             expected_constants: vec![],
             expected_instructions: flatten_instructions(vec![
                 Opcode::True.make(vec![]),            // 000
-                Opcode::JumpNotTruthy.make(vec![20]), // 001
+                Opcode::JumpNotTruthy.make(vec![18]), // 001
                 Opcode::True.make(vec![]),            // 004
-                Opcode::JumpNotTruthy.make(vec![14]), // 005
-                Opcode::Jump.make(vec![14]),          // 008
-                Opcode::Jump.make(vec![4]),           // 011
-                Opcode::Jump.make(vec![20]),          // 014
-                Opcode::Jump.make(vec![0]),           // 017
-                                                      // 020
+                Opcode::JumpNotTruthy.make(vec![13]), // 005
+                Opcode::Jump.make(vec![13]),          // 008
+                Opcode::JumpRel.make(vec![-9]),       // 011
+                Opcode::Jump.make(vec![18]),          // 013
+                Opcode::JumpRel.make(vec![-18]),      // 016
+                                                      // 018
             ]),
         }];
 
@@ -96,11 +98,11 @@ pub mod tests {
             .to_string(),
             expected_constants: vec![],
             expected_instructions: flatten_instructions(vec![
-                Opcode::True.make(vec![]),            // 000
-                Opcode::JumpNotTruthy.make(vec![10]), // 001
-                Opcode::Jump.make(vec![0]),           // 004
-                Opcode::Jump.make(vec![0]),           // 007
-                                                      // 010
+                Opcode::True.make(vec![]),           // 000
+                Opcode::JumpNotTruthy.make(vec![8]), // 001
+                Opcode::JumpRel.make(vec![-6]),      // 004
+                Opcode::JumpRel.make(vec![-8]),      // 006
+                                                     // 008
             ]),
         }];
 
@@ -122,14 +124,14 @@ pub mod tests {
             expected_constants: vec![],
             expected_instructions: flatten_instructions(vec![
                 Opcode::True.make(vec![]),            // 000
-                Opcode::JumpNotTruthy.make(vec![20]), // 001
+                Opcode::JumpNotTruthy.make(vec![16]), // 001
                 Opcode::True.make(vec![]),            // 004
-                Opcode::JumpNotTruthy.make(vec![14]), // 005
-                Opcode::Jump.make(vec![4]),           // 008
-                Opcode::Jump.make(vec![4]),           // 011
-                Opcode::Jump.make(vec![0]),           // 014
-                Opcode::Jump.make(vec![0]),           // 017
-                                                      // 020
+                Opcode::JumpNotTruthy.make(vec![12]), // 005
+                Opcode::JumpRel.make(vec![-6]),       // 008
+                Opcode::JumpRel.make(vec![-8]),       // 010
+                Opcode::JumpRel.make(vec![-14]),      // 012
+                Opcode::JumpRel.make(vec![-16]),      // 014
+                                                      // 016
             ]),
         }];
 
@@ -151,17 +153,29 @@ pub mod tests {
             expected_constants: vec![],
             expected_instructions: flatten_instructions(vec![
                 Opcode::True.make(vec![]),            // 000
-                Opcode::JumpNotTruthy.make(vec![20]), // 001
+                Opcode::JumpNotTruthy.make(vec![17]), // 001
                 Opcode::True.make(vec![]),            // 004
-                Opcode::JumpNotTruthy.make(vec![14]), // 005
-                Opcode::Jump.make(vec![4]),           // 008
-                Opcode::Jump.make(vec![4]),           // 011
-                Opcode::Jump.make(vec![20]),          // 014
-                Opcode::Jump.make(vec![0]),           // 017
-                                                      // 020
+                Opcode::JumpNotTruthy.make(vec![12]), // 005
+                Opcode::JumpRel.make(vec![-6]),       // 008
+                Opcode::JumpRel.make(vec![-8]),       // 010
+                Opcode::Jump.make(vec![17]),          // 012
+                Opcode::JumpRel.make(vec![-17]),      // 015
+                                                      // 017
             ]),
         }];
 
         run_compiler(tests);
     }
+
+    #[test]
+    fn test_long_while_jumps_back_absolutely() {
+        let input = format!("while (true) {{ {} }}", "puts(1); ".repeat(30));
+        let mut compiler = Compiler::new();
+        compiler.compile(parse(&input)).unwrap();
+        let instructions = compiler.bytecode().instructions;
+
+        // The 30 calls take 8 bytes each, too far for the offset of `JumpRel`
+        let last = instructions.iter_decoded().last();
+        assert_eq!(last, Some((244, Opcode::Jump, vec![0])));
+    }
 }
@@ -0,0 +1,61 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::lexer::Position;
+
+/// Something the compiler or the VM reports without stopping, handed to the callback set
+/// with `Compiler::set_diagnostics` or `VM::set_diagnostics` rather than printed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub message: String,
+    /// Where in the source it comes from, when it is known
+    pub position: Option<Position>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    /// A likely mistake, such as an unused variable
+    Warning,
+    /// An event of the execution, such as a function call, sent by the VM
+    Trace,
+}
+
+/// Receives the diagnostics of a compiler or a VM.
+pub type DiagnosticsCallback = dyn Fn(Diagnostic);
+
+impl Diagnostic {
+    pub fn warning(message: String, position: Position) -> Self {
+        Diagnostic {
+            kind: DiagnosticKind::Warning,
+            message,
+            position: Some(position),
+        }
+    }
+
+    pub fn trace(message: String) -> Self {
+        Diagnostic {
+            kind: DiagnosticKind::Trace,
+            message,
+            position: None,
+        }
+    }
+}
+
+impl Display for DiagnosticKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DiagnosticKind::Warning => write!(f, "Warning"),
+            DiagnosticKind::Trace => write!(f, "Trace"),
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.kind, self.message)?;
+        if let Some(position) = self.position {
+            write!(f, " at {position}")?;
+        }
+        Ok(())
+    }
+}
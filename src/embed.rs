@@ -0,0 +1,110 @@
+//! String-in, string-out entry point, for hosts without a terminal such as the browser.
+
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    rc::Rc,
+};
+
+use crate::{compiler::Compiler, lexer::Lexer, object::Object, parser::Parser, vm::VM};
+
+/// `Write` implementation appending to a buffer that can still be read once it has been
+/// handed to the VM.
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Compiles and runs `src`, returning everything the program printed with `puts`
+/// followed by its result (omitted when it is `null`).
+///
+/// Errors are returned as text in the same format as the REPL, after the output
+/// produced before the failure. Nothing is ever written to the standard output.
+///
+/// With the `wasm` feature this function is exported to JavaScript through
+/// `wasm-bindgen`.
+///
+/// # Examples
+///
+/// ```
+/// use chimpanzee::embed::eval_to_string;
+///
+/// assert_eq!(eval_to_string("puts(true); 1 + 2"), "true\n3");
+/// ```
+#[cfg_attr(feature = "wasm", wasm_bindgen::prelude::wasm_bindgen)]
+pub fn eval_to_string(src: &str) -> String {
+    let mut parser = Parser::new(Lexer::new(src));
+    let program = parser.parse_program();
+    if !parser.errors.is_empty() {
+        return parser.errors.to_string();
+    }
+
+    let mut compiler = Compiler::new();
+    if let Err(err) = compiler.compile(program) {
+        return format!("Compiler error:\n\t{err}\n");
+    }
+
+    let output = SharedBuffer::default();
+    let mut vm = VM::new(compiler.bytecode());
+    vm.set_output(Box::new(output.clone()));
+    // There is no standard input to read from when embedded, `read_line` returns `null`
+    vm.set_input(Box::new(io::empty()));
+    let result = vm.run().and_then(|()| match vm.result().as_ref() {
+        Object::ERROR(err) => Err(err.clone()),
+        Object::NULL => Ok(String::new()),
+        result => Ok(result.to_string()),
+    });
+
+    let mut text = String::from_utf8_lossy(&output.0.borrow()).into_owned();
+    match result {
+        Ok(result) => text.push_str(&result),
+        Err(err) => {
+            text.push_str("Runtime error:\n\t");
+            text.push_str(&err);
+            text.push('\n');
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval_to_string() {
+        let tests = vec![
+            ("1 + 2", "3"),
+            ("\"mon\" + \"key\"", "\"monkey\""),
+            ("if (false) { 1 }", ""),
+            ("puts(1); puts(\"two\", [3]);", "1\n\"two\"\n[3]\n"),
+            ("let f = fn(x) { puts(x); x * 2 }; f(f(2))", "2\n4\n8"),
+            ("puts(1); 1 / 0", "1\nRuntime error:\n\tDivision by zero\n"),
+            (
+                "len(1)",
+                "Runtime error:\n\targument to `len` not supported, got INTEGER\n",
+            ),
+            ("a", "Compiler error:\n\tUndefined variable: a at 1:1\n"),
+            ("[read_line()]", "[null]"),
+            ("1; let a = 2;", "1"),
+            ("let a = 2;", ""),
+            (
+                "1 + )",
+                "Parser errors:\n\tThere is no prefix parser for the token )\n",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(eval_to_string(input), expected, "input: {input}");
+        }
+    }
+}
@@ -0,0 +1,98 @@
+//! C interface, to embed the interpreter in C or C++ programs.
+//!
+//! ```c
+//! char *result = monkey_eval("let a = 5; a * 2");
+//! printf("%s\n", result); // 10
+//! monkey_free_string(result);
+//! ```
+
+use std::{
+    ffi::{c_char, CStr, CString},
+    panic,
+};
+
+use crate::embed::eval_to_string;
+
+/// Compiles and runs the NUL-terminated UTF-8 program `source` and returns a newly
+/// allocated string with its output and result, or with the error that stopped it. See
+/// `embed::eval_to_string` for the format.
+///
+/// The returned string must be released with `monkey_free_string`. A panic inside the
+/// interpreter is caught and reported as an error instead of unwinding into the caller.
+///
+/// # Safety
+///
+/// `source` must be null or point to a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_eval(source: *const c_char) -> *mut c_char {
+    let result = panic::catch_unwind(|| {
+        if source.is_null() {
+            return "Error: the source is a null pointer".to_string();
+        }
+        // SAFETY: guaranteed by the caller
+        match unsafe { CStr::from_ptr(source) }.to_str() {
+            Ok(source) => eval_to_string(source),
+            Err(err) => format!("Error: the source is not valid UTF-8: {err}"),
+        }
+    })
+    .unwrap_or_else(|_| "Error: the interpreter panicked".to_string());
+
+    // The output can only contain a NUL if the program printed one
+    CString::new(result.replace('\0', "\\0")).map_or(std::ptr::null_mut(), CString::into_raw)
+}
+
+/// Releases a string returned by `monkey_eval`. Passing a null pointer does nothing.
+///
+/// # Safety
+///
+/// `string` must be null or a pointer returned by `monkey_eval` that has not been freed
+/// yet.
+#[no_mangle]
+pub unsafe extern "C" fn monkey_free_string(string: *mut c_char) {
+    if !string.is_null() {
+        // SAFETY: guaranteed by the caller
+        drop(unsafe { CString::from_raw(string) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Calls the FFI functions the way a C host would.
+    fn eval_as_host(source: &[u8]) -> String {
+        let source = CString::new(source).unwrap();
+        unsafe {
+            let result = monkey_eval(source.as_ptr());
+            assert!(!result.is_null());
+            let text = CStr::from_ptr(result).to_str().unwrap().to_string();
+            monkey_free_string(result);
+            text
+        }
+    }
+
+    #[test]
+    fn test_monkey_eval() {
+        assert_eq!(eval_as_host(b"let a = 5; a * 2"), "10");
+        assert_eq!(eval_as_host("\"héllo\"".as_bytes()), "\"héllo\"");
+        assert_eq!(eval_as_host(b"puts(1); 2"), "1\n2");
+        assert_eq!(
+            eval_as_host(b"1 / 0"),
+            "Runtime error:\n\tDivision by zero\n"
+        );
+        assert!(eval_as_host(b"\"\xff\"").starts_with("Error: the source is not valid UTF-8"));
+    }
+
+    #[test]
+    fn test_null_pointers() {
+        unsafe {
+            let result = monkey_eval(std::ptr::null());
+            assert_eq!(
+                CStr::from_ptr(result).to_str(),
+                Ok("Error: the source is a null pointer")
+            );
+            monkey_free_string(result);
+            monkey_free_string(std::ptr::null_mut());
+        }
+    }
+}
@@ -490,6 +490,28 @@ if (num < 10) {
         assert_eq!(format(input), expected);
     }
 
+    #[test]
+    fn test_else_if_chain() {
+        let input = r#"
+            if (num < 10) { "small" } else if (num < 100) {
+                "medium" } else if (num < 1000) { "large" }
+            else { "huge" }
+        "#;
+
+        let expected = r#"if (num < 10) {
+    "small";
+} else if (num < 100) {
+    "medium";
+} else if (num < 1000) {
+    "large";
+} else {
+    "huge";
+}
+"#;
+
+        assert_eq!(format(input), expected);
+    }
+
     #[test]
     fn test_function_as_first_class_citizen() {
         let input = r"
@@ -580,4 +602,155 @@ a(12);
 
         assert_eq!(format(input), expected);
     }
+
+    #[test]
+    fn test_format_block_expressions() {
+        let input = r"
+        let y = {let x = 1;
+        x + 1;};
+        {
+  puts(y); let z = 2;
+        }
+        ";
+
+        let expected = r"let y = {
+    let x = 1;
+    x + 1
+};
+{
+    puts(y);
+    let z = 2;
+}
+";
+
+        assert_eq!(format(input), expected);
+    }
+
+    #[test]
+    fn test_format_uninitialized_let_and_assignment() {
+        let input = "let x;x=5 ;let f = fn() {x = x+1;};";
+
+        let expected = r"let x;
+x = 5;
+let f = fn () {
+    x = x + 1;
+};
+";
+
+        assert_eq!(format(input), expected);
+    }
+
+    #[test]
+    fn test_format_interpolated_strings() {
+        let input = r#"let a = f"1+1=${1+1}, \$${ price*(1+tax) }";"#;
+
+        let expected = r#"let a = f"1+1=${1 + 1}, \$${price * (1 + tax)}";
+"#;
+
+        assert_eq!(format(input), expected);
+    }
+
+    #[test]
+    fn test_format_spread() {
+        let input = "let a = [...b,1+2];f(... a, ...[1]);";
+
+        let expected = r"let a = [...b, (1 + 2)];
+f(...a, ...[1]);
+";
+
+        assert_eq!(format(input), expected);
+    }
+
+    #[test]
+    fn test_format_method_calls() {
+        let input = r#"let a = [1,2].push(3).len();("a"+b).to_upper( );-b.len()"#;
+
+        let expected = r#"let a = [1, 2].push(3).len();
+("a" + b).to_upper();
+-b.len();
+"#;
+
+        assert_eq!(format(input), expected);
+    }
+
+    #[test]
+    fn test_format_enums() {
+        let input = "enum Color {Red,Green,\nBlue,};let c = Red;";
+
+        let expected = r"enum Color { Red, Green, Blue }
+let c = Red;
+";
+
+        assert_eq!(format(input), expected);
+    }
+
+    #[test]
+    fn test_format_try_catch() {
+        let input = "let a = try {1/0} catch(e) {puts(e);0};";
+
+        let expected = r"let a = try {
+    1 / 0
+} catch (e) {
+    puts(e);
+    0
+};
+";
+
+        assert_eq!(format(input), expected);
+    }
+
+    #[test]
+    fn test_format_throw() {
+        let input = "let f = fn(x) {if (x<0) {throw  \"negative\"} x};throw(f(-1))";
+
+        let expected = r#"let f = fn (x) {
+    if (x < 0) {
+        throw "negative";
+    }
+    x
+};
+throw f(-1);
+"#;
+
+        assert_eq!(format(input), expected);
+    }
+
+    #[test]
+    fn test_format_keeps_comments() {
+        let input = r"# helper
+let a = 1;   # one
+let f = fn(x) { # doc
+  # inside
+  x + 1 # result
+  # end of body
+};
+let b = [1, # first
+  2];
+if (a > 0) {puts(a); # print
+} else {
+    # nothing
+}
+# last
+";
+
+        let expected = r"# helper
+let a = 1; # one
+let f = fn (x) { # doc
+    # inside
+    x + 1 # result
+    # end of body
+};
+let b = [1, 2]; # first
+if (a > 0) {
+    puts(a); # print
+} else {
+    # nothing
+}
+# last
+";
+
+        let formatted = Formatter::format(input);
+        assert_eq!(formatted, expected);
+        assert_eq!(Formatter::format(&formatted), expected);
+    }
 }
@@ -1,9 +1,15 @@
 pub mod cli;
 mod formatter_tests;
 
-use crate::parser::{
-    ast::{BlockStatement, Expression, FunctionLiteral, Precedence, Program, Statement},
-    parse,
+use crate::{
+    lexer::{token::escape_interpolated, Lexer},
+    parser::{
+        ast::{
+            BlockStatement, Comment, Expression, FunctionCall, FunctionLiteral, InterpolatedString,
+            Precedence, Program, Statement, StringPart, TryCatch,
+        },
+        Parser,
+    },
 };
 
 /// A formatter function scope.
@@ -68,8 +74,11 @@ impl Formatter {
         }
     }
 
+    /// Formats `input`, keeping its comments. They are written back between statements:
+    /// the ones found inside of a statement are moved right after it.
     pub fn format(input: &str) -> String {
-        let program = parse(input);
+        let lexer = Lexer::new(input).with_trivia(true);
+        let program = Parser::new(lexer).parse_program();
         Self::format_program(program)
     }
 
@@ -87,13 +96,25 @@ impl Formatter {
     }
 
     fn visit_statement(&mut self, stmt: &Statement) {
+        if let Statement::Comment(comment) = stmt {
+            self.visit_comment(comment);
+            return;
+        }
         self.push_indent();
         match stmt {
             Statement::Let(let_stmt) => {
                 self.push("let ");
                 self.push(let_stmt.name.value.as_str());
+                if let Some(value) = &let_stmt.value {
+                    self.push(" = ");
+                    self.visit_expression(value);
+                }
+                self.push(";");
+            }
+            Statement::Assign(assign) => {
+                self.push(assign.name.value.as_str());
                 self.push(" = ");
-                self.visit_expression(&let_stmt.value);
+                self.visit_expression(&assign.value);
                 self.push(";");
             }
             Statement::Return(return_stmt) => {
@@ -101,9 +122,17 @@ impl Formatter {
                 self.visit_expression(&return_stmt.return_value);
                 self.push(";");
             }
+            Statement::Throw(throw_stmt) => {
+                self.push("throw ");
+                self.visit_expression(&throw_stmt.value);
+                self.push(";");
+            }
             Statement::Expression(exp_stmt) => {
                 self.visit_expression(exp_stmt);
-                if let Some(Expression::Conditional(_)) = self.last_expression {
+                if let Some(
+                    Expression::Conditional(_) | Expression::TryCatch(_) | Expression::Block(_),
+                ) = self.last_expression
+                {
                 } else if self.formatter_function_scope.is_some() {
                     if !self.formatter_function_scope.clone().unwrap().is_end() {
                         self.push(";");
@@ -126,19 +155,35 @@ impl Formatter {
                 self.push(cf.to_string().as_str());
                 self.push(";");
             }
+            Statement::Enum(enum_stmt) => self.push(enum_stmt.to_string().as_str()),
+            Statement::Comment(_) => unreachable!("comments are visited on their own"),
         }
         self.push("\n");
         self.last_expression = None;
     }
 
+    /// A trailing comment stays at the end of the line before it, unless that line is a
+    /// comment too.
+    fn visit_comment(&mut self, comment: &Comment) {
+        let last_line = self.output.strip_suffix('\n').map(|output| {
+            let start = output.rfind('\n').map_or(0, |i| i + 1);
+            &output[start..]
+        });
+        match last_line {
+            Some(line) if comment.trailing && !line.trim_start().starts_with('#') => {
+                self.output.pop();
+                self.push(" ");
+            }
+            _ => self.push_indent(),
+        }
+        self.push(&comment.text);
+        self.push("\n");
+    }
+
     fn visit_expression(&mut self, exp: &Expression) {
         match exp {
-            Expression::Identifier(ident) => {
-                self.push(ident.value.as_str());
-            }
-            Expression::Primitive(primitive) => {
-                self.push(primitive.to_string().as_str());
-            }
+            Expression::Identifier(ident) => self.push(ident.value.as_str()),
+            Expression::Primitive(primitive) => self.push(primitive.to_string().as_str()),
             Expression::Prefix(prefix) => {
                 self.push(prefix.token.to_string().as_str());
 
@@ -178,20 +223,25 @@ impl Formatter {
                 }
             }
             Expression::Conditional(if_exp) => {
-                self.push("if (");
+                for (i, (condition, consequence)) in if_exp.branches.iter().enumerate() {
+                    if i > 0 {
+                        self.push(" else ");
+                    }
+                    self.push("if (");
 
-                self.last_expression = Some(exp.clone());
-                self.visit_expression(&if_exp.condition);
-                self.push(") {");
-                self.push("\n");
+                    self.last_expression = Some(exp.clone());
+                    self.visit_expression(condition);
+                    self.push(") {");
+                    self.push("\n");
 
-                self.indent += 1;
-                self.last_expression = Some(exp.clone());
-                self.visit_block_statement(&if_exp.consequence);
-                self.indent -= 1;
+                    self.indent += 1;
+                    self.last_expression = Some(exp.clone());
+                    self.visit_block_statement(consequence);
+                    self.indent -= 1;
 
-                self.push_indent();
-                self.push("}");
+                    self.push_indent();
+                    self.push("}");
+                }
                 if let Some(alternative) = &if_exp.alternative {
                     self.push(" else {\n");
                     self.indent += 1;
@@ -203,20 +253,9 @@ impl Formatter {
                     self.push("}");
                 }
             }
+            Expression::TryCatch(try_catch) => self.visit_try_catch(try_catch),
             Expression::FunctionLiteral(func) => self.visit_function_literal(func),
-            Expression::FunctionCall(call) => {
-                self.last_expression = Some(exp.clone());
-                self.visit_expression(&call.function);
-                self.push("(");
-                for (i, arg) in call.arguments.iter().enumerate() {
-                    self.last_expression = Some(exp.clone());
-                    self.visit_expression(arg);
-                    if i < call.arguments.len() - 1 {
-                        self.push(", ");
-                    }
-                }
-                self.push(")");
-            }
+            Expression::FunctionCall(call) => self.visit_function_call(exp, call),
             Expression::ArrayLiteral(array) => {
                 self.push(array.to_string().as_str());
             }
@@ -226,12 +265,24 @@ impl Formatter {
             Expression::IndexExpression(index) => {
                 self.last_expression = Some(exp.clone());
                 self.visit_expression(&index.left);
-                self.push("[");
+                self.push(if index.optional { "?[" } else { "[" });
 
                 self.last_expression = Some(exp.clone());
                 self.visit_expression(&index.index);
                 self.push("]");
             }
+            Expression::Block(block) => {
+                self.push("{\n");
+                self.visit_body(block);
+                self.push_indent();
+                self.push("}");
+            }
+            Expression::InterpolatedString(string) => self.visit_interpolated_string(string),
+            Expression::Spread(spread) => {
+                self.push("...");
+                self.last_expression = None;
+                self.visit_expression(spread);
+            }
         }
 
         self.last_expression = Some(exp.clone());
@@ -239,6 +290,87 @@ impl Formatter {
         self.preference = self.get_precedence(exp);
     }
 
+    fn visit_function_call(&mut self, exp: &Expression, call: &FunctionCall) {
+        if let (true, Some((receiver, arguments))) = (call.method, call.arguments.split_first()) {
+            self.visit_method_call(exp, &call.function, receiver, arguments);
+            return;
+        }
+        self.last_expression = Some(exp.clone());
+        self.visit_expression(&call.function);
+        self.push(if call.optional { "?.(" } else { "(" });
+        for (i, arg) in call.arguments.iter().enumerate() {
+            self.last_expression = Some(exp.clone());
+            self.visit_expression(arg);
+            if i < call.arguments.len() - 1 {
+                self.push(", ");
+            }
+        }
+        self.push(")");
+    }
+
+    /// Operators bind less tightly than the dot, so a receiver using one is parenthesized.
+    fn visit_method_call(
+        &mut self,
+        exp: &Expression,
+        method: &Expression,
+        receiver: &Expression,
+        arguments: &[Expression],
+    ) {
+        let needs_parenthesis = matches!(receiver, Expression::Infix(_) | Expression::Prefix(_));
+        if needs_parenthesis {
+            self.push("(");
+        }
+        self.last_expression = None;
+        self.visit_expression(receiver);
+        if needs_parenthesis {
+            self.push(")");
+        }
+        self.push(".");
+        self.visit_expression(method);
+        self.push("(");
+        for (i, arg) in arguments.iter().enumerate() {
+            self.last_expression = Some(exp.clone());
+            self.visit_expression(arg);
+            if i < arguments.len() - 1 {
+                self.push(", ");
+            }
+        }
+        self.push(")");
+    }
+
+    /// Both blocks are formatted like the body of a block expression, as their last
+    /// expression is the value of the `try`.
+    fn visit_try_catch(&mut self, try_catch: &TryCatch) {
+        self.push("try {\n");
+        self.visit_body(&try_catch.body);
+        self.push_indent();
+
+        self.push("} catch (");
+        self.push(try_catch.error.value.as_str());
+        self.push(") {\n");
+        self.visit_body(&try_catch.handler);
+        self.push_indent();
+        self.push("}");
+    }
+
+    /// The expressions of the string are formatted on their own, as if they were not
+    /// nested in another expression.
+    fn visit_interpolated_string(&mut self, string: &InterpolatedString) {
+        self.push("f\"");
+        for part in &string.parts {
+            match part {
+                StringPart::Text(text) => self.push(&escape_interpolated(text)),
+                StringPart::Expression(expression) => {
+                    self.push("${");
+                    self.last_expression = None;
+                    self.visit_expression(expression);
+                    self.push("}");
+                }
+            }
+        }
+        self.push("\"");
+    }
+
     fn visit_function_literal(&mut self, func: &FunctionLiteral) {
         self.push("fn (");
         let parameters = func
@@ -250,17 +382,25 @@ impl Formatter {
         self.push(") {");
         self.push("\n");
 
-        self.enter_function(func);
-        for stmt in &func.body.statements {
-            self.visit_statement(stmt);
-            self.formatter_function_scope.as_mut().unwrap().next();
-        }
-        self.leave_function();
+        self.visit_body(&func.body);
 
         self.push_indent();
         self.push("}");
     }
 
+    /// Visits the body of a function or of a block expression, whose last expression is
+    /// its value and is not followed by a semicolon.
+    fn visit_body(&mut self, body: &BlockStatement) {
+        self.enter_function(body);
+        for stmt in &body.statements {
+            self.visit_statement(stmt);
+            if !matches!(stmt, Statement::Comment(_)) {
+                self.formatter_function_scope.as_mut().unwrap().next();
+            }
+        }
+        self.leave_function();
+    }
+
     fn visit_block_statement(&mut self, block: &BlockStatement) {
         for stmt in &block.statements {
             self.visit_statement(stmt);
@@ -275,10 +415,13 @@ impl Formatter {
         }
     }
 
-    fn enter_function(&mut self, function: &FunctionLiteral) {
+    fn enter_function(&mut self, body: &BlockStatement) {
         self.formatter_function_scope = Some(Box::new(FormatterFunctionScope::new(
             self.formatter_function_scope.clone(),
-            function.body.statements.len(),
+            body.statements
+                .iter()
+                .filter(|stmt| !matches!(stmt, Statement::Comment(_)))
+                .count(),
         )));
 
         self.indent += 1;
@@ -3,11 +3,12 @@ use crate::{
     object::{
         builtins::BuiltinFunction,
         enviroment::Environment,
+        random::Random,
         {Function, Object, FALSE, NULL, TRUE},
     },
     parser::ast::{
         BlockStatement, Conditional, Expression, HashMapLiteral, Identifier, IndexExpression,
-        Primitive, Program, Statement,
+        InterpolatedString, Primitive, Program, Statement, StringPart, TryCatch,
     },
 };
 
@@ -15,6 +16,10 @@ use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
 pub struct Evaluator {
     env: Rc<RefCell<Environment>>,
+    // Drawn from by `rand` and `rand_int`, reset by `seed`
+    random: Random,
+    // Value of the `throw` being raised, that the `catch` receives instead of the error
+    thrown: Option<Object>,
 }
 
 impl Default for Evaluator {
@@ -27,16 +32,19 @@ impl Evaluator {
     pub fn new() -> Self {
         Evaluator {
             env: Rc::new(RefCell::new(Environment::new())),
+            random: Random::default(),
+            thrown: None,
         }
     }
 
     pub fn eval(&mut self, program: Program) -> Object {
+        self.thrown = None;
         let mut result = NULL;
         for statement in program.statements {
             result = self.eval_statement(statement);
-            match result {
-                Object::RETURN(x) => return *x,
-                Object::ERROR(x) => return Object::ERROR(x),
+            match &mut result {
+                Object::RETURN(x) => return x.take(),
+                Object::ERROR(_) => return result,
                 _ => (),
             }
         }
@@ -66,14 +74,38 @@ impl Evaluator {
                 }
                 Object::RETURN(Box::new(value))
             }
-            Statement::Let(x) => {
+            Statement::Throw(x) => {
                 let value = self.eval_expression(x.value);
                 if Self::is_error(&value) {
                     return value;
                 }
+                let error = Object::ERROR(value.error_message());
+                self.thrown = Some(value);
+                error
+            }
+            Statement::Let(x) => {
+                let value = match x.value {
+                    Some(value) => self.eval_expression(value),
+                    None => NULL,
+                };
+                // The value may be a block that returned from the function
+                if matches!(value, Object::ERROR(_) | Object::RETURN(_)) {
+                    return value;
+                }
                 self.env.borrow_mut().set(x.name.to_string(), value);
                 NULL
             }
+            Statement::Assign(x) => {
+                let value = self.eval_expression(x.value);
+                if matches!(value, Object::ERROR(_) | Object::RETURN(_)) {
+                    return value;
+                }
+                if self.env.borrow_mut().assign(&x.name.value, value) {
+                    NULL
+                } else {
+                    Object::ERROR(format!("identifier not found: {}", x.name.value))
+                }
+            }
             Statement::While(stm) => {
                 let mut result = NULL;
                 while Self::is_truthy(&self.eval_expression(stm.condition.clone())) {
@@ -85,6 +117,13 @@ impl Evaluator {
                 }
                 result
             }
+            Statement::Enum(x) => {
+                for variant in x.into_let_statements() {
+                    self.eval_statement(Statement::Let(variant));
+                }
+                NULL
+            }
+            Statement::Comment(_) => NULL,
 
             _ => unimplemented!(), // I have decided not to implement the rest of the expressions,
                                    // I will focus on the compiler
@@ -107,6 +146,16 @@ impl Evaluator {
                 if Self::is_error(&left) {
                     return left;
                 }
+                // `&&`, `||` and `??` short-circuit and evaluate to one of their operands
+                match operator.token {
+                    Token::And if !Self::is_truthy(&left) => return left,
+                    Token::Or if Self::is_truthy(&left) => return left,
+                    Token::NullCoalesce if left != NULL => return left,
+                    Token::And | Token::Or | Token::NullCoalesce => {
+                        return self.eval_expression(*operator.right)
+                    }
+                    _ => {}
+                }
                 let right = self.eval_expression(*operator.right);
                 if Self::is_error(&right) {
                     return right;
@@ -114,6 +163,7 @@ impl Evaluator {
                 Self::eval_infix_expression(&operator.token, left, right)
             }
             Expression::Conditional(conditional) => self.eval_conditional_expression(conditional),
+            Expression::TryCatch(try_catch) => self.eval_try_catch(try_catch),
             Expression::Identifier(x) => self.eval_identifier(&x),
             Expression::FunctionLiteral(x) => {
                 let parameters = x.parameters;
@@ -125,8 +175,16 @@ impl Evaluator {
                 })
             }
             Expression::FunctionCall(x) => {
-                let function = self.eval_expression(*x.function);
-                if Self::is_error(&function) {
+                let function = match *x.function {
+                    // A method is the builtin of that name, whatever the variables in scope
+                    Expression::Identifier(method) if x.method => {
+                        BuiltinFunction::get_builtin(&method.value).unwrap_or_else(|| {
+                            Object::ERROR(format!("unknown method: {}", method.value))
+                        })
+                    }
+                    function => self.eval_expression(function),
+                };
+                if Self::is_error(&function) || (x.optional && function == NULL) {
                     return function;
                 }
                 let args = self.eval_expressions(x.arguments);
@@ -140,12 +198,17 @@ impl Evaluator {
                 if elements.len() == 1 && Self::is_error(&elements[0]) {
                     return elements[0].clone();
                 }
-                Object::ARRAY(elements)
+                Object::ARRAY(elements.into())
             }
             Expression::IndexExpression(index_expression) => {
                 self.eval_index_expression(index_expression)
             }
             Expression::HashMapLiteral(hashmap) => self.eval_hashmap_literal(hashmap),
+            Expression::Block(block) => self.eval_block_expression(block),
+            Expression::InterpolatedString(string) => self.eval_interpolated_string(string),
+            Expression::Spread(_) => Object::ERROR(
+                "spreading is only allowed in array literals and call arguments".to_string(),
+            ),
             _ => unimplemented!(), // I have decided not to implement the rest of the expressions,
                                    // I will focus on the compiler
         }
@@ -154,6 +217,7 @@ impl Evaluator {
     fn eval_primitive_expression(expression: Primitive) -> Object {
         match expression {
             Primitive::IntegerLiteral(x) => Object::INTEGER(x),
+            Primitive::FloatLiteral(x) => Object::FLOAT(x),
             Primitive::BooleanLiteral(x) => {
                 if x {
                     TRUE
@@ -162,6 +226,7 @@ impl Evaluator {
                 }
             }
             Primitive::StringLiteral(s) => Object::STRING(s),
+            Primitive::CharLiteral(c) => Object::CHAR(c),
         }
     }
 
@@ -184,6 +249,7 @@ impl Evaluator {
     fn eval_minus_operator_expression(right: &Object) -> Object {
         match right {
             Object::INTEGER(x) => Object::INTEGER(-x),
+            Object::FLOAT(x) => Object::FLOAT(-x),
             _ => Object::ERROR(format!("unknown operator: -{right}")),
         }
     }
@@ -193,12 +259,29 @@ impl Evaluator {
             (Object::INTEGER(x), Object::INTEGER(y)) => {
                 Self::eval_integer_infix_expression(operator, x, y)
             }
+            (left @ Object::FLOAT(_), right @ (Object::FLOAT(_) | Object::INTEGER(_)))
+            | (left @ Object::INTEGER(_), right @ Object::FLOAT(_)) => {
+                match (left.as_float(), right.as_float()) {
+                    (Some(x), Some(y)) => Self::eval_float_infix_expression(operator, x, y),
+                    _ => unreachable!(),
+                }
+            }
             (Object::BOOLEAN(x), Object::BOOLEAN(y)) => {
                 Self::eval_boolean_infix_expression(operator, x, y)
             }
-            (Object::STRING(x), Object::STRING(y)) => {
-                Self::eval_string_infix_expression(operator, x, &y)
+            (Object::STRING(ref mut x), Object::STRING(ref y)) => {
+                Self::eval_string_infix_expression(operator, std::mem::take(x), y)
             }
+            (Object::CHAR(x), Object::CHAR(y)) => Self::eval_char_infix_expression(operator, x, y),
+            (Object::CHAR(x), Object::INTEGER(y)) => match operator {
+                Token::Plus => Self::offset_char(x, y),
+                Token::Minus => Self::offset_char(x, -y),
+                _ => Object::ERROR(format!("unknown operator: CHAR {operator} INTEGER")),
+            },
+            (Object::INTEGER(x), Object::CHAR(y)) => match operator {
+                Token::Plus => Self::offset_char(y, x),
+                _ => Object::ERROR(format!("unknown operator: INTEGER {operator} CHAR")),
+            },
             (left, right) => Object::ERROR(format!(
                 "type mismatch: {} {} {}",
                 left.get_type(),
@@ -213,7 +296,18 @@ impl Evaluator {
             Token::Plus => Object::INTEGER(left + right),
             Token::Minus => Object::INTEGER(left - right),
             Token::Asterisk => Object::INTEGER(left * right),
-            Token::Slash => Object::INTEGER(left / right),
+            Token::Slash => left.checked_div(right).map_or_else(
+                || Object::ERROR(format!("invalid division: {left} / {right}")),
+                Object::INTEGER,
+            ),
+            Token::Modulo => left.checked_rem(right).map_or_else(
+                || Object::ERROR(format!("invalid division: {left} % {right}")),
+                Object::INTEGER,
+            ),
+            Token::DoubleSlash => Object::floor_div(left, right).map_or_else(
+                || Object::ERROR(format!("invalid division: {left} // {right}")),
+                Object::INTEGER,
+            ),
             Token::LT => Object::BOOLEAN(left < right),
             Token::GT => Object::BOOLEAN(left > right),
             Token::LTE => Object::BOOLEAN(left <= right),
@@ -224,12 +318,29 @@ impl Evaluator {
         }
     }
 
+    #[allow(clippy::float_cmp)] // `==` is exact on floats, as in most languages
+    fn eval_float_infix_expression(operator: &Token, left: f64, right: f64) -> Object {
+        match operator {
+            Token::Plus => Object::FLOAT(left + right),
+            Token::Minus => Object::FLOAT(left - right),
+            Token::Asterisk => Object::FLOAT(left * right),
+            Token::Slash => Object::FLOAT(left / right),
+            Token::DoubleSlash => Object::FLOAT((left / right).floor()),
+            Token::Modulo => Object::FLOAT(left % right),
+            Token::LT => Object::BOOLEAN(left < right),
+            Token::GT => Object::BOOLEAN(left > right),
+            Token::LTE => Object::BOOLEAN(left <= right),
+            Token::GTE => Object::BOOLEAN(left >= right),
+            Token::Equal => Object::BOOLEAN(left == right),
+            Token::NotEqual => Object::BOOLEAN(left != right),
+            _ => Object::ERROR(format!("unknown operator: FLOAT {operator} FLOAT")),
+        }
+    }
+
     fn eval_boolean_infix_expression(operator: &Token, left: bool, right: bool) -> Object {
         match operator {
             Token::Equal => Object::BOOLEAN(left == right),
             Token::NotEqual => Object::BOOLEAN(left != right),
-            Token::And => Object::BOOLEAN(left && right),
-            Token::Or => Object::BOOLEAN(left || right),
             _ => Object::ERROR(format!("unknown operator: BOOLEAN {operator} BOOLEAN")),
         }
     }
@@ -240,23 +351,60 @@ impl Evaluator {
                 left.push_str(right);
                 Object::STRING(left)
             }
-
+            Token::LT => Object::BOOLEAN(left.as_str() < right),
+            Token::GT => Object::BOOLEAN(left.as_str() > right),
+            Token::LTE => Object::BOOLEAN(left.as_str() <= right),
+            Token::GTE => Object::BOOLEAN(left.as_str() >= right),
+            Token::Equal => Object::BOOLEAN(left == right),
+            Token::NotEqual => Object::BOOLEAN(left != right),
             _ => Object::ERROR(format!("unknown operator: STRING {operator} STRING")),
         }
     }
 
+    fn eval_char_infix_expression(operator: &Token, left: char, right: char) -> Object {
+        match operator {
+            Token::Minus => Object::INTEGER(i64::from(left as u32) - i64::from(right as u32)),
+            Token::LT => Object::BOOLEAN(left < right),
+            Token::GT => Object::BOOLEAN(left > right),
+            Token::LTE => Object::BOOLEAN(left <= right),
+            Token::GTE => Object::BOOLEAN(left >= right),
+            Token::Equal => Object::BOOLEAN(left == right),
+            Token::NotEqual => Object::BOOLEAN(left != right),
+            _ => Object::ERROR(format!("unknown operator: CHAR {operator} CHAR")),
+        }
+    }
+
+    fn offset_char(c: char, offset: i64) -> Object {
+        match Object::offset_char(c, offset) {
+            Ok(c) => Object::CHAR(c),
+            Err(err) => Object::ERROR(err),
+        }
+    }
+
     fn eval_conditional_expression(&mut self, conditional: Conditional) -> Object {
-        let condition = self.eval_expression(*conditional.condition);
-        if Self::is_error(&condition) {
-            return condition;
-        }
-        if Self::is_truthy(&condition) {
-            self.eval_block_statemet(conditional.consequence)
-        } else if let Some(alternative) = conditional.alternative {
-            self.eval_block_statemet(alternative)
-        } else {
-            NULL
+        for (condition, consequence) in conditional.branches {
+            let condition = self.eval_expression(condition);
+            if Self::is_error(&condition) {
+                return condition;
+            }
+            if Self::is_truthy(&condition) {
+                return self.eval_block_statemet(consequence);
+            }
+        }
+        match conditional.alternative {
+            Some(alternative) => self.eval_block_statemet(alternative),
+            None => NULL,
+        }
+    }
+
+    fn eval_try_catch(&mut self, try_catch: TryCatch) -> Object {
+        let result = self.eval_block_statemet(try_catch.body);
+        if !Self::is_error(&result) {
+            return result;
         }
+        let caught = self.thrown.take().unwrap_or(result);
+        self.env.borrow_mut().set(try_catch.error.value, caught);
+        self.eval_block_statemet(try_catch.handler)
     }
 
     fn is_truthy(object: &Object) -> bool {
@@ -284,6 +432,19 @@ impl Evaluator {
     fn eval_expressions(&mut self, expressions: Vec<Expression>) -> Vec<Object> {
         let mut result = vec![];
         for expression in expressions {
+            if let Expression::Spread(spread) = expression {
+                match self.eval_expression(*spread) {
+                    Object::ARRAY(ref elements) => result.extend(elements.iter().cloned()),
+                    error @ Object::ERROR(_) => return vec![error],
+                    other => {
+                        return vec![Object::ERROR(format!(
+                            "cannot spread {}, only arrays can be spread",
+                            other.get_type()
+                        ))]
+                    }
+                }
+                continue;
+            }
             let evaluated = self.eval_expression(expression);
             if Self::is_error(&evaluated) {
                 return vec![evaluated];
@@ -293,21 +454,103 @@ impl Evaluator {
         result
     }
 
-    fn apply_function(&mut self, function: Object, args: Vec<Object>) -> Object {
+    /// Evaluates a block expression in its own environment, a `return` inside of it only
+    /// leaves the block.
+    fn eval_block_expression(&mut self, block: BlockStatement) -> Object {
+        let enclosed = Environment::new_enclosed_environment(Rc::clone(&self.env));
+        let env = Rc::clone(&self.env);
+        self.env = Rc::new(RefCell::new(enclosed));
+        // A `return` is left as is, to leave the enclosing function
+        let evaluated = self.eval_block_statemet(block);
+        self.env = env;
+        evaluated
+    }
+
+    fn eval_interpolated_string(&mut self, string: InterpolatedString) -> Object {
+        let mut result = String::new();
+        for part in string.parts {
+            match part {
+                StringPart::Text(text) => result.push_str(&text),
+                StringPart::Expression(expression) => {
+                    let value = self.eval_expression(expression);
+                    if Self::is_error(&value) {
+                        return value;
+                    }
+                    match BuiltinFunction::STR.call(vec![value]) {
+                        Object::STRING(ref s) => result.push_str(s),
+                        other => return other,
+                    }
+                }
+            }
+        }
+        Object::STRING(result)
+    }
+
+    fn apply_function(&mut self, mut function: Object, args: Vec<Object>) -> Object {
         match function {
-            Object::FUNCTION(function) => {
-                let extended_env = Self::extend_function_env(&function, args);
+            Object::FUNCTION(ref mut function) => {
+                let extended_env = Self::extend_function_env(function, args);
                 let env = Rc::clone(&self.env);
                 self.env = Rc::new(RefCell::new(extended_env));
-                let evaluated = self.eval_block_statemet(function.body);
+                let evaluated = self.eval_block_statemet(std::mem::take(&mut function.body));
                 self.env = env;
                 evaluated
             }
-            Object::BUILTIN(function) => function.call(args),
+            Object::PARTIAL(ref mut partial) => {
+                let mut all_args = std::mem::take(&mut partial.args);
+                all_args.extend(args);
+                self.apply_function(partial.function.take(), all_args)
+            }
+            Object::BUILTIN(BuiltinFunction::REPEAT) => self.apply_repeat(&args),
+            Object::BUILTIN(BuiltinFunction::APPLY) => self.eval_apply(args),
+            Object::BUILTIN(
+                ref function @ (BuiltinFunction::RAND
+                | BuiltinFunction::RAND_INT
+                | BuiltinFunction::SEED),
+            ) => function.call_random(&args, &mut self.random),
+            Object::BUILTIN(ref function) => function.call(args),
             _ => Object::ERROR(format!("not a function: {function}")),
         }
     }
 
+    /// `apply(f, args)`, unlike other calls, checks that `f` takes as many parameters as
+    /// there are arguments.
+    fn eval_apply(&mut self, args: Vec<Object>) -> Object {
+        let (function, args) = match BuiltinFunction::apply_arguments(args) {
+            Ok(call) => call,
+            Err(error) => return error,
+        };
+        if let Object::FUNCTION(function) = &function {
+            if function.parameters.len() != args.len() {
+                return Object::ERROR(format!(
+                    "wrong number of arguments. got={}, want={}",
+                    args.len(),
+                    function.parameters.len()
+                ));
+            }
+        }
+        match self.apply_function(function, args) {
+            Object::RETURN(ref mut result) => result.take(),
+            result => result,
+        }
+    }
+
+    fn apply_repeat(&mut self, args: &[Object]) -> Object {
+        let count = match BuiltinFunction::repeat_count(args) {
+            Ok(count) => count,
+            Err(error) => return error,
+        };
+        let mut results = Vec::with_capacity(count);
+        for _ in 0..count {
+            match self.apply_function(args[1].clone(), vec![]) {
+                Object::RETURN(ref mut result) => results.push(result.take()),
+                error @ Object::ERROR(_) => return error,
+                result => results.push(result),
+            }
+        }
+        Object::ARRAY(results.into())
+    }
+
     fn extend_function_env(function: &Function, args: Vec<Object>) -> Environment {
         let mut env = Environment::new_enclosed_environment(Rc::clone(&function.environment));
         for (param, arg) in function.parameters.iter().zip(args) {
@@ -318,7 +561,7 @@ impl Evaluator {
 
     fn eval_index_expression(&mut self, index_expression: IndexExpression) -> Object {
         let left = self.eval_expression(*index_expression.left);
-        if Self::is_error(&left) {
+        if Self::is_error(&left) || (index_expression.optional && left == NULL) {
             return left;
         }
         let index = self.eval_expression(*index_expression.index);
@@ -368,6 +611,6 @@ impl Evaluator {
             }
             hashmap.insert(key, value);
         }
-        Object::HASHMAP(hashmap)
+        Object::HASHMAP(hashmap.into())
     }
 }
@@ -30,6 +30,67 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_eval_float_expression() {
+        let tests = vec![
+            ("2.5", Object::FLOAT(2.5)),
+            ("-2.5", Object::FLOAT(-2.5)),
+            ("1.5 + 1", Object::FLOAT(2.5)),
+            ("3 * 0.5", Object::FLOAT(1.5)),
+            ("1.0 / 4", Object::FLOAT(0.25)),
+            ("1.0 / 0", Object::FLOAT(f64::INFINITY)),
+            ("0.5 + 0.5 == 1", Object::BOOLEAN(true)),
+            ("1.5 != 1.5", Object::BOOLEAN(false)),
+            ("1.5 < 2", Object::BOOLEAN(true)),
+            ("2.0 >= 2", Object::BOOLEAN(true)),
+            ("5.0 / 2", Object::FLOAT(2.5)),
+            ("5 / 2", Object::INTEGER(2)),
+            ("5 // 2", Object::INTEGER(2)),
+            ("-7 // 2", Object::INTEGER(-4)),
+            ("-5.5 // 2", Object::FLOAT(-3.0)),
+            ("5.5 % 2", Object::FLOAT(1.5)),
+            ("-5.5 % 2", Object::FLOAT(-1.5)),
+            (
+                "1 // 0",
+                Object::ERROR("invalid division: 1 // 0".to_string()),
+            ),
+            ("7 % 3", Object::INTEGER(1)),
+            (
+                "1 / 0",
+                Object::ERROR("invalid division: 1 / 0".to_string()),
+            ),
+            (
+                "1 % 0",
+                Object::ERROR("invalid division: 1 % 0".to_string()),
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_eval_block_expressions() {
+        let tests = vec![
+            ("{ let x = 1; x + 1 }", Object::INTEGER(2)),
+            ("{ let x = 1; }", Object::NULL),
+            (
+                "let x = 1; let y = { let x = 10; x }; x + y",
+                Object::INTEGER(11),
+            ),
+            (
+                "let f = fn() { let a = { return 1; 2 }; a + 1 }; f()",
+                Object::INTEGER(1),
+            ),
+            ("let x = 1; { let y = 2; x = y; }; x", Object::INTEGER(2)),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "input: {input}");
+        }
+    }
+
     #[test]
     fn test_eval_boolean_expression() {
         let tests = vec![
@@ -103,6 +164,19 @@ mod tests {
             ("if (1 > 2) { 10 }", None),
             ("if (1 > 2) { 10 } else { 20 }", Some(20)),
             ("if (1 < 2) { 10 } else { 20 }", Some(10)),
+            (
+                "let x = 1; if (x < 2) { 10 } else if (x < 3) { 20 } else { 30 }",
+                Some(10),
+            ),
+            (
+                "let x = 2; if (x < 2) { 10 } else if (x < 3) { 20 } else { 30 }",
+                Some(20),
+            ),
+            (
+                "let x = 3; if (x < 2) { 10 } else if (x < 3) { 20 } else { 30 }",
+                Some(30),
+            ),
+            ("let x = 3; if (x < 2) { 10 } else if (x < 3) { 20 }", None),
         ];
 
         for (input, expected) in tests {
@@ -193,7 +267,7 @@ mod tests {
 
         let evaluated = test_eval(input);
 
-        match evaluated {
+        match &evaluated {
             Object::FUNCTION(x) => {
                 assert_eq!(x.parameters.len(), 1);
                 assert_eq!(x.parameters[0].to_string(), "x");
@@ -253,6 +327,17 @@ mod tests {
         test_string_object(evaluated, "Hello World!".to_string());
     }
 
+    #[test]
+    fn test_string_comparison() {
+        test_boolean_object(test_eval("\"apple\" < \"banana\""), true);
+        test_boolean_object(test_eval("\"apple\" >= \"banana\""), false);
+        test_boolean_object(test_eval("\"mon\" + \"key\" == \"monkey\""), true);
+        test_error_object(
+            test_eval("\"1\" < 2"),
+            "type mismatch: STRING < INTEGER".to_string(),
+        );
+    }
+
     #[test]
     fn test_builttin_len_function() {
         let tests_striung = vec![
@@ -260,6 +345,7 @@ mod tests {
             (r#"len("four")"#, 4),
             (r#"len("hello world")"#, 11),
             (r"len([1,2,3,4,5])", 5),
+            (r#"len({"a": 1, "b": 2})"#, 2),
         ];
 
         for (input, expected) in tests_striung {
@@ -282,13 +368,182 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_builtin_repeat_function() {
+        match &test_eval("repeat(3, fn() { return 1; })") {
+            Object::ARRAY(x) => {
+                assert_eq!(x.len(), 3);
+                for element in x.iter().cloned() {
+                    test_integer_object(element, 1);
+                }
+            }
+            _ => panic!("The object is not an array"),
+        }
+
+        let tests = vec![
+            (
+                "repeat(-2, fn() { 1 })",
+                "first argument to `repeat` must not be negative, got -2",
+            ),
+            (
+                "repeat(2, fn() { 1 + true })",
+                "type mismatch: INTEGER + BOOLEAN",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            test_error_object(test_eval(input), expected.to_string());
+        }
+    }
+
+    #[test]
+    fn test_builtin_assert_functions() {
+        test_integer_object(test_eval("assert(true); assert_eq(2, 1 + 1); 5"), 5);
+
+        let tests = vec![
+            ("assert(false); 5", "assertion failed: got false"),
+            ("assert_eq(2, 3); 5", "assertion failed: left: 2, right: 3"),
+        ];
+
+        for (input, expected) in tests {
+            test_error_object(test_eval(input), expected.to_string());
+        }
+    }
+
+    #[test]
+    fn test_builtin_type_predicates() {
+        test_boolean_object(test_eval("is_fn(fn(x) { x })"), true);
+        test_boolean_object(test_eval("is_fn(len)"), true);
+        test_boolean_object(test_eval("is_int(\"1\")"), false);
+        test_boolean_object(test_eval("is_null(if (false) { 1 })"), true);
+        test_boolean_object(test_eval("is_hash([])"), false);
+    }
+
+    #[test]
+    fn test_builtin_partial() {
+        test_integer_object(
+            test_eval("let add = fn(a, b) { a + b }; let inc = partial(add, 1); inc(2)"),
+            3,
+        );
+        test_integer_object(
+            test_eval("let sub = fn(a, b) { a - b }; partial(partial(sub, 10), 4)()"),
+            6,
+        );
+        test_error_object(
+            test_eval("let add = fn(a, b) { a + b }; partial(add, 1, 2, 3)"),
+            "too many arguments for `partial`: the function takes 2, got 3".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_builtin_apply() {
+        test_integer_object(test_eval("apply(fn(a, b) { a + b }, [1, 2])"), 3);
+        test_integer_object(test_eval("apply(fn(a) { return a * 2; }, [4])"), 8);
+        test_integer_object(test_eval("apply(len, [\"abc\"])"), 3);
+        test_error_object(
+            test_eval("apply(fn(a, b) { a + b }, [1, 2, 3])"),
+            "wrong number of arguments. got=3, want=2".to_string(),
+        );
+        test_error_object(
+            test_eval("apply(fn(a) { a }, 1)"),
+            "second argument to `apply` not supported, must be ARRAY, got INTEGER".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_builtin_method_calls() {
+        test_boolean_object(test_eval("[1, 2, 3].len() == len([1, 2, 3])"), true);
+        test_boolean_object(test_eval(r#""ab".to_upper() == "AB""#), true);
+        test_integer_object(test_eval("[1, 2].push(3).slice(1).len()"), 2);
+        test_integer_object(test_eval("let len = fn(x) { 99 }; [1, 2].len()"), 2);
+        test_integer_object(test_eval("let len = fn(x) { 99 }; len([1, 2])"), 99);
+    }
+
+    #[test]
+    fn test_builtin_random() {
+        let seeded = "seed(3); [rand(), rand_int(0, 1000), rand()]";
+        assert_eq!(test_eval(seeded), test_eval(seeded));
+        test_boolean_object(
+            test_eval("let r = rand_int(-2, 2); (r >= -2) && (r < 2)"),
+            true,
+        );
+        test_error_object(
+            test_eval("rand_int(2, 2)"),
+            "first argument to `rand_int` must be less than the second, got 2 and 2".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_builtin_dbg() {
+        test_integer_object(test_eval("dbg(1 + 2) * 2"), 6);
+        test_error_object(
+            test_eval("dbg(1, 2)"),
+            "wrong number of arguments. got=2, want=1".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_try_catch() {
+        test_integer_object(test_eval("try { 1 } catch (e) { 2 }"), 1);
+        test_integer_object(test_eval("try { len(1); 1 } catch (e) { 2 }"), 2);
+        test_error_object(
+            test_eval("try { len(1) } catch (e) { e }"),
+            "argument to `len` not supported, got INTEGER".to_string(),
+        );
+        assert_eq!(
+            test_eval("try { throw \"oops\"; } catch (e) { e }"),
+            Object::STRING("oops".to_string())
+        );
+        test_integer_object(test_eval("try { throw 5; } catch (e) { e * 10 }"), 50);
+        test_integer_object(
+            test_eval("let f = fn() { throw [1, 2]; }; try { f() } catch (e) { e[1] }"),
+            2,
+        );
+        test_error_object(test_eval("throw 1 + 1; 3"), "2".to_string());
+        test_integer_object(test_eval("try { 1 / 0 } catch (e) { 2 }"), 2);
+        test_error_object(
+            test_eval("try { 1 } catch (e) { 2 }; len(1)"),
+            "argument to `len` not supported, got INTEGER".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_logical_operators_return_operands() {
+        test_integer_object(test_eval("0 || 5"), 0);
+        test_integer_object(test_eval("false || 5"), 5);
+        test_integer_object(test_eval("3 && 4"), 4);
+        test_integer_object(test_eval("let f = fn(x) { x && 10 }; f(1)"), 10);
+        test_boolean_object(test_eval("3 && 4 == 4"), true);
+        test_boolean_object(test_eval("false && (1 + true)"), false);
+        test_error_object(
+            test_eval("true && (1 + true)"),
+            "type mismatch: INTEGER + BOOLEAN".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_null_coalescing() {
+        test_integer_object(test_eval("[][0] ?? 5"), 5);
+        test_integer_object(test_eval("0 ?? 5"), 0);
+        test_boolean_object(test_eval("false ?? 5"), false);
+        test_integer_object(test_eval("1 ?? (1 + true)"), 1);
+    }
+
+    #[test]
+    fn test_optional_chaining() {
+        test_null_object(test_eval("[][0]?[0]"));
+        test_null_object(test_eval("[][0]?.(1 + true)"));
+        test_integer_object(test_eval("[1, 2]?[1]"), 2);
+        test_integer_object(test_eval("let f = fn(x) { x + 1 }; f?.(1)"), 2);
+    }
+
     #[test]
     fn test_array_literals() {
         let input = "[1, 2 * 2, 3 + 3]";
 
         let evaluated = test_eval(input);
 
-        match evaluated {
+        match &evaluated {
             Object::ARRAY(x) => {
                 assert_eq!(x.len(), 3);
                 test_integer_object(x[0].clone(), 1);
@@ -453,7 +708,7 @@ mod tests {
         expected.insert(Object::BOOLEAN(false), Object::INTEGER(6));
 
         let evaluated = test_eval(input);
-        match evaluated {
+        match &evaluated {
             Object::HASHMAP(hash) => {
                 assert_eq!(hash.len(), expected.len());
 
@@ -540,21 +795,21 @@ mod tests {
     }
 
     fn test_error_object(object: Object, expected: String) {
-        match object {
-            Object::ERROR(x) => assert_eq!(x, expected),
+        match &object {
+            Object::ERROR(x) => assert_eq!(*x, expected),
             _ => panic!("The object is not an  error"),
         }
     }
 
     fn test_string_object(object: Object, expected: String) {
-        match object {
+        match &object {
             Object::STRING(s) => assert_eq!(format!("{s}"), expected),
             _ => panic!("The object is not an string"),
         }
     }
 
     fn test_array_object(object: Object, expected: Vec<i64>) {
-        match object {
+        match &object {
             Object::ARRAY(x) => {
                 assert_eq!(x.len(), expected.len());
                 for (i, v) in x.iter().enumerate() {
@@ -564,4 +819,94 @@ mod tests {
             _ => panic!("The object is not an array"),
         }
     }
+
+    #[test]
+    fn test_eval_assignment() {
+        let tests = vec![
+            ("let x; x = 5; x", Object::INTEGER(5)),
+            ("let x; x", Object::NULL),
+            (
+                "let i = 0; while (i < 3) { i = i + 1; } i",
+                Object::INTEGER(3),
+            ),
+            (
+                "let count = 0; let inc = fn() { count = count + 1; }; inc(); inc(); count",
+                Object::INTEGER(2),
+            ),
+            ("let x = 1; if (true) { x = 2; }; x", Object::INTEGER(2)),
+            ("let x = 1; { x = 2; }; x", Object::INTEGER(2)),
+            (
+                "x = 1;",
+                Object::ERROR("identifier not found: x".to_string()),
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_eval_interpolated_strings() {
+        let tests = vec![
+            (r#"f"1+1=${1+1}""#, Object::STRING("1+1=2".to_string())),
+            (r#"f"costs \$5""#, Object::STRING("costs $5".to_string())),
+            (
+                r#"let name = "world"; f"hello ${name} ${'!'}""#,
+                Object::STRING("hello world !".to_string()),
+            ),
+            (
+                r#"f"${missing}""#,
+                Object::ERROR("identifier not found: missing".to_string()),
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_eval_spread() {
+        let tests = vec![
+            (
+                "[...[1, 2], 3]",
+                Object::ARRAY(
+                    vec![Object::INTEGER(1), Object::INTEGER(2), Object::INTEGER(3)].into(),
+                ),
+            ),
+            (
+                "let f = fn(a, b) { a * 10 + b }; f(...[1, 2])",
+                Object::INTEGER(12),
+            ),
+            ("len(...[[1, 2, 3]])", Object::INTEGER(3)),
+            (
+                "[...1]",
+                Object::ERROR("cannot spread INTEGER, only arrays can be spread".to_string()),
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_eval_enums() {
+        let tests = vec![
+            ("enum Color { Red, Green, Blue } Blue", Object::INTEGER(2)),
+            (
+                "enum Color { Red, Green } Red == Green",
+                Object::BOOLEAN(false),
+            ),
+            (
+                "let f = fn() { enum Direction { Up, Down } Up }; f()",
+                Object::INTEGER(0),
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(test_eval(input), expected, "input: {input}");
+        }
+    }
 }
@@ -1,11 +1,36 @@
 pub mod token;
-use crate::lexer::token::Token;
+use std::fmt::Display;
+
+use crate::lexer::token::{lookup_keyword, InterpolationPart, Token};
+
+/// Location of a token in the source, both starting at 1. Columns count characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
 
 pub struct Lexer {
     input: Vec<char>,
     position: usize,      // current position in input (points to current char)
     read_position: usize, // current reading position in input (after current char)
     ch: char,             // current char under examination
+
+    line: usize,   // line of the current char
+    column: usize, // column of the current char
+    token_position: Position,
+
+    /// Whether comments are kept, see `with_trivia`
+    trivia: bool,
+    comments: Vec<String>,
+    // Whether the first of `comments` is on the line of the token before it
+    comment_trails: bool,
 }
 
 impl Lexer {
@@ -15,13 +40,59 @@ impl Lexer {
             position: 0,
             read_position: 0,
             ch: '\0',
+
+            line: 1,
+            column: 0,
+            token_position: Position::default(),
+
+            trivia: false,
+            comments: Vec::new(),
+            comment_trails: false,
         };
 
         lexer.read_char();
         lexer
     }
 
+    /// Lexer for code nested in another source, such as the expressions of an interpolated
+    /// string, so that its positions are those of the enclosing source. `position` is the
+    /// position of the first character of `input`.
+    pub fn new_at(input: &str, position: Position) -> Lexer {
+        let mut lexer = Lexer::new("");
+        lexer.input = input.chars().collect();
+        lexer.read_position = 0;
+        lexer.line = position.line;
+        lexer.column = position.column.saturating_sub(1);
+        lexer.read_char();
+        lexer
+    }
+
+    /// Sets whether comments are kept instead of being discarded (the default). They can
+    /// then be read with `leading_comments`, so that a formatter can write them back.
+    #[must_use]
+    pub fn with_trivia(mut self, trivia: bool) -> Self {
+        self.trivia = trivia;
+        self
+    }
+
+    /// Comments found between the last token returned by `next_token` and the one before
+    /// it, in order, with their `#`. Always empty unless `with_trivia` is set.
+    pub fn leading_comments(&self) -> &[String] {
+        &self.comments
+    }
+
+    /// Whether the first of the `leading_comments` follows the token before it on the same
+    /// line, rather than being on a line of its own.
+    pub fn first_comment_trails(&self) -> bool {
+        self.comment_trails
+    }
+
     fn read_char(&mut self) {
+        if self.ch == '\n' {
+            self.line += 1;
+            self.column = 0;
+        }
+        self.column += 1;
         if self.read_position >= self.input.len() {
             self.ch = '\0';
         } else {
@@ -31,62 +102,32 @@ impl Lexer {
         self.read_position += 1;
     }
 
+    /// Position of the first character of the last token returned by `next_token`.
+    pub fn token_position(&self) -> Position {
+        self.token_position
+    }
+
     pub fn next_token(&mut self) -> Token {
-        self.skip_whitespace();
+        self.comments.clear();
+        self.skip_whitespace_and_comments();
+        self.token_position = Position {
+            line: self.line,
+            column: self.column,
+        };
         let token = match self.ch {
-            '=' => {
-                if self.peek_char() == '=' {
-                    self.read_char();
-                    Token::Equal
-                } else {
-                    Token::Assign
-                }
-            }
+            '=' => self.read_pair('=', Token::Equal, Token::Assign),
             '+' => Token::Plus,
             '-' => Token::Minus,
-            '!' => {
-                if self.peek_char() == '=' {
-                    self.read_char();
-                    Token::NotEqual
-                } else {
-                    Token::Bang
-                }
-            }
-            '/' => Token::Slash,
+            '!' => self.read_pair('=', Token::NotEqual, Token::Bang),
+            '/' => self.read_pair('/', Token::DoubleSlash, Token::Slash),
             '*' => Token::Asterisk,
-            '<' => {
-                if self.peek_char() == '=' {
-                    self.read_char();
-                    Token::LTE
-                } else {
-                    Token::LT
-                }
-            }
-            '>' => {
-                if self.peek_char() == '=' {
-                    self.read_char();
-                    Token::GTE
-                } else {
-                    Token::GT
-                }
-            }
-            '&' => {
-                if self.peek_char() == '&' {
-                    self.read_char();
-                    Token::And
-                } else {
-                    Token::Illegal("&".to_string())
-                }
-            }
-            '|' => {
-                if self.peek_char() == '|' {
-                    self.read_char();
-                    Token::Or
-                } else {
-                    Token::Illegal("|".to_string())
-                }
-            }
+            '<' => self.read_pair('=', Token::LTE, Token::LT),
+            '>' => self.read_pair('=', Token::GTE, Token::GT),
+            '&' => self.read_pair('&', Token::And, Token::Illegal("&".to_string())),
+            '|' => self.read_pair('|', Token::Or, Token::Illegal("|".to_string())),
+            '?' => self.read_question_mark(),
             '%' => Token::Modulo,
+            '.' => self.read_dot(),
             ';' => Token::Semicolon,
             '(' => Token::LParen,
             ')' => Token::RParen,
@@ -96,37 +137,81 @@ impl Lexer {
             '[' => Token::LSquare,
             ']' => Token::RSquare,
             ':' => Token::Colon,
-            '"' => {
-                let string = self.read_string();
-                Token::String(string)
-            }
+            '"' => self.read_string(),
+            '\'' => self.read_char_literal(),
             '\0' => Token::Eof,
+            'f' if self.peek_char() == '"' => {
+                self.read_char();
+                return self.read_interpolated_string();
+            }
             'a'..='z' | 'A'..='Z' | '_' => {
                 let ident_string = self.read_identifier();
-                return match ident_string.as_str() {
-                    "fn" => Token::Function,
-                    "let" => Token::Let,
-                    "true" => Token::True,
-                    "false" => Token::False,
-                    "if" => Token::If,
-                    "else" => Token::Else,
-                    "return" => Token::Return,
-                    "while" => Token::While,
-                    "break" => Token::Break,
-                    "continue" => Token::Continue,
-                    _ => Token::Ident(ident_string),
-                };
+                return lookup_keyword(&ident_string).unwrap_or(Token::Ident(ident_string));
             }
-            '0'..='9' => return Token::Int(self.read_number()),
+            '0'..='9' => return self.read_number(),
             s => Token::Illegal(s.to_string()),
         };
         self.read_char();
         token
     }
 
-    fn skip_whitespace(&mut self) {
-        while self.ch.is_whitespace() {
+    /// Two characters operators: returns `pair` and consumes the next character if it is
+    /// `second`, and `single` otherwise.
+    fn read_pair(&mut self, second: char, pair: Token, single: Token) -> Token {
+        if self.peek_char() == second {
             self.read_char();
+            pair
+        } else {
+            single
+        }
+    }
+
+    /// `...`, or a single `.` as in `array.len()`.
+    fn read_dot(&mut self) -> Token {
+        if self.peek_char() != '.' || self.input.get(self.read_position + 1) != Some(&'.') {
+            return Token::Dot;
+        }
+        self.read_char();
+        self.read_char();
+        Token::Spread
+    }
+
+    /// `??`, `?[` and `?.`, a lone `?` is not a valid token.
+    fn read_question_mark(&mut self) -> Token {
+        let token = match self.peek_char() {
+            '?' => Token::NullCoalesce,
+            '[' => Token::OptionalLSquare,
+            '.' => Token::OptionalCall,
+            _ => return Token::Illegal("?".to_string()),
+        };
+        self.read_char();
+        token
+    }
+
+    /// Comments start with `#` and end with the line.
+    fn skip_whitespace_and_comments(&mut self) {
+        // Nothing comes before the first token, so a comment there cannot trail it
+        let mut same_line = self.position > 0;
+        loop {
+            while self.ch.is_whitespace() {
+                same_line &= self.ch != '\n';
+                self.read_char();
+            }
+            if self.ch != '#' {
+                return;
+            }
+            let position = self.position;
+            while !matches!(self.ch, '\n' | '\0') {
+                self.read_char();
+            }
+            if self.trivia {
+                let comment: String = self.input[position..self.position].iter().collect();
+                if self.comments.is_empty() {
+                    self.comment_trails = same_line;
+                }
+                self.comments.push(comment.trim_end().to_string());
+            }
+            same_line = false;
         }
     }
 
@@ -146,29 +231,192 @@ impl Lexer {
         self.input[position..self.position].iter().collect()
     }
 
-    fn read_number(&mut self) -> String {
+    /// Reads an integer, or a float if the digits are followed by a `.` and more digits,
+    /// by an exponent (`1e3`, `2.5E-1`), or both.
+    fn read_number(&mut self) -> Token {
         let position = self.position;
-        while self.ch.is_numeric() {
+        let mut is_float = false;
+        self.read_digits();
+        if self.ch == '.' && self.peek_char().is_ascii_digit() {
+            self.read_char();
+            self.read_digits();
+            is_float = true;
+        }
+        if matches!(self.ch, 'e' | 'E') {
+            self.read_char();
+            if matches!(self.ch, '+' | '-') {
+                self.read_char();
+            }
+            if !self.ch.is_ascii_digit() {
+                let literal = self.input[position..self.position]
+                    .iter()
+                    .collect::<String>();
+                return Token::Illegal(format!("missing digits in the exponent of {literal}"));
+            }
+            self.read_digits();
+            is_float = true;
+        }
+
+        let literal = self.input[position..self.position].iter().collect();
+        if is_float {
+            Token::Float(literal)
+        } else {
+            Token::Int(literal)
+        }
+    }
+
+    fn read_digits(&mut self) {
+        while self.ch.is_ascii_digit() {
             self.read_char();
         }
-        self.input[position..self.position].iter().collect()
     }
 
-    fn read_string(&mut self) -> String {
+    /// Reads a char literal such as `'a'` or `'\n'`, the current char being the opening quote.
+    ///
+    /// Empty, multi-character and unterminated literals produce a `Token::Illegal`
+    /// describing the problem.
+    fn read_char_literal(&mut self) -> Token {
+        let mut chars = Vec::new();
+        let mut unknown_escape = None;
+        loop {
+            self.read_char();
+            match self.ch {
+                '\'' => break,
+                '\0' => return Token::Illegal("unterminated char literal".to_string()),
+                '\\' => {
+                    self.read_char();
+                    match Self::escaped_char(self.ch) {
+                        Some(c) => chars.push(c),
+                        None => unknown_escape = Some(self.ch),
+                    }
+                }
+                c => chars.push(c),
+            }
+        }
+
+        if let Some(c) = unknown_escape {
+            return Token::Illegal(format!("unknown escape sequence \\{c}"));
+        }
+
+        match chars.as_slice() {
+            [c] => Token::Char(*c),
+            [] => Token::Illegal("empty char literal".to_string()),
+            _ => Token::Illegal(format!(
+                "char literal must contain a single character, got '{}'",
+                chars.iter().collect::<String>()
+            )),
+        }
+    }
+
+    fn escaped_char(ch: char) -> Option<char> {
+        match ch {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            'r' => Some('\r'),
+            '0' => Some('\0'),
+            '\\' | '\'' | '"' => Some(ch),
+            _ => None,
+        }
+    }
+
+    /// Reads a string literal, the current char being the opening quote. A string that is
+    /// still open at the end of the input produces a `Token::Illegal`.
+    fn read_string(&mut self) -> Token {
         let position = self.position + 1;
         loop {
             self.read_char();
-            if self.ch == '"' || self.ch == '\0' {
-                break; // TODO: handle unterminated string
+            match self.ch {
+                '"' => break,
+                '\0' if self.position >= self.input.len() => {
+                    return Token::Illegal(format!(
+                        "unterminated string literal starting at line {}",
+                        self.token_position.line
+                    ));
+                }
+                _ => {}
+            }
+        }
+        Token::String(self.input[position..self.position].iter().collect())
+    }
+
+    /// Reads `f"..."`, starting on its opening quote. `${` starts an expression that ends
+    /// at the matching `}`, and `\$` and `\\` are the only escapes of the text.
+    fn read_interpolated_string(&mut self) -> Token {
+        let unterminated = Token::Illegal(format!(
+            "unterminated interpolated string literal starting at line {}",
+            self.token_position.line
+        ));
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        loop {
+            self.read_char();
+            match self.ch {
+                '"' => break,
+                '\\' if matches!(self.peek_char(), '$' | '\\') => {
+                    self.read_char();
+                    text.push(self.ch);
+                }
+                '$' if self.peek_char() == '{' => {
+                    self.read_char();
+                    let position = Position {
+                        line: self.line,
+                        column: self.column + 1,
+                    };
+                    let Some(code) = self.read_interpolated_code() else {
+                        return unterminated;
+                    };
+                    if !text.is_empty() {
+                        parts.push(InterpolationPart::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(InterpolationPart::Code(code, position));
+                }
+                '\0' if self.position >= self.input.len() => return unterminated,
+                c => text.push(c),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(InterpolationPart::Text(text));
+        }
+        self.read_char();
+        Token::InterpolatedString(parts)
+    }
+
+    /// Reads the source of an `${...}` expression, starting on its `{`, up to the matching
+    /// `}`. Braces inside of strings and chars are not counted. Returns `None` at the end
+    /// of the input.
+    fn read_interpolated_code(&mut self) -> Option<String> {
+        let start = self.position + 1;
+        let mut depth = 1;
+        loop {
+            self.read_char();
+            match self.ch {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(self.input[start..self.position].iter().collect());
+                    }
+                }
+                quote @ ('"' | '\'') => loop {
+                    self.read_char();
+                    match self.ch {
+                        '\\' if quote == '\'' => self.read_char(),
+                        c if c == quote => break,
+                        '\0' if self.position >= self.input.len() => return None,
+                        _ => {}
+                    }
+                },
+                '\0' if self.position >= self.input.len() => return None,
+                _ => {}
             }
         }
-        self.input[position..self.position].iter().collect()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lexer::token::KEYWORDS;
 
     #[test]
     fn test_next_token_basic() {
@@ -379,4 +627,357 @@ mod tests {
             assert_eq!(token, expected_token);
         }
     }
+
+    #[test]
+    fn test_char_literals() {
+        let input = r"'a' '\n' '\'' 'é'";
+
+        let expected = vec![
+            Token::Char('a'),
+            Token::Char('\n'),
+            Token::Char('\''),
+            Token::Char('é'),
+            Token::Eof,
+        ];
+
+        let mut lexer = Lexer::new(input);
+
+        for expected_token in expected {
+            assert_eq!(lexer.next_token(), expected_token);
+        }
+    }
+
+    #[test]
+    fn test_float_exponents() {
+        let input = "1e3 2.5e-1 1E+2 1e 3e- 4.0e";
+
+        let expected = vec![
+            Token::Float(String::from("1e3")),
+            Token::Float(String::from("2.5e-1")),
+            Token::Float(String::from("1E+2")),
+            Token::Illegal(String::from("missing digits in the exponent of 1e")),
+            Token::Illegal(String::from("missing digits in the exponent of 3e-")),
+            Token::Illegal(String::from("missing digits in the exponent of 4.0e")),
+            Token::Eof,
+        ];
+
+        let mut lexer = Lexer::new(input);
+
+        for expected_token in expected {
+            assert_eq!(lexer.next_token(), expected_token);
+        }
+    }
+
+    #[test]
+    fn test_float_literals() {
+        let input = "1.5 0.25 10 3.0.5 7. x";
+
+        let expected = vec![
+            Token::Float(String::from("1.5")),
+            Token::Float(String::from("0.25")),
+            Token::Int(String::from("10")),
+            Token::Float(String::from("3.0")),
+            Token::Dot,
+            Token::Int(String::from("5")),
+            Token::Int(String::from("7")),
+            Token::Dot,
+            Token::Ident(String::from("x")),
+            Token::Eof,
+        ];
+
+        let mut lexer = Lexer::new(input);
+
+        for expected_token in expected {
+            assert_eq!(lexer.next_token(), expected_token);
+        }
+    }
+
+    #[test]
+    fn test_question_mark_operators() {
+        let input = "a ?? b ? ? a?[0] f?.()";
+
+        let expected = vec![
+            Token::Ident(String::from("a")),
+            Token::NullCoalesce,
+            Token::Ident(String::from("b")),
+            Token::Illegal(String::from("?")),
+            Token::Illegal(String::from("?")),
+            Token::Ident(String::from("a")),
+            Token::OptionalLSquare,
+            Token::Int(String::from("0")),
+            Token::RSquare,
+            Token::Ident(String::from("f")),
+            Token::OptionalCall,
+            Token::LParen,
+            Token::RParen,
+            Token::Eof,
+        ];
+
+        let mut lexer = Lexer::new(input);
+
+        for expected_token in expected {
+            assert_eq!(lexer.next_token(), expected_token);
+        }
+    }
+
+    #[test]
+    fn test_invalid_char_literals() {
+        let tests = vec![
+            ("''", "empty char literal"),
+            (
+                "'ab'",
+                "char literal must contain a single character, got 'ab'",
+            ),
+            (r"'\q'", r"unknown escape sequence \q"),
+            ("'a", "unterminated char literal"),
+        ];
+
+        for (input, expected) in tests {
+            let mut lexer = Lexer::new(input);
+            assert_eq!(lexer.next_token(), Token::Illegal(expected.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_unterminated_string_literal() {
+        let mut lexer = Lexer::new("let a = \"abc");
+        for _ in 0..3 {
+            lexer.next_token();
+        }
+        assert_eq!(
+            lexer.next_token(),
+            Token::Illegal("unterminated string literal starting at line 1".to_string())
+        );
+        assert_eq!(lexer.next_token(), Token::Eof);
+
+        let mut lexer = Lexer::new("puts(1);\n\"one\ntwo");
+        let tokens = std::iter::from_fn(|| Some(lexer.next_token()))
+            .take_while(|token| *token != Token::Eof)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens.last(),
+            Some(&Token::Illegal(
+                "unterminated string literal starting at line 2".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        let input = "# Adds one\nlet a = 1; # trailing\n\n  # first\r\n#second\n\"#\"#";
+
+        let mut lexer = Lexer::new(input);
+        let expected = [
+            Token::Let,
+            Token::Ident("a".to_string()),
+            Token::Assign,
+            Token::Int("1".to_string()),
+            Token::Semicolon,
+            Token::String("#".to_string()),
+            Token::Eof,
+        ];
+        for token in expected {
+            assert_eq!(lexer.next_token(), token);
+            assert!(lexer.leading_comments().is_empty());
+        }
+
+        let mut lexer = Lexer::new(input).with_trivia(true);
+        assert_eq!(lexer.next_token(), Token::Let);
+        assert_eq!(lexer.leading_comments(), ["# Adds one"]);
+        assert!(!lexer.first_comment_trails());
+        for _ in 0..4 {
+            lexer.next_token();
+            assert!(lexer.leading_comments().is_empty());
+        }
+        assert_eq!(lexer.next_token(), Token::String("#".to_string()));
+        assert_eq!(
+            lexer.leading_comments(),
+            ["# trailing", "# first", "#second"]
+        );
+        assert!(lexer.first_comment_trails());
+        assert_eq!(lexer.next_token(), Token::Eof);
+        assert_eq!(lexer.leading_comments(), ["#"]);
+        assert!(lexer.first_comment_trails());
+    }
+
+    #[test]
+    fn test_token_display_matches_source() {
+        let sources = [
+            "foo",
+            "42",
+            "1.5",
+            "2e-3",
+            r#""a \n b""#,
+            r#"f"x = ${x}\$""#,
+            "'a'",
+            r"'\n'",
+            r"'\''",
+            r"'\\'",
+            "=",
+            "+",
+            "-",
+            "!",
+            "*",
+            "/",
+            "//",
+            "<",
+            ">",
+            "<=",
+            ">=",
+            "==",
+            "!=",
+            "&&",
+            "||",
+            "??",
+            "?[",
+            "?.",
+            "%",
+            "...",
+            ".",
+            ",",
+            ";",
+            "(",
+            ")",
+            "{",
+            "}",
+            "[",
+            "]",
+            ":",
+        ];
+        let keywords = KEYWORDS.iter().map(|(keyword, _)| *keyword);
+
+        for source in sources.into_iter().chain(keywords) {
+            let mut lexer = Lexer::new(source);
+            let token = lexer.next_token();
+            assert_eq!(token.to_string(), source);
+            assert_eq!(lexer.next_token(), Token::Eof, "source: {source}");
+        }
+    }
+
+    #[test]
+    fn test_token_positions() {
+        let input = "let x = 5;\n  x + 'é'\n\nlen(\"a b\")";
+
+        let expected = vec![
+            (Token::Let, 1, 1),
+            (Token::Ident(String::from("x")), 1, 5),
+            (Token::Assign, 1, 7),
+            (Token::Int(String::from("5")), 1, 9),
+            (Token::Semicolon, 1, 10),
+            (Token::Ident(String::from("x")), 2, 3),
+            (Token::Plus, 2, 5),
+            (Token::Char('é'), 2, 7),
+            (Token::Ident(String::from("len")), 4, 1),
+            (Token::LParen, 4, 4),
+            (Token::String(String::from("a b")), 4, 5),
+            (Token::RParen, 4, 10),
+        ];
+
+        let mut lexer = Lexer::new(input);
+
+        for (token, line, column) in expected {
+            assert_eq!(lexer.next_token(), token);
+            assert_eq!(lexer.token_position(), Position { line, column });
+        }
+    }
+
+    #[test]
+    fn test_interpolated_strings() {
+        let mut lexer = Lexer::new(r#"f"1+1=${1+1}" f"\$5 \\ ${ {"}": '}'}["}"] }!" f"" f"a"#);
+        assert_eq!(
+            lexer.next_token(),
+            Token::InterpolatedString(vec![
+                InterpolationPart::Text("1+1=".to_string()),
+                InterpolationPart::Code("1+1".to_string(), Position { line: 1, column: 9 }),
+            ])
+        );
+        assert_eq!(
+            lexer.next_token(),
+            Token::InterpolatedString(vec![
+                InterpolationPart::Text("$5 \\ ".to_string()),
+                InterpolationPart::Code(
+                    r#" {"}": '}'}["}"] "#.to_string(),
+                    Position {
+                        line: 1,
+                        column: 26
+                    }
+                ),
+                InterpolationPart::Text("!".to_string()),
+            ])
+        );
+        assert_eq!(lexer.next_token(), Token::InterpolatedString(vec![]));
+        assert_eq!(
+            lexer.next_token(),
+            Token::Illegal(
+                "unterminated interpolated string literal starting at line 1".to_string()
+            )
+        );
+        assert_eq!(lexer.next_token(), Token::Eof);
+
+        let mut lexer = Lexer::new("f\"${1 + \n");
+        assert_eq!(
+            lexer.next_token(),
+            Token::Illegal(
+                "unterminated interpolated string literal starting at line 1".to_string()
+            )
+        );
+
+        // `f` alone is still an identifier
+        let mut lexer = Lexer::new("f(\"a\")");
+        assert_eq!(lexer.next_token(), Token::Ident("f".to_string()));
+    }
+
+    #[test]
+    fn test_spread() {
+        let mut lexer = Lexer::new("f(...a, 1.5) . ..");
+        let expected = vec![
+            Token::Ident("f".to_string()),
+            Token::LParen,
+            Token::Spread,
+            Token::Ident("a".to_string()),
+            Token::Comma,
+            Token::Float("1.5".to_string()),
+            Token::RParen,
+            Token::Dot,
+            Token::Dot,
+            Token::Dot,
+            Token::Eof,
+        ];
+        for token in expected {
+            assert_eq!(lexer.next_token(), token);
+        }
+    }
+
+    #[test]
+    fn test_floor_division() {
+        let mut lexer = Lexer::new("5 // 2 / 1");
+        let expected = vec![
+            Token::Int("5".to_string()),
+            Token::DoubleSlash,
+            Token::Int("2".to_string()),
+            Token::Slash,
+            Token::Int("1".to_string()),
+            Token::Eof,
+        ];
+        for token in expected {
+            assert_eq!(lexer.next_token(), token);
+        }
+    }
+
+    #[test]
+    fn test_keywords() {
+        for (keyword, token) in KEYWORDS {
+            assert_eq!(lookup_keyword(keyword).as_ref(), Some(token));
+            assert_eq!(token.to_string(), *keyword);
+            assert_eq!(Lexer::new(keyword).next_token(), *token);
+        }
+
+        for ident in ["lets", "Fn", "iff", "elsewhere", "f", "_"] {
+            assert_eq!(lookup_keyword(ident), None);
+            assert_eq!(
+                Lexer::new(ident).next_token(),
+                Token::Ident(ident.to_string())
+            );
+        }
+    }
 }
@@ -1,5 +1,7 @@
 use std::fmt::Display;
 
+use crate::lexer::Position;
+
 #[allow(dead_code)]
 #[derive(Debug, PartialEq, Clone)] // I should find a way of avoiding this thanks to lifetimes, but
                                    // not for now (the issue is with the parser...)
@@ -10,7 +12,10 @@ pub enum Token {
     // Identifiers + literals
     Ident(String), // add, foobar, x, y, ...
     Int(String),
+    Float(String),
     String(String),
+    InterpolatedString(Vec<InterpolationPart>), // f"1 + 1 = ${1 + 1}"
+    Char(char),
 
     // Operators
     Assign,
@@ -19,6 +24,7 @@ pub enum Token {
     Bang,
     Asterisk,
     Slash,
+    DoubleSlash, // //
     LT,
     GT,
     LTE,
@@ -27,7 +33,12 @@ pub enum Token {
     NotEqual,
     And,
     Or,
+    NullCoalesce,
+    OptionalLSquare, // ?[
+    OptionalCall,    // ?.
     Modulo,
+    Spread, // ...
+    Dot,    // .
 
     // Delimiters
     Comma,
@@ -52,18 +63,64 @@ pub enum Token {
     While,
     Break,
     Continue,
+    Enum,
+    Try,
+    Catch,
+    Throw,
+}
+
+/// Every keyword with its token. Adding a keyword only needs a new entry here, and a
+/// variant of `Token` to go with it.
+pub const KEYWORDS: &[(&str, Token)] = &[
+    ("fn", Token::Function),
+    ("let", Token::Let),
+    ("true", Token::True),
+    ("false", Token::False),
+    ("if", Token::If),
+    ("else", Token::Else),
+    ("return", Token::Return),
+    ("while", Token::While),
+    ("break", Token::Break),
+    ("continue", Token::Continue),
+    ("enum", Token::Enum),
+    ("try", Token::Try),
+    ("catch", Token::Catch),
+    ("throw", Token::Throw),
+];
+
+/// The token of a keyword, `None` if `ident` is not one.
+pub fn lookup_keyword(ident: &str) -> Option<Token> {
+    KEYWORDS
+        .iter()
+        .find(|(keyword, _)| *keyword == ident)
+        .map(|(_, token)| token.clone())
 }
 
+/// Tokens display as the source they are read from, so that error messages quote the
+/// code as it was written. `Illegal` and `Eof` are the exceptions, having no spelling.
 impl Display for Token {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Token::Ident(x) | Token::Int(x) | Token::String(x) => write!(f, "{x}"),
+            Token::Ident(x) | Token::Int(x) | Token::Float(x) => write!(f, "{x}"),
+            Token::String(x) => write!(f, "\"{x}\""),
+            Token::InterpolatedString(parts) => {
+                write!(f, "f\"")?;
+                for part in parts {
+                    match part {
+                        InterpolationPart::Text(text) => write!(f, "{}", escape_interpolated(text)),
+                        InterpolationPart::Code(code, _) => write!(f, "${{{code}}}"),
+                    }?;
+                }
+                write!(f, "\"")
+            }
+            Token::Char(c) => write!(f, "'{}'", escape_char(*c)),
             Token::Illegal(s) => write!(f, "Illegal: {s}"),
             Token::Eof => write!(f, "Eof"),
             Token::Assign => write!(f, "="),
             Token::Bang => write!(f, "!"),
             Token::Minus => write!(f, "-"),
             Token::Slash => write!(f, "/"),
+            Token::DoubleSlash => write!(f, "//"),
             Token::Asterisk => write!(f, "*"),
             Token::Equal => write!(f, "=="),
             Token::NotEqual => write!(f, "!="),
@@ -74,6 +131,9 @@ impl Display for Token {
             Token::Plus => write!(f, "+"),
             Token::And => write!(f, "&&"),
             Token::Or => write!(f, "||"),
+            Token::NullCoalesce => write!(f, "??"),
+            Token::OptionalLSquare => write!(f, "?["),
+            Token::OptionalCall => write!(f, "?."),
             Token::Comma => write!(f, ","),
             Token::Semicolon => write!(f, ";"),
             Token::LParen => write!(f, "("),
@@ -93,7 +153,38 @@ impl Display for Token {
             Token::While => write!(f, "while"),
             Token::Break => write!(f, "break"),
             Token::Continue => write!(f, "continue"),
+            Token::Enum => write!(f, "enum"),
+            Token::Try => write!(f, "try"),
+            Token::Catch => write!(f, "catch"),
+            Token::Throw => write!(f, "throw"),
             Token::Modulo => write!(f, "%"),
+            Token::Spread => write!(f, "..."),
+            Token::Dot => write!(f, "."),
         }
     }
 }
+
+/// A piece of an interpolated string: either text, with its escapes already resolved, or
+/// the source of an `${...}` expression along with the position of its first character.
+#[derive(Debug, PartialEq, Clone)]
+pub enum InterpolationPart {
+    Text(String),
+    Code(String, Position),
+}
+
+/// Escapes a char literal, so that it reads back as the same char.
+fn escape_char(c: char) -> String {
+    match c {
+        '\n' => "\\n".to_string(),
+        '\t' => "\\t".to_string(),
+        '\r' => "\\r".to_string(),
+        '\0' => "\\0".to_string(),
+        '\\' | '\'' => format!("\\{c}"),
+        _ => c.to_string(),
+    }
+}
+
+/// Escapes the text of an interpolated string, so that it reads back as the same text.
+pub fn escape_interpolated(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('$', "\\$")
+}
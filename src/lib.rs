@@ -1,9 +1,13 @@
 pub mod compiler;
+pub mod diagnostics;
+pub mod embed;
+pub mod ffi;
 pub mod formatter;
 pub mod interpreter;
 pub mod lexer;
 pub mod object;
 pub mod parser;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod repl;
 pub mod vm;
 
@@ -1,14 +1,23 @@
 use enum_stringify::EnumStringify;
 use num_derive::{FromPrimitive, ToPrimitive};
 use num_traits::FromPrimitive;
-use std::cmp::Ordering;
+use std::{
+    cmp::Ordering,
+    fmt::{self, Debug, Display, Formatter},
+    fs,
+    io::{self, BufRead, Write},
+    rc::Rc,
+    sync::OnceLock,
+};
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
 
-use crate::object::{Object, NULL};
+use crate::object::{json, random::Random, Object, PartialApplication, NULL};
 
 #[derive(Debug, PartialEq, Clone, FromPrimitive, ToPrimitive, EnumIter, EnumStringify)]
-#[enum_stringify(case = "lower")]
+// `snake` rather than `lower`, so that `TO_UPPER` is exposed as `to_upper` and not `to upper`
+#[enum_stringify(case = "snake")]
+#[allow(non_camel_case_types)]
 pub enum BuiltinFunction {
     LEN,
     FIRST,
@@ -16,6 +25,77 @@ pub enum BuiltinFunction {
     REST,
     PUSH,
     PUTS,
+    TO_UPPER,
+    TO_LOWER,
+    TRIM,
+    REPEAT,
+    ASSERT,
+    ASSERT_EQ,
+    EXIT,
+    READ_LINE,
+    READ_FILE,
+    TO_JSON,
+    FROM_JSON,
+    IS_INT,
+    IS_STRING,
+    IS_ARRAY,
+    IS_HASH,
+    IS_FN,
+    IS_NULL,
+    PARTIAL,
+    STR,
+    APPLY,
+    DBG,
+    REVERSE,
+    SLICE,
+    HASH_CODE,
+    STARTS_WITH,
+    ENDS_WITH,
+    REPLACE,
+    FLOOR,
+    CEIL,
+    ROUND,
+    SQRT,
+    FLATTEN,
+    FLATTEN_DEEP,
+    ZIP,
+    ENUMERATE,
+    CHR,
+    ORD,
+    CLOCK,
+    RAND,
+    RAND_INT,
+    SEED,
+}
+
+/// Number of arguments a builtin accepts.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Arity {
+    Exact(usize),
+    /// Variadic builtins, with their minimum number of arguments
+    AtLeast(usize),
+    /// Builtins with optional arguments, with their minimum and maximum number of arguments
+    Between(usize, usize),
+}
+
+impl Arity {
+    pub fn accepts(self, count: usize) -> bool {
+        match self {
+            Arity::Exact(n) => count == n,
+            Arity::AtLeast(n) => count >= n,
+            Arity::Between(min, max) => (min..=max).contains(&count),
+        }
+    }
+}
+
+impl Display for Arity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Arity::Exact(n) => write!(f, "{n}"),
+            Arity::AtLeast(n) => write!(f, "at least {n}"),
+            Arity::Between(min, max) => write!(f, "{min} to {max}"),
+        }
+    }
 }
 
 #[allow(clippy::needless_pass_by_value)] // false positive
@@ -33,13 +113,237 @@ impl BuiltinFunction {
     }
 
     pub fn call(&self, args: Vec<Object>) -> Object {
+        self.call_with_output(args, &mut io::stdout())
+    }
+
+    /// Calls the builtin, `puts` writing to `output` instead of the standard output.
+    pub fn call_with_output(&self, args: Vec<Object>, output: &mut dyn Write) -> Object {
+        self.call_with_io(args, &mut io::stdin().lock(), output)
+    }
+
+    /// Calls the builtin, `read_line` reading from `input` and `puts` writing to `output`
+    /// instead of the standard input and output.
+    pub fn call_with_io(
+        &self,
+        args: Vec<Object>,
+        input: &mut dyn BufRead,
+        output: &mut dyn Write,
+    ) -> Object {
         match self {
             BuiltinFunction::LEN => Self::call_len(args),
             BuiltinFunction::FIRST => Self::call_first(args),
             BuiltinFunction::LAST => Self::call_last(args),
             BuiltinFunction::REST => Self::call_rest(args),
             BuiltinFunction::PUSH => Self::call_push(args),
-            BuiltinFunction::PUTS => Self::call_puts(args),
+            BuiltinFunction::PUTS => Self::call_puts(args, output),
+            BuiltinFunction::TO_UPPER => {
+                Self::call_string_transform(args, "to_upper", str::to_uppercase)
+            }
+            BuiltinFunction::TO_LOWER => {
+                Self::call_string_transform(args, "to_lower", str::to_lowercase)
+            }
+            BuiltinFunction::TRIM => {
+                Self::call_string_transform(args, "trim", |s| s.trim().to_string())
+            }
+            BuiltinFunction::ASSERT => Self::call_assert(args),
+            BuiltinFunction::ASSERT_EQ => Self::call_assert_eq(args),
+            BuiltinFunction::REPEAT => Object::ERROR(
+                "`repeat` calls a function, it must be executed by the VM or the evaluator"
+                    .to_string(),
+            ),
+            BuiltinFunction::READ_LINE => Self::call_read_line(&args, input),
+            BuiltinFunction::READ_FILE => Self::call_read_file(args),
+            BuiltinFunction::TO_JSON => Self::call_to_json(args),
+            BuiltinFunction::FROM_JSON => Self::call_from_json(args),
+            BuiltinFunction::IS_INT => {
+                Self::call_type_predicate(&args, |o| matches!(o, Object::INTEGER(_)))
+            }
+            BuiltinFunction::IS_STRING => {
+                Self::call_type_predicate(&args, |o| matches!(o, Object::STRING(_)))
+            }
+            BuiltinFunction::IS_ARRAY => {
+                Self::call_type_predicate(&args, |o| matches!(o, Object::ARRAY(_)))
+            }
+            BuiltinFunction::IS_HASH => {
+                Self::call_type_predicate(&args, |o| matches!(o, Object::HASHMAP(_)))
+            }
+            BuiltinFunction::IS_FN => Self::call_type_predicate(&args, |o| {
+                matches!(
+                    o,
+                    Object::FUNCTION(_)
+                        | Object::COMPILEDFUNCTION(_)
+                        | Object::CLOSURE(_)
+                        | Object::BUILTIN(_)
+                        | Object::HOSTFUNCTION(_)
+                        | Object::PARTIAL(_)
+                )
+            }),
+            BuiltinFunction::IS_NULL => {
+                Self::call_type_predicate(&args, |o| matches!(o, Object::NULL))
+            }
+            BuiltinFunction::EXIT => {
+                Object::ERROR("`exit` is only supported in compiler mode".to_string())
+            }
+            BuiltinFunction::PARTIAL => Self::call_partial(args),
+            BuiltinFunction::STR => Self::call_str(&args),
+            BuiltinFunction::APPLY => Object::ERROR(
+                "`apply` calls a function, it must be executed by the VM or the evaluator"
+                    .to_string(),
+            ),
+            BuiltinFunction::DBG => Self::call_dbg(args, output),
+            BuiltinFunction::REVERSE => Self::call_reverse(args),
+            BuiltinFunction::SLICE => Self::call_slice(&args),
+            BuiltinFunction::HASH_CODE => Self::call_hash_code(&args),
+            BuiltinFunction::STARTS_WITH => {
+                Self::call_string_predicate(&args, "starts_with", |s, affix| s.starts_with(affix))
+            }
+            BuiltinFunction::ENDS_WITH => {
+                Self::call_string_predicate(&args, "ends_with", |s, affix| s.ends_with(affix))
+            }
+            BuiltinFunction::REPLACE => Self::call_replace(&args),
+            BuiltinFunction::FLOOR => Self::call_rounding(&args, "floor", f64::floor),
+            BuiltinFunction::CEIL => Self::call_rounding(&args, "ceil", f64::ceil),
+            BuiltinFunction::ROUND => Self::call_rounding(&args, "round", f64::round),
+            BuiltinFunction::SQRT => Self::call_sqrt(&args),
+            BuiltinFunction::FLATTEN => Self::call_flatten(&args, "flatten", false),
+            BuiltinFunction::FLATTEN_DEEP => Self::call_flatten(&args, "flatten_deep", true),
+            BuiltinFunction::ZIP => Self::call_zip(&args),
+            BuiltinFunction::ENUMERATE => Self::call_enumerate(&args),
+            BuiltinFunction::CHR => Self::call_chr(&args),
+            BuiltinFunction::ORD => Self::call_ord(&args),
+            BuiltinFunction::CLOCK => Self::call_clock(&args, default_clock().as_deref()),
+            BuiltinFunction::RAND | BuiltinFunction::RAND_INT | BuiltinFunction::SEED => {
+                Object::ERROR(format!(
+                    "`{self}` uses the random generator of the VM or the evaluator, it must be executed by them"
+                ))
+            }
+        }
+    }
+
+    /// How many arguments the builtin accepts, so that calls can be checked at compile time.
+    /// It must agree with the checks made when the builtin is called.
+    pub fn arity(&self) -> Arity {
+        match self {
+            BuiltinFunction::PUTS => Arity::AtLeast(0),
+            BuiltinFunction::PARTIAL => Arity::AtLeast(1),
+            BuiltinFunction::READ_LINE | BuiltinFunction::CLOCK | BuiltinFunction::RAND => {
+                Arity::Exact(0)
+            }
+            BuiltinFunction::SLICE => Arity::Between(2, 3),
+            BuiltinFunction::REPLACE => Arity::Exact(3),
+            BuiltinFunction::PUSH
+            | BuiltinFunction::REPEAT
+            | BuiltinFunction::ASSERT_EQ
+            | BuiltinFunction::APPLY
+            | BuiltinFunction::STARTS_WITH
+            | BuiltinFunction::ENDS_WITH
+            | BuiltinFunction::ZIP
+            | BuiltinFunction::RAND_INT => Arity::Exact(2),
+            BuiltinFunction::LEN
+            | BuiltinFunction::FIRST
+            | BuiltinFunction::LAST
+            | BuiltinFunction::REST
+            | BuiltinFunction::TO_UPPER
+            | BuiltinFunction::TO_LOWER
+            | BuiltinFunction::TRIM
+            | BuiltinFunction::ASSERT
+            | BuiltinFunction::EXIT
+            | BuiltinFunction::READ_FILE
+            | BuiltinFunction::TO_JSON
+            | BuiltinFunction::FROM_JSON
+            | BuiltinFunction::IS_INT
+            | BuiltinFunction::IS_STRING
+            | BuiltinFunction::IS_ARRAY
+            | BuiltinFunction::IS_HASH
+            | BuiltinFunction::IS_FN
+            | BuiltinFunction::IS_NULL
+            | BuiltinFunction::STR
+            | BuiltinFunction::DBG
+            | BuiltinFunction::REVERSE
+            | BuiltinFunction::HASH_CODE
+            | BuiltinFunction::FLOOR
+            | BuiltinFunction::CEIL
+            | BuiltinFunction::ROUND
+            | BuiltinFunction::SQRT
+            | BuiltinFunction::FLATTEN
+            | BuiltinFunction::FLATTEN_DEEP
+            | BuiltinFunction::ENUMERATE
+            | BuiltinFunction::CHR
+            | BuiltinFunction::ORD
+            | BuiltinFunction::SEED => Arity::Exact(1),
+        }
+    }
+
+    /// Whether an error returned by this builtin must stop the program, instead of being
+    /// handed back to it as a value.
+    pub fn halts_on_error(&self) -> bool {
+        matches!(self, BuiltinFunction::ASSERT | BuiltinFunction::ASSERT_EQ)
+    }
+
+    /// Checks the argument of `exit(code)` and returns the code.
+    pub fn exit_code(args: &[Object]) -> Result<i32, Object> {
+        if let Some(error) = Self::handle_number_of_arguments(args.len(), 1) {
+            return Err(error);
+        }
+        match &args[0] {
+            Object::INTEGER(code) => i32::try_from(*code).map_err(|_| {
+                Object::ERROR(format!("argument to `exit` is out of range, got {code}"))
+            }),
+            other => Err(Object::ERROR(format!(
+                "argument to `exit` not supported, must be INTEGER, got {}",
+                other.get_type()
+            ))),
+        }
+    }
+
+    /// Checks the arguments of `repeat(n, f)` and returns `n`.
+    ///
+    /// Whether `f` can be called is left to the caller, as functions are represented
+    /// differently by the VM and by the evaluator.
+    pub fn repeat_count(args: &[Object]) -> Result<usize, Object> {
+        if let Some(error) = Self::handle_number_of_arguments(args.len(), 2) {
+            return Err(error);
+        }
+        match &args[0] {
+            Object::INTEGER(n) => usize::try_from(*n).map_err(|_| {
+                Object::ERROR(format!(
+                    "first argument to `repeat` must not be negative, got {n}"
+                ))
+            }),
+            other => Err(Object::ERROR(format!(
+                "first argument to `repeat` not supported, must be INTEGER, got {}",
+                other.get_type()
+            ))),
+        }
+    }
+
+    /// Checks the arguments of `apply(f, args)` and returns `f` and the arguments to call it
+    /// with. Whether they match the parameters of `f` is checked by the call itself.
+    pub fn apply_arguments(mut args: Vec<Object>) -> Result<(Object, Vec<Object>), Object> {
+        if let Some(error) = Self::handle_number_of_arguments(args.len(), 2) {
+            return Err(error);
+        }
+        let arguments = match args.pop() {
+            Some(Object::ARRAY(ref arguments)) => Rc::clone(arguments),
+            other => {
+                return Err(Object::ERROR(format!(
+                    "second argument to `apply` not supported, must be ARRAY, got {}",
+                    other.unwrap_or(NULL).get_type()
+                )))
+            }
+        };
+        match args.pop() {
+            Some(
+                function @ (Object::FUNCTION(_)
+                | Object::CLOSURE(_)
+                | Object::BUILTIN(_)
+                | Object::HOSTFUNCTION(_)
+                | Object::PARTIAL(_)),
+            ) => Ok((function, arguments.to_vec())),
+            other => Err(Object::ERROR(format!(
+                "first argument to `apply` not supported, must be a function, got {}",
+                other.unwrap_or(NULL).get_type()
+            ))),
         }
     }
 
@@ -47,6 +351,7 @@ impl BuiltinFunction {
         Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| match &args[0] {
             Object::STRING(s) => Object::INTEGER(s.len() as i64),
             Object::ARRAY(a) => Object::INTEGER(a.len() as i64),
+            Object::HASHMAP(h) => Object::INTEGER(h.len() as i64),
             _ => Object::ERROR(format!(
                 "argument to `len` not supported, got {}",
                 args[0].get_type()
@@ -93,8 +398,8 @@ impl BuiltinFunction {
                 let length = a.len();
 
                 match length.cmp(&1) {
-                    Ordering::Greater => Object::ARRAY(a[1..length].to_vec()),
-                    Ordering::Equal => Object::ARRAY(vec![]),
+                    Ordering::Greater => Object::ARRAY(a[1..length].to_vec().into()),
+                    Ordering::Equal => Object::ARRAY(Rc::default()),
                     Ordering::Less => NULL,
                 }
             }
@@ -106,26 +411,512 @@ impl BuiltinFunction {
     }
 
     fn call_push(args: Vec<Object>) -> Object {
-        Self::handle_number_of_arguments(args.len(), 2).unwrap_or_else(|| match &args[0] {
-            Object::ARRAY(a) => {
-                let mut new_array = a.clone();
-                new_array.push(args[1].clone());
-                Object::ARRAY(new_array)
+        Self::handle_number_of_arguments(args.len(), 2).unwrap_or_else(|| {
+            match <[Object; 2]>::try_from(args) {
+                Ok([mut array @ Object::ARRAY(_), element]) => {
+                    if let Object::ARRAY(a) = &mut array {
+                        // The elements are only copied if the array is shared
+                        Rc::make_mut(a).push(element);
+                    }
+                    array
+                }
+                Ok([other, _]) => Object::ERROR(format!(
+                    "argument to `push` not supported, must be ARRAY, got {}",
+                    other.get_type()
+                )),
+                Err(_) => unreachable!("the number of arguments has been checked"),
             }
+        })
+    }
+
+    /// Strings are reversed by character, so that multibyte characters are kept intact.
+    fn call_reverse(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| match &args[0] {
+            Object::ARRAY(a) => Object::ARRAY(a.iter().rev().cloned().collect::<Vec<_>>().into()),
+            Object::STRING(s) => Object::STRING(s.chars().rev().collect()),
             _ => Object::ERROR(format!(
-                "argument to `push` not supported, must be ARRAY, got {}",
+                "argument to `reverse` not supported, must be ARRAY or STRING, got {}",
                 args[0].get_type()
             )),
         })
     }
 
-    fn call_puts(args: Vec<Object>) -> Object {
+    /// Replaces the arrays held by an array by their elements, only one level deep unless
+    /// `deep` is set. The other elements are kept as they are.
+    fn call_flatten(args: &[Object], name: &str, deep: bool) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| match &args[0] {
+            Object::ARRAY(elements) => {
+                let mut flattened = Vec::with_capacity(elements.len());
+                // The arrays being gone through, the innermost last
+                let mut pending = vec![elements.iter()];
+                while let Some(iter) = pending.last_mut() {
+                    match iter.next() {
+                        Some(Object::ARRAY(inner)) if deep || pending.len() == 1 => {
+                            pending.push(inner.iter());
+                        }
+                        Some(element) => flattened.push(element.clone()),
+                        None => {
+                            pending.pop();
+                        }
+                    }
+                }
+                Object::ARRAY(flattened.into())
+            }
+            _ => Object::ERROR(format!(
+                "argument to `{name}` not supported, must be ARRAY, got {}",
+                args[0].get_type()
+            )),
+        })
+    }
+
+    /// Pairs the elements of two arrays, as `[a0, b0]`, `[a1, b1]`... stopping at the end of
+    /// the shorter one.
+    fn call_zip(args: &[Object]) -> Object {
+        Self::handle_number_of_arguments(args.len(), 2).unwrap_or_else(|| match args {
+            [Object::ARRAY(a), Object::ARRAY(b)] => Object::ARRAY(
+                a.iter()
+                    .zip(b.iter())
+                    .map(|(x, y)| Object::ARRAY(vec![x.clone(), y.clone()].into()))
+                    .collect::<Vec<_>>()
+                    .into(),
+            ),
+            [Object::ARRAY(_), other] => Object::ERROR(format!(
+                "second argument to `zip` not supported, must be ARRAY, got {}",
+                other.get_type()
+            )),
+            [other, _] => Object::ERROR(format!(
+                "first argument to `zip` not supported, must be ARRAY, got {}",
+                other.get_type()
+            )),
+            _ => unreachable!("the number of arguments has been checked"),
+        })
+    }
+
+    /// Pairs the elements of an array with their index, as `[0, a0]`, `[1, a1]`...
+    fn call_enumerate(args: &[Object]) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| match &args[0] {
+            Object::ARRAY(a) => Object::ARRAY(
+                (0..)
+                    .zip(a.iter())
+                    .map(|(i, x)| Object::ARRAY(vec![Object::INTEGER(i), x.clone()].into()))
+                    .collect::<Vec<_>>()
+                    .into(),
+            ),
+            _ => Object::ERROR(format!(
+                "argument to `enumerate` not supported, must be ARRAY, got {}",
+                args[0].get_type()
+            )),
+        })
+    }
+
+    /// The one-character string of a code point.
+    fn call_chr(args: &[Object]) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| match &args[0] {
+            Object::INTEGER(n) => match u32::try_from(*n).ok().and_then(char::from_u32) {
+                Some(c) => Object::STRING(c.to_string()),
+                None => Object::ERROR(format!(
+                    "argument to `chr` is not a valid code point, got {n}"
+                )),
+            },
+            _ => Object::ERROR(format!(
+                "argument to `chr` not supported, must be INTEGER, got {}",
+                args[0].get_type()
+            )),
+        })
+    }
+
+    /// The code point of a char, or of the first character of a string.
+    fn call_ord(args: &[Object]) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| {
+            let c = match &args[0] {
+                Object::CHAR(c) => Some(*c),
+                Object::STRING(s) => s.chars().next(),
+                _ => {
+                    return Object::ERROR(format!(
+                        "argument to `ord` not supported, must be STRING or CHAR, got {}",
+                        args[0].get_type()
+                    ))
+                }
+            };
+            match c {
+                Some(c) => Object::INTEGER(i64::from(u32::from(c))),
+                None => Object::ERROR("argument to `ord` must not be an empty string".to_string()),
+            }
+        })
+    }
+
+    /// Calls `rand`, `rand_int` or `seed`, which draw from or reset `random`. Other builtins
+    /// result in an error.
+    pub fn call_random(&self, args: &[Object], random: &mut Random) -> Object {
+        match self {
+            BuiltinFunction::RAND => Self::handle_number_of_arguments(args.len(), 0)
+                .unwrap_or_else(|| Object::FLOAT(random.next_float())),
+            BuiltinFunction::RAND_INT => {
+                Self::handle_number_of_arguments(args.len(), 2).unwrap_or_else(|| {
+                    match args {
+                        [Object::INTEGER(low), Object::INTEGER(high)] if low < high => {
+                            Object::INTEGER(random.next_int(*low, *high))
+                        }
+                        [Object::INTEGER(low), Object::INTEGER(high)] => Object::ERROR(format!(
+                            "first argument to `rand_int` must be less than the second, got {low} and {high}"
+                        )),
+                        [Object::INTEGER(_), high] => Object::ERROR(format!(
+                            "second argument to `rand_int` not supported, must be INTEGER, got {}",
+                            high.get_type()
+                        )),
+                        [low, _] => Object::ERROR(format!(
+                            "first argument to `rand_int` not supported, must be INTEGER, got {}",
+                            low.get_type()
+                        )),
+                        _ => unreachable!("the number of arguments was checked"),
+                    }
+                })
+            }
+            BuiltinFunction::SEED => {
+                Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| match &args[0] {
+                    Object::INTEGER(seed) => {
+                        random.seed(u64::from_ne_bytes(seed.to_ne_bytes()));
+                        NULL
+                    }
+                    _ => Object::ERROR(format!(
+                        "argument to `seed` not supported, must be INTEGER, got {}",
+                        args[0].get_type()
+                    )),
+                })
+            }
+            _ => Object::ERROR(format!("`{self}` does not use the random generator")),
+        }
+    }
+
+    /// `slice(collection, start, end)` returns the elements of an array, or the characters
+    /// of a string, from `start` included to `end` excluded. Bounds are clamped to the
+    /// collection, and `end` defaults to its length.
+    fn call_slice(args: &[Object]) -> Object {
+        let arity = BuiltinFunction::SLICE.arity();
+        if !arity.accepts(args.len()) {
+            return Object::ERROR(format!(
+                "wrong number of arguments. got={}, want={arity}",
+                args.len()
+            ));
+        }
+        let length = match &args[0] {
+            Object::ARRAY(a) => a.len(),
+            Object::STRING(s) => s.chars().count(),
+            other => {
+                return Object::ERROR(format!(
+                    "first argument to `slice` not supported, must be ARRAY or STRING, got {}",
+                    other.get_type()
+                ))
+            }
+        };
+        let mut bounds = [0, length];
+        for (bound, arg) in bounds.iter_mut().zip(&args[1..]) {
+            match arg {
+                Object::INTEGER(i) => {
+                    *bound = usize::try_from(*i).map_or(0, |i| i.min(length));
+                }
+                other => {
+                    return Object::ERROR(format!(
+                        "bounds of `slice` not supported, must be INTEGER, got {}",
+                        other.get_type()
+                    ))
+                }
+            }
+        }
+        let [start, end] = bounds;
+        let end = end.max(start);
+
+        match &args[0] {
+            Object::ARRAY(a) => Object::ARRAY(a[start..end].to_vec().into()),
+            Object::STRING(s) => Object::STRING(s.chars().skip(start).take(end - start).collect()),
+            _ => unreachable!("the collection is checked above"),
+        }
+    }
+
+    fn call_puts(args: Vec<Object>, output: &mut dyn Write) -> Object {
         for arg in args {
-            println!("{arg}");
+            if let Err(err) = writeln!(output, "{arg}") {
+                return Object::ERROR(format!("unable to write to the output: {err}"));
+            }
         }
         NULL
     }
 
+    /// `dbg(x)` prints `x` along with its type and returns it, so that it can be wrapped
+    /// around any expression.
+    fn call_dbg(mut args: Vec<Object>, output: &mut dyn Write) -> Object {
+        if let Some(error) = Self::handle_number_of_arguments(args.len(), 1) {
+            return error;
+        }
+        let arg = args.remove(0);
+        if let Err(err) = writeln!(output, "[dbg] {arg} ({})", arg.get_type()) {
+            return Object::ERROR(format!("unable to write to the output: {err}"));
+        }
+        arg
+    }
+
+    /// `partial(f, args...)` binds the first arguments of `f`. Binding more arguments than a
+    /// user defined function takes is an error, other extra arguments are reported when
+    /// the function is called.
+    fn call_partial(mut args: Vec<Object>) -> Object {
+        if args.is_empty() {
+            return Object::ERROR(format!(
+                "wrong number of arguments. got=0, want={}",
+                BuiltinFunction::PARTIAL.arity()
+            ));
+        }
+        let bound = args.split_off(1);
+        let (function, bound) = match args.remove(0) {
+            // Partial applications are flattened, so that calling them never nests
+            Object::PARTIAL(ref mut partial) => (
+                partial.function.take(),
+                [std::mem::take(&mut partial.args), bound].concat(),
+            ),
+            function @ (Object::FUNCTION(_)
+            | Object::CLOSURE(_)
+            | Object::BUILTIN(_)
+            | Object::HOSTFUNCTION(_)) => (function, bound),
+            other => {
+                return Object::ERROR(format!(
+                    "first argument to `partial` not supported, must be a function, got {}",
+                    other.get_type()
+                ))
+            }
+        };
+
+        let parameters = match &function {
+            Object::FUNCTION(function) => Some(function.parameters.len()),
+            Object::CLOSURE(closure) => Some(closure.function.num_parameters),
+            _ => None,
+        };
+        if let Some(parameters) = parameters.filter(|parameters| bound.len() > *parameters) {
+            return Object::ERROR(format!(
+                "too many arguments for `partial`: the function takes {parameters}, got {}",
+                bound.len()
+            ));
+        }
+
+        Object::PARTIAL(PartialApplication {
+            function: Box::new(function),
+            args: bound,
+        })
+    }
+
+    fn call_assert(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| match &args[0] {
+            Object::NULL | Object::BOOLEAN(false) => {
+                Object::ERROR(format!("assertion failed: got {}", args[0]))
+            }
+            _ => NULL,
+        })
+    }
+
+    fn call_assert_eq(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 2).unwrap_or_else(|| {
+            if args[0] == args[1] {
+                NULL
+            } else {
+                Object::ERROR(format!(
+                    "assertion failed: left: {}, right: {}",
+                    args[0], args[1]
+                ))
+            }
+        })
+    }
+
+    /// The time given by `clock`, in milliseconds.
+    pub fn call_clock(args: &[Object], clock: Option<&Clock>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 0).unwrap_or_else(|| match clock {
+            Some(clock) => Object::INTEGER(clock()),
+            None => Object::ERROR("`clock` is not available on this platform".to_string()),
+        })
+    }
+
+    /// Reads a line from `input`, without its line ending. Returns `null` at the end of the
+    /// input.
+    fn call_read_line(args: &[Object], input: &mut dyn BufRead) -> Object {
+        Self::handle_number_of_arguments(args.len(), 0).unwrap_or_else(|| {
+            let mut line = String::new();
+            match input.read_line(&mut line) {
+                Ok(0) => NULL,
+                Ok(_) => {
+                    let length = line.trim_end_matches(['\n', '\r']).len();
+                    line.truncate(length);
+                    Object::STRING(line)
+                }
+                Err(err) => Object::ERROR(format!("unable to read from the input: {err}")),
+            }
+        })
+    }
+
+    fn call_read_file(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| match &args[0] {
+            Object::STRING(path) => match fs::read_to_string(path) {
+                Ok(contents) => Object::STRING(contents),
+                Err(err) => Object::ERROR(format!("unable to read file {path}: {err}")),
+            },
+            _ => Object::ERROR(format!(
+                "argument to `read_file` not supported, must be STRING, got {}",
+                args[0].get_type()
+            )),
+        })
+    }
+
+    fn call_to_json(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| {
+            match json::encode(&args[0]) {
+                Ok(json) => Object::STRING(json),
+                Err(err) => Object::ERROR(format!("unable to convert to JSON: {err}")),
+            }
+        })
+    }
+
+    fn call_from_json(args: Vec<Object>) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| match &args[0] {
+            Object::STRING(s) => {
+                json::decode(s).unwrap_or_else(|err| Object::ERROR(format!("invalid JSON: {err}")))
+            }
+            _ => Object::ERROR(format!(
+                "argument to `from_json` not supported, must be STRING, got {}",
+                args[0].get_type()
+            )),
+        })
+    }
+
+    /// Applies `transform` to a single STRING argument.
+    ///
+    /// Case mapping uses the Unicode default mappings from the standard library, so it is
+    /// locale-independent: `to_upper("i")` is always `"I"`, whatever the host locale.
+    fn call_string_transform(
+        args: Vec<Object>,
+        name: &str,
+        transform: impl Fn(&str) -> String,
+    ) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| match &args[0] {
+            Object::STRING(s) => Object::STRING(transform(s)),
+            _ => Object::ERROR(format!(
+                "argument to `{name}` not supported, must be STRING, got {}",
+                args[0].get_type()
+            )),
+        })
+    }
+
+    /// Applies `predicate` to two STRING arguments, such as `starts_with(s, prefix)`.
+    fn call_string_predicate(
+        args: &[Object],
+        name: &str,
+        predicate: impl Fn(&str, &str) -> bool,
+    ) -> Object {
+        if let Some(error) = Self::handle_number_of_arguments(args.len(), 2) {
+            return error;
+        }
+        match Self::string_arguments(args, name) {
+            Ok(strings) => Object::BOOLEAN(predicate(strings[0], strings[1])),
+            Err(error) => error,
+        }
+    }
+
+    /// `replace(s, from, to)` replaces every occurrence of `from`, which must not be empty.
+    fn call_replace(args: &[Object]) -> Object {
+        if let Some(error) = Self::handle_number_of_arguments(args.len(), 3) {
+            return error;
+        }
+        match Self::string_arguments(args, "replace") {
+            Ok(strings) if strings[1].is_empty() => Object::ERROR(
+                "second argument to `replace` must not be an empty string".to_string(),
+            ),
+            Ok(strings) => Object::STRING(strings[0].replace(strings[1], strings[2])),
+            Err(error) => error,
+        }
+    }
+
+    /// The arguments of a builtin taking only strings, or an error naming the first one
+    /// that is not.
+    fn string_arguments<'a>(args: &'a [Object], name: &str) -> Result<Vec<&'a str>, Object> {
+        const ORDINALS: [&str; 3] = ["first", "second", "third"];
+        args.iter()
+            .zip(ORDINALS)
+            .map(|(arg, ordinal)| match arg {
+                Object::STRING(s) => Ok(s.as_str()),
+                other => Err(Object::ERROR(format!(
+                    "{ordinal} argument to `{name}` not supported, must be STRING, got {}",
+                    other.get_type()
+                ))),
+            })
+            .collect()
+    }
+
+    /// Rounds a number to an integer with `round`. Integers are returned as they are, and
+    /// floats that do not fit in an integer, such as `NaN`, are an error.
+    #[allow(clippy::cast_possible_truncation)] // The range is checked first
+    fn call_rounding(args: &[Object], name: &str, round: fn(f64) -> f64) -> Object {
+        // 2^63, the first float beyond the integers
+        const LIMIT: f64 = 9_223_372_036_854_775_808.0;
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| match &args[0] {
+            Object::INTEGER(i) => Object::INTEGER(*i),
+            Object::FLOAT(x) => {
+                let rounded = round(*x);
+                if (-LIMIT..LIMIT).contains(&rounded) {
+                    Object::INTEGER(rounded as i64)
+                } else {
+                    Object::ERROR(format!(
+                        "argument to `{name}` is out of range, got {}",
+                        args[0]
+                    ))
+                }
+            }
+            other => Object::ERROR(format!(
+                "argument to `{name}` not supported, must be INTEGER or FLOAT, got {}",
+                other.get_type()
+            )),
+        })
+    }
+
+    fn call_sqrt(args: &[Object]) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| {
+            match args[0].as_float() {
+                Some(x) if x < 0.0 => Object::ERROR(format!(
+                    "argument to `sqrt` must not be negative, got {}",
+                    args[0]
+                )),
+                Some(x) => Object::FLOAT(x.sqrt()),
+                None => Object::ERROR(format!(
+                    "argument to `sqrt` not supported, must be INTEGER or FLOAT, got {}",
+                    args[0].get_type()
+                )),
+            }
+        })
+    }
+
+    /// Converts any value to a string: strings are returned as they are, chars become
+    /// one-character strings, and other values are displayed as `puts` would.
+    fn call_str(args: &[Object]) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| match &args[0] {
+            Object::STRING(s) => Object::STRING(s.clone()),
+            Object::CHAR(c) => Object::STRING(c.to_string()),
+            other => Object::STRING(other.to_string()),
+        })
+    }
+
+    fn call_hash_code(args: &[Object]) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1).unwrap_or_else(|| {
+            args[0].hash_code().map_or_else(
+                || {
+                    Object::ERROR(format!(
+                        "argument to `hash_code` not supported, must be a hashable value, got {}",
+                        args[0].get_type()
+                    ))
+                },
+                Object::INTEGER,
+            )
+        })
+    }
+
+    fn call_type_predicate(args: &[Object], predicate: impl Fn(&Object) -> bool) -> Object {
+        Self::handle_number_of_arguments(args.len(), 1)
+            .unwrap_or_else(|| Object::BOOLEAN(predicate(&args[0])))
+    }
+
     fn handle_number_of_arguments(got: usize, expected: usize) -> Option<Object> {
         if got != expected {
             return Some(Object::ERROR(format!(
@@ -135,3 +926,130 @@ impl BuiltinFunction {
         None
     }
 }
+
+/// Source of the time returned by `clock`, in milliseconds. It must never go backwards,
+/// but where it starts does not matter.
+pub type Clock = dyn Fn() -> i64;
+
+/// The default `Clock`: the milliseconds elapsed since it was first read. It relies on
+/// `Instant`, so changes of the system time do not affect it.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn monotonic_clock() -> i64 {
+    static START: OnceLock<std::time::Instant> = OnceLock::new();
+    let start = START.get_or_init(std::time::Instant::now);
+    i64::try_from(start.elapsed().as_millis()).unwrap_or(i64::MAX)
+}
+
+/// The `Clock` used unless the host provides one, `monotonic_clock`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn default_clock() -> Option<Box<Clock>> {
+    Some(Box::new(monotonic_clock))
+}
+
+/// `Instant` panics on wasm32, so there is no default `Clock` there and `clock` returns an
+/// error until the host provides one.
+#[cfg(target_arch = "wasm32")]
+pub fn default_clock() -> Option<Box<Clock>> {
+    None
+}
+
+/// Signature of the functions registered in a `BuiltinRegistry`.
+pub type HostFn = dyn Fn(&[Object]) -> Result<Object, String>;
+
+/// A Rust function provided by the host program, callable from Monkey like a builtin.
+///
+/// Returning `Err` is the same as returning an `ERROR` object, as native builtins do.
+#[derive(Clone)]
+pub struct HostFunction {
+    name: String,
+    function: Rc<HostFn>,
+}
+
+impl HostFunction {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn call(&self, args: &[Object]) -> Object {
+        (self.function)(args).unwrap_or_else(Object::ERROR)
+    }
+}
+
+impl PartialEq for HostFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && Rc::ptr_eq(&self.function, &other.function)
+    }
+}
+
+impl Debug for HostFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HostFunction")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Display for HostFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+/// Host functions exposed to Monkey code next to the native builtins.
+///
+/// Host functions get the builtin indices following the native ones, in registration
+/// order, so the same registry must be given to the `Compiler` and to the `VM`.
+#[derive(Clone, Default, Debug)]
+pub struct BuiltinRegistry {
+    functions: Vec<HostFunction>,
+}
+
+impl BuiltinRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `function` under `name`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `name` is already used by a builtin, or if there are no builtin indices
+    /// left (they are encoded on a single byte).
+    pub fn register(
+        &mut self,
+        name: &str,
+        function: impl Fn(&[Object]) -> Result<Object, String> + 'static,
+    ) -> Result<(), String> {
+        if BuiltinFunction::try_from(name).is_ok() || self.names().any(|n| n == name) {
+            return Err(format!("builtin {name} is already defined"));
+        }
+        if Self::native_count() + self.functions.len() > usize::from(u8::MAX) {
+            return Err(format!("unable to register {name}, too many builtins"));
+        }
+        self.functions.push(HostFunction {
+            name: name.to_string(),
+            function: Rc::new(function),
+        });
+        Ok(())
+    }
+
+    /// Names of the registered functions, in the order of their indices.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.functions.iter().map(HostFunction::name)
+    }
+
+    /// Index of the first host function, after the native builtins.
+    pub fn native_count() -> usize {
+        BuiltinFunction::iter().count()
+    }
+
+    /// Looks up a native builtin or a host function by its index.
+    pub fn get_builtin_by_id(&self, id: usize) -> Option<Object> {
+        BuiltinFunction::get_builtin_by_id(id).or_else(|| {
+            self.functions
+                .get(id.checked_sub(Self::native_count())?)
+                .cloned()
+                .map(Object::HOSTFUNCTION)
+        })
+    }
+}
@@ -42,4 +42,17 @@ impl Environment {
     pub fn set(&mut self, name: String, value: Object) {
         self.store.insert(name, value);
     }
+
+    /// Changes the value of an existing variable, in the environment that defines it.
+    /// Returns `false` if there is no such variable.
+    pub fn assign(&mut self, name: &str, value: Object) -> bool {
+        if let Some(current) = self.store.get_mut(name) {
+            *current = value;
+            return true;
+        }
+        match &self.outer {
+            Some(outer) => outer.borrow_mut().assign(name, value),
+            None => false,
+        }
+    }
 }
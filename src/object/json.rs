@@ -0,0 +1,430 @@
+//! Conversion between Monkey objects and JSON text, used by `to_json` and `from_json`.
+
+use std::{collections::HashMap, fmt::Write, iter::Peekable, str::CharIndices};
+
+use crate::object::Object;
+
+/// Nesting limit of `decode`, so that a deeply nested input cannot overflow the stack.
+const MAX_DEPTH: usize = 256;
+
+/// Serializes `object` to compact JSON.
+///
+/// Hashmap keys must be strings (or chars), and are sorted so that the output does not
+/// depend on the iteration order of the hashmap.
+///
+/// # Errors
+///
+/// Returns an error for functions, errors, hashmaps with non-string keys and infinite or
+/// `NaN` floats, which have no JSON representation.
+pub fn encode(object: &Object) -> Result<String, String> {
+    let mut json = String::new();
+    encode_into(object, &mut json)?;
+    Ok(json)
+}
+
+fn encode_into(object: &Object, json: &mut String) -> Result<(), String> {
+    match object {
+        Object::INTEGER(i) => json.push_str(&i.to_string()),
+        Object::FLOAT(x) if x.is_finite() => {
+            // The debug format keeps the decimal point of whole floats, so that they are
+            // decoded back as floats, and never loses precision
+            let _ = write!(json, "{x:?}");
+        }
+        Object::FLOAT(_) => return Err(format!("{object} cannot be converted to JSON")),
+        Object::BOOLEAN(b) => json.push_str(&b.to_string()),
+        Object::NULL => json.push_str("null"),
+        Object::STRING(s) => encode_string(s, json),
+        Object::CHAR(c) => encode_string(&c.to_string(), json),
+        Object::ARRAY(elements) => {
+            json.push('[');
+            for (i, element) in elements.iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                encode_into(element, json)?;
+            }
+            json.push(']');
+        }
+        Object::HASHMAP(map) => {
+            let mut pairs = map
+                .iter()
+                .map(|(key, value)| match key {
+                    Object::STRING(s) => Ok((s.clone(), value)),
+                    Object::CHAR(c) => Ok((c.to_string(), value)),
+                    _ => Err(format!(
+                        "JSON object keys must be STRING, got {}",
+                        key.get_type()
+                    )),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            pairs.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            json.push('{');
+            for (i, (key, value)) in pairs.into_iter().enumerate() {
+                if i > 0 {
+                    json.push(',');
+                }
+                encode_string(&key, json);
+                json.push(':');
+                encode_into(value, json)?;
+            }
+            json.push('}');
+        }
+        _ => return Err(format!("{} cannot be converted to JSON", object.get_type())),
+    }
+    Ok(())
+}
+
+fn encode_string(s: &str, json: &mut String) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => {
+                // Writing to a String cannot fail
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+/// Parses JSON text: objects become hashmaps and arrays become arrays.
+///
+/// Numbers with a fraction or an exponent become floats, the others integers.
+///
+/// # Errors
+///
+/// Returns a description of the first problem found, with its byte offset.
+pub fn decode(json: &str) -> Result<Object, String> {
+    let mut decoder = Decoder {
+        chars: json.char_indices().peekable(),
+        len: json.len(),
+        depth: 0,
+    };
+    let object = decoder.parse_value()?;
+    decoder.skip_whitespace();
+    match decoder.chars.peek() {
+        None => Ok(object),
+        Some(&(offset, c)) => Err(format!("unexpected character '{c}' at {offset}")),
+    }
+}
+
+struct Decoder<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    len: usize,
+    depth: usize,
+}
+
+impl Decoder<'_> {
+    fn parse_value(&mut self) -> Result<Object, String> {
+        self.skip_whitespace();
+        match self.peek()? {
+            '{' => self.nested(Self::parse_object),
+            '[' => self.nested(Self::parse_array),
+            '"' => self.parse_string().map(Object::STRING),
+            '-' | '0'..='9' => self.parse_number(),
+            't' => self.parse_keyword("true", Object::BOOLEAN(true)),
+            'f' => self.parse_keyword("false", Object::BOOLEAN(false)),
+            'n' => self.parse_keyword("null", Object::NULL),
+            c => Err(format!("unexpected character '{c}' at {}", self.offset())),
+        }
+    }
+
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Object, String>) -> Result<Object, String> {
+        if self.depth >= MAX_DEPTH {
+            return Err(format!(
+                "nesting deeper than {MAX_DEPTH} levels at {}",
+                self.offset()
+            ));
+        }
+        self.depth += 1;
+        let object = parse(self);
+        self.depth -= 1;
+        object
+    }
+
+    fn parse_object(&mut self) -> Result<Object, String> {
+        self.expect('{')?;
+        let mut map = HashMap::new();
+        self.skip_whitespace();
+        if self.peek()? == '}' {
+            self.chars.next();
+            return Ok(Object::HASHMAP(map.into()));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            let value = self.parse_value()?;
+            map.insert(Object::STRING(key), value);
+
+            self.skip_whitespace();
+            match self.next()? {
+                (_, ',') => {}
+                (_, '}') => return Ok(Object::HASHMAP(map.into())),
+                (offset, c) => return Err(format!("expected ',' or '}}', got '{c}' at {offset}")),
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Object, String> {
+        self.expect('[')?;
+        let mut elements = Vec::new();
+        self.skip_whitespace();
+        if self.peek()? == ']' {
+            self.chars.next();
+            return Ok(Object::ARRAY(elements.into()));
+        }
+        loop {
+            elements.push(self.parse_value()?);
+
+            self.skip_whitespace();
+            match self.next()? {
+                (_, ',') => {}
+                (_, ']') => return Ok(Object::ARRAY(elements.into())),
+                (offset, c) => return Err(format!("expected ',' or ']', got '{c}' at {offset}")),
+            }
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            match self.next()? {
+                (_, '"') => return Ok(s),
+                (_, '\\') => s.push(self.parse_escape()?),
+                (offset, c) if c.is_control() => {
+                    return Err(format!("unescaped control character in string at {offset}"))
+                }
+                (_, c) => s.push(c),
+            }
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<char, String> {
+        let (offset, c) = self.next()?;
+        let escaped = match c {
+            '"' => '"',
+            '\\' => '\\',
+            '/' => '/',
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'u' => {
+                let high = self.parse_hex4()?;
+                let code = if (0xD800..0xDC00).contains(&high) {
+                    // Characters outside the BMP are written as a surrogate pair
+                    self.expect('\\')?;
+                    self.expect('u')?;
+                    let low = self.parse_hex4()?;
+                    if !(0xDC00..0xE000).contains(&low) {
+                        return Err(format!("invalid surrogate pair at {offset}"));
+                    }
+                    0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+                } else {
+                    high
+                };
+                char::from_u32(code).ok_or(format!("invalid unicode escape at {offset}"))?
+            }
+            c => return Err(format!("invalid escape '\\{c}' at {offset}")),
+        };
+        Ok(escaped)
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let (offset, c) = self.next()?;
+            let digit = c
+                .to_digit(16)
+                .ok_or(format!("invalid hexadecimal digit '{c}' at {offset}"))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn parse_number(&mut self) -> Result<Object, String> {
+        let start = self.offset();
+        let mut number = String::new();
+        let mut is_float = false;
+        if self.peek()? == '-' {
+            number.push('-');
+            self.chars.next();
+        }
+        while let Some(&(_, c)) = self.chars.peek() {
+            match c {
+                '0'..='9' => {}
+                '.' | 'e' | 'E' => is_float = true,
+                '+' | '-' if number.ends_with(['e', 'E']) => {}
+                _ => break,
+            }
+            number.push(c);
+            self.chars.next();
+        }
+        if is_float {
+            number
+                .parse()
+                .map(Object::FLOAT)
+                .map_err(|_| format!("invalid number {number} at {start}"))
+        } else {
+            number
+                .parse()
+                .map(Object::INTEGER)
+                .map_err(|_| format!("invalid integer {number} at {start}"))
+        }
+    }
+
+    fn parse_keyword(&mut self, keyword: &str, object: Object) -> Result<Object, String> {
+        let start = self.offset();
+        for expected in keyword.chars() {
+            match self.chars.next() {
+                Some((_, c)) if c == expected => {}
+                _ => return Err(format!("invalid literal at {start}, expected {keyword}")),
+            }
+        }
+        Ok(object)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.next()? {
+            (_, c) if c == expected => Ok(()),
+            (offset, c) => Err(format!("expected '{expected}', got '{c}' at {offset}")),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .chars
+            .next_if(|(_, c)| matches!(c, ' ' | '\t' | '\n' | '\r'))
+            .is_some()
+        {}
+    }
+
+    fn next(&mut self) -> Result<(usize, char), String> {
+        self.chars
+            .next()
+            .ok_or("unexpected end of input".to_string())
+    }
+
+    fn peek(&mut self) -> Result<char, String> {
+        self.chars
+            .peek()
+            .map(|&(_, c)| c)
+            .ok_or("unexpected end of input".to_string())
+    }
+
+    fn offset(&mut self) -> usize {
+        self.chars.peek().map_or(self.len, |&(offset, _)| offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let inputs = vec![
+            r#"{"a":[1,-2,{"b":null}],"c":{"d":{"e":[[],{}]}},"f":true}"#,
+            r#"[1,"two",[false,[3,[4]]],{"five":"\"quoted\"\n\\"}]"#,
+            r#""héllo 🐒""#,
+            "-9223372036854775808",
+            "[1.5,-0.25,2.0,1e300,-1e-7]",
+            "[]",
+        ];
+
+        for input in inputs {
+            let decoded = decode(input).unwrap();
+            assert_eq!(encode(&decoded), Ok(input.to_string()), "input: {input}");
+            assert_eq!(decode(&encode(&decoded).unwrap()), Ok(decoded));
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        let decoded = decode(" { \"key\" : [ 1 , \"\\u00e9\\ud83d\\ude00\\t\" ] } ").unwrap();
+        let expected = Object::HASHMAP(
+            HashMap::from([(
+                Object::STRING("key".to_string()),
+                Object::ARRAY(vec![Object::INTEGER(1), Object::STRING("é😀\t".to_string())].into()),
+            )])
+            .into(),
+        );
+        assert_eq!(decoded, expected);
+    }
+
+    #[test]
+    fn test_encode() {
+        let map = Object::HASHMAP(
+            HashMap::from([
+                (Object::STRING("b".to_string()), Object::CHAR('x')),
+                (
+                    Object::STRING("a".to_string()),
+                    Object::STRING("\u{1}".to_string()),
+                ),
+            ])
+            .into(),
+        );
+        assert_eq!(encode(&map), Ok(r#"{"a":"\u0001","b":"x"}"#.to_string()));
+
+        let map = Object::HASHMAP(HashMap::from([(Object::INTEGER(1), Object::NULL)]).into());
+        assert_eq!(
+            encode(&map),
+            Err("JSON object keys must be STRING, got INTEGER".to_string())
+        );
+        assert_eq!(
+            encode(&Object::ARRAY(
+                vec![Object::ERROR("oops".to_string())].into()
+            )),
+            Err("ERROR cannot be converted to JSON".to_string())
+        );
+        assert_eq!(
+            encode(&Object::FLOAT(f64::NAN)),
+            Err("NaN cannot be converted to JSON".to_string())
+        );
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let tests = vec![
+            ("", "unexpected end of input"),
+            ("[1, 2", "unexpected end of input"),
+            ("[1 2]", "expected ',' or ']', got '2' at 3"),
+            ("{\"a\" 1}", "expected ':', got '1' at 5"),
+            ("{1: 2}", "expected '\"', got '1' at 1"),
+            ("[1,]", "unexpected character ']' at 3"),
+            ("tru", "invalid literal at 0, expected true"),
+            ("1.5.2", "invalid number 1.5.2 at 0"),
+            ("[1e]", "invalid number 1e at 1"),
+            (
+                "99999999999999999999",
+                "invalid integer 99999999999999999999 at 0",
+            ),
+            ("\"\\x\"", "invalid escape '\\x' at 2"),
+            ("\"\\ud83d\"", "expected '\\', got '\"' at 7"),
+            ("[1] [2]", "unexpected character '[' at 4"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(decode(input), Err(expected.to_string()), "input: {input}");
+        }
+
+        let deep = "[".repeat(MAX_DEPTH + 1);
+        assert_eq!(
+            decode(&deep),
+            Err(format!(
+                "nesting deeper than {MAX_DEPTH} levels at {MAX_DEPTH}"
+            ))
+        );
+    }
+}
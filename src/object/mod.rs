@@ -1,59 +1,187 @@
 pub mod builtins;
 pub mod enviroment;
+pub mod json;
+pub mod random;
 pub mod test_utils;
 
 use std::{
     cell::RefCell,
-    collections::HashMap,
+    cmp::Ordering,
+    collections::{hash_map::DefaultHasher, HashMap},
     fmt::{self, Display, Formatter},
-    hash::Hash,
+    hash::{Hash, Hasher},
     rc::Rc,
 };
 
 use crate::parser::ast::{BlockStatement, Identifier};
 
-use crate::object::{builtins::BuiltinFunction, enviroment::Environment};
+use crate::object::{
+    builtins::{BuiltinFunction, HostFunction},
+    enviroment::Environment,
+};
 
 pub const TRUE: Object = Object::BOOLEAN(true);
 pub const FALSE: Object = Object::BOOLEAN(false);
 pub const NULL: Object = Object::NULL;
 
-#[derive(Debug, PartialEq, Clone)]
+/// Significant digits shown when displaying a float. A `f64` only holds 15 to 17 of them,
+/// so the last ones are mostly representation errors, as in `0.1 + 0.2`.
+pub const FLOAT_DISPLAY_PRECISION: usize = 15;
+
+/// Arrays and hashmaps nested deeper than this are displayed as `[...]` and `{...}`, so
+/// that displaying a deeply nested value cannot overflow the stack.
+pub const MAX_DISPLAY_DEPTH: usize = 1000;
+
+/// Formats a float with at most `significant_digits` significant digits (between 1 and 17).
+///
+/// This is how floats are displayed, with `FLOAT_DISPLAY_PRECISION` digits:
+///
+/// * whole numbers keep a decimal point, `1.0` and not `1` which would read as an integer,
+/// * otherwise the shortest representation is used, without trailing zeros,
+/// * magnitudes from `1e16` and below `1e-5` use scientific notation, such as `2.5e-7`,
+/// * the special values are written `NaN`, `Inf` and `-Inf`.
+///
+/// # Examples
+///
+/// ```
+/// use chimpanzee::object::format_float;
+///
+/// assert_eq!(format_float(2.0, 15), "2.0");
+/// assert_eq!(format_float(0.1 + 0.2, 15), "0.3");
+/// assert_eq!(format_float(2.0 / 3.0, 3), "0.667");
+/// ```
+pub fn format_float(value: f64, significant_digits: usize) -> String {
+    if value.is_nan() {
+        return String::from("NaN");
+    }
+    if value.is_infinite() {
+        return String::from(if value > 0.0 { "Inf" } else { "-Inf" });
+    }
+
+    let digits = significant_digits.clamp(1, 17);
+    // Rounds to `digits` significant digits, whatever the magnitude
+    let scientific = format!("{:.*e}", digits - 1, value);
+    let rounded = scientific.parse::<f64>().unwrap_or(value);
+    if rounded.is_infinite() {
+        // Rounding up the largest floats overflows, the digits are trimmed by hand instead
+        let (mantissa, exponent) = scientific.split_once('e').unwrap_or((&scientific, "0"));
+        let mantissa = mantissa.trim_end_matches('0').trim_end_matches('.');
+        return format!("{mantissa}e{exponent}");
+    }
+    let magnitude = rounded.abs();
+    if magnitude != 0.0 && !(1e-5..1e16).contains(&magnitude) {
+        format!("{rounded:e}")
+    } else if rounded.fract() == 0.0 {
+        format!("{rounded:.1}")
+    } else {
+        format!("{rounded}")
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum Object {
     INTEGER(i64),
+    FLOAT(f64),
     BOOLEAN(bool),
     STRING(String),
+    CHAR(char),
     RETURN(Box<Object>),
     ERROR(String),
     FUNCTION(Function),
     COMPILEDFUNCTION(CompiledFunction),
     CLOSURE(Closure),
     BUILTIN(BuiltinFunction),
-    ARRAY(Vec<Object>),
-    HASHMAP(HashMap<Object, Object>),
+    HOSTFUNCTION(HostFunction),
+    PARTIAL(PartialApplication),
+    ARRAY(Rc<Vec<Object>>),
+    HASHMAP(Rc<HashMap<Object, Object>>),
     NULL,
 }
 
 impl Display for Object {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Object::INTEGER(i) => write!(f, "{i}"),
-            Object::BOOLEAN(b) => write!(f, "{b}"),
-            Object::STRING(s) => write!(f, "\"{s}\""),
-            Object::RETURN(o) => write!(f, "{o}",),
-            Object::FUNCTION(o) => write!(f, "{o}"),
-            Object::COMPILEDFUNCTION(o) => write!(f, "{o}"),
-            Object::CLOSURE(o) => write!(f, "{o}"),
-            Object::BUILTIN(o) => write!(f, "{o}"),
-            Object::ERROR(s) => write!(f, "ERROR: {s}"),
-            Object::ARRAY(a) => Self::format_array(f, a),
-            Object::HASHMAP(h) => {
-                let mut values: Vec<String> = h.iter().map(|(k, v)| format!("{k}: {v}")).collect();
-                values.sort();
-                write!(f, "{{{}}}", values.join(", "))
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.fmt_nested(f, MAX_DISPLAY_DEPTH)
+    }
+}
+
+/// Drops the elements of arrays and hashmaps iteratively, as the derived drop would recurse
+/// into deeply nested values and could overflow the stack.
+impl Drop for Object {
+    fn drop(&mut self) {
+        let mut pending = Vec::new();
+        take_children(self, &mut pending);
+        while let Some(mut object) = pending.pop() {
+            take_children(&mut object, &mut pending);
+        }
+    }
+}
+
+/// Moves the elements of an array or hashmap owned only by `object` into `pending`.
+fn take_children(object: &mut Object, pending: &mut Vec<Object>) {
+    match object {
+        Object::ARRAY(elements) => {
+            if let Some(elements) = Rc::get_mut(elements) {
+                pending.append(elements);
             }
-            Object::NULL => write!(f, "null"),
         }
+        Object::HASHMAP(map) => {
+            if let Some(map) = Rc::get_mut(map) {
+                for (key, value) in map.drain() {
+                    pending.push(key);
+                    pending.push(value);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Compares the elements of arrays and hashmaps iteratively, as recursing into deeply
+/// nested values could overflow the stack.
+impl PartialEq for Object {
+    fn eq(&self, other: &Self) -> bool {
+        let mut pending = vec![(self, other)];
+        while let Some(pair) = pending.pop() {
+            let equal = match pair {
+                (Object::ARRAY(a), Object::ARRAY(b)) => {
+                    pending.extend(a.iter().zip(b.iter()));
+                    a.len() == b.len()
+                }
+                (Object::HASHMAP(a), Object::HASHMAP(b)) => {
+                    a.len() == b.len()
+                        && a.iter().all(|(key, value)| match b.get(key) {
+                            Some(other) => {
+                                pending.push((value, other));
+                                true
+                            }
+                            None => false,
+                        })
+                }
+                (Object::RETURN(a), Object::RETURN(b)) => {
+                    pending.push((a, b));
+                    true
+                }
+                (Object::INTEGER(a), Object::INTEGER(b)) => a == b,
+                (Object::FLOAT(a), Object::FLOAT(b)) => a == b,
+                (Object::BOOLEAN(a), Object::BOOLEAN(b)) => a == b,
+                (Object::STRING(a), Object::STRING(b)) | (Object::ERROR(a), Object::ERROR(b)) => {
+                    a == b
+                }
+                (Object::CHAR(a), Object::CHAR(b)) => a == b,
+                (Object::FUNCTION(a), Object::FUNCTION(b)) => a == b,
+                (Object::COMPILEDFUNCTION(a), Object::COMPILEDFUNCTION(b)) => a == b,
+                (Object::CLOSURE(a), Object::CLOSURE(b)) => a == b,
+                (Object::BUILTIN(a), Object::BUILTIN(b)) => a == b,
+                (Object::HOSTFUNCTION(a), Object::HOSTFUNCTION(b)) => a == b,
+                (Object::PARTIAL(a), Object::PARTIAL(b)) => a == b,
+                (Object::NULL, Object::NULL) => true,
+                _ => false,
+            };
+            if !equal {
+                return false;
+            }
+        }
+        true
     }
 }
 
@@ -65,6 +193,7 @@ impl Hash for Object {
             Object::INTEGER(i) => i.hash(state),
             Object::BOOLEAN(b) => b.hash(state),
             Object::STRING(s) => s.hash(state),
+            Object::CHAR(c) => c.hash(state),
             _ => "".hash(state),
         }
     }
@@ -74,31 +203,233 @@ impl Object {
     pub fn get_type(&self) -> String {
         match self {
             Object::INTEGER(_) => String::from("INTEGER"),
+            Object::FLOAT(_) => String::from("FLOAT"),
             Object::BOOLEAN(_) => String::from("BOOLEAN"),
             Object::STRING(_) => String::from("STRING"),
+            Object::CHAR(_) => String::from("CHAR"),
             Object::RETURN(_) => String::from("RETURN"),
             Object::ERROR(_) => String::from("ERROR"),
             Object::FUNCTION(_) => String::from("FUNCTION"),
             Object::COMPILEDFUNCTION(_) => String::from("COMPILEDFUNCTION"),
             Object::CLOSURE(_) => String::from("CLOSURE"),
-            Object::BUILTIN(_) => String::from("BUILTIN"),
+            Object::BUILTIN(_) | Object::HOSTFUNCTION(_) => String::from("BUILTIN"),
+            Object::PARTIAL(_) => String::from("PARTIAL"),
             Object::ARRAY(_) => String::from("ARRAY"),
             Object::HASHMAP(_) => String::from("HASHMAP"),
             Object::NULL => String::from("NULL"),
         }
     }
 
-    fn format_array(f: &mut std::fmt::Formatter<'_>, array: &[Object]) -> std::fmt::Result {
-        let values: Vec<String> = array.iter().map(ToString::to_string).collect();
-        write!(f, "[{}]", values.join(", "))
+    /// Displays the object, with the arrays and hashmaps nested deeper than `max_depth`
+    /// elided as `[...]` and `{...}`.
+    pub fn to_string_with_max_depth(&self, max_depth: usize) -> String {
+        struct Nested<'a>(&'a Object, usize);
+
+        impl Display for Nested<'_> {
+            fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+                self.0.fmt_nested(f, self.1)
+            }
+        }
+
+        Nested(self, max_depth).to_string()
+    }
+
+    fn fmt_nested(&self, f: &mut Formatter<'_>, depth: usize) -> fmt::Result {
+        match self {
+            Object::INTEGER(i) => write!(f, "{i}"),
+            Object::FLOAT(x) => write!(f, "{}", format_float(*x, FLOAT_DISPLAY_PRECISION)),
+            Object::BOOLEAN(b) => write!(f, "{b}"),
+            Object::STRING(s) => write!(f, "\"{s}\""),
+            Object::CHAR(c) => write!(f, "'{}'", c.escape_debug()),
+            Object::RETURN(o) => o.fmt_nested(f, depth),
+            Object::FUNCTION(o) => write!(f, "{o}"),
+            Object::COMPILEDFUNCTION(o) => write!(f, "{o}"),
+            Object::CLOSURE(o) => write!(f, "{o}"),
+            Object::BUILTIN(o) => write!(f, "{o}"),
+            Object::HOSTFUNCTION(o) => write!(f, "{o}"),
+            Object::PARTIAL(o) => write!(f, "{o}"),
+            Object::ERROR(s) => write!(f, "ERROR: {s}"),
+            Object::ARRAY(a) if depth == 0 && !a.is_empty() => write!(f, "[...]"),
+            Object::ARRAY(a) => {
+                write!(f, "[")?;
+                for (i, element) in a.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    element.fmt_nested(f, depth - 1)?;
+                }
+                write!(f, "]")
+            }
+            Object::HASHMAP(h) if depth == 0 && !h.is_empty() => write!(f, "{{...}}"),
+            Object::HASHMAP(h) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in Self::sorted_pairs(h).into_iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    key.fmt_nested(f, depth - 1)?;
+                    write!(f, ": ")?;
+                    value.fmt_nested(f, depth - 1)?;
+                }
+                write!(f, "}}")
+            }
+            Object::NULL => write!(f, "null"),
+        }
+    }
+
+    /// Entries of a hashmap sorted by key, the order in which they are displayed.
+    ///
+    /// Hashmaps do not remember the order of insertion, sorting their keys keeps the output
+    /// of programs the same from one run to the next. Keys are sorted by type first
+    /// (booleans, integers, chars and then strings) and by value within a type.
+    pub fn sorted_pairs(map: &HashMap<Object, Object>) -> Vec<(&Object, &Object)> {
+        let mut pairs = map.iter().collect::<Vec<_>>();
+        pairs.sort_by(|(a, _), (b, _)| Self::compare_keys(a, b));
+        pairs
+    }
+
+    fn compare_keys(a: &Object, b: &Object) -> Ordering {
+        let rank = |key: &Object| match key {
+            Object::BOOLEAN(_) => 0,
+            Object::INTEGER(_) => 1,
+            Object::CHAR(_) => 2,
+            Object::STRING(_) => 3,
+            _ => 4,
+        };
+        match (a, b) {
+            (Object::BOOLEAN(a), Object::BOOLEAN(b)) => a.cmp(b),
+            (Object::INTEGER(a), Object::INTEGER(b)) => a.cmp(b),
+            (Object::CHAR(a), Object::CHAR(b)) => a.cmp(b),
+            (Object::STRING(a), Object::STRING(b)) => a.cmp(b),
+            _ => rank(a).cmp(&rank(b)),
+        }
+    }
+
+    /// Shifts a char by `offset` code points, as done by `'a' + 1`.
+    pub fn offset_char(c: char, offset: i64) -> Result<char, String> {
+        i64::from(c as u32)
+            .checked_add(offset)
+            .and_then(|code| u32::try_from(code).ok())
+            .and_then(char::from_u32)
+            .ok_or(format!("Invalid char: {c} shifted by {offset}"))
+    }
+
+    /// Integer division rounding towards negative infinity, as done by `//`: `-7 // 2` is
+    /// `-4`, where `/` gives `-3`. `None` when dividing by zero or on overflow.
+    pub fn floor_div(left: i64, right: i64) -> Option<i64> {
+        let quotient = left.checked_div(right)?;
+        if left % right != 0 && (left < 0) != (right < 0) {
+            Some(quotient - 1)
+        } else {
+            Some(quotient)
+        }
+    }
+
+    /// Rough estimate of the memory used by the object, in bytes.
+    ///
+    /// It counts the object itself plus the data it owns: the bytes of strings, the
+    /// elements of arrays and the entries of hashmaps (recursively), and the instructions
+    /// and free variables of functions. Allocator overhead and spare capacity are ignored,
+    /// and so is the environment of the interpreter's `FUNCTION`.
+    pub fn approx_size(&self) -> usize {
+        // Iterative, as recursing into deeply nested values could overflow the stack
+        let mut size = 0;
+        let mut pending = vec![self];
+        while let Some(object) = pending.pop() {
+            size += std::mem::size_of::<Object>();
+            match object {
+                Object::STRING(s) | Object::ERROR(s) => size += s.len(),
+                Object::RETURN(value) => pending.push(value),
+                Object::COMPILEDFUNCTION(function) => size += function.instructions.len(),
+                Object::CLOSURE(closure) => {
+                    size += closure.function.instructions.len();
+                    pending.extend(&closure.free);
+                }
+                Object::PARTIAL(partial) => {
+                    pending.push(&partial.function);
+                    pending.extend(&partial.args);
+                }
+                Object::ARRAY(elements) => pending.extend(elements.iter()),
+                Object::HASHMAP(map) => {
+                    for (key, value) in map.iter() {
+                        pending.push(key);
+                        pending.push(value);
+                    }
+                }
+                Object::INTEGER(_)
+                | Object::FLOAT(_)
+                | Object::BOOLEAN(_)
+                | Object::CHAR(_)
+                | Object::FUNCTION(_)
+                | Object::BUILTIN(_)
+                | Object::HOSTFUNCTION(_)
+                | Object::NULL => {}
+            }
+        }
+        size
+    }
+
+    /// Moves the value out and leaves `NULL` behind, as fields cannot be moved out of an
+    /// `Object` by a pattern.
+    #[must_use]
+    pub fn take(&mut self) -> Object {
+        std::mem::replace(self, Object::NULL)
+    }
+
+    /// The value of a number as a float, integers being converted when mixed with floats.
+    #[allow(clippy::cast_precision_loss)] // Integers beyond 2^53 are rounded, as expected
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Object::INTEGER(i) => Some(*i as f64),
+            Object::FLOAT(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    /// Text of a value given to `throw`: strings without their quotes, and the message of
+    /// an error, so that throwing an error received by a `catch` raises it again.
+    pub fn error_message(&self) -> String {
+        match self {
+            Object::STRING(s) | Object::ERROR(s) => s.clone(),
+            other => other.to_string(),
+        }
+    }
+
+    /// Orders two values: numbers, integers and floats mixed, strings lexicographically
+    /// and chars by code point. `None` when the values cannot be ordered, because their
+    /// types differ or one of them is NaN.
+    pub fn compare(&self, other: &Object) -> Option<Ordering> {
+        match (self, other) {
+            (Object::INTEGER(left), Object::INTEGER(right)) => Some(left.cmp(right)),
+            (Object::FLOAT(_), Object::FLOAT(_) | Object::INTEGER(_))
+            | (Object::INTEGER(_), Object::FLOAT(_)) => {
+                self.as_float()?.partial_cmp(&other.as_float()?)
+            }
+            (Object::STRING(left), Object::STRING(right)) => Some(left.cmp(right)),
+            (Object::CHAR(left), Object::CHAR(right)) => Some(left.cmp(right)),
+            _ => None,
+        }
     }
 
     pub fn is_hashable(&self) -> bool {
         matches!(
             self,
-            Object::INTEGER(_) | Object::BOOLEAN(_) | Object::STRING(_)
+            Object::INTEGER(_) | Object::BOOLEAN(_) | Object::STRING(_) | Object::CHAR(_)
         )
     }
+
+    /// A hash of the object, `None` if it cannot be a hashmap key.
+    ///
+    /// The hasher uses fixed keys, so the same value has the same hash code from one run
+    /// to the next. Hashmaps do not use it: they hash their keys with random keys.
+    pub fn hash_code(&self) -> Option<i64> {
+        if !self.is_hashable() {
+            return None;
+        }
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        Some(i64::from_ne_bytes(hasher.finish().to_ne_bytes()))
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -124,6 +455,8 @@ pub struct CompiledFunction {
     pub instructions: Vec<u8>,
     pub num_locals: usize,
     pub num_parameters: usize,
+    /// Name of the variable the function was bound to, used in backtraces
+    pub name: Option<String>,
 }
 
 impl Display for CompiledFunction {
@@ -140,6 +473,8 @@ impl Display for CompiledFunction {
 pub struct Closure {
     pub function: CompiledFunction,
     pub free: Vec<Object>,
+    /// Index of `function` in the constant pool, `None` for the main program
+    pub constant_index: Option<usize>,
 }
 
 impl Display for Closure {
@@ -157,9 +492,21 @@ impl Closure {
         Self {
             function,
             free: Vec::new(),
+            constant_index: None,
         }
     }
 
+    /// Identity of closures, used by `==` and `!=`.
+    ///
+    /// Two closures are the same if they come from the same function literal and
+    /// captured the same values, their instructions are never compared. This means that
+    /// two identical literals such as `fn() {} == fn() {}` are different functions.
+    pub fn is_same(&self, other: &Closure) -> bool {
+        self.constant_index.is_some()
+            && self.constant_index == other.constant_index
+            && self.free == other.free
+    }
+
     pub fn add_free_variable(&mut self, variable: Object) {
         self.free.push(variable);
     }
@@ -169,11 +516,68 @@ impl Closure {
     }
 }
 
+/// A function with its first arguments already bound, as returned by `partial`.
+///
+/// Calling it calls `function` with `args` followed by the arguments of the call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartialApplication {
+    pub function: Box<Object>,
+    pub args: Vec<Object>,
+}
+
+impl Display for PartialApplication {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let args = self
+            .args
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        write!(
+            f,
+            "Partial(function={}, args=[{}])",
+            self.function,
+            args.join(", ")
+        )
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_approx_size_primitives() {
+        let base = std::mem::size_of::<Object>();
+        assert_eq!(Object::INTEGER(1).approx_size(), base);
+        assert_eq!(Object::BOOLEAN(true).approx_size(), base);
+        assert_eq!(Object::NULL.approx_size(), base);
+        assert_eq!(Object::STRING(String::new()).approx_size(), base);
+        assert_eq!(Object::STRING("monkey".to_string()).approx_size(), base + 6);
+        assert_eq!(Object::STRING("é".to_string()).approx_size(), base + 2);
+    }
+
+    #[test]
+    fn test_approx_size_grows_with_elements() {
+        let mut elements = vec![];
+        let mut previous = Object::ARRAY(elements.clone().into()).approx_size();
+        for i in 0..10 {
+            elements.push(Object::ARRAY(vec![Object::INTEGER(i); i as usize].into()));
+            let size = Object::ARRAY(elements.clone().into()).approx_size();
+            assert!(size > previous);
+            previous = size;
+        }
+
+        let mut map = HashMap::new();
+        let mut previous = Object::HASHMAP(map.clone().into()).approx_size();
+        for i in 0..10 {
+            map.insert(Object::INTEGER(i), Object::STRING("value".to_string()));
+            let size = Object::HASHMAP(map.clone().into()).approx_size();
+            assert!(size > previous);
+            previous = size;
+        }
+    }
+
     #[test]
     fn test_hashing_objects() {
         let mut map = HashMap::new();
@@ -227,4 +631,134 @@ mod tests {
         assert!(false_1.is_hashable());
         assert!(!return_object.is_hashable());
     }
+
+    #[test]
+    fn test_hash_code() {
+        let pairs = [
+            (Object::INTEGER(1), Object::INTEGER(1)),
+            (
+                Object::STRING("one".to_string()),
+                Object::STRING("one".to_string()),
+            ),
+            (Object::BOOLEAN(true), Object::BOOLEAN(true)),
+            (Object::CHAR('a'), Object::CHAR('a')),
+        ];
+        for (left, right) in pairs {
+            assert!(left.hash_code().is_some(), "{left}");
+            assert_eq!(left.hash_code(), right.hash_code(), "{left}");
+        }
+
+        assert_ne!(
+            Object::INTEGER(1).hash_code(),
+            Object::INTEGER(2).hash_code()
+        );
+        assert_eq!(
+            Object::ARRAY(vec![Object::INTEGER(1)].into()).hash_code(),
+            None
+        );
+        assert_eq!(Object::NULL.hash_code(), None);
+    }
+
+    #[test]
+    fn test_format_float() {
+        let tests = vec![
+            // Whole numbers
+            (1.0, "1.0"),
+            (-3.0, "-3.0"),
+            (0.0, "0.0"),
+            (1e15, "1000000000000000.0"),
+            // Fractional numbers
+            (2.5, "2.5"),
+            (-0.125, "-0.125"),
+            (0.1 + 0.2, "0.3"),
+            (1.0 / 3.0, "0.333333333333333"),
+            (0.000_12, "0.00012"),
+            // Very large and very small numbers
+            (1e16, "1e16"),
+            (-2.5e20, "-2.5e20"),
+            (f64::MAX, "1.79769313486232e308"),
+            (1.5e-7, "1.5e-7"),
+            // Special values
+            (f64::NAN, "NaN"),
+            (f64::INFINITY, "Inf"),
+            (f64::NEG_INFINITY, "-Inf"),
+        ];
+
+        for (value, expected) in tests {
+            assert_eq!(
+                Object::FLOAT(value).to_string(),
+                expected,
+                "value: {value:?}"
+            );
+        }
+
+        assert_eq!(format_float(2.0 / 3.0, 3), "0.667");
+        assert_eq!(format_float(1234.5, 2), "1200.0");
+        assert_eq!(format_float(0.1 + 0.2, 17), "0.30000000000000004");
+        assert_eq!(format_float(9.99, 0), "10.0");
+    }
+
+    #[test]
+    fn test_hashmap_display_is_sorted_by_key() {
+        let input = r#"{"b": 1, 10: 2, 'c': 3, true: 4, 2: 5, "a": [6], false: {"z": 1, "y": 2}}"#;
+        let expected =
+            r#"{false: {"y": 2, "z": 1}, true: 4, 2: 5, 10: 2, 'c': 3, "a": [6], "b": 1}"#;
+        // Every run builds new hashmaps, with a different iteration order
+        for _ in 0..20 {
+            assert_eq!(crate::utils::run_input(input).to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_deeply_nested_values() {
+        let nest = |depth: usize| {
+            let mut value = Object::INTEGER(1);
+            for _ in 0..depth {
+                value = Object::ARRAY(vec![value].into());
+            }
+            value
+        };
+        let depth = 200_000;
+        let (a, b) = (nest(depth), nest(depth));
+
+        let displayed = a.to_string();
+        assert!(displayed.starts_with(&"[".repeat(MAX_DISPLAY_DEPTH)));
+        assert!(displayed.contains("[[...]]"));
+        assert_eq!(a, b);
+        assert_ne!(a, nest(depth - 1));
+        assert_eq!(a.approx_size(), (depth + 1) * std::mem::size_of::<Object>());
+
+        let value = crate::utils::run_input(r#"[1, [2, [3], []], {"a": [4], "b": {}}]"#);
+        assert_eq!(
+            value.to_string_with_max_depth(2),
+            r#"[1, [2, [...], []], {"a": [...], "b": {}}]"#
+        );
+        assert_eq!(value.to_string_with_max_depth(0), "[...]");
+    }
+
+    #[test]
+    fn test_nested_hashmap_display() {
+        let string = |s: &str| Object::STRING(s.to_string());
+        let inner = HashMap::from([
+            (string("name"), string("monkey")),
+            (
+                string("tags"),
+                Object::ARRAY(vec![string("a"), Object::CHAR('b')].into()),
+            ),
+            (Object::INTEGER(1), Object::HASHMAP(HashMap::new().into())),
+        ]);
+        let map = Object::HASHMAP(
+            HashMap::from([
+                (string("inner"), Object::HASHMAP(inner.into())),
+                (Object::BOOLEAN(true), Object::FLOAT(1.5)),
+                (string("empty"), string("")),
+            ])
+            .into(),
+        );
+
+        assert_eq!(
+            map.to_string(),
+            r#"{true: 1.5, "empty": "", "inner": {1: {}, "name": "monkey", "tags": ["a", 'b']}}"#
+        );
+    }
 }
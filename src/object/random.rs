@@ -0,0 +1,87 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+};
+
+/// Pseudo-random numbers for `rand`, `rand_int` and `seed`, using `SplitMix64`.
+///
+/// It is small and fast but not cryptographically secure. Two generators seeded with the
+/// same value produce the same numbers.
+#[derive(Debug, Clone)]
+pub struct Random {
+    state: u64,
+}
+
+impl Default for Random {
+    /// A generator seeded differently on each run.
+    fn default() -> Self {
+        Self::new(RandomState::new().build_hasher().finish())
+    }
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Random { state: seed }
+    }
+
+    /// Restarts the sequence, as if the generator had been created with `seed`.
+    pub fn seed(&mut self, seed: u64) {
+        self.state = seed;
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A float in `[0, 1)`.
+    pub fn next_float(&mut self) -> f64 {
+        // The upper 52 bits become the mantissa of a float in [1, 2)
+        f64::from_bits(0x3FF0_0000_0000_0000 | (self.next_u64() >> 12)) - 1.0
+    }
+
+    /// An integer in `[low, high)`, `low` must be less than `high`.
+    pub fn next_int(&mut self, low: i64, high: i64) -> i64 {
+        let span = u128::from(high.abs_diff(low));
+        // Scaling rather than taking the remainder, which would favor the small offsets
+        let offset = (u128::from(self.next_u64()) * span) >> 64;
+        low.wrapping_add_unsigned(u64::try_from(offset).unwrap_or(u64::MAX))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded_sequences_match() {
+        let mut a = Random::new(42);
+        let mut b = Random::new(42);
+        let first: Vec<u64> = (0..10).map(|_| a.next_u64()).collect();
+        let second: Vec<u64> = (0..10).map(|_| b.next_u64()).collect();
+        assert_eq!(first, second);
+
+        a.seed(42);
+        assert_eq!(a.next_u64(), first[0]);
+        assert_ne!(Random::new(43).next_u64(), first[0]);
+    }
+
+    #[test]
+    fn test_ranges() {
+        let mut random = Random::new(7);
+        for _ in 0..10_000 {
+            let float = random.next_float();
+            assert!((0.0..1.0).contains(&float), "{float}");
+
+            let int = random.next_int(-3, 4);
+            assert!((-3..4).contains(&int), "{int}");
+        }
+
+        let extreme = random.next_int(i64::MIN, i64::MAX);
+        assert!(extreme < i64::MAX);
+        assert_eq!(random.next_int(5, 6), 5);
+    }
+}
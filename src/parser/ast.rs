@@ -1,6 +1,13 @@
 use enum_stringify::EnumStringify;
 
-use crate::{lexer::token::Token, parser::Parser};
+use crate::{
+    lexer::{
+        token::{escape_interpolated, InterpolationPart, Token},
+        Lexer, Position,
+    },
+    object::builtins::BuiltinFunction,
+    parser::Parser,
+};
 use std::fmt::Display;
 
 #[derive(PartialEq, Debug, Clone)]
@@ -8,6 +15,17 @@ pub struct Program {
     pub statements: Vec<Statement>,
 }
 
+impl Program {
+    /// Number of top-level statements, statements nested in blocks are not counted.
+    pub fn len(&self) -> usize {
+        self.statements.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.statements.is_empty()
+    }
+}
+
 impl Display for Program {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut program = String::new();
@@ -25,11 +43,16 @@ pub enum Expression {
     Prefix(PrefixOperator),
     Infix(InfixOperator),
     Conditional(Conditional),
+    TryCatch(TryCatch),
     FunctionLiteral(FunctionLiteral),
     FunctionCall(FunctionCall),
     ArrayLiteral(ArrayLiteral),
     HashMapLiteral(HashMapLiteral),
     IndexExpression(IndexExpression),
+    Block(BlockStatement),
+    InterpolatedString(InterpolatedString),
+    /// `...array`, only parsed as an element of an array literal or as a call argument
+    Spread(Box<Expression>),
 }
 
 impl Display for Expression {
@@ -40,28 +63,60 @@ impl Display for Expression {
             Expression::Prefix(x) => write!(f, "{x}"),
             Expression::Infix(x) => write!(f, "{x}"),
             Expression::Conditional(x) => write!(f, "{x}"),
+            Expression::TryCatch(x) => write!(f, "{x}"),
             Expression::FunctionLiteral(x) => write!(f, "{x}"),
             Expression::FunctionCall(x) => write!(f, "{x}"),
             Expression::ArrayLiteral(x) => write!(f, "{x}"),
             Expression::IndexExpression(x) => write!(f, "{x}"),
             Expression::HashMapLiteral(x) => write!(f, "{x}"),
+            Expression::Block(x) => write!(f, "{{\n{x}}}"),
+            Expression::InterpolatedString(x) => write!(f, "{x}"),
+            Expression::Spread(x) => write!(f, "...{x}"),
         }
     }
 }
 
 impl Expression {
     pub fn parse(parser: &mut Parser, precedence: Precedence) -> Result<Self, String> {
+        // Every nested construct (groups, blocks, literals, prefixes...) goes through here,
+        // so bounding the depth keeps malformed input from overflowing the stack.
+        parser.enter_nesting()?;
+        let expression = Self::parse_nested(parser, precedence);
+        parser.leave_nesting();
+        expression
+    }
+
+    pub fn is_spread(&self) -> bool {
+        matches!(self, Expression::Spread(_))
+    }
+
+    fn parse_nested(parser: &mut Parser, precedence: Precedence) -> Result<Self, String> {
         let mut left_exp = match parser.current_token.clone() {
             Token::Ident(_) => (Identifier::parse(parser)).map(Expression::Identifier),
-            Token::Int(_) | Token::False | Token::True | Token::String(_) => {
-                Primitive::parse(parser).map(Expression::Primitive)
+            Token::Int(_)
+            | Token::Float(_)
+            | Token::False
+            | Token::True
+            | Token::String(_)
+            | Token::Char(_) => Primitive::parse(parser).map(Expression::Primitive),
+            Token::Minus
+                if parser.peek_token == Token::Int(i64::MIN.unsigned_abs().to_string()) =>
+            {
+                // The magnitude of the smallest integer does not fit in an integer on its
+                // own, so its literal can only be read together with the minus
+                parser.next_token();
+                Ok(Expression::Primitive(Primitive::IntegerLiteral(i64::MIN)))
             }
             Token::Bang | Token::Minus => PrefixOperator::parse(parser).map(Expression::Prefix),
             Token::LParen => Self::parse_grouped_expression(parser),
             Token::If => Conditional::parse(parser).map(Expression::Conditional),
+            Token::Try => TryCatch::parse(parser).map(Expression::TryCatch),
             Token::Function => FunctionLiteral::parse(parser).map(Expression::FunctionLiteral),
             Token::LSquare => ArrayLiteral::parse(parser).map(Expression::ArrayLiteral),
-            Token::LSquirly => HashMapLiteral::parse(parser).map(Expression::HashMapLiteral),
+            Token::LSquirly => Self::parse_brace_expression(parser),
+            Token::InterpolatedString(parts) => {
+                InterpolatedString::parse(parser, parts).map(Expression::InterpolatedString)
+            }
 
             _ => Err(format!(
                 "There is no prefix parser for the token {}",
@@ -70,37 +125,19 @@ impl Expression {
         }?;
 
         while !parser.peek_token_is(&Token::Semicolon) && precedence < parser.peek_precedence() {
-            match &parser.peek_token {
-                Token::Plus
-                | Token::Minus
-                | Token::Slash
-                | Token::Asterisk
-                | Token::Equal
-                | Token::NotEqual
-                | Token::LT
-                | Token::GT
-                | Token::LTE
-                | Token::GTE
-                | Token::And
-                | Token::Or
-                | Token::Modulo => {
-                    parser.next_token(); // TODO: Solve this.
-                                         //  This is absolutely awful, I need to peek the next token
-                                         //  only if a infix operator is found, I want to also
-                                         //  avoid a double match
-                    left_exp = Expression::Infix(InfixOperator::parse(parser, left_exp)?);
-                }
-                Token::LParen => {
-                    parser.next_token();
-                    left_exp = Expression::FunctionCall(FunctionCall::parse(parser, left_exp)?);
+            // Every token with a precedence above `Lowest` can continue an expression, the
+            // precedence table alone decides which kind of expression it builds.
+            let peek_precedence = parser.peek_precedence();
+            parser.next_token();
+            left_exp = match peek_precedence {
+                Precedence::Call => {
+                    Expression::FunctionCall(FunctionCall::parse(parser, left_exp)?)
                 }
-                Token::LSquare => {
-                    parser.next_token();
-                    left_exp =
-                        Expression::IndexExpression(IndexExpression::parse(parser, left_exp)?);
+                Precedence::Index => {
+                    Expression::IndexExpression(IndexExpression::parse(parser, left_exp)?)
                 }
-                _ => return Ok(left_exp),
-            }
+                _ => Expression::Infix(InfixOperator::parse(parser, left_exp)?),
+            };
         }
 
         Ok(left_exp)
@@ -116,6 +153,47 @@ impl Expression {
         }
     }
 
+    /// A `{` opens either a hashmap literal or a block expression. `{}` is an empty
+    /// hashmap, and a first entry followed by `:` makes a hashmap; anything else, including
+    /// an assignment like `{ x = 1; }`, is a block.
+    fn parse_brace_expression(parser: &mut Parser) -> Result<Expression, String> {
+        match parser.peek_token {
+            Token::RSquirly => {
+                return HashMapLiteral::parse(parser, None).map(Expression::HashMapLiteral)
+            }
+            Token::Let
+            | Token::Return
+            | Token::Throw
+            | Token::While
+            | Token::Break
+            | Token::Continue
+            | Token::Enum => {
+                return Ok(Expression::Block(BlockStatement::parse(parser)));
+            }
+            _ => {}
+        }
+
+        parser.next_token();
+        if matches!(parser.current_token, Token::Ident(_)) && parser.peek_token_is(&Token::Assign) {
+            let first = parser.parse_statement();
+            let mut block = BlockStatement::parse(parser);
+            block.statements.splice(0..0, first);
+            return Ok(Expression::Block(block));
+        }
+
+        let first = Expression::parse(parser, Precedence::Lowest)?;
+        if parser.peek_token_is(&Token::Colon) {
+            return HashMapLiteral::parse(parser, Some(first)).map(Expression::HashMapLiteral);
+        }
+
+        if parser.peek_token_is(&Token::Semicolon) {
+            parser.next_token();
+        }
+        let mut block = BlockStatement::parse(parser);
+        block.statements.insert(0, Statement::Expression(first));
+        Ok(Expression::Block(block))
+    }
+
     fn parse_expression_list(parser: &mut Parser, end: &Token) -> Result<Vec<Expression>, String> {
         let mut list = Vec::new();
         if parser.peek_token_is(end) {
@@ -124,24 +202,40 @@ impl Expression {
         }
 
         parser.next_token();
-        list.push(Expression::parse(parser, Precedence::Lowest)?);
+        list.push(Self::parse_list_element(parser)?);
         while parser.peek_token_is(&Token::Comma) {
             parser.next_token();
+            // Trailing comma
+            if parser.peek_token_is(end) {
+                break;
+            }
             parser.next_token();
-            list.push(Expression::parse(parser, Precedence::Lowest)?);
+            list.push(Self::parse_list_element(parser)?);
         }
         if !parser.expect_peek(end) {
             return Err(String::new());
         }
         Ok(list)
     }
+
+    /// An element of an array literal or an argument of a call, which can be spread.
+    fn parse_list_element(parser: &mut Parser) -> Result<Expression, String> {
+        if !parser.current_token_is(&Token::Spread) {
+            return Expression::parse(parser, Precedence::Lowest);
+        }
+        parser.next_token();
+        let spread = Expression::parse(parser, Precedence::Lowest)?;
+        Ok(Expression::Spread(Box::new(spread)))
+    }
 }
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum Primitive {
     IntegerLiteral(i64),
+    FloatLiteral(f64),
     BooleanLiteral(bool),
     StringLiteral(String),
+    CharLiteral(char),
 }
 
 impl Primitive {
@@ -151,9 +245,14 @@ impl Primitive {
                 Ok(x) => Ok(Primitive::IntegerLiteral(x)),
                 Err(_) => Err("Error: expected a number, found an incopatible string".to_string()),
             },
+            Token::Float(x) => x
+                .parse::<f64>()
+                .map(Primitive::FloatLiteral)
+                .map_err(|_| format!("Error: invalid float literal {x}")),
             Token::True => Ok(Primitive::BooleanLiteral(true)),
             Token::False => Ok(Primitive::BooleanLiteral(false)),
             Token::String(x) => Ok(Primitive::StringLiteral(x)),
+            Token::Char(c) => Ok(Primitive::CharLiteral(c)),
             _ => Err(format!(
                 "There is no primitive parser for the token {}",
                 parser.current_token
@@ -166,16 +265,28 @@ impl Display for Primitive {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Primitive::IntegerLiteral(x) => write!(f, "{x}"),
+            // The `Debug` format always has a decimal point or an exponent, and reads back
+            // to the same value
+            Primitive::FloatLiteral(x) => write!(f, "{x:?}"),
             Primitive::BooleanLiteral(x) => write!(f, "{x}"),
             Primitive::StringLiteral(x) => write!(f, "\"{x}\""),
+            Primitive::CharLiteral(c) => write!(f, "'{}'", c.escape_debug()),
         }
     }
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(Debug, Clone)]
 pub struct PrefixOperator {
     pub token: Token,
     pub right: Box<Expression>,
+    /// Position of the operator, not taken into account when comparing expressions
+    pub position: Position,
+}
+
+impl PartialEq for PrefixOperator {
+    fn eq(&self, other: &Self) -> bool {
+        self.token == other.token && self.right == other.right
+    }
 }
 
 impl PrefixOperator {
@@ -183,13 +294,17 @@ impl PrefixOperator {
         PrefixOperator {
             token,
             right: Box::new(rigth),
+            position: Position::default(),
         }
     }
     fn parse(parser: &mut Parser) -> Result<Self, String> {
         let token = parser.current_token.clone();
+        let position = parser.current_position;
         parser.next_token();
         let right = Expression::parse(parser, Precedence::Prefix)?;
-        Ok(PrefixOperator::new(token, right))
+        let mut prefix = PrefixOperator::new(token, right);
+        prefix.position = position;
+        Ok(prefix)
     }
 }
 impl Display for PrefixOperator {
@@ -198,11 +313,19 @@ impl Display for PrefixOperator {
     }
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(Debug, Clone)]
 pub struct InfixOperator {
     pub token: Token,
     pub left: Box<Expression>,
     pub right: Box<Expression>,
+    /// Position of the operator, not taken into account when comparing expressions
+    pub position: Position,
+}
+
+impl PartialEq for InfixOperator {
+    fn eq(&self, other: &Self) -> bool {
+        self.token == other.token && self.left == other.left && self.right == other.right
+    }
 }
 
 impl InfixOperator {
@@ -211,15 +334,19 @@ impl InfixOperator {
             token,
             left: Box::new(left),
             right: Box::new(right),
+            position: Position::default(),
         }
     }
 
     fn parse(parser: &mut Parser, left: Expression) -> Result<Self, String> {
         let token = parser.current_token.clone();
+        let position = parser.current_position;
         let precedence = parser.current_precedence();
         parser.next_token();
         let right = Expression::parse(parser, precedence)?;
-        Ok(InfixOperator::new(token, left, right))
+        let mut infix = InfixOperator::new(token, left, right);
+        infix.position = position;
+        Ok(infix)
     }
 }
 
@@ -229,30 +356,58 @@ impl Display for InfixOperator {
     }
 }
 
+/// `if (a) { ... } else if (b) { ... } else { ... }`, whose value is the one of the block
+/// of the first truthy condition, or the one of the `else` block if there is none.
+/// A conditional without an `else` results in `null` when no condition holds.
 #[derive(PartialEq, Debug, Clone)]
 pub struct Conditional {
-    pub condition: Box<Expression>,
-    pub consequence: BlockStatement,
+    /// The conditions with their blocks, in order. There is always at least one.
+    pub branches: Vec<(Expression, BlockStatement)>,
     pub alternative: Option<BlockStatement>,
 }
 
 impl Display for Conditional {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut exp = String::new();
-        exp.push_str(&format!(
-            "if {}{{\n{}}}",
-            self.condition.as_ref(),
-            self.consequence
-        ));
+        for (i, (condition, consequence)) in self.branches.iter().enumerate() {
+            if i > 0 {
+                write!(f, " else ")?;
+            }
+            write!(f, "if {condition}{{\n{consequence}}}")?;
+        }
         if let Some(alternative) = &self.alternative {
-            exp.push_str(&format!(" else {{\n{alternative}}}"));
+            write!(f, " else {{\n{alternative}}}")?;
         }
-        write!(f, "{exp}")
+        Ok(())
     }
 }
 
 impl Conditional {
     fn parse(parser: &mut Parser) -> Result<Self, String> {
+        let mut branches = vec![Self::parse_branch(parser)?];
+        let mut alternative = None;
+
+        while parser.peek_token_is(&Token::Else) {
+            parser.next_token();
+            if parser.peek_token_is(&Token::If) {
+                parser.next_token();
+                branches.push(Self::parse_branch(parser)?);
+                continue;
+            }
+            if !parser.expect_peek(&Token::LSquirly) {
+                return Err(String::new());
+            }
+            alternative = Some(BlockStatement::parse(parser));
+            break;
+        }
+
+        Ok(Conditional {
+            branches,
+            alternative,
+        })
+    }
+
+    /// Parses `(condition) { ... }`, the current token being the `if`.
+    fn parse_branch(parser: &mut Parser) -> Result<(Expression, BlockStatement), String> {
         if !parser.expect_peek(&Token::LParen) {
             return Err(String::new());
         }
@@ -264,27 +419,54 @@ impl Conditional {
         if !parser.expect_peek(&Token::LSquirly) {
             return Err(String::new());
         }
-        let consequence = BlockStatement::parse(parser);
-        let mut alternative = None;
+        Ok((condition, BlockStatement::parse(parser)))
+    }
+}
 
-        if parser.peek_token_is(&Token::Else) {
-            parser.next_token();
-            if !parser.expect_peek(&Token::LSquirly) {
-                return Err(String::new());
-            }
+/// `try { body } catch (error) { handler }`, whose value is the one of the body, or the
+/// one of the handler if the body fails. The handler sees the error as `error`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct TryCatch {
+    pub body: BlockStatement,
+    pub error: Identifier,
+    pub handler: BlockStatement,
+}
 
-            alternative = Some(BlockStatement::parse(parser));
+impl Display for TryCatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "try {{\n{}}} catch ({}) {{\n{}}}",
+            self.body, self.error, self.handler
+        )
+    }
+}
+
+impl TryCatch {
+    fn parse(parser: &mut Parser) -> Result<Self, String> {
+        if !parser.expect_peek(&Token::LSquirly) {
+            return Err(String::new());
         }
+        let body = BlockStatement::parse(parser);
 
-        Ok(Conditional {
-            condition: Box::new(condition),
-            consequence,
-            alternative,
+        if !parser.expect_peek(&Token::Catch) || !parser.expect_peek(&Token::LParen) {
+            return Err(String::new());
+        }
+        let error = parser.parse_identifier().ok_or_else(String::new)?;
+        if !parser.expect_peek(&Token::RParen) || !parser.expect_peek(&Token::LSquirly) {
+            return Err(String::new());
+        }
+        let handler = BlockStatement::parse(parser);
+
+        Ok(TryCatch {
+            body,
+            error,
+            handler,
         })
     }
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Default)]
 pub struct BlockStatement {
     pub statements: Vec<Statement>,
 }
@@ -304,11 +486,15 @@ impl BlockStatement {
         parser.next_token();
         let mut statements: Vec<Statement> = Vec::new();
         while !parser.current_token_is(&Token::RSquirly) && !parser.current_token_is(&Token::Eof) {
+            statements.extend(parser.take_comments());
             if let Some(x) = parser.parse_statement() {
                 statements.push(x);
             }
             parser.next_token();
         }
+        if parser.current_token_is(&Token::RSquirly) {
+            statements.extend(parser.take_comments());
+        }
         BlockStatement { statements }
     }
 }
@@ -358,14 +544,16 @@ impl FunctionLiteral {
 
         parser.next_token();
 
-        let mut identifier = Identifier::new(parser.current_token.clone());
-        identifiers.push(identifier);
+        identifiers.push(Identifier::parse(parser)?);
 
         while parser.peek_token_is(&Token::Comma) {
             parser.next_token();
+            // Trailing comma
+            if parser.peek_token_is(&Token::RParen) {
+                break;
+            }
             parser.next_token();
-            identifier = Identifier::new(parser.current_token.clone());
-            identifiers.push(identifier);
+            identifiers.push(Identifier::parse(parser)?);
         }
 
         if !parser.expect_peek(&Token::RParen) {
@@ -380,6 +568,11 @@ impl FunctionLiteral {
 pub struct FunctionCall {
     pub function: Box<Expression>,
     pub arguments: Vec<Expression>,
+    /// `f?.(x)`, which evaluates to `null` without calling `f` when it is `null`
+    pub optional: bool,
+    /// `receiver.method(x)`, where `function` names a builtin that is called with the
+    /// receiver as its first argument, even if a variable of the same name is in scope
+    pub method: bool,
 }
 
 impl Display for FunctionCall {
@@ -389,17 +582,68 @@ impl Display for FunctionCall {
             .iter()
             .map(ToString::to_string)
             .collect::<Vec<String>>();
-        write!(f, "{}({})", self.function, arguments.join(", "))
+        if let (true, Some((receiver, arguments))) = (self.method, arguments.split_first()) {
+            return write!(f, "{receiver}.{}({})", self.function, arguments.join(", "));
+        }
+        let call = if self.optional { "?.(" } else { "(" };
+        write!(f, "{}{call}{})", self.function, arguments.join(", "))
     }
 }
 
 impl FunctionCall {
     fn parse(parser: &mut Parser, function: Expression) -> Result<Self, String> {
+        if parser.current_token_is(&Token::Dot) {
+            return Self::parse_method_call(parser, function);
+        }
+        let optional = parser.current_token_is(&Token::OptionalCall);
+        if optional && !parser.expect_peek(&Token::LParen) {
+            return Err(String::new());
+        }
         let arguments = Expression::parse_expression_list(parser, &Token::RParen)?;
 
         Ok(FunctionCall {
             function: Box::new(function),
             arguments,
+            optional,
+            method: false,
+        })
+    }
+
+    /// `receiver.method(args)`, the current token being the dot. It is another way of
+    /// writing `method(receiver, args)`, available for the builtins only.
+    fn parse_method_call(parser: &mut Parser, receiver: Expression) -> Result<Self, String> {
+        parser.next_token();
+        let method = Identifier::parse(parser)?;
+        if !BuiltinFunction::get_builtins_names().contains(&method.value) {
+            // Skips the arguments, which would otherwise be parsed as a statement of their own
+            if parser.peek_token_is(&Token::LParen) {
+                let mut depth = 0;
+                loop {
+                    parser.next_token();
+                    match parser.current_token {
+                        Token::LParen => depth += 1,
+                        Token::RParen if depth == 1 => break,
+                        Token::RParen => depth -= 1,
+                        Token::Eof => break,
+                        _ => {}
+                    }
+                }
+            }
+            return Err(format!(
+                "Unknown method {method}, only builtins can be called as methods"
+            ));
+        }
+        if !parser.expect_peek(&Token::LParen) {
+            return Err(String::new());
+        }
+        let mut arguments = vec![receiver];
+        arguments.extend(Expression::parse_expression_list(parser, &Token::RParen)?);
+
+        Ok(FunctionCall {
+            function: Box::new(Expression::Identifier(method)),
+            arguments,
+            optional: false,
+            method: true,
         })
     }
 }
@@ -407,40 +651,121 @@ impl FunctionCall {
 #[derive(PartialEq, Debug, Clone)]
 pub enum Statement {
     Let(LetStatement),
+    Assign(AssignStatement),
     Return(ReturnStatement),
+    Throw(ThrowStatement),
     Expression(Expression),
     While(WhileStatement),
     LoopStatements(LoopStatement),
+    Enum(EnumStatement),
+    /// Only produced when the lexer keeps the comments, see `Lexer::with_trivia`
+    Comment(Comment),
 }
 
 impl Display for Statement {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Statement::Let(statement) => write!(f, "{statement}"),
+            Statement::Assign(statement) => write!(f, "{statement}"),
             Statement::Return(statement) => write!(f, "{statement}"),
+            Statement::Throw(statement) => write!(f, "{statement}"),
             Statement::Expression(expression) => write!(f, "{expression}"),
             Statement::While(statement) => write!(f, "{statement}"),
             Statement::LoopStatements(statement) => write!(f, "{statement}"),
+            Statement::Enum(statement) => write!(f, "{statement}"),
+            Statement::Comment(comment) => write!(f, "{}", comment.text),
         }
     }
 }
 
+/// A `#` comment between two statements. The comments found inside of a statement are
+/// moved right after it.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Comment {
+    /// The text of the comment, starting with its `#`
+    pub text: String,
+    /// Whether the comment follows some code on its line, rather than having a line of its
+    /// own
+    pub trailing: bool,
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub struct LetStatement {
     pub name: Identifier,
-    pub value: Expression,
+    /// `None` for `let x;`, the variable is then `null` until it is assigned
+    pub value: Option<Expression>,
 }
 
 impl Display for LetStatement {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "let {} = {};", self.name, self.value)
+        match &self.value {
+            Some(value) => write!(f, "let {} = {value};", self.name),
+            None => write!(f, "let {};", self.name),
+        }
+    }
+}
+
+/// `enum Color { Red, Green, Blue }`, which defines each variant as a variable holding its
+/// index: `Red` is 0, `Green` is 1 and `Blue` is 2. The name of the enum is not defined.
+#[derive(PartialEq, Debug, Clone)]
+pub struct EnumStatement {
+    pub name: Identifier,
+    pub variants: Vec<Identifier>,
+}
+
+impl Display for EnumStatement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.variants.is_empty() {
+            return write!(f, "enum {} {{}}", self.name);
+        }
+        let variants = self
+            .variants
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<String>>();
+        write!(f, "enum {} {{ {} }}", self.name, variants.join(", "))
+    }
+}
+
+impl EnumStatement {
+    /// The `let` statements the enum stands for, one per variant.
+    pub fn into_let_statements(self) -> Vec<LetStatement> {
+        self.variants
+            .into_iter()
+            .zip(0..)
+            .map(|(name, index)| LetStatement {
+                name,
+                value: Some(Expression::Primitive(Primitive::IntegerLiteral(index))),
+            })
+            .collect()
     }
 }
 
+/// `x = value;`, which changes the value of a variable that is already defined.
 #[derive(PartialEq, Debug, Clone)]
+pub struct AssignStatement {
+    pub name: Identifier,
+    pub value: Expression,
+}
+
+impl Display for AssignStatement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} = {};", self.name, self.value)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Identifier {
     pub token: Token,
     pub value: String,
+    /// Not taken into account when comparing identifiers
+    pub position: Position,
+}
+
+impl PartialEq for Identifier {
+    fn eq(&self, other: &Self) -> bool {
+        self.token == other.token && self.value == other.value
+    }
 }
 
 impl Display for Identifier {
@@ -450,20 +775,12 @@ impl Display for Identifier {
 }
 
 impl Identifier {
-    fn new(token: Token) -> Self {
-        match token.clone() {
-            Token::Ident(s) => Identifier { token, value: s },
-            _ => panic!(
-                "This should be a Token::Ident; if not, the function has not been properly called."
-            ),
-        }
-    }
-
     fn parse(parser: &mut Parser) -> Result<Self, String> {
         match parser.current_token.clone() {
             Token::Ident(s) => Ok(Identifier {
                 token: parser.current_token.clone(),
                 value: s,
+                position: parser.current_position,
             }),
             _ => Err(format!(
                 "Expected an identifier, got {}",
@@ -484,6 +801,18 @@ impl Display for ReturnStatement {
     }
 }
 
+/// `throw value;`, which fails like a runtime error that a `catch` receives as `value`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct ThrowStatement {
+    pub value: Expression,
+}
+
+impl Display for ThrowStatement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "throw {};", &self.value)
+    }
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub struct WhileStatement {
     pub condition: Expression,
@@ -521,20 +850,90 @@ impl ArrayLiteral {
     }
 }
 
+/// A part of an interpolated string, its expressions are converted to strings with `str`.
+#[derive(PartialEq, Debug, Clone)]
+pub enum StringPart {
+    Text(String),
+    Expression(Expression),
+}
+
+/// `f"1 + 1 = ${1 + 1}"`
+#[derive(PartialEq, Debug, Clone)]
+pub struct InterpolatedString {
+    pub parts: Vec<StringPart>,
+}
+
+impl Display for InterpolatedString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "f\"")?;
+        for part in &self.parts {
+            match part {
+                StringPart::Text(text) => write!(f, "{}", escape_interpolated(text))?,
+                StringPart::Expression(expression) => write!(f, "${{{expression}}}")?,
+            }
+        }
+        write!(f, "\"")
+    }
+}
+
+impl InterpolatedString {
+    /// Parses each `${...}` with a parser of its own, which shares the nesting depth of
+    /// `parser` and reports its errors to it.
+    fn parse(parser: &mut Parser, parts: Vec<InterpolationPart>) -> Result<Self, String> {
+        let mut string_parts = Vec::new();
+        for part in parts {
+            let (code, position) = match part {
+                InterpolationPart::Text(text) => {
+                    string_parts.push(StringPart::Text(text));
+                    continue;
+                }
+                InterpolationPart::Code(code, position) => (code, position),
+            };
+            if code.trim().is_empty() {
+                return Err(format!(
+                    "Empty expression in interpolated string at {position}"
+                ));
+            }
+
+            let mut inner = Parser::new(Lexer::new_at(&code, position));
+            inner.depth = parser.depth;
+            let expression = Expression::parse(&mut inner, Precedence::Lowest);
+            parser
+                .errors
+                .add_errors(std::mem::take(&mut inner.errors).errors);
+            let expression = expression?;
+            if !inner.peek_token_is(&Token::Eof) {
+                return Err(format!(
+                    "Unexpected {} after the expression `{expression}` in interpolated string at {position}",
+                    inner.peek_token
+                ));
+            }
+            string_parts.push(StringPart::Expression(expression));
+        }
+        Ok(InterpolatedString {
+            parts: string_parts,
+        })
+    }
+}
+
 #[derive(PartialEq, Debug, Clone)]
 pub struct IndexExpression {
     pub left: Box<Expression>,
     pub index: Box<Expression>,
+    /// `a?[i]`, which evaluates to `null` without indexing `a` when it is `null`
+    pub optional: bool,
 }
 
 impl Display for IndexExpression {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "({}[{}])", self.left, self.index)
+        let bracket = if self.optional { "?[" } else { "[" };
+        write!(f, "({}{bracket}{}])", self.left, self.index)
     }
 }
 
 impl IndexExpression {
     fn parse(parser: &mut Parser, left: Expression) -> Result<Self, String> {
+        let optional = parser.current_token_is(&Token::OptionalLSquare);
         parser.next_token();
         let index = Expression::parse(parser, Precedence::Lowest)?;
         if !parser.expect_peek(&Token::RSquare) {
@@ -543,6 +942,7 @@ impl IndexExpression {
         Ok(IndexExpression {
             left: Box::new(left),
             index: Box::new(index),
+            optional,
         })
     }
 }
@@ -564,11 +964,17 @@ impl Display for HashMapLiteral {
 }
 
 impl HashMapLiteral {
-    fn parse(parser: &mut Parser) -> Result<Self, String> {
+    /// Parses the pairs of a hashmap whose `{` has been consumed. `first_key` is the
+    /// first key when it was already parsed while telling hashmaps and blocks apart.
+    fn parse(parser: &mut Parser, mut first_key: Option<Expression>) -> Result<Self, String> {
         let mut pairs = Vec::new();
-        while !parser.peek_token_is(&Token::RSquirly) {
-            parser.next_token();
-            let key = Expression::parse(parser, Precedence::Lowest)?;
+        while first_key.is_some() || !parser.peek_token_is(&Token::RSquirly) {
+            let key = if let Some(key) = first_key.take() {
+                key
+            } else {
+                parser.next_token();
+                Expression::parse(parser, Precedence::Lowest)?
+            };
             if !parser.expect_peek(&Token::Colon) {
                 return Err(String::new());
             }
@@ -611,29 +1017,43 @@ impl LoopStatement {
     }
 }
 
-#[derive(PartialEq, PartialOrd, Clone, Copy)]
+#[derive(Debug, PartialEq, PartialOrd, Clone, Copy)]
 pub enum Precedence {
     Lowest = 0,
-    Equals = 1,      // ==
-    LessGreater = 2, // > or <
-    Sum = 3,         // +
-    Product = 4,     // *
-    Prefix = 5,      // -X or !X
-    Call = 6,        // myFunction(X)
-    Index = 7,       // array[index]
+    NullCoalesce = 1, // ??
+    Equals = 2,       // ==
+    LessGreater = 3,  // > or <
+    Sum = 4,          // +
+    Product = 5,      // *
+    Prefix = 6,       // -X or !X
+    Call = 7,         // myFunction(X)
+    Index = 8,        // array[index]
+}
+
+/// The precedence table of every infix, call and index operator.
+///
+/// This is the only place the parser looks at to decide how tightly an operator binds, and
+/// whether it can continue an expression at all: tokens mapped to `Lowest` are never
+/// treated as infix operators. Adding a new binary operator only needs a new line here
+/// (and an `InfixOperator` able to parse it).
+pub fn precedence_of(token: &Token) -> Precedence {
+    match token {
+        Token::NullCoalesce => Precedence::NullCoalesce,
+        Token::Equal | Token::NotEqual => Precedence::Equals,
+        Token::LT | Token::GT | Token::LTE | Token::GTE => Precedence::LessGreater,
+        Token::Plus | Token::Minus | Token::Or => Precedence::Sum,
+        Token::Slash | Token::DoubleSlash | Token::Asterisk | Token::And | Token::Modulo => {
+            Precedence::Product
+        }
+        Token::LParen | Token::OptionalCall | Token::Dot => Precedence::Call,
+        Token::LSquare | Token::OptionalLSquare => Precedence::Index,
+        _ => Precedence::Lowest,
+    }
 }
 
 impl From<&Token> for Precedence {
     fn from(value: &Token) -> Self {
-        match value {
-            Token::Equal | Token::NotEqual => Precedence::Equals,
-            Token::LT | Token::GT | Token::LTE | Token::GTE => Precedence::LessGreater,
-            Token::Plus | Token::Minus | Token::Or => Precedence::Sum,
-            Token::Slash | Token::Asterisk | Token::And | Token::Modulo => Precedence::Product,
-            Token::LParen => Precedence::Call,
-            Token::LSquare => Precedence::Index,
-            _ => Precedence::Lowest,
-        }
+        precedence_of(value)
     }
 }
 
@@ -649,16 +1069,19 @@ mod tests {
                     name: Identifier {
                         token: Token::Ident("myVar".to_string()),
                         value: "myVar".to_string(),
+                        position: Position::default(),
                     },
-                    value: Expression::Identifier(Identifier {
+                    value: Some(Expression::Identifier(Identifier {
                         token: Token::Ident("anotherVar".to_string()),
                         value: "anotherVar".to_string(),
-                    }),
+                        position: Position::default(),
+                    })),
                 }),
                 Statement::Return(ReturnStatement {
                     return_value: Expression::Identifier(Identifier {
                         token: Token::Ident("myVar".to_string()),
                         value: "myVar".to_string(),
+                        position: Position::default(),
                     }),
                 }),
             ],
@@ -669,4 +1092,31 @@ mod tests {
             "let myVar = anotherVar;\nreturn myVar;\n"
         );
     }
+
+    #[test]
+    fn test_precedence_table_ordering() {
+        let ordered = [
+            Token::NullCoalesce,
+            Token::Equal,
+            Token::LT,
+            Token::Plus,
+            Token::Modulo,
+            Token::LParen,
+            Token::LSquare,
+        ];
+
+        for pair in ordered.windows(2) {
+            assert!(
+                precedence_of(&pair[0]) < precedence_of(&pair[1]),
+                "{} should bind less tightly than {}",
+                pair[0],
+                pair[1]
+            );
+        }
+        assert_eq!(
+            precedence_of(&Token::Modulo),
+            precedence_of(&Token::Asterisk)
+        );
+        assert_eq!(precedence_of(&Token::Comma), Precedence::Lowest);
+    }
 }
@@ -1,25 +1,40 @@
 pub mod ast;
 pub mod parser_errors;
 mod parser_tests;
+pub mod visitor;
 
 use crate::{
-    lexer::{token::Token, Lexer},
+    lexer::{token::Token, Lexer, Position},
     parser::ast::{
-        Expression, Identifier, LetStatement, Precedence, Program, ReturnStatement, Statement,
+        Comment, Expression, Identifier, LetStatement, Precedence, Program, ReturnStatement,
+        Statement, ThrowStatement,
     },
 };
 
 use self::{
-    ast::{BlockStatement, LoopStatement, WhileStatement},
+    ast::{AssignStatement, BlockStatement, EnumStatement, LoopStatement, WhileStatement},
     parser_errors::ParserErrors,
 };
 
+/// Maximum nesting depth for expressions, deeper inputs are rejected with an error
+/// instead of overflowing the stack.
+pub const MAX_NESTING_DEPTH: usize = 256;
+
 pub struct Parser {
     lexer: Lexer,
 
     pub errors: ParserErrors,
     pub current_token: Token,
     pub peek_token: Token,
+    pub current_position: Position,
+    pub peek_position: Position,
+
+    // Comments found since the last statement boundary, up to the current token, and the
+    // ones before the peek token. Always empty unless the lexer keeps the comments.
+    comments: Vec<Comment>,
+    peek_comments: Vec<Comment>,
+
+    depth: usize,
 }
 
 impl Parser {
@@ -29,6 +44,11 @@ impl Parser {
             errors: ParserErrors::new(),
             current_token: Token::Illegal(String::new()),
             peek_token: Token::Illegal(String::new()),
+            current_position: Position::default(),
+            peek_position: Position::default(),
+            comments: Vec::new(),
+            peek_comments: Vec::new(),
+            depth: 0,
         };
 
         parser.next_token();
@@ -39,7 +59,30 @@ impl Parser {
 
     pub fn next_token(&mut self) {
         self.current_token = self.peek_token.clone();
+        self.current_position = self.peek_position;
         self.peek_token = self.lexer.next_token();
+        self.peek_position = self.lexer.token_position();
+
+        self.comments.append(&mut self.peek_comments);
+        let trails = self.lexer.first_comment_trails();
+        self.peek_comments = self
+            .lexer
+            .leading_comments()
+            .iter()
+            .enumerate()
+            .map(|(i, text)| Comment {
+                text: text.clone(),
+                trailing: i == 0 && trails,
+            })
+            .collect();
+    }
+
+    /// The comments found since the last call, which sit between two statements when it is
+    /// called before parsing a statement.
+    pub(crate) fn take_comments(&mut self) -> impl Iterator<Item = Statement> {
+        std::mem::take(&mut self.comments)
+            .into_iter()
+            .map(Statement::Comment)
     }
 
     pub fn parse_program(&mut self) -> Program {
@@ -48,23 +91,40 @@ impl Parser {
         };
 
         while self.current_token != Token::Eof {
+            program.statements.extend(self.take_comments());
             if let Some(statement) = self.parse_statement() {
                 program.statements.push(statement);
             }
             self.next_token();
         }
+        program.statements.extend(self.take_comments());
 
         program
     }
 
     pub fn parse_statement(&mut self) -> Option<Statement> {
+        if let Err(s) = self.enter_nesting() {
+            self.push_error(s);
+            return None;
+        }
+        let statement = self.parse_nested_statement();
+        self.leave_nesting();
+        statement
+    }
+
+    fn parse_nested_statement(&mut self) -> Option<Statement> {
         match self.current_token {
             Token::Let => self.parse_let_statement().map(Statement::Let),
+            Token::Ident(_) if self.peek_token_is(&Token::Assign) => {
+                self.parse_assign_statement().map(Statement::Assign)
+            }
             Token::Return => self.parse_return_statement().map(Statement::Return),
+            Token::Throw => self.parse_throw_statement().map(Statement::Throw),
             Token::While => self.parse_while_statement().map(Statement::While),
             Token::Break | Token::Continue => {
                 self.parse_loop_statement().map(Statement::LoopStatements)
             }
+            Token::Enum => self.parse_enum_statement().map(Statement::Enum),
             _ => self.parse_expression_statement().map(Statement::Expression),
         }
     }
@@ -78,10 +138,17 @@ impl Parser {
             Token::Ident(value) => Identifier {
                 token: self.current_token.clone(),
                 value,
+                position: self.current_position,
             },
             _ => unreachable!("This should never happen, we already checked for Ident"),
         };
 
+        // `let x;` declares a variable without a value
+        if self.peek_token_is(&Token::Semicolon) {
+            self.next_token();
+            return Some(LetStatement { name, value: None });
+        }
+
         if !self.expect_peek(&Token::Assign) {
             return None;
         }
@@ -104,7 +171,34 @@ impl Parser {
             self.next_token();
         }
 
-        Some(LetStatement { name, value })
+        Some(LetStatement {
+            name,
+            value: Some(value),
+        })
+    }
+
+    fn parse_assign_statement(&mut self) -> Option<AssignStatement> {
+        let name = Identifier {
+            token: self.current_token.clone(),
+            value: self.current_token.to_string(),
+            position: self.current_position,
+        };
+        self.next_token();
+        self.next_token();
+
+        let value = match Expression::parse(self, Precedence::Lowest) {
+            Ok(x) => x,
+            Err(s) => {
+                self.push_error(s);
+                return None;
+            }
+        };
+
+        if self.peek_token_is(&Token::Semicolon) {
+            self.next_token();
+        }
+
+        Some(AssignStatement { name, value })
     }
 
     fn parse_return_statement(&mut self) -> Option<ReturnStatement> {
@@ -125,6 +219,24 @@ impl Parser {
         Some(ReturnStatement { return_value })
     }
 
+    fn parse_throw_statement(&mut self) -> Option<ThrowStatement> {
+        self.next_token();
+
+        let value = match Expression::parse(self, Precedence::Lowest) {
+            Ok(x) => x,
+            Err(s) => {
+                self.push_error(s);
+                return None;
+            }
+        };
+
+        if self.peek_token_is(&Token::Semicolon) {
+            self.next_token();
+        }
+
+        Some(ThrowStatement { value })
+    }
+
     fn parse_while_statement(&mut self) -> Option<WhileStatement> {
         self.next_token();
 
@@ -151,6 +263,48 @@ impl Parser {
         smt
     }
 
+    fn parse_enum_statement(&mut self) -> Option<EnumStatement> {
+        let name = self.parse_identifier()?;
+        if !self.expect_peek(&Token::LSquirly) {
+            return None;
+        }
+
+        let mut variants: Vec<Identifier> = Vec::new();
+        while !self.peek_token_is(&Token::RSquirly) {
+            let variant = self.parse_identifier()?;
+            if variants.iter().any(|v| v.value == variant.value) {
+                self.push_error(format!(
+                    "Duplicate variant {} in enum {} at {}",
+                    variant.value, name.value, variant.position
+                ));
+                return None;
+            }
+            variants.push(variant);
+            if !self.peek_token_is(&Token::RSquirly) && !self.expect_peek(&Token::Comma) {
+                return None;
+            }
+        }
+        self.next_token();
+
+        if self.peek_token_is(&Token::Semicolon) {
+            self.next_token();
+        }
+
+        Some(EnumStatement { name, variants })
+    }
+
+    /// Moves to the next token, which must be an identifier.
+    fn parse_identifier(&mut self) -> Option<Identifier> {
+        if !self.expect_peek(&Token::Ident(String::new())) {
+            return None;
+        }
+        Some(Identifier {
+            token: self.current_token.clone(),
+            value: self.current_token.to_string(),
+            position: self.current_position,
+        })
+    }
+
     fn parse_expression_statement(&mut self) -> Option<Expression> {
         let expression = Expression::parse(self, Precedence::Lowest);
         if self.peek_token_is(&Token::Semicolon) {
@@ -170,6 +324,7 @@ impl Parser {
         match self.current_token {
             Token::Ident(_) => matches!(token, Token::Ident(_)),
             Token::Int(_) => matches!(token, Token::Int(_)),
+            Token::Float(_) => matches!(token, Token::Float(_)),
             _ => &self.current_token == token,
         }
     }
@@ -178,6 +333,7 @@ impl Parser {
         match self.peek_token {
             Token::Ident(_) => matches!(token, Token::Ident(_)),
             Token::Int(_) => matches!(token, Token::Int(_)),
+            Token::Float(_) => matches!(token, Token::Float(_)),
             _ => &self.peek_token == token,
         }
     }
@@ -207,6 +363,20 @@ impl Parser {
         Precedence::from(&self.current_token)
     }
 
+    pub(crate) fn enter_nesting(&mut self) -> Result<(), String> {
+        if self.depth >= MAX_NESTING_DEPTH {
+            return Err(format!(
+                "Expression nesting is too deep (maximum depth is {MAX_NESTING_DEPTH})"
+            ));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    pub(crate) fn leave_nesting(&mut self) {
+        self.depth -= 1;
+    }
+
     fn push_error(&mut self, message: String) {
         if !message.is_empty() {
             self.errors.add_error(message);
@@ -219,3 +389,28 @@ pub fn parse(input: &str) -> Program {
     let mut parser = Parser::new(lexer);
     parser.parse_program()
 }
+
+/// Parses arbitrary bytes, reporting every problem as an error instead of panicking.
+///
+/// This is the entry point used by the fuzz targets: invalid UTF-8 is replaced
+/// lossily and any lexer or parser error is returned in the `Err` variant.
+///
+/// # Examples
+///
+/// ```
+/// use chimpanzee::parser::try_parse;
+///
+/// assert!(try_parse(b"let x = 5;").is_ok());
+/// assert!(try_parse(b"fn(1) {}").is_err());
+/// ```
+pub fn try_parse(input: &[u8]) -> Result<Program, ParserErrors> {
+    let input = String::from_utf8_lossy(input);
+    let lexer = Lexer::new(&input);
+    let mut parser = Parser::new(lexer);
+    let program = parser.parse_program();
+    if parser.errors.is_empty() {
+        Ok(program)
+    } else {
+        Err(parser.errors)
+    }
+}
@@ -2,14 +2,14 @@
 mod tests {
 
     use crate::{
-        lexer::{token::Token, Lexer},
+        lexer::{token::Token, Lexer, Position},
         parser::{
             ast::{
-                BlockStatement, Conditional, Expression, FunctionCall, Identifier, InfixOperator,
-                LetStatement, LoopStatement, Primitive, Program, ReturnStatement, Statement,
-                WhileStatement,
+                BlockStatement, Comment, Conditional, Expression, FunctionCall, Identifier,
+                InfixOperator, LetStatement, LoopStatement, Primitive, Program, ReturnStatement,
+                Statement, StringPart, ThrowStatement, WhileStatement,
             },
-            Parser,
+            try_parse, Parser,
         },
     };
 
@@ -26,25 +26,29 @@ mod tests {
                 name: Identifier {
                     token: Token::Ident("x".to_string()),
                     value: "x".to_string(),
+                    position: Position::default(),
                 },
-                value: Expression::Primitive(Primitive::IntegerLiteral(5)),
+                value: Some(Expression::Primitive(Primitive::IntegerLiteral(5))),
             }),
             Statement::Let(LetStatement {
                 name: Identifier {
                     token: Token::Ident("y".to_string()),
                     value: "y".to_string(),
+                    position: Position::default(),
                 },
-                value: Expression::Primitive(Primitive::BooleanLiteral(true)),
+                value: Some(Expression::Primitive(Primitive::BooleanLiteral(true))),
             }),
             Statement::Let(LetStatement {
                 name: Identifier {
                     token: Token::Ident("foobar".to_string()),
                     value: "foobar".to_string(),
+                    position: Position::default(),
                 },
-                value: Expression::Identifier(Identifier {
+                value: Some(Expression::Identifier(Identifier {
                     token: Token::Ident("y".to_string()),
                     value: "y".to_string(),
-                }),
+                    position: Position::default(),
+                })),
             }),
         ];
 
@@ -76,6 +80,7 @@ mod tests {
                 return_value: Expression::Identifier(Identifier {
                     token: Token::Ident("y".to_string()),
                     value: "y".to_string(),
+                    position: Position::default(),
                 }),
             }),
         ];
@@ -114,6 +119,36 @@ mod tests {
         assert_ne!(parser.errors.len(), 0);
     }
 
+    #[test]
+    fn test_method_call_errors() {
+        let tests = [
+            (
+                "a.foo()",
+                "Unknown method foo, only builtins can be called as methods",
+            ),
+            ("a.len", "Expected next token to be (, got Eof instead"),
+            ("a.1()", "Expected an identifier, got 1"),
+        ];
+        for (input, expected) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+            assert_eq!(
+                parser.errors.errors.first(),
+                Some(&expected.to_string()),
+                "input: {input}"
+            );
+        }
+
+        // The arguments of an unknown method are skipped, and so is the method call
+        let mut parser = Parser::new(Lexer::new("a.foo(1, (2)); let b = 3;"));
+        let program = parser.parse_program();
+        assert_eq!(
+            parser.errors.errors,
+            vec!["Unknown method foo, only builtins can be called as methods".to_string()]
+        );
+        assert_eq!(program.to_string(), "let b = 3;\n");
+    }
+
     #[test]
     fn test_identifier_expression() {
         let input = "foobar;";
@@ -127,6 +162,7 @@ mod tests {
             &Statement::Expression(Expression::Identifier(Identifier {
                 token: Token::Ident("foobar".to_string()),
                 value: "foobar".to_string(),
+                position: Position::default(),
             }))
         );
     }
@@ -184,6 +220,7 @@ mod tests {
             ("false == false", "false", "==", "false"),
             ("false && true", "false", "&&", "true"),
             ("true || false", "true", "||", "false"),
+            ("5 ?? 5", "5", "??", "5"),
         ];
 
         for (input, left, operator, right) in tests {
@@ -211,6 +248,25 @@ mod tests {
             ("3 + 4; -5 * 5", "(3 + 4)\n((-5) * 5)"),
             ("5 > 4 == 3 < 4", "((5 > 4) == (3 < 4))"),
             ("5 < 4 != 3 > 4", "((5 < 4) != (3 > 4))"),
+            ("a + b % c", "(a + (b % c))"),
+            ("a % b * c", "((a % b) * c)"),
+            ("a % b == c - d", "((a % b) == (c - d))"),
+            ("-a % b[0]", "((-a) % (b[0]))"),
+            ("a || b && c", "(a || (b && c))"),
+            ("a ?? b == c", "(a ?? (b == c))"),
+            ("a || b ?? c + d", "((a || b) ?? (c + d))"),
+            ("a ?? b ?? c", "((a ?? b) ?? c)"),
+            ("a?[0]", "(a?[0])"),
+            ("a?[0]?[1] + b[2]", "(((a?[0])?[1]) + (b[2]))"),
+            ("f?.(1, 2)(3)", "f?.(1, 2)(3)"),
+            ("-f?.()", "(-f?.())"),
+            ("a?[0] ?? b", "((a?[0]) ?? b)"),
+            ("f(a) % g(b) < c", "((f(a) % g(b)) < c)"),
+            ("a.len()", "a.len()"),
+            ("-a.push(1).len() * 2", "((-a.push(1).len()) * 2)"),
+            ("(1 + 2).len()", "(1 + 2).len()"),
+            ("a[0].slice(1, 2)[0]", "((a[0]).slice(1, 2)[0])"),
+            ("f(a).zip(b + c)", "f(a).zip((b + c))"),
             (
                 "3 + 4 * 5 == 3 * 1 + 4 * 5",
                 "((3 + (4 * 5)) == ((3 * 1) + (4 * 5)))",
@@ -299,6 +355,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_else_if_chain() {
+        let input = "if (x < y) { x } else if (x > y) { y } else if (x == 1) { 1 } else { 0 }";
+        let program = generate_program(input);
+
+        assert_eq!(program.statements.len(), 1);
+        match &program.statements[0] {
+            Statement::Expression(Expression::Conditional(conditional)) => {
+                let expected = [("(x < y)", "x"), ("(x > y)", "y"), ("(x == 1)", "1")];
+                assert_eq!(conditional.branches.len(), expected.len());
+                for ((condition, consequence), (expected_condition, expected_consequence)) in
+                    conditional.branches.iter().zip(expected)
+                {
+                    assert_eq!(condition.to_string(), expected_condition);
+                    check_block_statement(consequence, expected_consequence);
+                }
+                check_block_statement(conditional.alternative.as_ref().unwrap(), "0");
+            }
+            _ => panic!("It is not a conditional expression"),
+        }
+
+        let program = generate_program("if (a) { 1 } else if (b) { 2 }");
+        match &program.statements[0] {
+            Statement::Expression(Expression::Conditional(conditional)) => {
+                assert_eq!(conditional.branches.len(), 2);
+                assert!(conditional.alternative.is_none());
+            }
+            _ => panic!("It is not a conditional expression"),
+        }
+    }
+
     #[test]
     fn test_function_literal_parsing() {
         let input = "fn(x, y) { x + y; }";
@@ -317,6 +404,8 @@ mod tests {
             ("fn() {}", Vec::new()),
             ("fn(x) {}", vec!["x"]),
             ("fn(x,y,z) {}", vec!["x", "y", "z"]),
+            ("fn(x,) {}", vec!["x"]),
+            ("fn(\n    x,\n    y,\n) {}", vec!["x", "y"]),
         ];
 
         for (input, expected) in tests {
@@ -357,6 +446,7 @@ mod tests {
                 "add",
                 vec!["1", "(2 * 3)", "(4 + 5)"],
             ),
+            ("add(1, 2 * 3,);", "add", vec!["1", "(2 * 3)"]),
         ];
 
         for (input, name, argumnets) in tests {
@@ -383,6 +473,19 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_char_literal_expression() {
+        let input = "'a';";
+
+        let program = generate_program(input);
+
+        assert_eq!(program.statements.len(), 1);
+        match &program.statements[0] {
+            Statement::Expression(exp) => check_primitive_literal(exp, "a"),
+            _ => panic!("It is not an expression statement"),
+        }
+    }
+
     #[test]
     fn test_array_literal() {
         let input = "[1,2*2,3+3]";
@@ -404,6 +507,28 @@ mod tests {
         check_infix_expression(&expressions[2], "3", "+", "3");
     }
 
+    #[test]
+    fn test_trailing_commas() {
+        let tests = vec![
+            ("[1, 2, 3,]", "[1, 2, 3]"),
+            ("[\n    1,\n    2,\n]", "[1, 2]"),
+            ("{\"a\": 1,}", "{\"a\": 1}"),
+            ("{\n    1: true,\n    2: false,\n}", "{1: true, 2: false}"),
+        ];
+
+        for (input, expected) in tests {
+            let program = generate_program(input);
+            assert_eq!(program.to_string().trim_end(), expected);
+        }
+
+        for input in ["[,]", "[1,,]", "{,}", "{1: 2,,}", "fn(,) {}", "add(1,,)"] {
+            let lexer = Lexer::new(input);
+            let mut parser = Parser::new(lexer);
+            parser.parse_program();
+            assert_ne!(parser.errors.len(), 0, "input: {input}");
+        }
+    }
+
     #[test]
     fn test_parsing_index_expression_complete() {
         let input = "myArray[1+1]";
@@ -537,6 +662,46 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parsing_block_expressions() {
+        let tests = vec![
+            ("{ let x = 1; x + 1 }", 2),
+            ("{ x + 1 }", 1),
+            ("{ x; y; }", 2),
+            ("{ return 3; }", 1),
+            ("{ while (true) { break; } }", 1),
+            ("{ let x = 1; }", 1),
+        ];
+
+        for (input, statements) in tests {
+            let program = generate_program(input);
+            assert_eq!(program.statements.len(), 1, "input: {input}");
+            match &program.statements[0] {
+                Statement::Expression(Expression::Block(block)) => {
+                    assert_eq!(block.statements.len(), statements, "input: {input}");
+                }
+                statement => panic!("{input} is not a block expression: {statement:?}"),
+            }
+        }
+
+        for input in ["{}", "{x: 1}", "{1 + 1: true, \"a\": 2}"] {
+            let program = generate_program(input);
+            assert!(
+                matches!(
+                    program.statements[0],
+                    Statement::Expression(Expression::HashMapLiteral(_))
+                ),
+                "{input} is not a hashmap literal"
+            );
+        }
+
+        let program = generate_program("let y = { let x = 1; x * 2 } + 3;");
+        assert_eq!(
+            program.to_string().trim_end(),
+            "let y = ({\nlet x = 1;\n(x * 2)\n} + 3);"
+        );
+    }
+
     #[test]
     fn test_parsing_function_literal_with_name() {
         let input = "let myFunction = fn(){};";
@@ -546,7 +711,7 @@ mod tests {
         assert_eq!(program.statements.len(), 1);
         match program.statements[0].clone() {
             Statement::Let(l) => match l.value {
-                Expression::FunctionLiteral(f) => {
+                Some(Expression::FunctionLiteral(f)) => {
                     assert_eq!(f.name, Some("myFunction".to_string()));
                 }
                 _ => panic!("It is not a function literal"),
@@ -586,8 +751,10 @@ mod tests {
                 left: Box::new(Expression::Identifier(Identifier {
                     token: Token::Ident("x".to_string()),
                     value: "x".to_string(),
+                    position: Position::default(),
                 })),
                 right: Box::new(Expression::Primitive(Primitive::IntegerLiteral(3))),
+                position: Position::default(),
             }),
             body: BlockStatement {
                 statements: vec![
@@ -595,25 +762,32 @@ mod tests {
                         name: Identifier {
                             token: Token::Ident("x".to_string()),
                             value: "x".to_string(),
+                            position: Position::default(),
                         },
-                        value: Expression::Infix(InfixOperator {
+                        value: Some(Expression::Infix(InfixOperator {
                             token: Token::Plus,
                             left: Box::new(Expression::Identifier(Identifier {
                                 token: Token::Ident("x".to_string()),
                                 value: "x".to_string(),
+                                position: Position::default(),
                             })),
                             right: Box::new(Expression::Primitive(Primitive::IntegerLiteral(3))),
-                        }),
+                            position: Position::default(),
+                        })),
                     }),
                     Statement::Expression(Expression::FunctionCall(FunctionCall {
                         function: Box::new(Expression::Identifier(Identifier {
                             token: Token::Ident("puts".to_string()),
                             value: "puts".to_string(),
+                            position: Position::default(),
                         })),
                         arguments: vec![Expression::Identifier(Identifier {
                             token: Token::Ident("x".to_string()),
                             value: "x".to_string(),
+                            position: Position::default(),
                         })],
+                        optional: false,
+                        method: false,
                     })),
                 ],
             },
@@ -649,23 +823,31 @@ mod tests {
                 left: Box::new(Expression::Identifier(Identifier {
                     token: Token::Ident("x".to_string()),
                     value: "x".to_string(),
+                    position: Position::default(),
                 })),
                 right: Box::new(Expression::Primitive(Primitive::IntegerLiteral(3))),
+                position: Position::default(),
             }),
             body: BlockStatement {
                 statements: vec![Statement::Expression(Expression::Conditional(
                     Conditional {
-                        condition: Box::new(Expression::Infix(InfixOperator {
-                            token: Token::Equal,
-                            left: Box::new(Expression::Identifier(Identifier {
-                                token: Token::Ident("x".to_string()),
-                                value: "x".to_string(),
-                            })),
-                            right: Box::new(Expression::Primitive(Primitive::IntegerLiteral(2))),
-                        })),
-                        consequence: BlockStatement {
-                            statements: vec![Statement::LoopStatements(LoopStatement::Break)],
-                        },
+                        branches: vec![(
+                            Expression::Infix(InfixOperator {
+                                token: Token::Equal,
+                                left: Box::new(Expression::Identifier(Identifier {
+                                    token: Token::Ident("x".to_string()),
+                                    value: "x".to_string(),
+                                    position: Position::default(),
+                                })),
+                                right: Box::new(Expression::Primitive(Primitive::IntegerLiteral(
+                                    2,
+                                ))),
+                                position: Position::default(),
+                            }),
+                            BlockStatement {
+                                statements: vec![Statement::LoopStatements(LoopStatement::Break)],
+                            },
+                        )],
                         alternative: Some(BlockStatement {
                             statements: vec![Statement::LoopStatements(LoopStatement::Continue)],
                         }),
@@ -688,6 +870,96 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_try_parse_crash_corpus() {
+        // Inputs that used to panic or overflow the stack
+        let mut inputs = vec![
+            "fn(".to_string(),
+            "fn( ".to_string(),
+            "fn(1) { 1 }".to_string(),
+            "fn(x, 2) { x }".to_string(),
+            "!fn(".to_string(),
+            "\"\"|fn( ".to_string(),
+        ];
+        for prefix in [
+            "(",
+            "[",
+            "{",
+            "!",
+            "-",
+            "fn(){",
+            "while(1){",
+            "if(1){",
+            "f(",
+            "a[",
+        ] {
+            inputs.push(prefix.repeat(10_000));
+        }
+
+        for input in inputs {
+            assert!(try_parse(input.as_bytes()).is_err(), "expected an error");
+        }
+    }
+
+    #[test]
+    fn test_try_parse_valid_input() {
+        let program = try_parse(b"let add = fn(x, y) { x + y }; add(1, 2);").unwrap();
+        assert_eq!(program.statements.len(), 2);
+
+        assert!(try_parse(&[0xff, 0xfe, b'1']).is_err());
+    }
+
+    #[test]
+    fn test_try_parse_never_panics() {
+        let atoms: [&[u8]; 24] = [
+            b"fn",
+            b"(",
+            b")",
+            b"{",
+            b"}",
+            b"[",
+            b"]",
+            b",",
+            b";",
+            b"let",
+            b"x",
+            b"=",
+            b"1",
+            b"\"",
+            b"if",
+            b"else",
+            b"while",
+            b"return",
+            b"+",
+            b"-",
+            b"!",
+            b":",
+            b" ",
+            &[0xc3],
+        ];
+
+        // Simple xorshift generator, good enough to build random token soups
+        let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed as usize
+        };
+
+        for _ in 0..20_000 {
+            let mut input = Vec::new();
+            for _ in 0..next() % 16 {
+                if next() % 4 == 0 {
+                    input.push(next() as u8);
+                } else {
+                    input.extend_from_slice(atoms[next() % atoms.len()]);
+                }
+            }
+            let _ = try_parse(&input);
+        }
+    }
+
     fn generate_program(input: &str) -> Program {
         let lexer = Lexer::new(input);
         let mut parser = Parser::new(lexer);
@@ -715,8 +987,10 @@ mod tests {
         match exp {
             Expression::Primitive(p) => match p {
                 Primitive::IntegerLiteral(i) => assert_eq!(i.to_string(), value),
+                Primitive::FloatLiteral(x) => assert_eq!(format!("{x:?}"), value),
                 Primitive::BooleanLiteral(b) => assert_eq!(b.to_string(), value),
                 Primitive::StringLiteral(s) => assert_eq!(s, value),
+                Primitive::CharLiteral(c) => assert_eq!(c.to_string(), value),
             },
             _ => panic!("It is not a literal"),
         }
@@ -741,8 +1015,9 @@ mod tests {
     ) {
         match exp {
             Expression::Conditional(p) => {
-                assert_eq!(format!("({condition})"), p.condition.as_ref().to_string());
-                check_block_statement(&p.consequence, consequence);
+                assert_eq!(p.branches.len(), 1);
+                assert_eq!(format!("({condition})"), p.branches[0].0.to_string());
+                check_block_statement(&p.branches[0].1, consequence);
                 match alternative {
                     Some(a) => check_block_statement(p.alternative.as_ref().unwrap(), a),
                     None => assert!(p.alternative.is_none()),
@@ -796,4 +1071,227 @@ mod tests {
             _ => panic!("It is not an index expression"),
         }
     }
+
+    #[test]
+    fn test_parsing_uninitialized_let_and_assignment() {
+        let program = generate_program("let x;\nx = 5;\nx == 5;");
+
+        assert_eq!(program.statements.len(), 3);
+        match &program.statements[0] {
+            Statement::Let(statement) => {
+                assert_eq!(statement.name.value, "x");
+                assert_eq!(statement.value, None);
+            }
+            statement => panic!("{statement:?} is not a let statement"),
+        }
+        match &program.statements[1] {
+            Statement::Assign(statement) => {
+                assert_eq!(statement.name.value, "x");
+                assert_eq!(
+                    statement.value,
+                    Expression::Primitive(Primitive::IntegerLiteral(5))
+                );
+            }
+            statement => panic!("{statement:?} is not an assignment"),
+        }
+        assert!(matches!(
+            program.statements[2],
+            Statement::Expression(Expression::Infix(_))
+        ));
+        assert_eq!(program.to_string(), "let x;\nx = 5;\n(x == 5)\n");
+
+        // A brace followed by an assignment starts a block, not a hash
+        let program = generate_program("let y = { x = 2; x };");
+        match &program.statements[0] {
+            Statement::Let(LetStatement {
+                value: Some(Expression::Block(block)),
+                ..
+            }) => {
+                assert_eq!(block.statements.len(), 2);
+                assert!(matches!(block.statements[0], Statement::Assign(_)));
+            }
+            statement => panic!("{statement:?} is not a let of a block"),
+        }
+    }
+
+    #[test]
+    fn test_parsing_interpolated_strings() {
+        let program = generate_program(r#"f"1+1=${1+1} costs \$${price * 2}";"#);
+
+        assert_eq!(program.statements.len(), 1);
+        match &program.statements[0] {
+            Statement::Expression(Expression::InterpolatedString(string)) => {
+                assert_eq!(string.parts.len(), 4);
+                assert_eq!(string.parts[0], StringPart::Text("1+1=".to_string()));
+                assert_eq!(string.parts[2], StringPart::Text(" costs $".to_string()));
+            }
+            statement => panic!("{statement:?} is not an interpolated string"),
+        }
+        assert_eq!(
+            program.to_string(),
+            "f\"1+1=${(1 + 1)} costs \\$${(price * 2)}\"\n"
+        );
+
+        let tests = [
+            (
+                r#"f"${}""#,
+                "Empty expression in interpolated string at 1:5",
+            ),
+            (
+                r#"f"${a b}""#,
+                "Unexpected b after the expression `a` in interpolated string at 1:5",
+            ),
+            (
+                r#"f"${1 +}""#,
+                "There is no prefix parser for the token Eof",
+            ),
+        ];
+        for (input, expected) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+            assert_eq!(
+                parser.errors.errors,
+                vec![expected.to_string()],
+                "input: {input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parsing_spread() {
+        let program = generate_program("f(1, ...args); [...[1, 2], 3, ...a + b];");
+
+        assert_eq!(
+            program.to_string(),
+            "f(1, ...args)\n[...[1, 2], 3, ...(a + b)]\n"
+        );
+        match &program.statements[0] {
+            Statement::Expression(Expression::FunctionCall(call)) => {
+                assert!(!call.arguments[0].is_spread());
+                assert!(call.arguments[1].is_spread());
+            }
+            statement => panic!("{statement:?} is not a call"),
+        }
+
+        // Spreading is only allowed in arrays and arguments
+        for input in ["...a;", "let x = ...a;", "{...a}"] {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+            assert!(!parser.errors.is_empty(), "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_parsing_enums() {
+        let program = generate_program("enum Color { Red, Green, Blue, }\nenum Empty {};");
+
+        assert_eq!(program.statements.len(), 2);
+        match &program.statements[0] {
+            Statement::Enum(statement) => {
+                assert_eq!(statement.name.value, "Color");
+                let variants: Vec<&str> = statement
+                    .variants
+                    .iter()
+                    .map(|v| v.value.as_str())
+                    .collect();
+                assert_eq!(variants, vec!["Red", "Green", "Blue"]);
+            }
+            statement => panic!("{statement:?} is not an enum"),
+        }
+        assert_eq!(
+            program.to_string(),
+            "enum Color { Red, Green, Blue }\nenum Empty {}\n"
+        );
+
+        let tests = [
+            (
+                "enum Color { Red, Red }",
+                "Duplicate variant Red in enum Color at 1:19",
+            ),
+            (
+                "enum Color { Red Green }",
+                "Expected next token to be ,, got Green instead",
+            ),
+            ("enum { Red }", "Expected next token to be , got { instead"),
+        ];
+        for (input, expected) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+            assert_eq!(parser.errors.errors[0], expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_parsing_try_catch_and_throw() {
+        let program = generate_program("try { risky(); 1 } catch (e) { recover(e) }");
+
+        assert_eq!(program.statements.len(), 1);
+        match &program.statements[0] {
+            Statement::Expression(Expression::TryCatch(try_catch)) => {
+                assert_eq!(try_catch.body.statements.len(), 2);
+                assert_eq!(try_catch.error.value, "e");
+                assert_eq!(try_catch.handler.to_string(), "recover(e)\n");
+            }
+            statement => panic!("{statement:?} is not a try expression"),
+        }
+
+        assert_eq!(
+            generate_program("throw \"oops\"").to_string(),
+            "throw \"oops\";\n"
+        );
+        assert_eq!(
+            generate_program("{ throw 1; }").statements[0],
+            Statement::Expression(Expression::Block(BlockStatement {
+                statements: vec![Statement::Throw(ThrowStatement {
+                    value: Expression::Primitive(Primitive::IntegerLiteral(1)),
+                })],
+            }))
+        );
+
+        let tests = [
+            (
+                "try { 1 }",
+                "Expected next token to be catch, got Eof instead",
+            ),
+            (
+                "try { 1 } catch { 2 }",
+                "Expected next token to be (, got { instead",
+            ),
+            (
+                "try { 1 } catch (1) { 2 }",
+                "Expected next token to be , got 1 instead",
+            ),
+        ];
+        for (input, expected) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+            assert_eq!(parser.errors.errors[0], expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_comments_are_statements_with_trivia() {
+        let input = "# a\n1; # b\nwhile (true) {\n  [2, # c\n 3]\n  # d\n}";
+        let comment = |text: &str, trailing| {
+            Statement::Comment(Comment {
+                text: text.to_string(),
+                trailing,
+            })
+        };
+
+        let program = Parser::new(Lexer::new(input).with_trivia(true)).parse_program();
+        let Statement::While(wh) = &program.statements[3] else {
+            panic!("expected a while, got {}", program.statements[3]);
+        };
+        assert_eq!(program.statements[0], comment("# a", false));
+        assert_eq!(program.statements[2], comment("# b", true));
+        assert_eq!(program.statements.len(), 4);
+        // The comment inside of the array is moved after it
+        assert_eq!(
+            wh.body.statements[1..],
+            [comment("# c", true), comment("# d", false)]
+        );
+
+        assert_eq!(generate_program(input).statements.len(), 2);
+    }
 }
@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+
+use crate::parser::ast::{BlockStatement, Expression, Program, Statement, StringPart};
+
+/// Read-only traversal of the AST, for linters and other analyzers.
+///
+/// Every method walks into the children of its node by default, so an implementation
+/// only overrides the nodes it is interested in. An overridden method that still wants
+/// to reach the children calls the matching `walk_*` function.
+pub trait Visitor {
+    fn visit_program(&mut self, program: &Program) {
+        walk_program(self, program);
+    }
+
+    fn visit_statement(&mut self, statement: &Statement) {
+        walk_statement(self, statement);
+    }
+
+    fn visit_block(&mut self, block: &BlockStatement) {
+        walk_block(self, block);
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        walk_expression(self, expression);
+    }
+}
+
+pub fn walk_program<V: Visitor + ?Sized>(visitor: &mut V, program: &Program) {
+    for statement in &program.statements {
+        visitor.visit_statement(statement);
+    }
+}
+
+pub fn walk_statement<V: Visitor + ?Sized>(visitor: &mut V, statement: &Statement) {
+    match statement {
+        Statement::Let(statement) => {
+            if let Some(value) = &statement.value {
+                visitor.visit_expression(value);
+            }
+        }
+        Statement::Assign(statement) => visitor.visit_expression(&statement.value),
+        Statement::Return(statement) => visitor.visit_expression(&statement.return_value),
+        Statement::Throw(statement) => visitor.visit_expression(&statement.value),
+        Statement::Expression(expression) => visitor.visit_expression(expression),
+        Statement::While(statement) => {
+            visitor.visit_expression(&statement.condition);
+            visitor.visit_block(&statement.body);
+        }
+        Statement::LoopStatements(_) | Statement::Enum(_) | Statement::Comment(_) => {}
+    }
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, block: &BlockStatement) {
+    for statement in &block.statements {
+        visitor.visit_statement(statement);
+    }
+}
+
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, expression: &Expression) {
+    match expression {
+        Expression::Identifier(_) | Expression::Primitive(_) => {}
+        Expression::Prefix(prefix) => visitor.visit_expression(&prefix.right),
+        Expression::Spread(spread) => visitor.visit_expression(spread),
+        Expression::Infix(infix) => {
+            visitor.visit_expression(&infix.left);
+            visitor.visit_expression(&infix.right);
+        }
+        Expression::Conditional(conditional) => {
+            for (condition, consequence) in &conditional.branches {
+                visitor.visit_expression(condition);
+                visitor.visit_block(consequence);
+            }
+            if let Some(alternative) = &conditional.alternative {
+                visitor.visit_block(alternative);
+            }
+        }
+        Expression::TryCatch(try_catch) => {
+            visitor.visit_block(&try_catch.body);
+            visitor.visit_block(&try_catch.handler);
+        }
+        Expression::FunctionLiteral(function) => visitor.visit_block(&function.body),
+        Expression::FunctionCall(call) => {
+            visitor.visit_expression(&call.function);
+            for argument in &call.arguments {
+                visitor.visit_expression(argument);
+            }
+        }
+        Expression::ArrayLiteral(array) => {
+            for element in &array.elements {
+                visitor.visit_expression(element);
+            }
+        }
+        Expression::HashMapLiteral(hashmap) => {
+            for (key, value) in &hashmap.pairs {
+                visitor.visit_expression(key);
+                visitor.visit_expression(value);
+            }
+        }
+        Expression::IndexExpression(index) => {
+            visitor.visit_expression(&index.left);
+            visitor.visit_expression(&index.index);
+        }
+        Expression::Block(block) => visitor.visit_block(block),
+        Expression::InterpolatedString(string) => {
+            for part in &string.parts {
+                if let StringPart::Expression(expression) = part {
+                    visitor.visit_expression(expression);
+                }
+            }
+        }
+    }
+}
+
+/// Counts the statements and expressions of a program by kind, such as `"let"` or
+/// `"function"`. Blocks are not counted, only their contents.
+#[derive(Debug, Default)]
+pub struct NodeCounter {
+    pub counts: HashMap<&'static str, usize>,
+}
+
+impl NodeCounter {
+    pub fn count(program: &Program) -> Self {
+        let mut counter = NodeCounter::default();
+        counter.visit_program(program);
+        counter
+    }
+
+    /// Number of nodes of the given kind, 0 if there are none.
+    pub fn get(&self, kind: &str) -> usize {
+        self.counts.get(kind).copied().unwrap_or(0)
+    }
+
+    fn add(&mut self, kind: &'static str) {
+        *self.counts.entry(kind).or_insert(0) += 1;
+    }
+}
+
+impl Visitor for NodeCounter {
+    fn visit_statement(&mut self, statement: &Statement) {
+        self.add(match statement {
+            Statement::Let(_) => "let",
+            Statement::Assign(_) => "assignment",
+            Statement::Return(_) => "return",
+            Statement::Throw(_) => "throw",
+            Statement::Expression(_) => "expression statement",
+            Statement::While(_) => "while",
+            Statement::LoopStatements(_) => "break or continue",
+            Statement::Enum(_) => "enum",
+            Statement::Comment(_) => "comment",
+        });
+        walk_statement(self, statement);
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        self.add(match expression {
+            Expression::Identifier(_) => "identifier",
+            Expression::Primitive(_) => "literal",
+            Expression::Prefix(_) => "prefix",
+            Expression::Infix(_) => "infix",
+            Expression::Conditional(_) => "if",
+            Expression::TryCatch(_) => "try",
+            Expression::FunctionLiteral(_) => "function",
+            Expression::FunctionCall(_) => "call",
+            Expression::ArrayLiteral(_) => "array",
+            Expression::HashMapLiteral(_) => "hashmap",
+            Expression::IndexExpression(_) => "index",
+            Expression::Block(_) => "block",
+            Expression::InterpolatedString(_) => "interpolated string",
+            Expression::Spread(_) => "spread",
+        });
+        walk_expression(self, expression);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{ast::FunctionLiteral, parse};
+
+    use super::*;
+
+    #[derive(Default)]
+    struct FunctionNames {
+        names: Vec<Option<String>>,
+    }
+
+    impl Visitor for FunctionNames {
+        fn visit_expression(&mut self, expression: &Expression) {
+            if let Expression::FunctionLiteral(FunctionLiteral { name, .. }) = expression {
+                self.names.push(name.clone());
+            }
+            walk_expression(self, expression);
+        }
+    }
+
+    #[test]
+    fn test_visitor_finds_nested_function_literals() {
+        let program = parse(
+            r"
+            let add = fn(a, b) { a + b };
+            let compose = fn(f, g) {
+                fn(x) { f(g(x)) }
+            };
+            let i = 0;
+            while (i < 3) {
+                puts(compose(fn(x) { x }, add)[0]);
+                let i = i + 1;
+            }
+            { if (true) { [fn() { 1 }] } else { {1: fn() { 2 }} } }
+            ",
+        );
+
+        let mut visitor = FunctionNames::default();
+        visitor.visit_program(&program);
+        assert_eq!(visitor.names.len(), 6);
+        assert_eq!(visitor.names[0], Some("add".to_string()));
+        assert_eq!(visitor.names[1], Some("compose".to_string()));
+        assert_eq!(visitor.names[2], None);
+
+        assert_eq!(NodeCounter::count(&program).get("function"), 6);
+    }
+
+    #[test]
+    fn test_node_counter() {
+        let program = parse("let x = 1 + 2; if (x > 2) { return -x; } puts(x, [x]);");
+        assert_eq!(program.len(), 3);
+
+        let counter = NodeCounter::count(&program);
+        assert_eq!(counter.get("let"), 1);
+        assert_eq!(counter.get("return"), 1);
+        assert_eq!(counter.get("expression statement"), 2);
+        assert_eq!(counter.get("infix"), 2);
+        assert_eq!(counter.get("prefix"), 1);
+        assert_eq!(counter.get("literal"), 3);
+        assert_eq!(counter.get("identifier"), 5);
+        assert_eq!(counter.get("call"), 1);
+        assert_eq!(counter.get("array"), 1);
+        assert_eq!(counter.get("while"), 0);
+    }
+}
@@ -0,0 +1,353 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::{BufRead, Write},
+    rc::Rc,
+};
+
+use crate::{
+    compiler::{
+        symbol_table::{Symbol, SymbolTable},
+        Bytecode, Compiler,
+    },
+    lexer::Lexer,
+    object::Object,
+    parser::Parser,
+    vm::{STACK_SIZE, VM},
+};
+
+/// The expressions displayed at every step of a debugging session.
+#[derive(Default)]
+pub struct Watches {
+    sources: Vec<String>,
+    symbol_table: SymbolTable,
+    constants: Vec<Object>,
+    function_locals: HashMap<usize, Vec<Symbol>>,
+    // The watches compiled for each function they were evaluated in, `None` being the
+    // main program
+    compiled: RefCell<HashMap<Option<usize>, Rc<CompiledWatches>>>,
+}
+
+struct CompiledWatches {
+    // Global slot that holds each local variable of the function, with its local index
+    locals: Vec<(usize, usize)>,
+    bytecodes: Vec<Result<Bytecode, String>>,
+}
+
+impl Watches {
+    /// Prepares `sources` to be compiled against the state of `compiler`, which compiled
+    /// the program being debugged.
+    pub fn new(sources: &[String], compiler: &Compiler) -> Self {
+        Self {
+            sources: sources.to_vec(),
+            symbol_table: compiler.symbol_table.clone(),
+            constants: compiler.constants.clone(),
+            function_locals: compiler.function_locals.clone(),
+            compiled: RefCell::default(),
+        }
+    }
+
+    /// Evaluates every watch in a separate VM, returning the displayed value of each.
+    ///
+    /// The watches see the global variables and the locals of the function `vm` is
+    /// executing, the latter shadowing the former. They all work on a single copy of the
+    /// globals, so that they can never change the state of the debugged program.
+    pub fn evaluate(&self, vm: &VM) -> Vec<String> {
+        if self.sources.is_empty() {
+            return vec![];
+        }
+        let (function, locals) = vm.current_locals();
+        let compiled = self.compile(function);
+
+        let mut globals = vm.globals.clone();
+        for &(global, local) in &compiled.locals {
+            if let (Some(slot), Some(value)) = (globals.get_mut(global), locals.get(local)) {
+                *slot = Rc::clone(value);
+            }
+        }
+        compiled
+            .bytecodes
+            .iter()
+            .map(|bytecode| {
+                let bytecode = match bytecode {
+                    Ok(bytecode) => bytecode.clone(),
+                    Err(err) => return format!("<error: {err}>"),
+                };
+                let mut sandbox = VM::with_capacity(bytecode, STACK_SIZE, 0);
+                sandbox.globals = std::mem::take(&mut globals);
+                let value = sandbox
+                    .run()
+                    .and_then(|()| sandbox.last_popped_stack_element());
+                globals = std::mem::take(&mut sandbox.globals);
+                match value {
+                    Ok(value) => value.to_string(),
+                    Err(err) => format!("<error: {err}>"),
+                }
+            })
+            .collect()
+    }
+
+    /// Compiles the watches for `function`, whose locals are turned into extra globals.
+    fn compile(&self, function: Option<usize>) -> Rc<CompiledWatches> {
+        if let Some(compiled) = self.compiled.borrow().get(&function) {
+            return Rc::clone(compiled);
+        }
+
+        let mut symbol_table = self.symbol_table.clone();
+        let locals = function
+            .and_then(|index| self.function_locals.get(&index))
+            .map_or(&[][..], Vec::as_slice)
+            .iter()
+            .map(|local| (symbol_table.define(local.name.clone()).index, local.index))
+            .collect();
+        let bytecodes = self
+            .sources
+            .iter()
+            .map(|source| {
+                let mut parser = Parser::new(Lexer::new(source));
+                let program = parser.parse_program();
+                if !parser.errors.is_empty() {
+                    return Err(parser.errors.errors.join(", "));
+                }
+                let mut compiler =
+                    Compiler::new_with_state(symbol_table.clone(), self.constants.clone());
+                compiler.compile(program).map(|()| compiler.bytecode())
+            })
+            .collect();
+
+        let compiled = Rc::new(CompiledWatches { locals, bytecodes });
+        self.compiled
+            .borrow_mut()
+            .insert(function, Rc::clone(&compiled));
+        compiled
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DebugOutcome {
+    /// Every instruction was executed.
+    Finished,
+    /// The user stopped the session with `q` (or by closing the input).
+    Aborted,
+}
+
+/// Single-steps `vm`, reading a command from `input` before each instruction:
+///
+/// * an empty line executes the next instruction,
+/// * `c` runs the rest of the program without stopping,
+/// * `q` aborts the session.
+///
+/// Before each prompt the next instruction, the top of the stack and the value of every
+/// watch are written to `output`. Runtime errors are returned as-is.
+pub fn debug(
+    vm: &mut VM,
+    watches: &Watches,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> Result<DebugOutcome, String> {
+    let mut continuing = false;
+    while !vm.is_finished() {
+        if !continuing {
+            let instruction = vm.next_instruction().unwrap_or_default();
+            let top = vm
+                .stack_top()
+                .map_or_else(|| "<empty>".to_string(), |top| top.to_string());
+            writeln!(output, "{instruction:<36} top: {top}").map_err(|err| err.to_string())?;
+            for (source, value) in watches.sources.iter().zip(watches.evaluate(vm)) {
+                writeln!(output, "    {source} = {value}").map_err(|err| err.to_string())?;
+            }
+            write!(output, "(debug) ").map_err(|err| err.to_string())?;
+            output.flush().map_err(|err| err.to_string())?;
+
+            let mut command = String::new();
+            let read = input
+                .read_line(&mut command)
+                .map_err(|err| err.to_string())?;
+            match command.trim() {
+                _ if read == 0 => return Ok(DebugOutcome::Aborted),
+                "" => {}
+                "c" => continuing = true,
+                "q" => return Ok(DebugOutcome::Aborted),
+                other => {
+                    writeln!(output, "Unknown command {other}, use Enter, c or q")
+                        .map_err(|err| err.to_string())?;
+                    continue;
+                }
+            }
+        }
+        vm.step()?;
+    }
+    Ok(DebugOutcome::Finished)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{compiler::Compiler, object::Object, parser::parse, vm::VM};
+
+    use super::*;
+
+    fn new_vm(input: &str) -> VM {
+        let mut compiler = Compiler::new();
+        compiler.compile(parse(input)).unwrap();
+        VM::new(compiler.bytecode())
+    }
+
+    #[test]
+    fn test_step_loop() {
+        let mut vm = new_vm("1 + 2");
+        let mut output = Vec::new();
+
+        let outcome = debug(
+            &mut vm,
+            &Watches::default(),
+            &mut Cursor::new("\n\nx\n\n\n"),
+            &mut output,
+        )
+        .unwrap();
+
+        assert_eq!(outcome, DebugOutcome::Finished);
+        assert_eq!(
+            vm.last_popped_stack_element().unwrap().as_ref(),
+            &Object::INTEGER(3)
+        );
+        let expected = [
+            "0000 OpConstant           0          top: <empty>\n(debug) ",
+            "0003 OpConstant           1          top: 1\n(debug) ",
+            "0006 OpAdd                           top: 2\n(debug) ",
+            "Unknown command x, use Enter, c or q\n",
+            "0006 OpAdd                           top: 2\n(debug) ",
+            "0007 OpPop                           top: 3\n(debug) ",
+        ]
+        .concat();
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_continue_and_abort() {
+        let mut vm = new_vm("let a = 1; a + 1");
+        let mut output = Vec::new();
+        let outcome = debug(
+            &mut vm,
+            &Watches::default(),
+            &mut Cursor::new("\nc\n"),
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(outcome, DebugOutcome::Finished);
+        assert_eq!(
+            vm.last_popped_stack_element().unwrap().as_ref(),
+            &Object::INTEGER(2)
+        );
+        assert_eq!(
+            String::from_utf8(output)
+                .unwrap()
+                .matches("(debug)")
+                .count(),
+            2
+        );
+
+        let mut vm = new_vm("1; 2; 3");
+        let outcome = debug(
+            &mut vm,
+            &Watches::default(),
+            &mut Cursor::new("\nq\n\n"),
+            &mut Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(outcome, DebugOutcome::Aborted);
+        assert!(!vm.is_finished());
+
+        let mut vm = new_vm("1; 2; 3");
+        let outcome = debug(
+            &mut vm,
+            &Watches::default(),
+            &mut Cursor::new(""),
+            &mut Vec::new(),
+        )
+        .unwrap();
+        assert_eq!(outcome, DebugOutcome::Aborted);
+    }
+
+    #[test]
+    fn test_watch() {
+        let input = "let a = 1; let a = a * 10; let b = 2; b";
+        let mut compiler = Compiler::new();
+        compiler.compile(parse(input)).unwrap();
+        let watches = Watches::new(
+            &["a".to_string(), "a + b".to_string(), "c".to_string()],
+            &compiler,
+        );
+        let mut vm = VM::new(compiler.bytecode());
+        let mut output = Vec::new();
+
+        let outcome = debug(
+            &mut vm,
+            &watches,
+            &mut Cursor::new("\n".repeat(10)),
+            &mut output,
+        );
+
+        assert_eq!(outcome, Ok(DebugOutcome::Finished));
+        let output = String::from_utf8(output).unwrap();
+        let values = output
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("a = "))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            vec!["null", "null", "1", "1", "1", "1", "10", "10", "10", "10"]
+        );
+        assert!(output.contains("a + b = <error: Unsupported types for binary operation>"));
+        assert!(output.contains("a + b = 12"));
+        assert!(output.contains("c = <error: Undefined variable: c at 1:1>"));
+        assert_eq!(vm.globals[0].as_ref(), &Object::INTEGER(10));
+    }
+
+    #[test]
+    fn test_watch_locals() {
+        let input = "let a = 1; let f = fn(a) { let b = a * 2; b }; f(5); a";
+        let mut compiler = Compiler::new();
+        compiler.compile(parse(input)).unwrap();
+        let watches = Watches::new(&["a".to_string(), "a = 7; a + 1".to_string()], &compiler);
+        let mut vm = VM::new(compiler.bytecode());
+        let mut output = Vec::new();
+
+        let outcome = debug(
+            &mut vm,
+            &watches,
+            &mut Cursor::new("\n".repeat(30)),
+            &mut output,
+        );
+
+        assert_eq!(outcome, Ok(DebugOutcome::Finished));
+        let output = String::from_utf8(output).unwrap();
+        let values = output
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("a = "))
+            .filter(|value| !value.contains('='))
+            .collect::<Vec<_>>();
+        assert!(values.contains(&"5"));
+        assert_eq!(values.first(), Some(&"null"));
+        assert_eq!(values.last(), Some(&"1"));
+        assert!(output.contains("a = 7; a + 1 = 8"));
+        assert_eq!(vm.globals[0].as_ref(), &Object::INTEGER(1));
+        assert_eq!(
+            vm.last_popped_stack_element().unwrap().as_ref(),
+            &Object::INTEGER(1)
+        );
+    }
+
+    #[test]
+    fn test_runtime_error() {
+        let mut vm = new_vm("1 / 0");
+        let result = debug(
+            &mut vm,
+            &Watches::default(),
+            &mut Cursor::new("c\n"),
+            &mut Vec::new(),
+        );
+        assert_eq!(result, Err("Division by zero".to_string()));
+    }
+}
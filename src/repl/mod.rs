@@ -1,25 +1,42 @@
+mod debugger;
 mod errors;
 
 use crate::{
     compiler::{
+        code::Instructions,
         symbol_table::SymbolTable,
         {Bytecode, Compiler},
     },
+    diagnostics::Diagnostic,
     interpreter::evaluator::Evaluator,
     lexer::{token::Token, Lexer},
     object::{
         builtins::BuiltinFunction,
+        random::Random,
         {Object, NULL},
     },
-    parser::{parser_errors::ParserErrors, Parser},
-    repl::errors::{CompilerError, LexerErrors, RuntimeError},
+    parser::{ast::Statement, parser_errors::ParserErrors, Parser},
+    repl::{
+        debugger::{DebugOutcome, Watches},
+        errors::{CompilerError, LexerErrors, RuntimeError},
+    },
     vm::{GLOBALS_SIZE, VM},
 };
 
 use clap_derive::{Parser, ValueEnum};
 use rustyline::{error::ReadlineError, DefaultEditor};
 use std::rc::Rc;
-use std::{error::Error, fs};
+use std::{
+    env,
+    error::Error,
+    fmt::Write as _,
+    fs,
+    io::{self, Write},
+    mem,
+    path::PathBuf,
+    process,
+};
+use strum::IntoEnumIterator;
 
 enum InputType {
     File(String),
@@ -34,6 +51,7 @@ enum Mode {
     Compiler,
 }
 
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Parser)]
 pub struct ReplCli {
     /// Input file, if not specified, the REPL will be launched
@@ -46,6 +64,28 @@ pub struct ReplCli {
     /// Show the logo
     #[clap(long)]
     logo: bool,
+
+    /// List the constant pool after every line of the compiler REPL
+    #[clap(long)]
+    show_constants: bool,
+
+    /// Evaluate the code, print its result and exit. When repeated, the pieces of code run
+    /// one after the other and share their variables
+    #[arg(short, long = "eval", value_name = "CODE")]
+    eval: Vec<String>,
+
+    /// File run before the first prompt of the REPL, `~/.monkeyrc` by default
+    #[arg(long, value_name = "FILE")]
+    rc: Option<PathBuf>,
+
+    /// Do not run a startup file before the first prompt of the REPL
+    #[arg(long, conflicts_with = "rc")]
+    no_rc: bool,
+
+    /// Parse and compile the input file, reporting its errors and warnings, without
+    /// running it
+    #[arg(long, requires = "filename", conflicts_with_all = ["mode", "eval"])]
+    check: bool,
 }
 
 impl ReplCli {
@@ -62,7 +102,36 @@ impl ReplCli {
         }
     }
 
+    /// The startup file of the REPL, and whether it was given explicitly. `~/.monkeyrc`
+    /// does not have to exist, while a missing `--rc` file is reported.
+    fn rc_file(&self) -> Option<(PathBuf, bool)> {
+        if self.no_rc {
+            return None;
+        }
+        match &self.rc {
+            Some(path) => Some((path.clone(), true)),
+            None => env::var_os("HOME").map(|home| (PathBuf::from(home).join(".monkeyrc"), false)),
+        }
+    }
+
+    /// Reads the startup file, printing an error if it cannot be read.
+    fn read_rc_file(&self) -> Option<(PathBuf, String)> {
+        let (path, explicit) = self.rc_file()?;
+        match fs::read_to_string(&path) {
+            Ok(contents) => Some((path, contents)),
+            Err(err) => {
+                if explicit || path.exists() {
+                    eprintln!("Unable to read the startup file {}: {err}", path.display());
+                }
+                None
+            }
+        }
+    }
+
     pub fn run(&self) -> Result<(), Box<dyn Error>> {
+        if !self.eval.is_empty() {
+            return self.run_eval();
+        }
         //TODO: Implement our own editor for competition
         let mut rl = DefaultEditor::new()?;
         match &self.get_input_type() {
@@ -77,10 +146,19 @@ impl ReplCli {
                     Mode::Compiler => self.compiler(&mut rl),
                 }
             }
+            InputType::File(filename) if self.check => Self::check_file(filename),
             InputType::File(filename) => self.run_file(filename),
         }
     }
 
+    /// Compiles the file without running it. The errors are returned, which makes the
+    /// command exit with a non-zero status, while the warnings are only printed.
+    fn check_file(file_path: &str) -> Result<(), Box<dyn Error>> {
+        let contents = ReplCli::read_file_contents(file_path)?;
+        compile(&contents)?;
+        Ok(())
+    }
+
     fn rlpl(&self, rl: &mut DefaultEditor) -> Result<(), LexerErrors> {
         let mut errors = LexerErrors::new();
         loop {
@@ -137,6 +215,12 @@ impl ReplCli {
 
     pub fn interpreter(&self, rl: &mut DefaultEditor) -> Result<(), Box<dyn Error>> {
         let mut evaluator = Evaluator::new();
+        if let Some((path, contents)) = self.read_rc_file() {
+            if let Err(err) = interpret(&mut evaluator, &contents) {
+                eprintln!("{err}");
+                eprintln!("in the startup file {}", path.display());
+            }
+        }
         loop {
             match rl.readline(self.get_prompt().as_str()) {
                 Ok(line) => match interpret(&mut evaluator, &line) {
@@ -160,62 +244,29 @@ impl ReplCli {
     }
 
     pub fn compiler(&self, rl: &mut DefaultEditor) -> Result<(), Box<dyn Error>> {
-        let mut symbol_table = SymbolTable::new();
-        for (i, builtin) in BuiltinFunction::get_builtins_names().iter().enumerate() {
-            symbol_table.define_builtin(i, builtin.clone());
+        let mut session = CompilerSession::new();
+        session.show_constants = self.show_constants;
+        if let Some((path, contents)) = self.read_rc_file() {
+            let exit_code = session.run(&contents, false);
+            if session.line_failed {
+                eprintln!("in the startup file {}", path.display());
+            }
+            if exit_code.is_some() {
+                return Ok(());
+            }
         }
-        let mut constants = Vec::new();
-        let mut globals = {
-            let mut v = Vec::with_capacity(GLOBALS_SIZE);
-            (0..GLOBALS_SIZE).for_each(|_| v.push(Rc::new(NULL)));
-            v
-        };
 
         loop {
             match rl.readline(self.get_prompt().as_str()) {
-                Ok(line) => {
-                    let lexer = Lexer::new(&line);
-                    let mut parser = Parser::new(lexer);
-                    let program = parser.parse_program();
-                    if !parser.errors.is_empty() {
-                        eprintln!("{}", parser.errors);
-                    }
-                    let mut compiler =
-                        Compiler::new_with_state(symbol_table.clone(), constants.clone());
-                    if let Err(err) = compiler.compile(program) {
-                        let err = CompilerError::new(err);
-                        eprintln!("{err}",);
-                    }
-
-                    let mut vm = VM::new_with_global_store(compiler.bytecode(), globals.clone());
-                    if let Err(err) = vm.run() {
-                        eprintln!("{err}",);
-                    }
-                    constants = compiler.constants;
-                    symbol_table = compiler.symbol_table;
-
-                    let vm_result: Result<String, Box<dyn Error>> = match vm
-                        .last_popped_stack_element()
-                    {
-                        Ok(obj) => match obj.as_ref() {
-                            Object::ERROR(error) => Err(Box::new(RuntimeError::new(error.clone()))),
-                            x => Ok(x.to_string()),
-                        },
-                        Err(_) => Err(Box::new(RuntimeError::new(String::from(
-                            "No object returned from VM",
-                        )))),
-                    };
-
-                    globals = vm.globals;
-                    match vm_result {
-                        Ok(str) => {
-                            if str != Object::NULL.to_string() {
-                                println!("{str}");
-                            }
+                Ok(line) => match line.strip_prefix(':') {
+                    Some(command) => session.run_command(command),
+                    None => {
+                        // In the REPL `exit` ends the session, whatever its code
+                        if session.eval(&line).is_some() {
+                            break;
                         }
-                        Err(err) => eprintln!("{err}",),
                     }
-                }
+                },
                 Err(ReadlineError::Interrupted | ReadlineError::Eof) => {
                     break;
                 }
@@ -293,12 +344,48 @@ impl ReplCli {
             }
             Mode::Compiler => {
                 let bytecode = compile(&contents)?;
-                run_vm(bytecode)?;
+                let mut vm = VM::new(bytecode);
+                run_vm(&mut vm)?;
+                if let Some(code) = vm.exit_code() {
+                    io::stdout().flush()?;
+                    process::exit(code);
+                }
             }
         }
         Ok(())
     }
 
+    /// Runs the code given with `-e`, stopping at the first piece of code that fails.
+    fn run_eval(&self) -> Result<(), Box<dyn Error>> {
+        match self.get_mode() {
+            Mode::Lexer => self.eval.iter().try_for_each(|code| Ok(lex(code)?)),
+            Mode::Parser => self.eval.iter().try_for_each(|code| Ok(parse(code)?)),
+            Mode::Interpreter => {
+                let mut evaluator = Evaluator::new();
+                for code in &self.eval {
+                    let result = interpret(&mut evaluator, code)?;
+                    if result != Object::NULL.to_string() {
+                        println!("{result}");
+                    }
+                }
+                Ok(())
+            }
+            Mode::Compiler => {
+                let mut session = CompilerSession::new();
+                for code in &self.eval {
+                    let exit_code = session.eval(code);
+                    // The error has already been printed
+                    let exit_code = exit_code.or(session.line_failed.then_some(1));
+                    if let Some(code) = exit_code {
+                        io::stdout().flush()?;
+                        process::exit(code);
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
     fn read_file_contents(file_path: &str) -> Result<String, Box<dyn Error>> {
         if file_path.ends_with(".monkey") {
             Ok(fs::read_to_string(file_path)?)
@@ -308,6 +395,339 @@ impl ReplCli {
     }
 }
 
+/// State kept between the lines of a compiler REPL session.
+struct CompilerSession {
+    /// Holds the symbol table and the constants of the session, and is reset before each
+    /// line rather than created anew
+    compiler: Compiler,
+    globals: Vec<Rc<Object>>,
+    /// Expressions shown at every step of `:debug`
+    watches: Vec<String>,
+    /// Whether the constant pool is listed after every line
+    show_constants: bool,
+    /// Whether the last line evaluated printed an error
+    line_failed: bool,
+    /// Handed from VM to VM, so that `seed` affects the following lines
+    random: Random,
+}
+
+impl CompilerSession {
+    fn new() -> Self {
+        let mut symbol_table = SymbolTable::new();
+        for (i, builtin) in BuiltinFunction::get_builtins_names().iter().enumerate() {
+            symbol_table.define_builtin(i, builtin.clone());
+        }
+        let mut compiler = Compiler::new_with_state(symbol_table, Vec::new());
+        compiler.set_diagnostics(Box::new(print_diagnostic));
+        Self {
+            compiler,
+            globals: {
+                let mut v = Vec::with_capacity(GLOBALS_SIZE);
+                (0..GLOBALS_SIZE).for_each(|_| v.push(Rc::new(NULL)));
+                v
+            },
+            watches: Vec::new(),
+            show_constants: false,
+            line_failed: false,
+            random: Random::default(),
+        }
+    }
+
+    /// Runs `line` and prints its value, returning the exit code if it called `exit`.
+    fn eval(&mut self, line: &str) -> Option<i32> {
+        self.run(line, true)
+    }
+
+    /// Runs `line`, printing its value if `echo` is set. Errors are always printed.
+    fn run(&mut self, line: &str, echo: bool) -> Option<i32> {
+        self.line_failed = false;
+        let lexer = Lexer::new(line);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        if !parser.errors.is_empty() {
+            eprintln!("{}", parser.errors);
+            self.line_failed = true;
+        }
+        // A line holding a single expression leaves its value on the stack, otherwise the
+        // value of the last expression statement is echoed
+        let single_expression = match program.statements.as_slice() {
+            [Statement::Expression(expression)] => Some(expression.clone()),
+            _ => None,
+        };
+        let compiler = &mut self.compiler;
+        compiler.reset_scopes();
+        if let Err(err) = match single_expression.clone() {
+            Some(expression) => compiler.compile_expression_public(expression),
+            None => compiler.compile(program),
+        } {
+            let err = CompilerError::new(err);
+            eprintln!("{err}",);
+            self.line_failed = true;
+        }
+
+        let mut vm = VM::new_with_global_store(compiler.bytecode(), self.globals.clone());
+        vm.set_random(mem::take(&mut self.random));
+        if let Err(err) = vm.run() {
+            eprintln!("{err}",);
+            self.line_failed = true;
+        }
+
+        let value = if single_expression.is_some() {
+            vm.stack_top()
+        } else {
+            Some(vm.result())
+        };
+        let vm_result: Result<String, Box<dyn Error>> = match value {
+            Some(obj) => match obj.as_ref() {
+                Object::ERROR(error) => Err(Box::new(RuntimeError::new(error.clone()))),
+                x => Ok(x.to_string()),
+            },
+            None => Err(Box::new(RuntimeError::new(String::from(
+                "No object returned from VM",
+            )))),
+        };
+
+        let exit_code = vm.exit_code();
+        self.random = vm.take_random();
+        self.globals = vm.globals;
+        match vm_result {
+            Ok(str) => {
+                if echo && str != Object::NULL.to_string() {
+                    println!("{str}");
+                }
+            }
+            Err(err) => {
+                eprintln!("{err}",);
+                self.line_failed = true;
+            }
+        }
+        if echo && self.show_constants {
+            print!("{}", self.constants_listing());
+        }
+        exit_code
+    }
+
+    /// Runs a REPL command, `command` being the line without its leading `:`.
+    fn run_command(&mut self, command: &str) {
+        let (name, argument) = command.split_once(' ').unwrap_or((command, ""));
+        match COMMANDS.iter().find(|command| command.name == name) {
+            Some(command) => (command.run)(self, argument.trim()),
+            None => eprintln!("Unknown command :{name}, type :help to list the commands"),
+        }
+    }
+
+    fn help(&mut self, _: &str) {
+        print!("{}", help_text());
+    }
+
+    /// Steps through `source` with the debugger.
+    ///
+    /// The program sees the session's variables, but whatever it defines or modifies is
+    /// discarded afterwards, so the session is left as it was before the command.
+    fn debug(&mut self, source: &str) {
+        let lexer = Lexer::new(source);
+        let mut parser = Parser::new(lexer);
+        let program = parser.parse_program();
+        if !parser.errors.is_empty() {
+            eprintln!("{}", parser.errors);
+            return;
+        }
+        let mut compiler = Compiler::new_with_state(
+            self.compiler.symbol_table.clone(),
+            self.compiler.constants.clone(),
+        );
+        compiler
+            .function_locals
+            .clone_from(&self.compiler.function_locals);
+        if let Err(err) = compiler.compile(program) {
+            eprintln!("{}", CompilerError::new(err));
+            return;
+        }
+
+        let watches = Watches::new(&self.watches, &compiler);
+        let mut vm = VM::new_with_global_store(compiler.bytecode(), self.globals.clone());
+        match debugger::debug(
+            &mut vm,
+            &watches,
+            &mut io::stdin().lock(),
+            &mut io::stdout(),
+        ) {
+            Ok(DebugOutcome::Finished) => match vm.last_popped_stack_element() {
+                Ok(obj) => println!("{obj}"),
+                Err(err) => eprintln!("{}", RuntimeError::new(err)),
+            },
+            Ok(DebugOutcome::Aborted) => println!("Aborted"),
+            Err(err) => eprintln!("{}", RuntimeError::new(err)),
+        }
+    }
+
+    /// Prints an estimate of the memory used by the constant pool and the globals.
+    fn memory_summary(&mut self, _: &str) {
+        let constants_size: usize = self
+            .compiler
+            .constants
+            .iter()
+            .map(Object::approx_size)
+            .sum();
+        println!(
+            "constants: {} objects, ~{constants_size} bytes",
+            self.compiler.constants.len()
+        );
+
+        let globals = &self.globals[..self.compiler.symbol_table.num_definitions];
+        let globals_size: usize = globals.iter().map(|global| global.approx_size()).sum();
+        println!("globals: {} objects, ~{globals_size} bytes", globals.len());
+    }
+
+    /// Prints the size of the bytecode of `source`, compiled with the session's variables
+    /// and constants. Nothing is run, and the session is left as it was.
+    fn stats(&mut self, source: &str) {
+        let mut parser = Parser::new(Lexer::new(source));
+        let program = parser.parse_program();
+        if !parser.errors.is_empty() {
+            eprintln!("{}", parser.errors);
+            return;
+        }
+        let mut compiler = Compiler::new_with_state(
+            self.compiler.symbol_table.clone(),
+            self.compiler.constants.clone(),
+        );
+        match compiler.compile(program) {
+            Ok(()) => println!("{}", compiler.bytecode().stats()),
+            Err(err) => eprintln!("{}", CompilerError::new(err)),
+        }
+    }
+
+    fn constants(&mut self, _: &str) {
+        print!("{}", self.constants_listing());
+    }
+
+    /// The constant pool, one constant per line after its index. Compiled functions are
+    /// followed by their instructions.
+    fn constants_listing(&self) -> String {
+        let mut listing = String::new();
+        for (i, constant) in self.compiler.constants.iter().enumerate() {
+            if let Object::COMPILEDFUNCTION(function) = constant {
+                let _ = writeln!(
+                    listing,
+                    "{i:04} fn {} (num_parameters={}, num_locals={})",
+                    function.name.as_deref().unwrap_or("<anonymous>"),
+                    function.num_parameters,
+                    function.num_locals
+                );
+                for line in Instructions::new(function.instructions.clone())
+                    .to_string()
+                    .lines()
+                {
+                    let _ = writeln!(listing, "       {line}");
+                }
+            } else {
+                let _ = writeln!(listing, "{i:04} {constant}");
+            }
+        }
+        listing
+    }
+
+    /// Adds a watch, or lists the current ones if `expression` is empty.
+    fn watch(&mut self, expression: &str) {
+        if expression.is_empty() {
+            for watch in &self.watches {
+                println!("{watch}");
+            }
+        } else {
+            self.watches.push(expression.to_string());
+        }
+    }
+
+    /// Removes a watch, or all of them if `expression` is empty.
+    fn unwatch(&mut self, expression: &str) {
+        if expression.is_empty() {
+            self.watches.clear();
+        } else if let Some(index) = self.watches.iter().position(|watch| watch == expression) {
+            self.watches.remove(index);
+        } else {
+            eprintln!("No watch on {expression}");
+        }
+    }
+}
+
+/// A command of the compiler REPL, typed after a `:`.
+struct Command {
+    name: &'static str,
+    usage: &'static str,
+    description: &'static str,
+    run: fn(&mut CompilerSession, &str),
+}
+
+const COMMANDS: [Command; 7] = [
+    Command {
+        name: "help",
+        usage: ":help",
+        description: "lists the commands and the builtin functions",
+        run: CompilerSession::help,
+    },
+    Command {
+        name: "debug",
+        usage: ":debug <program>",
+        description: "steps through the program, which sees the session's variables",
+        run: CompilerSession::debug,
+    },
+    Command {
+        name: "watch",
+        usage: ":watch [expression]",
+        description: "shows the expression at every step of :debug, lists the watches without one",
+        run: CompilerSession::watch,
+    },
+    Command {
+        name: "unwatch",
+        usage: ":unwatch [expression]",
+        description: "removes a watch, or all of them without an expression",
+        run: CompilerSession::unwatch,
+    },
+    Command {
+        name: "mem",
+        usage: ":mem",
+        description: "estimates the memory used by the constants and the globals",
+        run: CompilerSession::memory_summary,
+    },
+    Command {
+        name: "constants",
+        usage: ":constants",
+        description: "lists the constant pool, with the instructions of the functions",
+        run: CompilerSession::constants,
+    },
+    Command {
+        name: "stats",
+        usage: ":stats <program>",
+        description: "compiles the program without running it and shows the size of its bytecode",
+        run: CompilerSession::stats,
+    },
+];
+
+/// Text printed by `:help`, built from the commands and the builtins so that it stays up
+/// to date.
+fn help_text() -> String {
+    let mut text = String::from("Commands:\n");
+    let width = COMMANDS.iter().map(|c| c.usage.len()).max().unwrap_or(0);
+    for command in &COMMANDS {
+        let _ = writeln!(text, "  {:width$}  {}", command.usage, command.description);
+    }
+
+    text.push_str("\nBuiltin functions and their number of arguments:\n");
+    let builtins = BuiltinFunction::iter()
+        .map(|builtin| (builtin.to_string(), builtin.arity()))
+        .collect::<Vec<_>>();
+    let width = builtins
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0);
+    for (name, arity) in builtins {
+        let _ = writeln!(text, "  {name:width$}  {arity}");
+    }
+    text
+}
+
 fn lex(line: &str) -> Result<(), LexerErrors> {
     let mut lexer = Lexer::new(line);
     let mut token = Token::Illegal(String::new());
@@ -347,8 +767,8 @@ fn interpret(interpreter: &mut Evaluator, line: &str) -> Result<String, Box<dyn
     }
     let evaluated = interpreter.eval(program);
 
-    if let Object::ERROR(error) = evaluated {
-        Err(Box::new(RuntimeError::new(error)))
+    if let Object::ERROR(error) = &evaluated {
+        Err(Box::new(RuntimeError::new(error.clone())))
     } else {
         Ok(evaluated.to_string())
     }
@@ -362,14 +782,20 @@ fn compile(line: &str) -> Result<Bytecode, Box<dyn Error>> {
         return Err(Box::new(parser.errors));
     }
     let mut compiler = Compiler::new();
+    compiler.set_diagnostics(Box::new(print_diagnostic));
     match compiler.compile(program) {
         Ok(()) => Ok(compiler.bytecode()),
         Err(e) => Err(Box::new(CompilerError::new(e))),
     }
 }
 
-fn run_vm(bytecode: Bytecode) -> Result<String, Box<dyn Error>> {
-    let mut vm = VM::new(bytecode);
+// Taken by value, as `DiagnosticsCallback` requires
+#[allow(clippy::needless_pass_by_value)]
+fn print_diagnostic(diagnostic: Diagnostic) {
+    eprintln!("{diagnostic}");
+}
+
+fn run_vm(vm: &mut VM) -> Result<String, Box<dyn Error>> {
     match vm.run() {
         Ok(()) => match vm.last_popped_stack_element() {
             Ok(obj) => match obj.as_ref() {
@@ -380,6 +806,50 @@ fn run_vm(bytecode: Bytecode) -> Result<String, Box<dyn Error>> {
                 "No object returned from VM",
             )))),
         },
-        Err(e) => Err(Box::new(RuntimeError::new(e))),
+        Err(e) => {
+            let backtrace = vm.backtrace().join("\n\t  ");
+            Err(Box::new(RuntimeError::new(format!("{e}\n\t  {backtrace}"))))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_help_text() {
+        let text = help_text();
+        assert!(text.starts_with("Commands:\n  :help "));
+        assert!(text.contains("steps through the program"));
+        assert!(text.contains("  len "));
+        assert!(text.contains("  puts "));
+        assert!(text.contains("at least 0"));
+        for command in &COMMANDS {
+            assert!(text.contains(command.usage), "{} is missing", command.name);
+        }
+        for name in BuiltinFunction::get_builtins_names() {
+            assert!(text.contains(&format!("  {name} ")), "{name} is missing");
+        }
+    }
+
+    #[test]
+    fn test_constants_listing() {
+        let mut session = CompilerSession::new();
+        session.eval("let a = 5;");
+        session.eval("let b = 10;");
+        assert_eq!(session.constants_listing(), "0000 5\n0001 10\n");
+
+        session.eval("let add = fn(x) { x + a };");
+        let expected = "\
+0000 5
+0001 10
+0002 fn add (num_parameters=1, num_locals=1)
+       0000 OpGetLocal           0
+       0002 OpGetGlobal          0
+       0005 OpAdd
+       0006 OpReturnValue
+";
+        assert_eq!(session.constants_listing(), expected);
     }
 }
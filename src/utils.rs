@@ -1,5 +1,5 @@
 use crate::{
-    compiler::Compiler,
+    compiler::{Bytecode, Compiler},
     interpreter::evaluator::Evaluator,
     lexer::Lexer,
     object::Object,
@@ -37,3 +37,60 @@ pub fn run_input(input: &str) -> Object {
     let compiler = compile_program(program.clone());
     execute_vm(&compiler)
 }
+
+/// Parses and compiles `source`, without running it, so that the compilation can be timed
+/// on its own. The bytecode is run by `run_bytecode`.
+pub fn compile_source(source: &str) -> Result<Bytecode, String> {
+    let mut parser = Parser::new(Lexer::new(source));
+    let program = parser.parse_program();
+    if !parser.errors.is_empty() {
+        return Err(parser.errors.to_string());
+    }
+
+    let mut compiler = Compiler::new();
+    compiler.compile(program)?;
+    Ok(compiler.bytecode())
+}
+
+/// Runs bytecode returned by `compile_source`, returning the result of the program (see
+/// `VM::result`). A program that results in an error object fails like any runtime error.
+pub fn run_bytecode(bytecode: Bytecode) -> Result<Object, String> {
+    let mut vm = VM::new(bytecode);
+    vm.run()?;
+    match vm.result().as_ref() {
+        Object::ERROR(err) => Err(err.clone()),
+        result => Ok(result.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compile_then_run_matches_evaluation() {
+        let tests = [
+            "1 + 2 * 3",
+            "let a = [1, 2, 3]; a[1] + len(a)",
+            "let f = fn(x) { if (x < 2) { x } else { f(x - 1) + f(x - 2) } }; f(10)",
+            "let h = {\"a\": 1}; h[\"a\"]",
+            "let i = 0; while (i < 5) { let i = i + 1; } i",
+            "if (false) { 1 }",
+        ];
+
+        for input in tests {
+            let bytecode = compile_source(input).unwrap();
+            let compiled = run_bytecode(bytecode).unwrap();
+            let evaluated = Evaluator::new().eval(parse_program(input));
+            assert_eq!(compiled, evaluated, "input: {input}");
+        }
+
+        assert!(compile_source("let = 1;").is_err_and(|err| err.starts_with("Parser errors:")));
+        assert_eq!(
+            compile_source("missing").err(),
+            Some("Undefined variable: missing at 1:1".to_string())
+        );
+        let bytecode = compile_source("1 + true").unwrap();
+        assert!(run_bytecode(bytecode).is_err());
+    }
+}
@@ -1,12 +1,24 @@
 #[allow(clippy::too_many_lines)]
 #[cfg(test)]
 mod tests {
+    use std::{
+        cell::{Cell, RefCell},
+        env, fs,
+        io::Cursor,
+        process,
+        rc::Rc,
+    };
+
     use crate::{
-        compiler::Compiler,
-        object::Object,
+        compiler::{verifier, Compiler},
+        diagnostics::DiagnosticKind,
+        object::{
+            builtins::{BuiltinFunction, BuiltinRegistry},
+            Object,
+        },
         parser::parse,
         vm::{
-            test_utils::{run_vm_tests, VmTestCase},
+            test_utils::{run_vm_tests, run_vm_with_error_output, VmTestCase},
             VM,
         },
     };
@@ -60,6 +72,17 @@ mod tests {
                     .to_string(),
                 expected: Object::INTEGER(99),
             },
+            VmTestCase {
+                input: r"
+                    let sign = fn(x) {
+                        if (x < 0) { return -1; } else if (x > 0) { return 1; } else { return 0; }
+                    };
+                    [sign(-5), sign(5), sign(0)];"
+                    .to_string(),
+                expected: Object::ARRAY(
+                    vec![Object::INTEGER(-1), Object::INTEGER(1), Object::INTEGER(0)].into(),
+                ),
+            },
         ];
         run_vm_tests(tests);
     }
@@ -271,9 +294,9 @@ mod tests {
                 Ok(()) => {
                     panic!("Expected error, but got no error");
                 }
-                Err(e) => match test.expected {
+                Err(e) => match &test.expected {
                     Object::ERROR(msg) => {
-                        assert_eq!(e, msg);
+                        assert_eq!(&e, msg);
                     }
                     _ => {
                         unreachable!("Poorly written test, the expected value should be an error");
@@ -303,7 +326,8 @@ mod tests {
                 expected: Object::ERROR("argument to `len` not supported, got INTEGER".to_string()),
             },
             VmTestCase {
-                input: r#"len("one", "two")"#.to_string(),
+                // Called through a variable so that the arity is not checked at compile time
+                input: r#"let length = len; length("one", "two")"#.to_string(),
                 expected: Object::ERROR("wrong number of arguments. got=2, want=1".to_string()),
             },
             VmTestCase {
@@ -315,9 +339,25 @@ mod tests {
                 expected: Object::INTEGER(0),
             },
             VmTestCase {
-                input: r"len([1, 2, 3], [4, 5, 6])".to_string(),
+                input: r"let length = len; length([1, 2, 3], [4, 5, 6])".to_string(),
                 expected: Object::ERROR("wrong number of arguments. got=2, want=1".to_string()),
             },
+            VmTestCase {
+                input: r#"len({"a": 1, "b": 2})"#.to_string(),
+                expected: Object::INTEGER(2),
+            },
+            VmTestCase {
+                input: r"len({})".to_string(),
+                expected: Object::INTEGER(0),
+            },
+            VmTestCase {
+                input: r"len(5)".to_string(),
+                expected: Object::ERROR("argument to `len` not supported, got INTEGER".to_string()),
+            },
+            VmTestCase {
+                input: r"len(true)".to_string(),
+                expected: Object::ERROR("argument to `len` not supported, got BOOLEAN".to_string()),
+            },
             VmTestCase {
                 input: r"first([1, 2, 3])".to_string(),
                 expected: Object::INTEGER(1),
@@ -348,7 +388,7 @@ mod tests {
             },
             VmTestCase {
                 input: r"rest([1, 2, 3])".to_string(),
-                expected: Object::ARRAY(vec![Object::INTEGER(2), Object::INTEGER(3)]),
+                expected: Object::ARRAY(vec![Object::INTEGER(2), Object::INTEGER(3)].into()),
             },
             VmTestCase {
                 input: r"rest([])".to_string(),
@@ -356,7 +396,7 @@ mod tests {
             },
             VmTestCase {
                 input: r"push([], 1)".to_string(),
-                expected: Object::ARRAY(vec![Object::INTEGER(1)]),
+                expected: Object::ARRAY(vec![Object::INTEGER(1)].into()),
             },
             VmTestCase {
                 input: r"push(1, 1)".to_string(),
@@ -364,9 +404,1245 @@ mod tests {
                     "argument to `push` not supported, must be ARRAY, got INTEGER".to_string(),
                 ),
             },
+            VmTestCase {
+                input: r#"to_upper("hello")"#.to_string(),
+                expected: Object::STRING("HELLO".to_string()),
+            },
+            VmTestCase {
+                input: r#"to_upper("straße")"#.to_string(),
+                expected: Object::STRING("STRASSE".to_string()),
+            },
+            VmTestCase {
+                input: r#"to_lower("ÉCOLE")"#.to_string(),
+                expected: Object::STRING("école".to_string()),
+            },
+            VmTestCase {
+                input: r"to_lower(1)".to_string(),
+                expected: Object::ERROR(
+                    "argument to `to_lower` not supported, must be STRING, got INTEGER".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"trim("  héllo wörld ")"#.to_string(),
+                expected: Object::STRING("héllo wörld".to_string()),
+            },
+            VmTestCase {
+                input: r"trim([1])".to_string(),
+                expected: Object::ERROR(
+                    "argument to `trim` not supported, must be STRING, got ARRAY".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"to_json({"a": [1, true, "x"], "b": {"c": 'd'}})"#.to_string(),
+                expected: Object::STRING(r#"{"a":[1,true,"x"],"b":{"c":"d"}}"#.to_string()),
+            },
+            VmTestCase {
+                input: r#"from_json(" [1, [2, [null]], false]")[1]"#.to_string(),
+                expected: Object::ARRAY(vec![
+                    Object::INTEGER(2),
+                    Object::ARRAY(vec![Object::NULL].into()),
+                ].into()),
+            },
+            VmTestCase {
+                input: r#"let v = {"a": [[1], {"b": "c"}]}; from_json(to_json(v))["a"][1]["b"]"#
+                    .to_string(),
+                expected: Object::STRING("c".to_string()),
+            },
+            VmTestCase {
+                input: r"to_json([1.5, 2.0])".to_string(),
+                expected: Object::STRING("[1.5,2.0]".to_string()),
+            },
+            VmTestCase {
+                input: r#"from_json("1.5") + from_json("2e3")"#.to_string(),
+                expected: Object::FLOAT(2001.5),
+            },
+            VmTestCase {
+                input: r#"from_json("[1,")"#.to_string(),
+                expected: Object::ERROR("invalid JSON: unexpected end of input".to_string()),
+            },
+            VmTestCase {
+                input: r"to_json([len])".to_string(),
+                expected: Object::ERROR(
+                    "unable to convert to JSON: BUILTIN cannot be converted to JSON".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r"repeat(3, fn() { 1 })".to_string(),
+                expected: Object::ARRAY(vec![
+                    Object::INTEGER(1),
+                    Object::INTEGER(1),
+                    Object::INTEGER(1),
+                ].into()),
+            },
+            VmTestCase {
+                input: r"repeat(0, fn() { 1 })".to_string(),
+                expected: Object::ARRAY(vec![].into()),
+            },
+            VmTestCase {
+                input: r"repeat(-1, fn() { 1 })".to_string(),
+                expected: Object::ERROR(
+                    "first argument to `repeat` must not be negative, got -1".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"repeat("3", fn() { 1 })"#.to_string(),
+                expected: Object::ERROR(
+                    "first argument to `repeat` not supported, must be INTEGER, got STRING"
+                        .to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r"repeat(2, 1)".to_string(),
+                expected: Object::ERROR(
+                    "second argument to `repeat` not supported, must be a function, got INTEGER"
+                        .to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r"let rep = repeat; rep(2)".to_string(),
+                expected: Object::ERROR("wrong number of arguments. got=1, want=2".to_string()),
+            },
+            VmTestCase {
+                input: r#"[str(1), str("a"), str('b'), str([1, "c"]), str(len)]"#.to_string(),
+                expected: Object::ARRAY(vec![
+                    Object::STRING("1".to_string()),
+                    Object::STRING("a".to_string()),
+                    Object::STRING("b".to_string()),
+                    Object::STRING("[1, \"c\"]".to_string()),
+                    Object::STRING("len".to_string()),
+                ].into()),
+            },
+            VmTestCase {
+                input: r"reverse([1, [2, 3], 4])".to_string(),
+                expected: Object::ARRAY(vec![
+                    Object::INTEGER(4),
+                    Object::ARRAY(vec![Object::INTEGER(2), Object::INTEGER(3)].into()),
+                    Object::INTEGER(1),
+                ].into()),
+            },
+            VmTestCase {
+                input: r"let a = [1, 2]; reverse(a); a".to_string(),
+                expected: Object::ARRAY(vec![Object::INTEGER(1), Object::INTEGER(2)].into()),
+            },
+            VmTestCase {
+                input: r"reverse([])".to_string(),
+                expected: Object::ARRAY(vec![].into()),
+            },
+            VmTestCase {
+                input: r#"reverse("héllo, 世界 🐒")"#.to_string(),
+                expected: Object::STRING("🐒 界世 ,olléh".to_string()),
+            },
+            VmTestCase {
+                input: r"reverse(12)".to_string(),
+                expected: Object::ERROR(
+                    "argument to `reverse` not supported, must be ARRAY or STRING, got INTEGER"
+                        .to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r"slice([1, 2, 3, 4], 1, 3)".to_string(),
+                expected: Object::ARRAY(vec![Object::INTEGER(2), Object::INTEGER(3)].into()),
+            },
+            VmTestCase {
+                input: r"slice([1, 2, 3, 4], 2)".to_string(),
+                expected: Object::ARRAY(vec![Object::INTEGER(3), Object::INTEGER(4)].into()),
+            },
+            VmTestCase {
+                input: r"slice([1, 2, 3], -5, 10)".to_string(),
+                expected: Object::ARRAY(vec![
+                    Object::INTEGER(1),
+                    Object::INTEGER(2),
+                    Object::INTEGER(3),
+                ].into()),
+            },
+            VmTestCase {
+                input: r"slice([1, 2, 3], 2, 1)".to_string(),
+                expected: Object::ARRAY(vec![].into()),
+            },
+            VmTestCase {
+                input: r#"slice("héllo wörld", 1, 9)"#.to_string(),
+                expected: Object::STRING("éllo wör".to_string()),
+            },
+            VmTestCase {
+                input: r#"slice("日本語", 1)"#.to_string(),
+                expected: Object::STRING("本語".to_string()),
+            },
+            VmTestCase {
+                input: r#"slice("abc", 5)"#.to_string(),
+                expected: Object::STRING(String::new()),
+            },
+            VmTestCase {
+                input: r"slice(1, 0)".to_string(),
+                expected: Object::ERROR(
+                    "first argument to `slice` not supported, must be ARRAY or STRING, got INTEGER"
+                        .to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"slice([1], "0")"#.to_string(),
+                expected: Object::ERROR(
+                    "bounds of `slice` not supported, must be INTEGER, got STRING".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"[hash_code("key") == hash_code("k" + "ey"), hash_code(3) == hash_code(1 + 2)]"#
+                    .to_string(),
+                expected: Object::ARRAY(vec![Object::BOOLEAN(true), Object::BOOLEAN(true)].into()),
+            },
+            VmTestCase {
+                input: r"hash_code(true) == hash_code(false)".to_string(),
+                expected: Object::BOOLEAN(false),
+            },
+            VmTestCase {
+                input: r"hash_code([1])".to_string(),
+                expected: Object::ERROR(
+                    "argument to `hash_code` not supported, must be a hashable value, got ARRAY"
+                        .to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"[starts_with("monkey", "mon"), starts_with("monkey", "key"), starts_with("", "")]"#
+                    .to_string(),
+                expected: Object::ARRAY(vec![
+                    Object::BOOLEAN(true),
+                    Object::BOOLEAN(false),
+                    Object::BOOLEAN(true),
+                ].into()),
+            },
+            VmTestCase {
+                input: r#"[ends_with("monkey", "key"), ends_with("monkey", "Key")]"#.to_string(),
+                expected: Object::ARRAY(vec![Object::BOOLEAN(true), Object::BOOLEAN(false)].into()),
+            },
+            VmTestCase {
+                input: r#"starts_with("monkey", 'm')"#.to_string(),
+                expected: Object::ERROR(
+                    "second argument to `starts_with` not supported, must be STRING, got CHAR"
+                        .to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"ends_with(1, "1")"#.to_string(),
+                expected: Object::ERROR(
+                    "first argument to `ends_with` not supported, must be STRING, got INTEGER"
+                        .to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"replace("a-b-c--d", "-", ", ")"#.to_string(),
+                expected: Object::STRING("a, b, c, , d".to_string()),
+            },
+            VmTestCase {
+                input: r#"replace("banana", "ana", "")"#.to_string(),
+                expected: Object::STRING("bna".to_string()),
+            },
+            VmTestCase {
+                input: r#"replace("abc", "", "x")"#.to_string(),
+                expected: Object::ERROR(
+                    "second argument to `replace` must not be an empty string".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"replace("abc", "b", [1])"#.to_string(),
+                expected: Object::ERROR(
+                    "third argument to `replace` not supported, must be STRING, got ARRAY"
+                        .to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r"[floor(2.7), floor(-2.2), floor(3), ceil(2.2), ceil(-2.7), ceil(-0.5)]"
+                    .to_string(),
+                expected: Object::ARRAY(vec![
+                    Object::INTEGER(2),
+                    Object::INTEGER(-3),
+                    Object::INTEGER(3),
+                    Object::INTEGER(3),
+                    Object::INTEGER(-2),
+                    Object::INTEGER(0),
+                ].into()),
+            },
+            VmTestCase {
+                input: r"[round(2.5), round(2.49), round(-2.5), round(-7)]".to_string(),
+                expected: Object::ARRAY(vec![
+                    Object::INTEGER(3),
+                    Object::INTEGER(2),
+                    Object::INTEGER(-3),
+                    Object::INTEGER(-7),
+                ].into()),
+            },
+            VmTestCase {
+                input: r"round(1e19)".to_string(),
+                expected: Object::ERROR(
+                    "argument to `round` is out of range, got 1e19".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r"floor(0.0 / 0.0)".to_string(),
+                expected: Object::ERROR("argument to `floor` is out of range, got NaN".to_string()),
+            },
+            VmTestCase {
+                input: r#"ceil("1.5")"#.to_string(),
+                expected: Object::ERROR(
+                    "argument to `ceil` not supported, must be INTEGER or FLOAT, got STRING"
+                        .to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r"[sqrt(2.25), sqrt(16), sqrt(0)]".to_string(),
+                expected: Object::ARRAY(vec![
+                    Object::FLOAT(1.5),
+                    Object::FLOAT(4.0),
+                    Object::FLOAT(0.0),
+                ].into()),
+            },
+            VmTestCase {
+                input: r"sqrt(-0.25)".to_string(),
+                expected: Object::ERROR(
+                    "argument to `sqrt` must not be negative, got -0.25".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r"sqrt(true)".to_string(),
+                expected: Object::ERROR(
+                    "argument to `sqrt` not supported, must be INTEGER or FLOAT, got BOOLEAN"
+                        .to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r"flatten([[1, 2], [3]])".to_string(),
+                expected: Object::ARRAY(vec![
+                    Object::INTEGER(1),
+                    Object::INTEGER(2),
+                    Object::INTEGER(3),
+                ].into()),
+            },
+            VmTestCase {
+                input: r#"flatten([1, [2, [3, [4]]], [], "a"])"#.to_string(),
+                expected: Object::ARRAY(vec![
+                    Object::INTEGER(1),
+                    Object::INTEGER(2),
+                    Object::ARRAY(vec![
+                        Object::INTEGER(3),
+                        Object::ARRAY(vec![Object::INTEGER(4)].into()),
+                    ].into()),
+                    Object::STRING("a".to_string()),
+                ].into()),
+            },
+            VmTestCase {
+                input: r#"flatten_deep([1, [2, [3, [4]]], [[]], "a"])"#.to_string(),
+                expected: Object::ARRAY(vec![
+                    Object::INTEGER(1),
+                    Object::INTEGER(2),
+                    Object::INTEGER(3),
+                    Object::INTEGER(4),
+                    Object::STRING("a".to_string()),
+                ].into()),
+            },
+            VmTestCase {
+                input: r"flatten_deep([])".to_string(),
+                expected: Object::ARRAY(vec![].into()),
+            },
+            VmTestCase {
+                input: r#"flatten("ab")"#.to_string(),
+                expected: Object::ERROR(
+                    "argument to `flatten` not supported, must be ARRAY, got STRING".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r"flatten_deep({})".to_string(),
+                expected: Object::ERROR(
+                    "argument to `flatten_deep` not supported, must be ARRAY, got HASHMAP"
+                        .to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"zip([1, 2, 3], ["a", "b", "c"])"#.to_string(),
+                expected: Object::ARRAY(vec![
+                    Object::ARRAY(
+                        vec![Object::INTEGER(1), Object::STRING("a".to_string())].into(),
+                    ),
+                    Object::ARRAY(
+                        vec![Object::INTEGER(2), Object::STRING("b".to_string())].into(),
+                    ),
+                    Object::ARRAY(
+                        vec![Object::INTEGER(3), Object::STRING("c".to_string())].into(),
+                    ),
+                ].into()),
+            },
+            VmTestCase {
+                input: r#"zip([1, 2, 3], ["a"])"#.to_string(),
+                expected: Object::ARRAY(vec![
+                    Object::ARRAY(
+                        vec![Object::INTEGER(1), Object::STRING("a".to_string())].into(),
+                    ),
+                ].into()),
+            },
+            VmTestCase {
+                input: r"zip([], [1, 2])".to_string(),
+                expected: Object::ARRAY(vec![].into()),
+            },
+            VmTestCase {
+                input: r#"enumerate(["a", "b"])"#.to_string(),
+                expected: Object::ARRAY(vec![
+                    Object::ARRAY(
+                        vec![Object::INTEGER(0), Object::STRING("a".to_string())].into(),
+                    ),
+                    Object::ARRAY(
+                        vec![Object::INTEGER(1), Object::STRING("b".to_string())].into(),
+                    ),
+                ].into()),
+            },
+            VmTestCase {
+                input: r"enumerate([])".to_string(),
+                expected: Object::ARRAY(vec![].into()),
+            },
+            VmTestCase {
+                input: r"zip(1, [2])".to_string(),
+                expected: Object::ERROR(
+                    "first argument to `zip` not supported, must be ARRAY, got INTEGER".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"zip([1], "2")"#.to_string(),
+                expected: Object::ERROR(
+                    "second argument to `zip` not supported, must be ARRAY, got STRING".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r"enumerate(true)".to_string(),
+                expected: Object::ERROR(
+                    "argument to `enumerate` not supported, must be ARRAY, got BOOLEAN".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"[ord("A"), ord("abc"), ord('é'), ord("€")]"#.to_string(),
+                expected: Object::ARRAY(vec![
+                    Object::INTEGER(65),
+                    Object::INTEGER(97),
+                    Object::INTEGER(233),
+                    Object::INTEGER(8364),
+                ].into()),
+            },
+            VmTestCase {
+                input: r"[chr(65), chr(8364), chr(128000)]".to_string(),
+                expected: Object::ARRAY(vec![
+                    Object::STRING("A".to_string()),
+                    Object::STRING("€".to_string()),
+                    Object::STRING("🐀".to_string()),
+                ].into()),
+            },
+            VmTestCase {
+                input: r#"chr(ord("🐒")) == "🐒""#.to_string(),
+                expected: Object::BOOLEAN(true),
+            },
+            VmTestCase {
+                input: r#"ord("")"#.to_string(),
+                expected: Object::ERROR(
+                    "argument to `ord` must not be an empty string".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r"ord(65)".to_string(),
+                expected: Object::ERROR(
+                    "argument to `ord` not supported, must be STRING or CHAR, got INTEGER"
+                        .to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r"chr(55296)".to_string(),
+                expected: Object::ERROR(
+                    "argument to `chr` is not a valid code point, got 55296".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r"seed(1); rand_int(5, 6)".to_string(),
+                expected: Object::INTEGER(5),
+            },
+            VmTestCase {
+                input: r"rand_int(3, 1)".to_string(),
+                expected: Object::ERROR(
+                    "first argument to `rand_int` must be less than the second, got 3 and 1"
+                        .to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r"rand_int(0, 1.5)".to_string(),
+                expected: Object::ERROR(
+                    "second argument to `rand_int` not supported, must be INTEGER, got FLOAT"
+                        .to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"seed("a")"#.to_string(),
+                expected: Object::ERROR(
+                    "argument to `seed` not supported, must be INTEGER, got STRING".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r"chr(-1)".to_string(),
+                expected: Object::ERROR(
+                    "argument to `chr` is not a valid code point, got -1".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r#"chr("A")"#.to_string(),
+                expected: Object::ERROR(
+                    "argument to `chr` not supported, must be INTEGER, got STRING".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r"[1, 2, 3].len() == len([1, 2, 3])".to_string(),
+                expected: Object::BOOLEAN(true),
+            },
+            VmTestCase {
+                input: r#""ab".to_upper() == "AB""#.to_string(),
+                expected: Object::BOOLEAN(true),
+            },
+            VmTestCase {
+                input: r"[1, 2].push(3).slice(1).len()".to_string(),
+                expected: Object::INTEGER(2),
+            },
+            VmTestCase {
+                // A variable shadows the builtin in a call, but not in a method call
+                input: r"let len = fn(x) { 99 }; [[1, 2].len(), len([1, 2])]".to_string(),
+                expected: Object::ARRAY(vec![Object::INTEGER(2), Object::INTEGER(99)].into()),
+            },
+            VmTestCase {
+                input: r"let f = fn() { let len = 5; [1].len() }; f()".to_string(),
+                expected: Object::INTEGER(1),
+            },
+            VmTestCase {
+                input: r"let s = slice; s([1])".to_string(),
+                expected: Object::ERROR(
+                    "wrong number of arguments. got=1, want=2 to 3".to_string(),
+                ),
+            },
+        ];
+
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_type_predicates() {
+        let values = [
+            ("1", "is_int"),
+            ("\"one\"", "is_string"),
+            ("[1]", "is_array"),
+            ("{1: 2}", "is_hash"),
+            ("fn() { 1 }", "is_fn"),
+            ("len", "is_fn"),
+            ("if (false) { 1 }", "is_null"),
+            ("'c'", ""),
+            ("true", ""),
+        ];
+        let predicates = [
+            "is_int",
+            "is_string",
+            "is_array",
+            "is_hash",
+            "is_fn",
+            "is_null",
+        ];
+
+        let mut tests = Vec::new();
+        for (value, matching) in values {
+            for predicate in predicates {
+                tests.push(VmTestCase {
+                    input: format!("let value = {value}; {predicate}(value)"),
+                    expected: Object::BOOLEAN(predicate == matching),
+                });
+            }
+        }
+        tests.push(VmTestCase {
+            input: "let add = fn(a) { fn(b) { a + b } }; is_fn(add(1))".to_string(),
+            expected: Object::BOOLEAN(true),
+        });
+        tests.push(VmTestCase {
+            input: "let check = is_int; check(1, 2)".to_string(),
+            expected: Object::ERROR("wrong number of arguments. got=2, want=1".to_string()),
+        });
+
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_repeat_calls_closures() {
+        let tests = vec![
+            VmTestCase {
+                // The closure sees its free variables and the globals
+                input: r"
+                    let offset = 10;
+                    let counter = fn(start) {
+                        fn() { start + offset }
+                    };
+                    let results = repeat(2, counter(5));
+                    results[0] + results[1]"
+                    .to_string(),
+                expected: Object::INTEGER(30),
+            },
+            VmTestCase {
+                // Nested calls, inside a function with locals
+                input: r"
+                    let grid = fn(size) {
+                        let row = fn() { repeat(size, fn() { 0 }) };
+                        repeat(size, row)
+                    };
+                    let g = grid(2);
+                    [len(g), len(g[1]), 1 + 1]"
+                    .to_string(),
+                expected: Object::ARRAY(
+                    vec![Object::INTEGER(2), Object::INTEGER(2), Object::INTEGER(2)].into(),
+                ),
+            },
+            VmTestCase {
+                input: r"repeat(2, fn() { len([1, 2]) }); repeat(1, rest)".to_string(),
+                expected: Object::ERROR("wrong number of arguments. got=0, want=1".to_string()),
+            },
         ];
 
         run_vm_tests(tests);
+
+        assert_eq!(
+            run_vm_with_error_output("repeat(2, fn(a) { a })"),
+            Err("Wrong number of arguments: want=1, got=0".to_string())
+        );
+    }
+
+    #[test]
+    fn test_partial_application() {
+        let tests = vec![
+            VmTestCase {
+                input: "let add = fn(a, b) { a + b }; let inc = partial(add, 1); inc(2)".to_string(),
+                expected: Object::INTEGER(3),
+            },
+            VmTestCase {
+                input: "let add = fn(a, b, c) { a * 100 + b * 10 + c }; partial(partial(add, 1), 2)(3)"
+                    .to_string(),
+                expected: Object::INTEGER(123),
+            },
+            VmTestCase {
+                // Every argument can be bound
+                input: "let add = fn(a, b) { a + b }; partial(add, 1, 2)()".to_string(),
+                expected: Object::INTEGER(3),
+            },
+            VmTestCase {
+                input: "let offset = 10; let f = fn(a, b) { a - b + offset }; repeat(2, partial(f, 5, 1))"
+                    .to_string(),
+                expected: Object::ARRAY(vec![Object::INTEGER(14), Object::INTEGER(14)].into()),
+            },
+            VmTestCase {
+                input: "partial(push, [1])(2)".to_string(),
+                expected: Object::ARRAY(vec![Object::INTEGER(1), Object::INTEGER(2)].into()),
+            },
+            VmTestCase {
+                input: "let f = fn(a) { a }; is_fn(partial(f))".to_string(),
+                expected: Object::BOOLEAN(true),
+            },
+            VmTestCase {
+                input: "let add = fn(a, b) { a + b }; partial(add, 1, 2, 3)".to_string(),
+                expected: Object::ERROR(
+                    "too many arguments for `partial`: the function takes 2, got 3".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: "partial(1, 2)".to_string(),
+                expected: Object::ERROR(
+                    "first argument to `partial` not supported, must be a function, got INTEGER"
+                        .to_string(),
+                ),
+            },
+            VmTestCase {
+                input: "partial(len, [1])(2)".to_string(),
+                expected: Object::ERROR("wrong number of arguments. got=2, want=1".to_string()),
+            },
+        ];
+
+        run_vm_tests(tests);
+
+        assert_eq!(
+            run_vm_with_error_output("let add = fn(a, b) { a + b }; partial(add, 1)(2, 3)"),
+            Err("Wrong number of arguments: want=2, got=3".to_string())
+        );
+    }
+
+    #[test]
+    fn test_dbg() {
+        let tests = vec![
+            VmTestCase {
+                input: "dbg(1 + 2) * 2".to_string(),
+                expected: Object::INTEGER(6),
+            },
+            VmTestCase {
+                input: "let a = [1, \"two\"]; len(dbg(a))".to_string(),
+                expected: Object::INTEGER(2),
+            },
+        ];
+        run_vm_tests(tests);
+
+        let tests = vec![
+            ("dbg(1 + 2) * 2", "[dbg] 3 (INTEGER)\n6"),
+            (
+                "let f = fn(x) { dbg(x) }; f(\"a\"); f([1, 'b'])",
+                "[dbg] \"a\" (STRING)\n[dbg] [1, 'b'] (ARRAY)\n[1, 'b']",
+            ),
+            ("dbg(first([]))", "[dbg] null (NULL)\n"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(
+                crate::embed::eval_to_string(input),
+                expected,
+                "input: {input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let tests = vec![
+            VmTestCase {
+                input: "apply(fn(a, b) { a + b }, [1, 2]) == 3".to_string(),
+                expected: Object::BOOLEAN(true),
+            },
+            VmTestCase {
+                input: "let x = 10; let f = fn(a) { a + x }; apply(f, [1]) + apply(f, [2])"
+                    .to_string(),
+                expected: Object::INTEGER(23),
+            },
+            VmTestCase {
+                input: "apply(partial(push, [1]), [2])".to_string(),
+                expected: Object::ARRAY(vec![Object::INTEGER(1), Object::INTEGER(2)].into()),
+            },
+            VmTestCase {
+                input: "apply(len, [\"abc\"])".to_string(),
+                expected: Object::INTEGER(3),
+            },
+            VmTestCase {
+                input: "let fact = fn(n) { if (n < 2) { 1 } else { n * apply(fact, [n - 1]) } }; fact(5)"
+                    .to_string(),
+                expected: Object::INTEGER(120),
+            },
+            VmTestCase {
+                input: "apply(1, [])".to_string(),
+                expected: Object::ERROR(
+                    "first argument to `apply` not supported, must be a function, got INTEGER"
+                        .to_string(),
+                ),
+            },
+            VmTestCase {
+                input: "apply(len, \"abc\")".to_string(),
+                expected: Object::ERROR(
+                    "second argument to `apply` not supported, must be ARRAY, got STRING"
+                        .to_string(),
+                ),
+            },
+        ];
+
+        run_vm_tests(tests);
+
+        assert_eq!(
+            run_vm_with_error_output("apply(fn(a, b) { a + b }, [1])"),
+            Err("Wrong number of arguments: want=2, got=1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_assertions() {
+        let tests = vec![
+            VmTestCase {
+                input: r"assert(1 < 2); assert([]); 10".to_string(),
+                expected: Object::INTEGER(10),
+            },
+            VmTestCase {
+                input: r#"assert_eq([1, "a"], [1, "a"]); assert_eq(len("ab"), 2)"#.to_string(),
+                expected: Object::NULL,
+            },
+        ];
+
+        run_vm_tests(tests);
+
+        let tests = vec![
+            ("assert(1 > 2); 10", "assertion failed: got false"),
+            (
+                "let check = fn(x) { assert(x) }; check(true); check(if (false) { 1 }); 10",
+                "assertion failed: got null",
+            ),
+            (
+                r#"assert_eq(1 + 1, 3); puts("unreachable")"#,
+                "assertion failed: left: 2, right: 3",
+            ),
+            (
+                r#"assert_eq([1, "a"], [1, "b"])"#,
+                r#"assertion failed: left: [1, "a"], right: [1, "b"]"#,
+            ),
+            (
+                "let check = assert; check()",
+                "wrong number of arguments. got=0, want=1",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(
+                run_vm_with_error_output(input),
+                Err(expected.to_string()),
+                "input: {input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_exit() {
+        let tests = vec![
+            ("exit(2); 10", Some(2)),
+            ("let f = fn() { exit(0); 5 }; let a = 1; f(); 3", Some(0)),
+            ("repeat(5, fn() { exit(7) }); 10", Some(7)),
+            ("10", None),
+        ];
+
+        for (input, expected) in tests {
+            let mut compiler = Compiler::new();
+            compiler.compile(parse(input)).unwrap();
+            let mut vm = VM::new(compiler.bytecode());
+            vm.run().unwrap();
+
+            assert_eq!(vm.exit_code(), expected, "input: {input}");
+            assert!(vm.is_finished());
+        }
+
+        let tests = vec![
+            VmTestCase {
+                input: r#"exit("1")"#.to_string(),
+                expected: Object::ERROR(
+                    "argument to `exit` not supported, must be INTEGER, got STRING".to_string(),
+                ),
+            },
+            VmTestCase {
+                input: r"exit(2147483648)".to_string(),
+                expected: Object::ERROR(
+                    "argument to `exit` is out of range, got 2147483648".to_string(),
+                ),
+            },
+        ];
+
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_read_line() {
+        let input = r#"
+            let name = read_line();
+            let lines = repeat(2, read_line);
+            let read = read_line;
+            [name, lines, read_line(), read(1)]"#;
+        let mut compiler = Compiler::new();
+        compiler.compile(parse(input)).unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        vm.set_input(Box::new(Cursor::new("monkey\r\nfirst line\n\n")));
+        vm.run().unwrap();
+
+        assert_eq!(
+            vm.last_popped_stack_element().unwrap().as_ref(),
+            &Object::ARRAY(
+                vec![
+                    Object::STRING("monkey".to_string()),
+                    Object::ARRAY(
+                        vec![
+                            Object::STRING("first line".to_string()),
+                            Object::STRING(String::new()),
+                        ]
+                        .into()
+                    ),
+                    Object::NULL,
+                    Object::ERROR("wrong number of arguments. got=1, want=0".to_string()),
+                ]
+                .into()
+            )
+        );
+    }
+
+    #[test]
+    fn test_clock() {
+        let input = r#"
+            let start = clock();
+            let end = clock();
+            let time = clock;
+            [start, end - start, time(1)]"#;
+        let mut compiler = Compiler::new();
+        compiler.compile(parse(input)).unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        let now = Rc::new(Cell::new(1000));
+        let time = Rc::clone(&now);
+        vm.set_clock(Box::new(move || {
+            time.set(time.get() + 250);
+            time.get()
+        }));
+        vm.run().unwrap();
+
+        assert_eq!(
+            vm.last_popped_stack_element().unwrap().as_ref(),
+            &Object::ARRAY(
+                vec![
+                    Object::INTEGER(1250),
+                    Object::INTEGER(250),
+                    Object::ERROR("wrong number of arguments. got=1, want=0".to_string()),
+                ]
+                .into()
+            )
+        );
+        assert_eq!(now.get(), 1500);
+
+        // Without a clock, as on wasm32, `clock` fails instead
+        assert_eq!(
+            BuiltinFunction::call_clock(&[], None),
+            Object::ERROR("`clock` is not available on this platform".to_string())
+        );
+    }
+
+    #[test]
+    fn test_random() {
+        let run = |input: &str| {
+            let mut compiler = Compiler::new();
+            compiler.compile(parse(input)).unwrap();
+            let mut vm = VM::new(compiler.bytecode());
+            vm.run().unwrap();
+            vm.last_popped_stack_element().unwrap().as_ref().clone()
+        };
+
+        let seeded = r"
+            seed(42);
+            let floats = repeat(5, rand);
+            let ints = [];
+            let i = 0;
+            while (i < 5) {
+                ints = push(ints, rand_int(10, 20));
+                i = i + 1;
+            }
+            [floats, ints]";
+        let first = run(seeded);
+        assert_eq!(first, run(seeded));
+        assert_ne!(first, run(&seeded.replace("seed(42)", "seed(43)")));
+
+        let in_range = r"
+            let i = 0;
+            let ok = true;
+            while (i < 1000) {
+                let n = rand_int(-3, 4);
+                let f = rand();
+                ok = ok && (n >= -3) && (n < 4) && (f >= 0.0) && (f < 1.0);
+                i = i + 1;
+            }
+            ok";
+        assert_eq!(run(in_range), Object::BOOLEAN(true));
+    }
+
+    #[test]
+    fn test_read_file() {
+        let path = env::temp_dir().join(format!("read_file-{}.txt", process::id()));
+        fs::write(&path, "let a = 1;\nlet b = 2;\n").unwrap();
+        let path = path.to_str().unwrap();
+
+        let tests = vec![
+            VmTestCase {
+                input: format!(r#"read_file("{path}")"#),
+                expected: Object::STRING("let a = 1;\nlet b = 2;\n".to_string()),
+            },
+            VmTestCase {
+                input: format!(r#"len(read_file("{path}"))"#),
+                expected: Object::INTEGER(22),
+            },
+            VmTestCase {
+                input: r"read_file(1)".to_string(),
+                expected: Object::ERROR(
+                    "argument to `read_file` not supported, must be STRING, got INTEGER"
+                        .to_string(),
+                ),
+            },
+        ];
+        run_vm_tests(tests);
+        fs::remove_file(path).unwrap();
+
+        let mut compiler = Compiler::new();
+        compiler
+            .compile(parse(r#"read_file("missing.txt")"#))
+            .unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        vm.run().unwrap();
+        match vm.last_popped_stack_element().unwrap().as_ref() {
+            Object::ERROR(err) => assert!(err.starts_with("unable to read file missing.txt: ")),
+            other => panic!("expected an error, got {other}"),
+        }
+    }
+
+    #[test]
+    fn test_many_global_references() {
+        // Identifiers cannot contain digits, so the globals are named ga, gb... gzz
+        let names = ('a'..='z')
+            .flat_map(|first| ('a'..='z').map(move |second| format!("g{first}{second}")))
+            .take(200)
+            .collect::<Vec<_>>();
+        let mut input = String::new();
+        for (i, name) in names.iter().enumerate() {
+            input.push_str(&format!("let {name} = {i};\n"));
+        }
+        let references = [names.join(", "), names.join(", "), names.join(", ")].join(", ");
+        // Every global is referenced several times from nested scopes, where they are
+        // resolved through the outer symbol tables
+        input.push_str(&format!(
+            "let outer = fn(x) {{ let inner = fn() {{ push([{references}], x) }}; inner() }};\n"
+        ));
+        input.push_str(
+            "let values = outer(1000); [len(values), values[0], values[399], values[600]]",
+        );
+
+        run_vm_tests(vec![VmTestCase {
+            input,
+            expected: Object::ARRAY(
+                vec![
+                    Object::INTEGER(601),
+                    Object::INTEGER(0),
+                    Object::INTEGER(199),
+                    Object::INTEGER(1000),
+                ]
+                .into(),
+            ),
+        }]);
+    }
+
+    #[test]
+    fn test_backtrace() {
+        let input = r"
+            let inner = fn(x) { x / 0 };
+            let middle = fn(x) { inner(x) + 1 };
+            let outer = fn() { (fn() { middle(1) })() };
+            outer()";
+        let mut compiler = Compiler::new();
+        compiler.compile(parse(input)).unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+
+        assert_eq!(vm.run(), Err("Division by zero".to_string()));
+        let backtrace = vm.backtrace();
+        // 0000 OpGetLocal 0, 0002 OpConstant 0, 0005 OpDiv
+        assert_eq!(backtrace[0], "inner at 0005");
+        let names = backtrace
+            .iter()
+            .map(|frame| frame.split(" at ").next().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec!["inner", "middle", "<anonymous>", "outer", "<main>"]
+        );
+
+        let vm = VM::new(Compiler::new().bytecode());
+        assert_eq!(vm.backtrace(), vec!["<main> at 0000"]);
+    }
+
+    #[test]
+    fn test_large_arrays_are_passed_without_copying() {
+        let mut compiler = Compiler::new();
+        let program = parse(
+            r"
+            let identity = fn(x) { x };
+            let pass = fn(array, depth) {
+                if (depth == 0) { array } else { pass(identity(array), depth - 1) }
+            };
+            let append = fn(array) { push(array, 0) };
+            ",
+        );
+        compiler.compile(program).unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        vm.run().unwrap();
+
+        let array = Rc::new((0..100_000).map(Object::INTEGER).collect::<Vec<_>>());
+        let passed = vm
+            .call_function(
+                "pass",
+                vec![Object::ARRAY(Rc::clone(&array)), Object::INTEGER(500)],
+            )
+            .unwrap();
+        match &passed {
+            Object::ARRAY(passed) => assert!(Rc::ptr_eq(passed, &array)),
+            other => panic!("expected an array, got {other}"),
+        }
+
+        // Pushing copies the shared array instead of modifying it
+        let appended = vm
+            .call_function("append", vec![Object::ARRAY(Rc::clone(&array))])
+            .unwrap();
+        assert_eq!(array.len(), 100_000);
+        match &appended {
+            Object::ARRAY(appended) => assert_eq!(appended.len(), 100_001),
+            other => panic!("expected an array, got {other}"),
+        }
+    }
+
+    #[test]
+    fn test_call_traces() {
+        let program = parse(
+            r"
+            let add = fn(a, b) { a + b };
+            let twice = fn(x) { add(x, x) };
+            twice(1);
+            fn() { 2 }();
+            ",
+        );
+        let mut compiler = Compiler::new();
+        compiler.compile(program).unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        let traces = Rc::new(RefCell::new(vec![]));
+        let sink = Rc::clone(&traces);
+        vm.set_diagnostics(Box::new(move |diagnostic| {
+            assert_eq!(diagnostic.kind, DiagnosticKind::Trace);
+            sink.borrow_mut().push(diagnostic.message);
+        }));
+        vm.run().unwrap();
+
+        assert_eq!(
+            *traces.borrow(),
+            vec!["call twice", "call add", "call <anonymous>"]
+        );
+    }
+
+    #[test]
+    fn test_call_function_from_rust() {
+        let mut compiler = Compiler::new();
+        let program = parse(
+            r"
+            let add = fn(a, b) { a + b };
+            let offset = 10;
+            let shifted = fn(x) { add(x, offset) };
+            let twice = partial(fn(f, x) { f(f(x)) }, shifted);
+            let fails = fn() { 1 + true };
+            ",
+        );
+        compiler.compile(program).unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        vm.run().unwrap();
+
+        let add = vm.call_function("add", vec![Object::INTEGER(1), Object::INTEGER(2)]);
+        assert_eq!(add, Ok(Object::INTEGER(3)));
+        let shifted = vm.call_function("shifted", vec![Object::INTEGER(5)]);
+        assert_eq!(shifted, Ok(Object::INTEGER(15)));
+        let twice = vm.call_function("twice", vec![Object::INTEGER(1)]);
+        assert_eq!(twice, Ok(Object::INTEGER(21)));
+
+        assert_eq!(
+            vm.call_function("add", vec![Object::INTEGER(1)]),
+            Err("Wrong number of arguments: want=2, got=1".to_string())
+        );
+        assert!(vm.call_function("fails", vec![]).is_err());
+        assert_eq!(
+            vm.call_function("offset", vec![]),
+            Err("offset is not a function, got INTEGER".to_string())
+        );
+        assert_eq!(
+            vm.call_function("missing", vec![]),
+            Err("Undefined variable: missing".to_string())
+        );
+
+        // Failed calls leave the VM usable
+        let add = vm.call_function("add", vec![Object::INTEGER(2), Object::INTEGER(2)]);
+        assert_eq!(add, Ok(Object::INTEGER(4)));
+
+        // The globals may not have room for the ones the program defines
+        let mut compiler = Compiler::new();
+        compiler
+            .compile(parse("let a = 1; let f = fn() { 1 };"))
+            .unwrap();
+        let mut vm = VM::with_capacity(compiler.bytecode(), 16, 1);
+        assert_eq!(
+            vm.call_function("f", vec![]),
+            Err("Undefined variable: f".to_string())
+        );
+    }
+
+    #[test]
+    fn test_host_functions() {
+        let mut registry = BuiltinRegistry::new();
+        registry
+            .register("double", |args| match args {
+                [Object::INTEGER(i)] => Ok(Object::INTEGER(i * 2)),
+                _ => Err(format!(
+                    "`double` expects an INTEGER, got {} arguments",
+                    args.len()
+                )),
+            })
+            .unwrap();
+        assert_eq!(
+            registry.register("len", |_| Ok(Object::NULL)),
+            Err("builtin len is already defined".to_string())
+        );
+        assert_eq!(
+            registry.register("double", |_| Ok(Object::NULL)),
+            Err("builtin double is already defined".to_string())
+        );
+
+        let input = r"
+            let quadruple = fn(x) { double(double(x)) };
+            [double(21), quadruple(len([1, 2])), repeat(2, fn() { double(3) }), double(), double == double]";
+        let mut compiler = Compiler::new_with_registry(&registry);
+        compiler.compile(parse(input)).unwrap();
+        let bytecode = compiler.bytecode();
+        assert!(verifier::verify(&bytecode).is_err());
+        assert_eq!(verifier::verify_with_registry(&bytecode, &registry), Ok(()));
+
+        let mut vm = VM::new(bytecode);
+        vm.set_registry(registry);
+        vm.run().unwrap();
+        assert_eq!(
+            vm.last_popped_stack_element().unwrap().as_ref(),
+            &Object::ARRAY(
+                vec![
+                    Object::INTEGER(42),
+                    Object::INTEGER(8),
+                    Object::ARRAY(vec![Object::INTEGER(6), Object::INTEGER(6)].into()),
+                    Object::ERROR("`double` expects an INTEGER, got 0 arguments".to_string()),
+                    Object::BOOLEAN(true),
+                ]
+                .into()
+            )
+        );
+
+        let mut compiler = Compiler::new();
+        assert!(compiler.compile(parse("double(1)")).is_err());
+    }
+
+    #[test]
+    fn test_function_identity() {
+        let tests = vec![
+            ("let f = fn() { 1 }; f == f", true),
+            ("let f = fn() { 1 }; let g = f; f != g", false),
+            ("fn() {} == fn() {}", false),
+            ("fn() { 1 } != fn() { 1 }", true),
+            (
+                "let adder = fn(a) { fn(b) { a + b } }; adder(1) == adder(1)",
+                true,
+            ),
+            (
+                "let adder = fn(a) { fn(b) { a + b } }; adder(1) == adder(2)",
+                false,
+            ),
+            ("len == len", true),
+            ("len == first", false),
+        ];
+
+        run_vm_tests(
+            tests
+                .into_iter()
+                .map(|(input, expected)| VmTestCase {
+                    input: input.to_string(),
+                    expected: Object::BOOLEAN(expected),
+                })
+                .collect(),
+        );
+
+        let errors = vec![
+            (
+                "let f = fn() { 1 }; f > f",
+                "Functions can only be compared with == and !=",
+            ),
+            ("let f = fn() { 1 }; {1: 2}[f]", "Unusable as hashmap key"),
+        ];
+        for (input, expected) in errors {
+            assert_eq!(run_vm_with_error_output(input), Err(expected.to_string()));
+        }
     }
 
     #[test]
@@ -444,6 +1720,18 @@ mod tests {
                     .to_string(),
                 expected: Object::INTEGER(99),
             },
+            VmTestCase {
+                // OpClosure must pop exactly its free variables, leaving the surrounding
+                // array elements on the stack
+                input: r"
+                let wrap = fn(a, b) {
+                    [a, fn() { a * 10 + b }, b]
+                };
+                let wrapped = wrap(1, 2);
+                wrapped[1]() + wrapped[0] + wrapped[2];"
+                    .to_string(),
+                expected: Object::INTEGER(15),
+            },
         ];
 
         run_vm_tests(tests);
@@ -0,0 +1,76 @@
+#[cfg(test)]
+mod tests {
+
+    use crate::{compiler::Compiler, object::Object, parser::parse, vm::VM};
+
+    fn run_with_threshold(input: &str, threshold: usize) -> VM {
+        let mut compiler = Compiler::new();
+        compiler.compile(parse(input)).unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        vm.set_gc_threshold(threshold);
+        vm.run().unwrap();
+        vm
+    }
+
+    fn stress_program(iterations: usize) -> String {
+        // Every call builds short-lived arrays at a different depth of the stack
+        format!(
+            r"
+            let build = fn(n) {{
+                let arr = [];
+                let i = 0;
+                while (i < n) {{
+                    let arr = push(arr, [i, i, i, i]);
+                    let i = i + 1;
+                }}
+                arr
+            }};
+            let deep = fn(depth) {{
+                if (depth == 0) {{
+                    len(build(50))
+                }} else {{
+                    deep(depth - 1)
+                }}
+            }};
+            let i = 0;
+            let total = 0;
+            while (i < {iterations}) {{
+                let total = total + deep(i % 20);
+                let i = i + 1;
+            }}
+            total"
+        )
+    }
+
+    #[test]
+    fn test_memory_stabilizes() {
+        // Both runs end with the same call depth, so that they can be compared exactly
+        let short = run_with_threshold(&stress_program(40), 1);
+        let long = run_with_threshold(&stress_program(400), 1);
+
+        assert_eq!(
+            long.last_popped_stack_element().unwrap().as_ref(),
+            &Object::INTEGER(400 * 50)
+        );
+        // Running ten times longer does not retain more memory
+        assert_eq!(short.retained_size(), long.retained_size());
+    }
+
+    #[test]
+    fn test_collection_frees_stale_slots() {
+        let input = stress_program(50);
+        let mut vm = run_with_threshold(&input, usize::MAX);
+        let before = vm.retained_size();
+        vm.collect_garbage();
+        let after = vm.retained_size();
+
+        assert!(after < before, "before: {before}, after: {after}");
+        assert_eq!(
+            vm.last_popped_stack_element().unwrap().as_ref(),
+            &Object::INTEGER(50 * 50)
+        );
+
+        let collected_while_running = run_with_threshold(&input, 16);
+        assert!(collected_while_running.retained_size() < before);
+    }
+}
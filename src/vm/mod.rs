@@ -1,4 +1,5 @@
 mod function_tests;
+mod gc_tests;
 mod shadowing_tests;
 mod test_utils;
 mod vm_tests;
@@ -6,20 +7,38 @@ mod while_statemets_tests;
 
 use crate::{
     compiler::{
-        code::{read_u16, Opcode},
+        code::{format_instruction_at, read_u16, Opcode},
         Bytecode,
     },
+    diagnostics::{Diagnostic, DiagnosticsCallback},
     object::{
-        builtins::BuiltinFunction,
-        {Closure, CompiledFunction, Object, FALSE, NULL, TRUE},
+        builtins::{default_clock, BuiltinFunction, BuiltinRegistry, Clock, HostFunction},
+        random::Random,
+        {Closure, CompiledFunction, Object, PartialApplication, FALSE, NULL, TRUE},
     },
 };
 use num_traits::FromPrimitive;
-use std::{collections::HashMap, rc::Rc};
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    rc::Rc,
+};
 
-const STACK_SIZE: usize = 2048;
+/// Default number of values on the stack, see `VM::with_capacity`.
+pub const STACK_SIZE: usize = 2048;
 const MAX_FRAMES: usize = 1024;
 pub const GLOBALS_SIZE: usize = 65536;
+pub const DEFAULT_GC_THRESHOLD: usize = 1024;
+/// Default number of function calls that can be active at once, see
+/// `VM::with_max_call_depth`.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+/// Longest string, in bytes, that repeating a string with `*` can build.
+pub const MAX_REPEATED_STRING_LEN: usize = 1 << 28;
+
+const MAX_CALL_DEPTH_EXCEEDED: &str = "maximum recursion depth exceeded";
+
+const STACK_OVERFLOW: &str = "Stack overflow :(, you gotta fix this";
 
 #[derive(Debug)]
 struct Frame {
@@ -40,6 +59,25 @@ impl Frame {
     fn get_instructions(&self) -> &Vec<u8> {
         &self.function.function.instructions
     }
+
+    /// How the frame is shown in a backtrace.
+    fn describe(&self) -> String {
+        let name = match (&self.function.function.name, self.function.constant_index) {
+            (Some(name), _) => name.as_str(),
+            (None, Some(_)) => "<anonymous>",
+            (None, None) => "<main>",
+        };
+        format!("{name} at {:04}", self.ip.max(0))
+    }
+}
+
+/// A `try` being executed, see `Opcode::Try`.
+#[derive(Debug)]
+struct Handler {
+    // Frame of the `try`, the ones above it are discarded when an error is caught
+    frames_index: usize,
+    sp: usize,
+    catch_ip: usize,
 }
 
 pub struct VM {
@@ -52,207 +90,570 @@ pub struct VM {
 
     frames: Vec<Frame>,
     frames_index: usize,
+    max_call_depth: usize,
+
+    // Innermost `try` last
+    handlers: Vec<Handler>,
+    // Value of the `throw` being raised, that the `catch` receives instead of an error
+    thrown: Option<Rc<Object>>,
+
+    // Heap objects (arrays, hashmaps, closures, strings...) created since the last
+    // garbage collection, and how many of them trigger the next one.
+    allocations: usize,
+    gc_threshold: usize,
+
+    // Shared instances of the values that carry no data, so that pushing them does not
+    // allocate. Equality between objects stays structural, these are only an optimization.
+    true_object: Rc<Object>,
+    false_object: Rc<Object>,
+    null_object: Rc<Object>,
+
+    // Where `puts` writes, the standard output by default
+    output: Box<dyn Write>,
+    // Where `read_line` reads. The standard input is locked on each call instead of being
+    // stored, so that the debugger and the REPL can still read from it.
+    input: Option<Box<dyn BufRead>>,
+    // Where `clock` reads the time, `default_clock` by default
+    clock: Option<Box<Clock>>,
+    // Drawn from by `rand` and `rand_int`, reset by `seed`
+    random: Random,
+    // Receives a trace event for each function call
+    diagnostics: Option<Box<DiagnosticsCallback>>,
+
+    // Set by `exit`, execution stops as soon as it is
+    exit_code: Option<i32>,
+
+    // Value of the last expression statement executed by the main program
+    result: Rc<Object>,
+    // Position of the `Pop` that set `result`, in the main program
+    result_ip: Option<i32>,
+
+    // Functions of the host program, resolved after the native builtins
+    registry: BuiltinRegistry,
+
+    // Index of the global variables by name, for `call_function`
+    global_indices: HashMap<String, usize>,
 }
 
 impl VM {
     pub fn new(bytecode: Bytecode) -> Self {
+        Self::with_capacity(bytecode, STACK_SIZE, GLOBALS_SIZE)
+    }
+
+    /// Creates a VM whose stack holds `stack_size` values and that has room for
+    /// `globals_size` global variables, instead of `STACK_SIZE` and `GLOBALS_SIZE`.
+    ///
+    /// Both are allocated upfront. A smaller stack saves memory but limits how deep
+    /// functions can recurse before failing with a stack overflow.
+    pub fn with_capacity(bytecode: Bytecode, stack_size: usize, globals_size: usize) -> Self {
         let main_function = CompiledFunction {
             instructions: bytecode.instructions.data,
             num_locals: 0,
             num_parameters: 0,
+            name: None,
         };
         let main_closure = Closure::new(main_function);
         let main_frame = Frame::new(main_closure, 0);
         let mut frames = Vec::with_capacity(MAX_FRAMES);
         frames.push(main_frame);
+        let null_object = Rc::new(NULL);
         Self {
             constants: bytecode.constants.into_iter().map(Rc::new).collect(),
 
             sp: 0,
 
-            // TODO: Improve this
-            stack: {
-                let mut v = Vec::with_capacity(STACK_SIZE);
-                (0..STACK_SIZE).for_each(|_| v.push(Rc::new(NULL)));
-                v
-            },
+            stack: vec![Rc::clone(&null_object); stack_size],
 
-            globals: {
-                let mut v = Vec::with_capacity(GLOBALS_SIZE);
-                (0..GLOBALS_SIZE).for_each(|_| v.push(Rc::new(NULL)));
-                v
-            },
+            globals: vec![Rc::clone(&null_object); globals_size],
 
             frames,
             frames_index: 1,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+
+            handlers: vec![],
+            thrown: None,
+
+            allocations: 0,
+            gc_threshold: DEFAULT_GC_THRESHOLD,
+
+            result: Rc::clone(&null_object),
+            result_ip: None,
+
+            true_object: Rc::new(TRUE),
+            false_object: Rc::new(FALSE),
+            null_object,
+
+            output: Box::new(io::stdout()),
+            input: None,
+            clock: default_clock(),
+            random: Random::default(),
+            diagnostics: None,
+
+            exit_code: None,
+
+            registry: BuiltinRegistry::default(),
+
+            global_indices: bytecode.globals,
         }
     }
 
+    /// Limits how many function calls can be active at once, `DEFAULT_MAX_CALL_DEPTH` by
+    /// default.
+    ///
+    /// A call beyond the limit does not stop the VM: it evaluates to the error object
+    /// `maximum recursion depth exceeded`, that the program can handle like any other
+    /// value. The stack can still overflow before the limit is reached, which does stop
+    /// the VM.
+    #[must_use]
+    pub fn with_max_call_depth(mut self, max_call_depth: usize) -> Self {
+        self.max_call_depth = max_call_depth;
+        self
+    }
+
     pub fn new_with_global_store(bytecode: Bytecode, globals: Vec<Rc<Object>>) -> Self {
         let mut vm = Self::new(bytecode);
         vm.globals = globals;
         vm
     }
 
-    #[allow(clippy::too_many_lines)]
+    /// Redirects the output of `puts`, which goes to the standard output by default.
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    /// Redirects the input of `read_line`, which is the standard input by default.
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        self.input = Some(input);
+    }
+
+    /// Replaces the source of the time returned by `clock`, which is `default_clock` by
+    /// default. On wasm32 there is none, and `clock` fails until one is set.
+    pub fn set_clock(&mut self, clock: Box<Clock>) {
+        self.clock = Some(clock);
+    }
+
+    /// Replaces the generator drawn from by `rand` and `rand_int`, so that a sequence
+    /// started by `seed` can carry on from one VM to the next.
+    pub fn set_random(&mut self, random: Random) {
+        self.random = random;
+    }
+
+    /// Takes the generator out of the VM, leaving a newly seeded one behind.
+    pub fn take_random(&mut self) -> Random {
+        std::mem::take(&mut self.random)
+    }
+
+    /// Hands `callback` a `Trace` diagnostic for each call of a Monkey function, naming the
+    /// function. Nothing is traced by default.
+    pub fn set_diagnostics(&mut self, callback: Box<DiagnosticsCallback>) {
+        self.diagnostics = Some(callback);
+    }
+
+    /// Makes the host functions of `registry` callable. It must be the registry the
+    /// bytecode was compiled with, see `Compiler::new_with_registry`.
+    pub fn set_registry(&mut self, registry: BuiltinRegistry) {
+        self.registry = registry;
+    }
+
+    /// Sets how many heap allocations happen between two garbage collections.
+    pub fn set_gc_threshold(&mut self, threshold: usize) {
+        self.gc_threshold = threshold;
+    }
+
     pub fn run(&mut self) -> Result<(), String> {
-        while self.current_frame().ip < self.current_frame().get_instructions().len() as i32 - 1 {
-            self.current_frame().ip += 1;
-            let ip = self.current_frame().ip as usize;
-            let ins = self.current_frame().get_instructions();
-            let op = Opcode::from_u8(ins[ip]).ok_or(format!("Unknown opcode {}", ins[ip]))?;
-            match op {
-                Opcode::Constant => {
-                    let const_index = read_u16(&ins[ip + 1..]);
-                    self.current_frame().ip += 2;
-                    self.push(self.constants[const_index as usize].clone())?;
+        while self.step()? {}
+        Ok(())
+    }
+
+    /// Calls the function stored in the global variable `name` with `args`, usually once
+    /// the program defining it has run, and returns its result.
+    ///
+    /// # Errors
+    ///
+    /// Fails if there is no such global, if it is not a function, or if the call fails.
+    /// Error objects returned by the function are turned into errors as well. The VM can
+    /// still be used after a failed call.
+    pub fn call_function(&mut self, name: &str, args: Vec<Object>) -> Result<Object, String> {
+        let index = *self
+            .global_indices
+            .get(name)
+            .ok_or_else(|| format!("Undefined variable: {name}"))?;
+        let function = Rc::clone(
+            self.globals
+                .get(index)
+                .ok_or_else(|| format!("Undefined variable: {name}"))?,
+        );
+        if !matches!(
+            *function,
+            Object::CLOSURE(_) | Object::BUILTIN(_) | Object::HOSTFUNCTION(_) | Object::PARTIAL(_)
+        ) {
+            return Err(format!(
+                "{name} is not a function, got {}",
+                function.get_type()
+            ));
+        }
+
+        let (frames_index, sp) = (self.frames_index, self.sp);
+        let args = args.into_iter().map(Rc::new).collect();
+        match self.call_function_synchronously(function, args) {
+            Ok(result) => match result.as_ref() {
+                Object::ERROR(err) => Err(err.clone()),
+                result => Ok(result.clone()),
+            },
+            Err(err) => {
+                while self.frames_index > frames_index {
+                    self.pop_frame();
                 }
-                Opcode::Add
-                | Opcode::Sub
-                | Opcode::Mul
-                | Opcode::Div
-                | Opcode::Modulo
-                | Opcode::Or
-                | Opcode::And => {
-                    self.execute_binary_operation(op)?;
+                self.sp = sp;
+                self.thrown = None;
+                Err(err)
+            }
+        }
+    }
+
+    /// Executes the next instruction.
+    ///
+    /// Returns `false` once there are no instructions left to execute, in which case
+    /// nothing is done. An error raised inside a `try` is not returned, execution
+    /// continues in the matching `catch` instead.
+    pub fn step(&mut self) -> Result<bool, String> {
+        let result = self.step_above(0);
+        if result.is_err() {
+            self.thrown = None;
+        }
+        result
+    }
+
+    /// Same as `step`, but only the errors raised inside a `try` of a frame above
+    /// `frames_index` are caught, the other ones are returned.
+    fn step_above(&mut self, frames_index: usize) -> Result<bool, String> {
+        match self.execute_instruction() {
+            Err(err)
+                if self
+                    .handlers
+                    .last()
+                    .is_some_and(|handler| handler.frames_index > frames_index) =>
+            {
+                self.catch_error(err)?;
+                Ok(true)
+            }
+            result => result,
+        }
+    }
+
+    /// Resumes execution at the innermost `catch`, with the error on the stack.
+    fn catch_error(&mut self, err: String) -> Result<(), String> {
+        let handler = self.handlers.pop().ok_or(err.clone())?;
+        while self.frames_index > handler.frames_index {
+            self.pop_frame();
+        }
+        self.sp = handler.sp;
+        self.current_frame().ip = handler.catch_ip as i32 - 1;
+        let error = self
+            .thrown
+            .take()
+            .unwrap_or_else(|| Rc::new(Object::ERROR(err)));
+        self.push(error)
+    }
+
+    #[allow(clippy::too_many_lines)]
+    fn execute_instruction(&mut self) -> Result<bool, String> {
+        if self.is_finished() {
+            return Ok(false);
+        }
+        self.current_frame().ip += 1;
+        let ip = self.current_frame().ip as usize;
+        let ins = self.current_frame().get_instructions();
+        let op = Opcode::from_u8(ins[ip]).ok_or(format!("Unknown opcode {}", ins[ip]))?;
+        match op {
+            Opcode::Constant => {
+                let const_index = read_u16(&ins[ip + 1..]);
+                self.current_frame().ip += 2;
+                self.push(self.constants[const_index as usize].clone())?;
+            }
+            Opcode::Add
+            | Opcode::Sub
+            | Opcode::Mul
+            | Opcode::Div
+            | Opcode::FloorDiv
+            | Opcode::Modulo => {
+                self.execute_binary_operation(op)?;
+            }
+            Opcode::Equal
+            | Opcode::NotEqual
+            | Opcode::GreaterThan
+            | Opcode::GreaterEqualThan
+            | Opcode::LessThan
+            | Opcode::LessEqualThan => {
+                self.execute_comparison(op)?;
+            }
+            Opcode::Pop => {
+                let value = self.pop()?;
+                // Expression statements of the main program, including the ones in its
+                // loops, but not the ones in function bodies
+                if self.frames_index == 1 {
+                    self.result = value;
+                    self.result_ip = Some(self.current_frame().ip);
                 }
-                Opcode::Equal
-                | Opcode::NotEqual
-                | Opcode::GreaterThan
-                | Opcode::GreaterEqualThan => {
-                    self.execute_comparison(op)?;
+            }
+            Opcode::PopN => {
+                let count = usize::from(ins[ip + 1]);
+                self.current_frame().ip += 1;
+                self.sp = self.sp.checked_sub(count).ok_or("Stack underflow")?;
+            }
+            Opcode::True => {
+                self.push(Rc::clone(&self.true_object))?;
+            }
+            Opcode::False => {
+                self.push(Rc::clone(&self.false_object))?;
+            }
+            Opcode::Bang => {
+                self.execute_bang_operation()?;
+            }
+            Opcode::Minus => {
+                self.execute_minus_operation()?;
+            }
+            Opcode::Jump => {
+                let pos = i32::from(read_u16(&ins[ip + 1..]));
+                self.current_frame().ip = pos - 1;
+            }
+            Opcode::JumpRel => {
+                // The offset is counted from the next instruction
+                let offset = i32::from(i8::from_be_bytes([ins[ip + 1]]));
+                self.current_frame().ip += 1 + offset;
+            }
+            Opcode::JumpNotTruthy => {
+                let pos = i32::from(read_u16(&ins[ip + 1..]));
+                self.current_frame().ip += 2;
+                let condition = self.pop()?;
+                if !self.is_truthy(&condition) {
+                    self.current_frame().ip = pos - 1;
                 }
-                Opcode::Pop => {
+            }
+            Opcode::JumpNotTruthyOrPop | Opcode::JumpTruthyOrPop => {
+                let pos = i32::from(read_u16(&ins[ip + 1..]));
+                self.current_frame().ip += 2;
+                let condition = self.stack_top().ok_or("Stack underflow")?;
+                if self.is_truthy(&condition) == (op == Opcode::JumpTruthyOrPop) {
+                    self.current_frame().ip = pos - 1;
+                } else {
                     self.pop()?;
                 }
-                Opcode::True => {
-                    self.push(Rc::new(TRUE))?;
-                }
-                Opcode::False => {
-                    self.push(Rc::new(FALSE))?;
-                }
-                Opcode::Bang => {
-                    self.execute_bang_operation()?;
-                }
-                Opcode::Minus => {
-                    self.execute_minus_operation()?;
-                }
-                Opcode::Jump => {
-                    let pos = i32::from(read_u16(&ins[ip + 1..]));
+            }
+            Opcode::JumpNotNullOrPop => {
+                let pos = i32::from(read_u16(&ins[ip + 1..]));
+                self.current_frame().ip += 2;
+                let value = self.stack_top().ok_or("Stack underflow")?;
+                if *value == Object::NULL {
+                    self.pop()?;
+                } else {
                     self.current_frame().ip = pos - 1;
                 }
-                Opcode::JumpNotTruthy => {
-                    let pos = i32::from(read_u16(&ins[ip + 1..]));
-                    self.current_frame().ip += 2;
-                    let condition = self.pop()?;
-                    if !self.is_truthy(&condition) {
-                        self.current_frame().ip = pos - 1;
-                    }
-                }
-                Opcode::Null => {
-                    self.push(Rc::new(NULL))?;
-                }
-                Opcode::SetGlobal => {
-                    let global_index = read_u16(&ins[ip + 1..]) as usize;
-                    self.current_frame().ip += 2;
-                    let value = self.pop()?;
-                    self.globals[global_index] = value;
+            }
+            Opcode::JumpNull => {
+                let pos = i32::from(read_u16(&ins[ip + 1..]));
+                self.current_frame().ip += 2;
+                if *self.stack_top().ok_or("Stack underflow")? == Object::NULL {
+                    self.current_frame().ip = pos - 1;
                 }
+            }
+            Opcode::Try => {
+                let catch_ip = read_u16(&ins[ip + 1..]) as usize;
+                self.current_frame().ip += 2;
+                self.handlers.push(Handler {
+                    frames_index: self.frames_index,
+                    sp: self.sp,
+                    catch_ip,
+                });
+            }
+            Opcode::EndTry => {
+                self.handlers.pop();
+            }
+            Opcode::Throw => {
+                let value = self.pop()?;
+                let message = format!("Uncaught error: {}", value.error_message());
+                self.thrown = Some(value);
+                return Err(message);
+            }
+            Opcode::Null => {
+                self.push(Rc::clone(&self.null_object))?;
+            }
+            Opcode::SetGlobal => {
+                let global_index = read_u16(&ins[ip + 1..]) as usize;
+                self.current_frame().ip += 2;
+                let value = self.pop()?;
+                *self
+                    .globals
+                    .get_mut(global_index)
+                    .ok_or("Too many global variables")? = value;
+            }
 
-                Opcode::GetGlobal => {
-                    let global_index = read_u16(&ins[ip + 1..]) as usize;
-                    self.current_frame().ip += 2;
-                    self.push(self.globals[global_index].clone())?;
-                }
-                Opcode::SetLocal => {
-                    let local_index = ins[ip + 1] as usize;
-                    self.current_frame().ip += 1;
-                    let value = self.pop()?;
-                    let base_pointer = self.current_frame().base_pointer;
-                    self.stack[base_pointer + local_index] = value;
-                }
-                Opcode::GetLocal => {
-                    let local_index = ins[ip + 1] as usize;
-                    self.current_frame().ip += 1;
-                    let base_pointer = self.current_frame().base_pointer;
-                    let value = Rc::clone(&self.stack[base_pointer + local_index]);
-                    self.push(value)?;
-                }
+            Opcode::GetGlobal => {
+                let global_index = read_u16(&ins[ip + 1..]) as usize;
+                self.current_frame().ip += 2;
+                let value = self
+                    .globals
+                    .get(global_index)
+                    .ok_or("Too many global variables")?;
+                self.push(Rc::clone(value))?;
+            }
+            Opcode::SetLocal => {
+                let local_index = ins[ip + 1] as usize;
+                self.current_frame().ip += 1;
+                let value = self.pop()?;
+                let base_pointer = self.current_frame().base_pointer;
+                self.stack[base_pointer + local_index] = value;
+            }
+            Opcode::GetLocal => {
+                let local_index = ins[ip + 1] as usize;
+                self.current_frame().ip += 1;
+                let base_pointer = self.current_frame().base_pointer;
+                let value = Rc::clone(&self.stack[base_pointer + local_index]);
+                self.push(value)?;
+            }
 
-                Opcode::GetBuiltin => {
-                    let builtin_index = ins[ip + 1] as usize;
-                    self.current_frame().ip += 1;
+            Opcode::GetBuiltin => {
+                let builtin_index = ins[ip + 1] as usize;
+                self.current_frame().ip += 1;
 
-                    let def = BuiltinFunction::get_builtin_by_id(builtin_index)
-                        .ok_or(format!("Unknown builtin function id {builtin_index}"))?;
+                let def = self
+                    .registry
+                    .get_builtin_by_id(builtin_index)
+                    .ok_or(format!("Unknown builtin function id {builtin_index}"))?;
 
-                    self.push(Rc::new(def))?;
-                }
-                Opcode::Array => {
-                    let num_elements = read_u16(&ins[ip + 1..]) as usize;
-                    self.current_frame().ip += 2;
-                    let array = self.build_array(self.sp - num_elements, self.sp)?;
-                    self.sp -= num_elements;
-                    self.push(array)?;
-                }
-                Opcode::HashMap => {
-                    let num_elements = read_u16(&ins[ip + 1..]) as usize;
-                    self.current_frame().ip += 2;
-                    let hashmap = self.build_hashmap(self.sp - num_elements, self.sp)?;
-                    self.sp -= num_elements;
-                    self.push(hashmap)?;
-                }
-                Opcode::Index => {
-                    let index = self.pop()?;
-                    let left = self.pop()?;
-                    self.execute_index_expression(&left, &index)?;
+                self.push(Rc::new(def))?;
+            }
+            Opcode::Array => {
+                let num_elements = read_u16(&ins[ip + 1..]) as usize;
+                self.current_frame().ip += 2;
+                let array = self.build_array(self.sp - num_elements, self.sp)?;
+                self.sp -= num_elements;
+                self.push(array)?;
+                self.track_allocation();
+            }
+            Opcode::HashMap => {
+                let num_elements = read_u16(&ins[ip + 1..]) as usize;
+                self.current_frame().ip += 2;
+                let hashmap = self.build_hashmap(self.sp - num_elements, self.sp)?;
+                self.sp -= num_elements;
+                self.push(hashmap)?;
+                self.track_allocation();
+            }
+            Opcode::Spread => {
+                let value = self.stack.get(self.sp - 1).ok_or("Stack underflow")?;
+                if !matches!(value.as_ref(), Object::ARRAY(_)) {
+                    return Err(format!(
+                        "Cannot spread {}, only arrays can be spread",
+                        value.get_type()
+                    ));
                 }
-                Opcode::Call => {
-                    let num_args = ins[ip + 1] as usize;
-                    self.current_frame().ip += 1;
+            }
+            Opcode::Index => {
+                let index = self.pop()?;
+                let left = self.pop()?;
+                self.execute_index_expression(&left, &index)?;
+            }
+            Opcode::Call => {
+                let num_args = ins[ip + 1] as usize;
+                self.current_frame().ip += 1;
 
-                    self.execute_call(num_args)?;
+                self.execute_call(num_args)?;
+            }
+            Opcode::CallSpread => {
+                let args = self.pop()?;
+                let Object::ARRAY(args) = args.as_ref() else {
+                    return Err(format!("Cannot spread {}", args.get_type()));
+                };
+                for arg in args.iter() {
+                    self.push(Rc::new(arg.clone()))?;
                 }
-                Opcode::ReturnValue => {
-                    let return_value = self.pop()?;
-
-                    match self.pop_frame() {
-                        Some(frame) => self.sp = frame.base_pointer - 1,
-                        None => Err("There was no frame")?,
-                    }
+                self.execute_call(args.len())?;
+            }
+            Opcode::ReturnValue => {
+                let return_value = self.pop()?;
 
-                    self.push(return_value)?;
+                match self.pop_frame() {
+                    Some(frame) => self.sp = frame.base_pointer - 1,
+                    None => Err("There was no frame")?,
                 }
-                Opcode::Return => {
-                    match self.pop_frame() {
-                        Some(frame) => self.sp = frame.base_pointer - 1,
-                        None => Err("There was no frame")?,
-                    }
 
-                    self.push(Rc::new(NULL))?;
+                self.push(return_value)?;
+            }
+            Opcode::Return => {
+                match self.pop_frame() {
+                    Some(frame) => self.sp = frame.base_pointer - 1,
+                    None => Err("There was no frame")?,
                 }
-                Opcode::Closure => {
-                    let const_index = read_u16(&ins[ip + 1..]) as usize;
-                    let num_free = ins[ip + 3] as usize;
 
-                    self.current_frame().ip += 3;
+                self.push(Rc::clone(&self.null_object))?;
+            }
+            Opcode::Closure => {
+                let const_index = read_u16(&ins[ip + 1..]) as usize;
+                let num_free = ins[ip + 3] as usize;
 
-                    self.push_closure(const_index, num_free)?;
-                }
-                Opcode::GetFree => {
-                    let free_index = ins[ip + 1] as usize;
-                    self.current_frame().ip += 1;
+                self.current_frame().ip += 3;
 
-                    let free = self.current_frame().function.free[free_index].clone();
-                    self.push(Rc::new(free))?;
-                }
-                Opcode::CurrentClosure => {
-                    let current_closure = self.current_frame().function.clone();
-                    self.push(Rc::new(Object::CLOSURE(current_closure)))?;
-                }
+                self.push_closure(const_index, num_free)?;
+            }
+            Opcode::GetFree => {
+                let free_index = ins[ip + 1] as usize;
+                self.current_frame().ip += 1;
+
+                let free = self.current_frame().function.free[free_index].clone();
+                self.push(Rc::new(free))?;
+            }
+            Opcode::CurrentClosure => {
+                let current_closure = self.current_frame().function.clone();
+                self.push(Rc::new(Object::CLOSURE(current_closure)))?;
             }
         }
-        Ok(())
+        Ok(true)
+    }
+
+    /// Whether every instruction of the current frame has been executed, or the program
+    /// called `exit`.
+    pub fn is_finished(&self) -> bool {
+        if self.exit_code.is_some() {
+            return true;
+        }
+        let frame = &self.frames[self.frames_index - 1];
+        frame.ip >= frame.get_instructions().len() as i32 - 1
+    }
+
+    /// The code passed to `exit`, if the program called it.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    /// Lists the active calls, innermost first, each with the offset of the instruction it
+    /// is executing, e.g. `["fib at 0012", "<main> at 0021"]`.
+    ///
+    /// Frames are left in place when an instruction fails, so after `run` returns an error
+    /// this shows where it happened.
+    pub fn backtrace(&self) -> Vec<String> {
+        self.frames[..self.frames_index]
+            .iter()
+            .rev()
+            .map(Frame::describe)
+            .collect()
+    }
+
+    /// Disassembles the instruction that the next call to `step` will execute.
+    pub fn next_instruction(&self) -> Option<String> {
+        let frame = &self.frames[self.frames_index - 1];
+        let ip = usize::try_from(frame.ip + 1).ok()?;
+        format_instruction_at(frame.get_instructions(), ip)
+    }
+
+    /// The function being executed, as its index in the constant pool (`None` for the main
+    /// program), and the values of its local variables by index.
+    pub fn current_locals(&self) -> (Option<usize>, &[Rc<Object>]) {
+        let frame = &self.frames[self.frames_index - 1];
+        let end = (frame.base_pointer + frame.function.function.num_locals).min(self.stack.len());
+        (
+            frame.function.constant_index,
+            &self.stack[frame.base_pointer.min(end)..end],
+        )
     }
 
     fn execute_binary_operation(&mut self, op: Opcode) -> Result<(), String> {
@@ -263,30 +664,72 @@ impl VM {
             (Object::INTEGER(_), Object::INTEGER(_)) => {
                 self.execute_bianary_integer_operation(&left, &right, op)?;
             }
-            (Object::BOOLEAN(left), Object::BOOLEAN(right)) => {
+            (Object::FLOAT(_), Object::FLOAT(_) | Object::INTEGER(_))
+            | (Object::INTEGER(_), Object::FLOAT(_)) => {
+                self.execute_binary_float_operation(&left, &right, op)?;
+            }
+            (Object::STRING(s1), Object::STRING(s2)) => {
                 let result = match op {
-                    Opcode::Or => *left || *right,
-                    Opcode::And => *left && *right,
+                    Opcode::Add => s1.to_string() + s2,
                     _ => {
                         return Err("Unsupported types for binary operation".to_string());
                     }
                 };
 
-                if result {
-                    self.push(Rc::new(TRUE))?;
-                } else {
-                    self.push(Rc::new(FALSE))?;
-                }
+                self.push(Rc::new(Object::STRING(result)))?;
+                self.track_allocation();
             }
-            (Object::STRING(s1), Object::STRING(s2)) => {
+            (Object::STRING(s), Object::INTEGER(count))
+            | (Object::INTEGER(count), Object::STRING(s))
+                if op == Opcode::Mul =>
+            {
+                let count = usize::try_from(*count)
+                    .map_err(|_| format!("Negative string repetition count: {count}"))?;
+                s.len()
+                    .checked_mul(count)
+                    .filter(|len| *len <= MAX_REPEATED_STRING_LEN)
+                    .ok_or("String repetition is too long")?;
+                self.push(Rc::new(Object::STRING(s.repeat(count))))?;
+                self.track_allocation();
+            }
+            (Object::ARRAY(left), Object::ARRAY(right)) if op == Opcode::Add => {
+                let result = left.iter().chain(right.iter()).cloned().collect::<Vec<_>>();
+                self.push(Rc::new(Object::ARRAY(result.into())))?;
+                self.track_allocation();
+            }
+            (Object::HASHMAP(left), Object::HASHMAP(right)) if op == Opcode::Add => {
+                // On conflicting keys, the value of the right map wins
+                let mut result = HashMap::clone(left);
+                result.extend(right.iter().map(|(k, v)| (k.clone(), v.clone())));
+                self.push(Rc::new(Object::HASHMAP(result.into())))?;
+                self.track_allocation();
+            }
+            (Object::ARRAY(_) | Object::HASHMAP(_), _)
+            | (_, Object::ARRAY(_) | Object::HASHMAP(_))
+                if op == Opcode::Add =>
+            {
+                return Err(format!(
+                    "Unsupported types for binary operation: {} + {}",
+                    left.get_type(),
+                    right.get_type()
+                ));
+            }
+            (Object::CHAR(c), Object::INTEGER(i)) => {
                 let result = match op {
-                    Opcode::Add => s1.to_string() + s2,
+                    Opcode::Add => Object::offset_char(*c, *i)?,
+                    Opcode::Sub => Object::offset_char(*c, i.checked_neg().ok_or("Invalid char")?)?,
                     _ => {
                         return Err("Unsupported types for binary operation".to_string());
                     }
                 };
-
-                self.push(Rc::new(Object::STRING(result)))?;
+                self.push(Rc::new(Object::CHAR(result)))?;
+            }
+            (Object::INTEGER(i), Object::CHAR(c)) if op == Opcode::Add => {
+                self.push(Rc::new(Object::CHAR(Object::offset_char(*c, *i)?)))?;
+            }
+            (Object::CHAR(c1), Object::CHAR(c2)) if op == Opcode::Sub => {
+                let result = i64::from(*c1 as u32) - i64::from(*c2 as u32);
+                self.push(Rc::new(Object::INTEGER(result)))?;
             }
             _ => return Err("Unsupported types for binary operation".to_string()),
         }
@@ -313,6 +756,13 @@ impl VM {
                     left / right
                 }
             }
+            Opcode::FloorDiv => {
+                if right == 0 {
+                    Err("Division by zero".to_string())?
+                } else {
+                    Object::floor_div(left, right).ok_or("Integer overflow")?
+                }
+            }
             Opcode::Modulo => {
                 if right == 0 {
                     Err("Division by zero".to_string())?
@@ -327,6 +777,31 @@ impl VM {
         Ok(())
     }
 
+    /// Floats follow IEEE 754, dividing by zero gives an infinity or `NaN` instead of
+    /// failing like integers do.
+    fn execute_binary_float_operation(
+        &mut self,
+        left: &Rc<Object>,
+        right: &Rc<Object>,
+        op: Opcode,
+    ) -> Result<(), String> {
+        let left = left.as_float().ok_or("Unable to cast to float")?;
+        let right = right.as_float().ok_or("Unable to cast to float")?;
+
+        let result = match op {
+            Opcode::Add => left + right,
+            Opcode::Sub => left - right,
+            Opcode::Mul => left * right,
+            Opcode::Div => left / right,
+            Opcode::FloorDiv => (left / right).floor(),
+            Opcode::Modulo => left % right,
+            _ => unreachable!(),
+        };
+
+        self.push(Rc::new(Object::FLOAT(result)))?;
+        Ok(())
+    }
+
     fn execute_comparison(&mut self, op: Opcode) -> Result<(), String> {
         let right = self.pop()?;
         let left = self.pop()?;
@@ -335,6 +810,12 @@ impl VM {
             (Object::INTEGER(_), Object::INTEGER(_)) => {
                 self.execute_integer_comparison(&left, &right, op)?;
             }
+            (Object::FLOAT(_), Object::FLOAT(_) | Object::INTEGER(_))
+            | (Object::INTEGER(_), Object::FLOAT(_))
+            | (Object::STRING(_), Object::STRING(_))
+            | (Object::CHAR(_), Object::CHAR(_)) => {
+                self.execute_ordered_comparison(left.compare(&right), op)?;
+            }
             (Object::BOOLEAN(_), Object::BOOLEAN(_)) => match op {
                 Opcode::Equal => {
                     self.push(self.native_boolean_to_boolean_object(left == right))?;
@@ -342,13 +823,59 @@ impl VM {
                 Opcode::NotEqual => {
                     self.push(self.native_boolean_to_boolean_object(left != right))?;
                 }
-                _ => Err("Unsupported types for comparison".to_string())?,
+                _ => Err(Self::comparison_error(&left, &right))?,
             },
-            _ => Err("Unsupported types for comparison".to_string())?,
+            (Object::CLOSURE(left), Object::CLOSURE(right)) => {
+                self.execute_identity_comparison(left.is_same(right), op)?;
+            }
+            (Object::BUILTIN(left), Object::BUILTIN(right)) => {
+                self.execute_identity_comparison(left == right, op)?;
+            }
+            (Object::HOSTFUNCTION(left), Object::HOSTFUNCTION(right)) => {
+                self.execute_identity_comparison(left == right, op)?;
+            }
+            _ => Err(Self::comparison_error(&left, &right))?,
         }
         Ok(())
     }
 
+    fn comparison_error(left: &Object, right: &Object) -> String {
+        format!(
+            "Unsupported types for comparison: {} and {}",
+            left.get_type(),
+            right.get_type()
+        )
+    }
+
+    /// Compares values through `Object::compare`. Values without an order, such as NaN,
+    /// are different from everything and neither smaller nor greater.
+    fn execute_ordered_comparison(
+        &mut self,
+        ordering: Option<Ordering>,
+        op: Opcode,
+    ) -> Result<(), String> {
+        let result = match op {
+            Opcode::Equal => ordering == Some(Ordering::Equal),
+            Opcode::NotEqual => ordering != Some(Ordering::Equal),
+            Opcode::GreaterThan => ordering == Some(Ordering::Greater),
+            Opcode::GreaterEqualThan => ordering.is_some_and(Ordering::is_ge),
+            Opcode::LessThan => ordering == Some(Ordering::Less),
+            Opcode::LessEqualThan => ordering.is_some_and(Ordering::is_le),
+            _ => unreachable!(),
+        };
+        self.push(self.native_boolean_to_boolean_object(result))
+    }
+
+    /// Functions can only be compared for identity
+    fn execute_identity_comparison(&mut self, same: bool, op: Opcode) -> Result<(), String> {
+        let result = match op {
+            Opcode::Equal => same,
+            Opcode::NotEqual => !same,
+            _ => Err("Functions can only be compared with == and !=".to_string())?,
+        };
+        self.push(self.native_boolean_to_boolean_object(result))
+    }
+
     fn execute_integer_comparison(
         &mut self,
         left: &Rc<Object>,
@@ -363,14 +890,12 @@ impl VM {
             Opcode::NotEqual => left != right,
             Opcode::GreaterThan => left > right,
             Opcode::GreaterEqualThan => left >= right,
+            Opcode::LessThan => left < right,
+            Opcode::LessEqualThan => left <= right,
             _ => unreachable!(),
         };
 
-        if result {
-            self.push(Rc::new(TRUE))?;
-        } else {
-            self.push(Rc::new(FALSE))?;
-        }
+        self.push(self.native_boolean_to_boolean_object(result))?;
         Ok(())
     }
 
@@ -388,6 +913,9 @@ impl VM {
             Object::INTEGER(i) => {
                 self.push(Rc::new(Object::INTEGER(-i)))?;
             }
+            Object::FLOAT(x) => {
+                self.push(Rc::new(Object::FLOAT(-x)))?;
+            }
             _ => {
                 return Err("Unsupported type for minus operation".to_string());
             }
@@ -401,7 +929,7 @@ impl VM {
             elements
                 .push((**(self.stack.get(i).ok_or("Unable to get element".to_string()))?).clone());
         }
-        Ok(Rc::new(Object::ARRAY(elements)))
+        Ok(Rc::new(Object::ARRAY(elements.into())))
     }
 
     fn build_hashmap(&self, start_index: usize, end_index: usize) -> Result<Rc<Object>, String> {
@@ -420,7 +948,7 @@ impl VM {
             }
             elements.insert(key, value);
         }
-        Ok(Rc::new(Object::HASHMAP(elements)))
+        Ok(Rc::new(Object::HASHMAP(elements.into())))
     }
 
     fn execute_index_expression(
@@ -431,7 +959,7 @@ impl VM {
         match (&**left, &**index) {
             (Object::ARRAY(elements), Object::INTEGER(i)) => {
                 if *i < 0 || *i >= elements.len() as i64 {
-                    self.push(Rc::new(Object::NULL))?;
+                    self.push(Rc::clone(&self.null_object))?;
                 } else {
                     let result = elements
                         .get(*i as usize)
@@ -448,7 +976,7 @@ impl VM {
                         self.push(Rc::new(value.clone()))?;
                     }
                     None => {
-                        self.push(Rc::new(Object::NULL))?;
+                        self.push(Rc::clone(&self.null_object))?;
                     }
                 }
             }
@@ -466,9 +994,11 @@ impl VM {
             .get(self.sp - 1 - num_args)
             .ok_or("Stack underflow")?;
 
-        match callee.as_ref().clone() {
-            Object::CLOSURE(func) => self.call_closure(func, num_args),
-            Object::BUILTIN(func) => self.call_builtin_function(&func, num_args),
+        match Rc::clone(callee).as_ref() {
+            Object::CLOSURE(func) => self.call_closure(func.clone(), num_args),
+            Object::BUILTIN(func) => self.call_builtin_function(func, num_args),
+            Object::HOSTFUNCTION(func) => self.call_host_function(func, num_args),
+            Object::PARTIAL(partial) => self.call_partial(partial.clone(), num_args),
             _ => Err("Calling non-function".to_string()),
         }
     }
@@ -481,9 +1011,25 @@ impl VM {
             ));
         }
 
+        // The main program has a frame of its own, which is not a call
+        if self.frames_index > self.max_call_depth {
+            self.sp -= num_args + 1;
+            return self.push_builtin_result(Object::ERROR(MAX_CALL_DEPTH_EXCEEDED.to_string()));
+        }
+
         let num_locals = func.function.num_locals;
         let frame = Frame::new(func, self.sp - num_args);
+        if frame.base_pointer + num_locals > self.stack.len() {
+            return Err(STACK_OVERFLOW.to_string());
+        }
         self.sp = frame.base_pointer + num_locals;
+        if let Some(report) = &self.diagnostics {
+            let name = frame.function.function.name.as_deref();
+            report(Diagnostic::trace(format!(
+                "call {}",
+                name.unwrap_or("<anonymous>")
+            )));
+        }
         self.push_frame(frame);
         Ok(())
     }
@@ -499,34 +1045,210 @@ impl VM {
         }
         args.reverse();
 
-        let result = callee.call(args);
+        let result = match callee {
+            BuiltinFunction::REPEAT => self.call_repeat(&args)?,
+            BuiltinFunction::APPLY => match BuiltinFunction::apply_arguments(args) {
+                Ok((function, args)) => return self.call_apply(function, args),
+                Err(error) => error,
+            },
+            BuiltinFunction::EXIT => match BuiltinFunction::exit_code(&args) {
+                Ok(code) => {
+                    self.exit_code = Some(code);
+                    NULL
+                }
+                Err(error) => error,
+            },
+            BuiltinFunction::CLOCK => BuiltinFunction::call_clock(&args, self.clock.as_deref()),
+            BuiltinFunction::RAND | BuiltinFunction::RAND_INT | BuiltinFunction::SEED => {
+                callee.call_random(&args, &mut self.random)
+            }
+            _ => match &mut self.input {
+                Some(input) => callee.call_with_io(args, input, &mut self.output),
+                None => callee.call_with_output(args, &mut self.output),
+            },
+        };
+        if let Object::ERROR(message) = &result {
+            if callee.halts_on_error() {
+                return Err(message.clone());
+            }
+        }
 
         self.sp -= 1;
+        self.push_builtin_result(result)
+    }
+
+    /// Pushes the result of a builtin. An error is a value that the program can check,
+    /// except inside a `try` where it is raised, so that the `catch` handles it.
+    fn push_builtin_result(&mut self, result: Object) -> Result<(), String> {
+        if let Object::ERROR(message) = &result {
+            if !self.handlers.is_empty() {
+                return Err(message.clone());
+            }
+        }
         self.push(Rc::new(result))?;
+        self.track_allocation();
         Ok(())
     }
 
+    fn call_host_function(&mut self, callee: &HostFunction, num_args: usize) -> Result<(), String> {
+        let args = self.stack[self.sp - num_args..self.sp]
+            .iter()
+            .map(|arg| arg.as_ref().clone())
+            .collect::<Vec<_>>();
+        self.sp -= num_args + 1;
+
+        let result = callee.call(&args);
+        self.push_builtin_result(result)
+    }
+
+    fn call_partial(&mut self, partial: PartialApplication, num_args: usize) -> Result<(), String> {
+        // The bound arguments are inserted between the callee and the arguments of the
+        // call, which turns it into a regular call of the original function.
+        let args = self.stack[self.sp - num_args..self.sp].to_vec();
+        self.sp -= num_args + 1;
+
+        let num_bound = partial.args.len();
+        self.push(Rc::new(*partial.function))?;
+        for arg in partial.args {
+            self.push(Rc::new(arg))?;
+        }
+        for arg in args {
+            self.push(arg)?;
+        }
+        self.execute_call(num_bound + num_args)
+    }
+
+    /// `apply(f, args)` is replaced on the stack by a regular call of `f` with the elements
+    /// of `args`, so that a closure runs in a frame of its own like any other call.
+    fn call_apply(&mut self, function: Object, args: Vec<Object>) -> Result<(), String> {
+        self.sp -= 1;
+        let num_args = args.len();
+        self.push(Rc::new(function))?;
+        for arg in args {
+            self.push(Rc::new(arg))?;
+        }
+        self.execute_call(num_args)
+    }
+
+    fn call_repeat(&mut self, args: &[Object]) -> Result<Object, String> {
+        let count = match BuiltinFunction::repeat_count(args) {
+            Ok(count) => count,
+            Err(error) => return Ok(error),
+        };
+        let function = match &args[1] {
+            function @ (Object::CLOSURE(_)
+            | Object::BUILTIN(_)
+            | Object::HOSTFUNCTION(_)
+            | Object::PARTIAL(_)) => Rc::new(function.clone()),
+            other => {
+                return Ok(Object::ERROR(format!(
+                    "second argument to `repeat` not supported, must be a function, got {}",
+                    other.get_type()
+                )))
+            }
+        };
+
+        let mut results = Vec::with_capacity(count);
+        for _ in 0..count {
+            let result = self.call_function_synchronously(Rc::clone(&function), vec![])?;
+            if let Object::ERROR(_) = *result {
+                return Ok(result.as_ref().clone());
+            }
+            if self.exit_code.is_some() {
+                break;
+            }
+            results.push(result.as_ref().clone());
+        }
+        Ok(Object::ARRAY(results.into()))
+    }
+
+    /// Calls `function` from inside an instruction and runs it to completion.
+    ///
+    /// The callee and its arguments are pushed above the current stack pointer and the
+    /// nested frames are executed until control comes back to the current one, so the
+    /// calling instruction can resume once the result is returned.
+    fn call_function_synchronously(
+        &mut self,
+        function: Rc<Object>,
+        args: Vec<Rc<Object>>,
+    ) -> Result<Rc<Object>, String> {
+        let depth = self.frames_index;
+        let num_args = args.len();
+        self.push(function)?;
+        for arg in args {
+            self.push(arg)?;
+        }
+        self.execute_call(num_args)?;
+
+        while self.frames_index > depth {
+            if self.exit_code.is_some() {
+                return Ok(Rc::clone(&self.null_object));
+            }
+            if !self.step_above(depth)? {
+                return Err("Function ended without returning".to_string());
+            }
+        }
+        self.pop()
+    }
+
     fn push_closure(&mut self, const_index: usize, num_free: usize) -> Result<(), String> {
-        match (*self.constants[const_index]).clone() {
+        match Rc::clone(&self.constants[const_index]).as_ref() {
             Object::COMPILEDFUNCTION(func) => {
-                let mut closure = Closure::new(func);
+                let mut closure = Closure::new(func.clone());
+                closure.constant_index = Some(const_index);
 
                 for obj in &self.stack[self.sp - num_free..self.sp] {
                     closure.add_free_variable(obj.as_ref().clone());
                 }
 
                 self.sp -= num_free;
-                self.push(Rc::new(Object::CLOSURE(closure)))
+                self.push(Rc::new(Object::CLOSURE(closure)))?;
+                self.track_allocation();
+                Ok(())
             }
             x => Err(format!["Function expected, got {}", x.get_type()]),
         }
     }
 
+    fn track_allocation(&mut self) {
+        self.allocations += 1;
+        if self.allocations >= self.gc_threshold {
+            self.collect_garbage();
+        }
+    }
+
+    /// Frees the objects that are only kept alive by the VM's own bookkeeping.
+    ///
+    /// Objects are reference counted, so anything the program can still reach (the stack
+    /// below `sp`, the globals, the free variables of closures...) stays alive. However,
+    /// popping a value does not clear its stack slot, so a big array built deep in a call
+    /// would otherwise survive until that slot is reused. The slots above the stack
+    /// pointer are cleared, except for the last popped element.
+    pub fn collect_garbage(&mut self) {
+        self.allocations = 0;
+        for slot in self.stack.iter_mut().skip(self.sp + 1) {
+            if !matches!(**slot, Object::NULL) {
+                *slot = Rc::clone(&self.null_object);
+            }
+        }
+    }
+
+    /// Rough estimate, in bytes, of the memory held by the stack (including the stale
+    /// slots above the stack pointer) and the globals.
+    pub fn retained_size(&self) -> usize {
+        self.stack
+            .iter()
+            .chain(self.globals.iter())
+            .filter(|object| !matches!(***object, Object::NULL))
+            .map(|object| object.approx_size())
+            .sum()
+    }
+
     fn native_boolean_to_boolean_object(&self, input: bool) -> Rc<Object> {
         if input {
-            Rc::new(TRUE)
+            Rc::clone(&self.true_object)
         } else {
-            Rc::new(FALSE)
+            Rc::clone(&self.false_object)
         }
     }
 
@@ -539,8 +1261,8 @@ impl VM {
     }
 
     fn push(&mut self, obj: Rc<Object>) -> Result<(), String> {
-        if self.sp >= STACK_SIZE {
-            Err("Stack overflow :(, you gotta fix this".to_string())
+        if self.sp >= self.stack.len() {
+            Err(STACK_OVERFLOW.to_string())
         } else {
             self.stack[self.sp] = obj;
             self.sp += 1;
@@ -561,7 +1283,7 @@ impl VM {
     }
 
     pub fn stack_top(&self) -> Option<Rc<Object>> {
-        self.stack.get(self.sp - 1).cloned()
+        self.stack.get(self.sp.checked_sub(1)?).cloned()
     }
 
     fn cast_to_integer(&self, obj: &Rc<Object>) -> Result<i64, String> {
@@ -571,11 +1293,31 @@ impl VM {
         }
     }
 
+    /// The result of the program: the value of the last expression statement executed
+    /// outside of a function, or `null` if there was none.
+    ///
+    /// Unlike `last_popped_stack_element`, it does not depend on how the statements that
+    /// follow are compiled: `1; let a = 2;` results in `1`, and a program that ends with a
+    /// loop results in the value of the last expression statement run before or in it.
+    pub fn result(&self) -> Rc<Object> {
+        Rc::clone(&self.result)
+    }
+
+    /// The value of the last statement of the program if it is an expression statement,
+    /// or `null` if the program ends with any other statement, such as a `let`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the program has not finished running, including when it stopped on an error.
     pub fn last_popped_stack_element(&self) -> Result<Rc<Object>, String> {
-        self.stack
-            .get(self.sp)
-            .ok_or("Stack underflow".to_string())
-            .cloned()
+        if !self.is_finished() {
+            return Err("The program has not finished running".to_string());
+        }
+        if self.frames_index == 1 && self.result_ip == Some(self.frames[0].ip) {
+            Ok(self.result())
+        } else {
+            Ok(Rc::clone(&self.null_object))
+        }
     }
 
     fn current_frame(&mut self) -> &mut Frame {
@@ -589,6 +1331,14 @@ impl VM {
 
     fn pop_frame(&mut self) -> Option<Frame> {
         self.frames_index -= 1;
+        // A `return` inside a `try` leaves it
+        while self
+            .handlers
+            .last()
+            .is_some_and(|handler| handler.frames_index > self.frames_index)
+        {
+            self.handlers.pop();
+        }
         self.frames.pop()
     }
 }
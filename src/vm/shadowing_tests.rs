@@ -63,12 +63,15 @@ mod tests {
                 let array = push(array, 4);
                 array"
                     .to_string(),
-                expected: Object::ARRAY(vec![
-                    Object::INTEGER(1),
-                    Object::INTEGER(2),
-                    Object::INTEGER(3),
-                    Object::INTEGER(4),
-                ]),
+                expected: Object::ARRAY(
+                    vec![
+                        Object::INTEGER(1),
+                        Object::INTEGER(2),
+                        Object::INTEGER(3),
+                        Object::INTEGER(4),
+                    ]
+                    .into(),
+                ),
             },
         ];
         run_vm_tests(tests);
@@ -91,11 +94,9 @@ mod tests {
                     let x = [4,5,6];
                     x"
                 .to_string(),
-                expected: Object::ARRAY(vec![
-                    Object::INTEGER(4),
-                    Object::INTEGER(5),
-                    Object::INTEGER(6),
-                ]),
+                expected: Object::ARRAY(
+                    vec![Object::INTEGER(4), Object::INTEGER(5), Object::INTEGER(6)].into(),
+                ),
             },
             VmTestCase {
                 input: r"
@@ -143,11 +144,9 @@ mod tests {
                     let x = [1,2,3];
                     x"
                 .to_string(),
-                expected: Object::ARRAY(vec![
-                    Object::INTEGER(1),
-                    Object::INTEGER(2),
-                    Object::INTEGER(3),
-                ]),
+                expected: Object::ARRAY(
+                    vec![Object::INTEGER(1), Object::INTEGER(2), Object::INTEGER(3)].into(),
+                ),
             },
             VmTestCase {
                 input: r"
@@ -1,11 +1,19 @@
 #[allow(clippy::too_many_lines)]
 #[cfg(test)]
 mod tests {
-    use std::collections::HashMap;
+    use std::{collections::HashMap, rc::Rc};
 
     use crate::{
+        compiler::{
+            code::{Instructions, Opcode},
+            Bytecode, Compiler,
+        },
         object::Object,
-        vm::test_utils::{run_vm_tests, run_vm_with_error_output, VmTestCase},
+        parser::{ast::Statement, parse},
+        vm::{
+            test_utils::{run_vm_tests, run_vm_with_error_output, VmTestCase},
+            VM,
+        },
     };
 
     #[test]
@@ -83,13 +91,17 @@ mod tests {
                 input: "4 % 5".to_string(),
                 expected: Object::INTEGER(4),
             },
+            VmTestCase {
+                input: "-9223372036854775808 + 1".to_string(),
+                expected: Object::INTEGER(i64::MIN + 1),
+            },
         ];
         run_vm_tests(tests);
     }
 
     #[test]
     fn test_division_by_zero() {
-        let tests = vec!["1 / 0", "1 % 0"];
+        let tests = vec!["1 / 0", "1 % 0", "1 // 0"];
 
         for test in tests {
             let result = run_vm_with_error_output(test);
@@ -258,6 +270,25 @@ mod tests {
                 input: "if ((if (false) { 10 })) { 10 } else { 20 }".to_string(),
                 expected: Object::INTEGER(20),
             },
+            VmTestCase {
+                input: "let x = 1; if (x < 2) { 10 } else if (x < 3) { 20 } else { 30 }"
+                    .to_string(),
+                expected: Object::INTEGER(10),
+            },
+            VmTestCase {
+                input: "let x = 2; if (x < 2) { 10 } else if (x < 3) { 20 } else { 30 }"
+                    .to_string(),
+                expected: Object::INTEGER(20),
+            },
+            VmTestCase {
+                input: "let x = 3; if (x < 2) { 10 } else if (x < 3) { 20 } else { 30 }"
+                    .to_string(),
+                expected: Object::INTEGER(30),
+            },
+            VmTestCase {
+                input: "let x = 3; if (x < 2) { 10 } else if (x < 3) { 20 }".to_string(),
+                expected: Object::NULL,
+            },
         ];
 
         run_vm_tests(tests);
@@ -283,6 +314,78 @@ mod tests {
         run_vm_tests(tests);
     }
 
+    #[test]
+    fn test_division() {
+        let tests = vec![
+            ("5 / 2", Object::INTEGER(2)),
+            ("-7 / 2", Object::INTEGER(-3)),
+            ("5.0 / 2", Object::FLOAT(2.5)),
+            ("5 / 2.0", Object::FLOAT(2.5)),
+            ("5 // 2", Object::INTEGER(2)),
+            ("-7 // 2", Object::INTEGER(-4)),
+            ("7 // -2", Object::INTEGER(-4)),
+            ("-8 // 2", Object::INTEGER(-4)),
+            ("5.0 // 2", Object::FLOAT(2.0)),
+            ("-5.5 // 2", Object::FLOAT(-3.0)),
+            ("1 + 7 // 2 * 2", Object::INTEGER(7)),
+        ];
+
+        run_vm_tests(
+            tests
+                .into_iter()
+                .map(|(input, expected)| VmTestCase {
+                    input: input.to_string(),
+                    expected,
+                })
+                .collect(),
+        );
+
+        assert_eq!(
+            run_vm_with_error_output("let min = -9223372036854775807 - 1; min // -1"),
+            Err("Integer overflow".to_string())
+        );
+    }
+
+    #[test]
+    fn test_float_arithmetic() {
+        let tests = vec![
+            ("2.5", Object::FLOAT(2.5)),
+            ("-2.5", Object::FLOAT(-2.5)),
+            ("0.1 + 0.2", Object::FLOAT(0.1 + 0.2)),
+            ("1.5 + 1", Object::FLOAT(2.5)),
+            ("1 - 0.5", Object::FLOAT(0.5)),
+            ("3 * 0.5", Object::FLOAT(1.5)),
+            ("1.0 / 4", Object::FLOAT(0.25)),
+            ("7.5 % 2", Object::FLOAT(1.5)),
+            ("1.0 / 0", Object::FLOAT(f64::INFINITY)),
+            ("-1 / 0.0", Object::FLOAT(f64::NEG_INFINITY)),
+            ("0.5 + 0.5 == 1", Object::BOOLEAN(true)),
+            ("1 == 1.0", Object::BOOLEAN(true)),
+            ("1.5 != 1.5", Object::BOOLEAN(false)),
+            ("[1.5, 2][0]", Object::FLOAT(1.5)),
+            ("1e3", Object::FLOAT(1000.0)),
+            ("1e3 == 1000.0", Object::BOOLEAN(true)),
+            ("2.5e-1 == 0.25", Object::BOOLEAN(true)),
+            ("-1.5E2", Object::FLOAT(-150.0)),
+        ];
+
+        run_vm_tests(
+            tests
+                .into_iter()
+                .map(|(input, expected)| VmTestCase {
+                    input: input.to_string(),
+                    expected,
+                })
+                .collect(),
+        );
+
+        let mut compiler = Compiler::new();
+        compiler.compile(parse("0.0 / 0")).unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        vm.run().unwrap();
+        assert_eq!(vm.last_popped_stack_element().unwrap().to_string(), "NaN");
+    }
+
     #[test]
     fn test_string_expressions() {
         let tests = vec![
@@ -303,47 +406,424 @@ mod tests {
         run_vm_tests(tests);
     }
 
+    #[test]
+    fn test_ordering_comparisons() {
+        let tests = vec![
+            ("\"apple\" < \"banana\"", true),
+            ("\"apple\" > \"banana\"", false),
+            ("\"apple\" <= \"apple\"", true),
+            ("\"b\" >= \"apple\"", true),
+            ("\"app\" < \"apple\"", true),
+            ("\"Zebra\" < \"apple\"", true),
+            ("\"monkey\" == \"mon\" + \"key\"", true),
+            ("\"monkey\" != \"monkey\"", false),
+            ("1.5 < 2.5", true),
+            ("1.5 > 1", true),
+            ("2 >= 2.0", true),
+            ("-0.5 <= -1", false),
+            ("0.0 / 0 < 1", false),
+            ("0.0 / 0 >= 1", false),
+            ("0.0 / 0 == 0.0 / 0", false),
+            ("0.0 / 0 != 0.0 / 0", true),
+        ];
+
+        run_vm_tests(
+            tests
+                .into_iter()
+                .map(|(input, expected)| VmTestCase {
+                    input: input.to_string(),
+                    expected: Object::BOOLEAN(expected),
+                })
+                .collect(),
+        );
+
+        let tests = vec![
+            (
+                "\"1\" < 2",
+                "Unsupported types for comparison: STRING and INTEGER",
+            ),
+            (
+                "1.5 >= 'a'",
+                "Unsupported types for comparison: FLOAT and CHAR",
+            ),
+            (
+                "true > false",
+                "Unsupported types for comparison: BOOLEAN and BOOLEAN",
+            ),
+        ];
+        for (input, expected) in tests {
+            let mut compiler = Compiler::new();
+            compiler.compile(parse(input)).unwrap();
+            let mut vm = VM::new(compiler.bytecode());
+            assert_eq!(vm.run(), Err(expected.to_string()), "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_string_repetition() {
+        let tests = vec![
+            VmTestCase {
+                input: "\"ab\" * 3".to_string(),
+                expected: Object::STRING("ababab".to_string()),
+            },
+            VmTestCase {
+                input: "2 * \"-\" + \">\"".to_string(),
+                expected: Object::STRING("-->".to_string()),
+            },
+            VmTestCase {
+                input: "\"x\" * 0".to_string(),
+                expected: Object::STRING(String::new()),
+            },
+        ];
+
+        run_vm_tests(tests);
+
+        assert_eq!(
+            run_vm_with_error_output("\"ab\" * -2"),
+            Err("Negative string repetition count: -2".to_string())
+        );
+        assert_eq!(
+            run_vm_with_error_output("\"ab\" * 100000000000000000"),
+            Err("String repetition is too long".to_string())
+        );
+        assert_eq!(
+            run_vm_with_error_output("\"ab\" - 2"),
+            Err("Unsupported types for binary operation".to_string())
+        );
+    }
+
+    #[test]
+    fn test_logical_operators_return_operands() {
+        let tests = vec![
+            // 0 is truthy in Monkey, only `false` and `null` are falsy
+            VmTestCase {
+                input: "0 || 5".to_string(),
+                expected: Object::INTEGER(0),
+            },
+            VmTestCase {
+                input: "0 || 5 == 5".to_string(),
+                expected: Object::BOOLEAN(false),
+            },
+            VmTestCase {
+                input: "false || 5".to_string(),
+                expected: Object::INTEGER(5),
+            },
+            VmTestCase {
+                input: "3 && 4".to_string(),
+                expected: Object::INTEGER(4),
+            },
+            VmTestCase {
+                input: "3 && 4 == 4".to_string(),
+                expected: Object::BOOLEAN(true),
+            },
+            VmTestCase {
+                input: "let maybe = if (false) { 1 }; let x = maybe || \"default\"; x".to_string(),
+                expected: Object::STRING("default".to_string()),
+            },
+            VmTestCase {
+                input: "[] && false".to_string(),
+                expected: Object::BOOLEAN(false),
+            },
+            VmTestCase {
+                input: "1 && 2 || 3".to_string(),
+                expected: Object::INTEGER(2),
+            },
+            VmTestCase {
+                input: "false && 1 || 3".to_string(),
+                expected: Object::INTEGER(3),
+            },
+            // The right operand is only evaluated when needed
+            VmTestCase {
+                input: "true || 1 / 0".to_string(),
+                expected: Object::BOOLEAN(true),
+            },
+            VmTestCase {
+                input: "false && assert(false)".to_string(),
+                expected: Object::BOOLEAN(false),
+            },
+            VmTestCase {
+                input: "let f = fn(a, b) { a || b }; [f(false, 2), f(1, 2)]".to_string(),
+                expected: Object::ARRAY(vec![Object::INTEGER(2), Object::INTEGER(1)].into()),
+            },
+        ];
+
+        run_vm_tests(tests);
+
+        assert_eq!(
+            run_vm_with_error_output("false || 1 / 0"),
+            Err("Division by zero".to_string())
+        );
+    }
+
+    #[test]
+    fn test_null_coalescing() {
+        let tests = vec![
+            VmTestCase {
+                input: "let missing = [][0]; (missing ?? 5) == 5".to_string(),
+                expected: Object::BOOLEAN(true),
+            },
+            // Unlike `||`, only `null` falls back to the right operand
+            VmTestCase {
+                input: "(0 ?? 5) == 0".to_string(),
+                expected: Object::BOOLEAN(true),
+            },
+            VmTestCase {
+                input: "false ?? 5".to_string(),
+                expected: Object::BOOLEAN(false),
+            },
+            VmTestCase {
+                input: "let h = {\"a\": 1}; [h[\"a\"] ?? 0, h[\"b\"] ?? 0]".to_string(),
+                expected: Object::ARRAY(vec![Object::INTEGER(1), Object::INTEGER(0)].into()),
+            },
+            VmTestCase {
+                input: "if (false) { 1 } ?? [][0] ?? \"default\"".to_string(),
+                expected: Object::STRING("default".to_string()),
+            },
+            VmTestCase {
+                input: "[][0] ?? [][1]".to_string(),
+                expected: Object::NULL,
+            },
+            // The right operand is only evaluated when the left one is null
+            VmTestCase {
+                input: "1 ?? 1 / 0".to_string(),
+                expected: Object::INTEGER(1),
+            },
+            VmTestCase {
+                input: "let f = fn(a) { a ?? assert(false) }; f(2)".to_string(),
+                expected: Object::INTEGER(2),
+            },
+        ];
+
+        run_vm_tests(tests);
+
+        assert_eq!(
+            run_vm_with_error_output("[][0] ?? 1 / 0"),
+            Err("Division by zero".to_string())
+        );
+    }
+
+    #[test]
+    fn test_optional_chaining() {
+        let tests = vec![
+            VmTestCase {
+                input: "let missing = [][0]; missing?[0]".to_string(),
+                expected: Object::NULL,
+            },
+            VmTestCase {
+                input: "let a = [1, 2]; a?[1]".to_string(),
+                expected: Object::INTEGER(2),
+            },
+            VmTestCase {
+                input: "let h = {\"a\": [1]}; [h[\"a\"]?[0], h[\"b\"]?[0] ?? 5]".to_string(),
+                expected: Object::ARRAY(vec![Object::INTEGER(1), Object::INTEGER(5)].into()),
+            },
+            VmTestCase {
+                input: "let double = fn(x) { x * 2 }; double?.(2)".to_string(),
+                expected: Object::INTEGER(4),
+            },
+            VmTestCase {
+                input: "let callbacks = {}; callbacks[\"done\"]?.(1)".to_string(),
+                expected: Object::NULL,
+            },
+            // The index and the arguments are not evaluated when the left side is null
+            VmTestCase {
+                input: "[][0]?[1 / 0]".to_string(),
+                expected: Object::NULL,
+            },
+            VmTestCase {
+                input: "let f = fn(g) { g?.(assert(false)) }; f([][0])".to_string(),
+                expected: Object::NULL,
+            },
+        ];
+
+        run_vm_tests(tests);
+
+        assert!(run_vm_with_error_output("[][0][0]").is_err());
+        assert!(run_vm_with_error_output("[][0]()").is_err());
+    }
+
+    #[test]
+    fn test_boolean_results_are_singletons() {
+        let tests = vec![
+            ("true", true),
+            ("false", false),
+            ("1 < 2", true),
+            ("1 == 2", false),
+            ("!true", false),
+            ("!5", false),
+            ("true != false", true),
+            ("(1 < 2) == true", true),
+            ("'a' >= 'b'", false),
+            ("true && false", false),
+            ("false || true", true),
+        ];
+
+        for (input, expected) in tests {
+            let mut compiler = Compiler::new();
+            compiler.compile(parse(input)).unwrap();
+            let mut vm = VM::new(compiler.bytecode());
+            vm.run().unwrap();
+
+            let result = vm.last_popped_stack_element().unwrap();
+            let singleton = if expected {
+                &vm.true_object
+            } else {
+                &vm.false_object
+            };
+            assert!(Rc::ptr_eq(&result, singleton), "input: {input}");
+            assert_eq!(*result, Object::BOOLEAN(expected));
+        }
+    }
+
+    #[test]
+    fn test_char_expressions() {
+        let tests = vec![
+            VmTestCase {
+                input: "'a'".to_string(),
+                expected: Object::CHAR('a'),
+            },
+            VmTestCase {
+                input: "'\\t'".to_string(),
+                expected: Object::CHAR('\t'),
+            },
+            VmTestCase {
+                input: "'a' + 1".to_string(),
+                expected: Object::CHAR('b'),
+            },
+            VmTestCase {
+                input: "2 + 'a'".to_string(),
+                expected: Object::CHAR('c'),
+            },
+            VmTestCase {
+                input: "'z' - 1".to_string(),
+                expected: Object::CHAR('y'),
+            },
+            VmTestCase {
+                input: "'z' - 'a'".to_string(),
+                expected: Object::INTEGER(25),
+            },
+            VmTestCase {
+                input: "'a' + 1 == 'b'".to_string(),
+                expected: Object::BOOLEAN(true),
+            },
+            VmTestCase {
+                input: "'a' < 'b'".to_string(),
+                expected: Object::BOOLEAN(true),
+            },
+            VmTestCase {
+                input: "'a' >= 'b'".to_string(),
+                expected: Object::BOOLEAN(false),
+            },
+            VmTestCase {
+                input: "'a' != 'a'".to_string(),
+                expected: Object::BOOLEAN(false),
+            },
+        ];
+
+        run_vm_tests(tests);
+    }
+
     #[test]
     fn test_array_expressions() {
         let tests = vec![
             VmTestCase {
                 input: "[]".to_string(),
-                expected: Object::ARRAY(vec![]),
+                expected: Object::ARRAY(vec![].into()),
             },
             VmTestCase {
                 input: "[1, 2, 3]".to_string(),
-                expected: Object::ARRAY(vec![
-                    Object::INTEGER(1),
-                    Object::INTEGER(2),
-                    Object::INTEGER(3),
-                ]),
+                expected: Object::ARRAY(
+                    vec![Object::INTEGER(1), Object::INTEGER(2), Object::INTEGER(3)].into(),
+                ),
             },
             VmTestCase {
                 input: "[1 + 2, 3 * 4, 5 + 6]".to_string(),
-                expected: Object::ARRAY(vec![
-                    Object::INTEGER(3),
-                    Object::INTEGER(12),
-                    Object::INTEGER(11),
-                ]),
+                expected: Object::ARRAY(
+                    vec![Object::INTEGER(3), Object::INTEGER(12), Object::INTEGER(11)].into(),
+                ),
             },
             VmTestCase {
                 input: "[\"yes\", false, [1,2]]".to_string(),
-                expected: Object::ARRAY(vec![
-                    Object::STRING("yes".to_string()),
-                    Object::BOOLEAN(false),
-                    Object::ARRAY(vec![Object::INTEGER(1), Object::INTEGER(2)]),
-                ]),
+                expected: Object::ARRAY(
+                    vec![
+                        Object::STRING("yes".to_string()),
+                        Object::BOOLEAN(false),
+                        Object::ARRAY(vec![Object::INTEGER(1), Object::INTEGER(2)].into()),
+                    ]
+                    .into(),
+                ),
             },
         ];
 
         run_vm_tests(tests);
     }
+
+    #[test]
+    fn test_array_concatenation() {
+        let tests = vec![
+            VmTestCase {
+                input: "[1, 2] + [3, 4]".to_string(),
+                expected: Object::ARRAY(
+                    vec![
+                        Object::INTEGER(1),
+                        Object::INTEGER(2),
+                        Object::INTEGER(3),
+                        Object::INTEGER(4),
+                    ]
+                    .into(),
+                ),
+            },
+            VmTestCase {
+                input: "[] + [1] + []".to_string(),
+                expected: Object::ARRAY(vec![Object::INTEGER(1)].into()),
+            },
+            VmTestCase {
+                input: "[] + []".to_string(),
+                expected: Object::ARRAY(vec![].into()),
+            },
+            VmTestCase {
+                // The operands are left untouched
+                input: "let a = [1]; let b = a + [[2]]; [a, b]".to_string(),
+                expected: Object::ARRAY(
+                    vec![
+                        Object::ARRAY(vec![Object::INTEGER(1)].into()),
+                        Object::ARRAY(
+                            vec![
+                                Object::INTEGER(1),
+                                Object::ARRAY(vec![Object::INTEGER(2)].into()),
+                            ]
+                            .into(),
+                        ),
+                    ]
+                    .into(),
+                ),
+            },
+        ];
+
+        run_vm_tests(tests);
+
+        let errors = vec![
+            (
+                "[1] + 2",
+                "Unsupported types for binary operation: ARRAY + INTEGER",
+            ),
+            (
+                "\"a\" + [1]",
+                "Unsupported types for binary operation: STRING + ARRAY",
+            ),
+            ("[1] - [1]", "Unsupported types for binary operation"),
+        ];
+        for (input, expected) in errors {
+            assert_eq!(run_vm_with_error_output(input), Err(expected.to_string()));
+        }
+    }
+
     #[test]
     fn test_hashmap_expressions() {
         let tests = vec![
             VmTestCase {
                 input: "{}".to_string(),
-                expected: Object::HASHMAP(HashMap::new()),
+                expected: Object::HASHMAP(HashMap::new().into()),
             },
             VmTestCase {
                 input: "{1:2, 2:3}".to_string(),
@@ -353,7 +833,8 @@ mod tests {
                         (Object::INTEGER(2), Object::INTEGER(3)),
                     ]
                     .into_iter()
-                    .collect(),
+                    .collect::<HashMap<_, _>>()
+                    .into(),
                 ),
             },
             VmTestCase {
@@ -364,7 +845,8 @@ mod tests {
                         (Object::INTEGER(4), Object::INTEGER(3)),
                     ]
                     .into_iter()
-                    .collect(),
+                    .collect::<HashMap<_, _>>()
+                    .into(),
                 ),
             },
         ];
@@ -372,6 +854,43 @@ mod tests {
         run_vm_tests(tests);
     }
 
+    #[test]
+    fn test_hashmap_merge() {
+        let tests = vec![
+            (r#"({"a": 1} + {"b": 2})["a"]"#, Object::INTEGER(1)),
+            (r#"({"a": 1} + {"b": 2})["b"]"#, Object::INTEGER(2)),
+            (r#"len(to_json({"a": 1} + {"b": 2}))"#, Object::INTEGER(13)),
+            // The right side wins on conflicting keys
+            (r#"({"a": 1, "b": 2} + {"b": 3})["b"]"#, Object::INTEGER(3)),
+            (r#"({"b": 3} + {"a": 1, "b": 2})["b"]"#, Object::INTEGER(2)),
+            (
+                r#"to_json({"a": 1, "b": 2} + {"b": 3})"#,
+                Object::STRING(r#"{"a":1,"b":3}"#.to_string()),
+            ),
+            ("({} + {1: true})[1]", Object::BOOLEAN(true)),
+            // The operands are left untouched
+            (
+                r#"let a = {"x": 1}; let b = a + {"x": 2}; [a["x"], b["x"]]"#,
+                Object::ARRAY(vec![Object::INTEGER(1), Object::INTEGER(2)].into()),
+            ),
+        ];
+
+        run_vm_tests(
+            tests
+                .into_iter()
+                .map(|(input, expected)| VmTestCase {
+                    input: input.to_string(),
+                    expected,
+                })
+                .collect(),
+        );
+
+        assert_eq!(
+            run_vm_with_error_output("{1: 2} + [1]"),
+            Err("Unsupported types for binary operation: HASHMAP + ARRAY".to_string())
+        );
+    }
+
     #[test]
     fn test_index_expression() {
         let tests = vec![
@@ -419,4 +938,749 @@ mod tests {
 
         run_vm_tests(tests);
     }
+
+    #[test]
+    fn test_block_expressions() {
+        let tests = vec![
+            VmTestCase {
+                input: "{ let x = 1; x + 1 }".to_string(),
+                expected: Object::INTEGER(2),
+            },
+            VmTestCase {
+                input: "let y = { let x = 2; x * 3 } + 1; y".to_string(),
+                expected: Object::INTEGER(7),
+            },
+            VmTestCase {
+                input: "{ let x = 1; }".to_string(),
+                expected: Object::NULL,
+            },
+            VmTestCase {
+                input: "{ 1; let x = 1; }".to_string(),
+                expected: Object::NULL,
+            },
+            VmTestCase {
+                // The bindings of a block do not leak out of it
+                input: "let x = 1; let y = { let x = 10; x }; [x, y]".to_string(),
+                expected: Object::ARRAY(vec![Object::INTEGER(1), Object::INTEGER(10)].into()),
+            },
+            VmTestCase {
+                input: "let f = fn(a) { let b = { let c = a * 2; c + 1 }; b * a }; f(3)"
+                    .to_string(),
+                expected: Object::INTEGER(21),
+            },
+            VmTestCase {
+                // `return` leaves the enclosing function, not only the block
+                input: "let f = fn() { let a = { return 1; 2 }; a + 1 }; f()".to_string(),
+                expected: Object::INTEGER(1),
+            },
+            VmTestCase {
+                input: "let f = fn() { let x = 1; let y = { let z = 1; x = 2; x + z }; x + y }; f()"
+                    .to_string(),
+                expected: Object::INTEGER(5),
+            },
+            VmTestCase {
+                input: "let i = 0; while (true) { i = i + 1; { if (i > 2) { break; } } } i"
+                    .to_string(),
+                expected: Object::INTEGER(3),
+            },
+            VmTestCase {
+                input: "let n = 0; let i = 0; while (i < 5) { i = i + 1; let skip = { if (i % 2 == 0) { continue; }; false }; n = n + i; } n"
+                    .to_string(),
+                expected: Object::INTEGER(9),
+            },
+            VmTestCase {
+                // A closure created in a block captures the variables of the block
+                input: "let f = fn(a) { let g = { let b = a + 1; fn() { a + b } }; g() }; f(1)"
+                    .to_string(),
+                expected: Object::INTEGER(3),
+            },
+            VmTestCase {
+                input: "{ { 1 } + { 2 } }".to_string(),
+                expected: Object::INTEGER(3),
+            },
+        ];
+
+        run_vm_tests(tests);
+    }
+
+    #[test]
+    fn test_break_and_continue_inside_operands() {
+        // The values already computed for the enclosing expressions are popped before
+        // jumping, otherwise they would pile up until the stack overflows
+        let input = r#"
+            let f = fn(a, b, c) { a + b + c };
+            let i = 0;
+            let n = 0;
+            while (i < 5000) {
+                i = i + 1;
+                n = n + 1 + { if (i % 2 == 0) { continue; }; 1 };
+                n = n + f(1, 2, [3, { if (i % 3 == 0) { continue; }; 4 }][0]);
+                let h = {"a": 1, "b": { if (i > 4000) { break; }; 2 }};
+                let s = "${i} ${f(i, {continue; 0}, 0)}";
+            }
+            let j = 0;
+            while (true) {
+                j = j + 1;
+                puts(len([1, ...[2, 3], 4, { if (j < 5000) { continue; }; break; }]));
+            }
+            [i, j, n]
+        "#;
+        let mut compiler = Compiler::new();
+        compiler.compile(parse(input)).unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        vm.run().unwrap();
+
+        assert_eq!(
+            vm.last_popped_stack_element().unwrap().as_ref(),
+            &Object::ARRAY(
+                vec![
+                    Object::INTEGER(4001),
+                    Object::INTEGER(5000),
+                    Object::INTEGER(12006)
+                ]
+                .into()
+            )
+        );
+        assert_eq!(vm.stack_top(), None);
+    }
+
+    #[test]
+    fn test_deeply_nested_arrays() {
+        // Freeing the arrays, when they are reassigned and when the VM is dropped, must
+        // not recurse into every level
+        let input = r"
+            let a = [];
+            let i = 0;
+            while (i < 200000) {
+                a = [a];
+                i = i + 1;
+            }
+            len(a)
+        ";
+        let mut compiler = Compiler::new();
+        compiler.compile(parse(input)).unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        vm.run().unwrap();
+
+        assert_eq!(
+            vm.last_popped_stack_element().unwrap().as_ref(),
+            &Object::INTEGER(1)
+        );
+        drop(vm);
+    }
+
+    #[test]
+    fn test_program_result() {
+        let tests = vec![
+            ("1 + 2", Object::INTEGER(3)),
+            ("1; 2;", Object::INTEGER(2)),
+            ("let a = 5;", Object::NULL),
+            ("10; let a = 5;", Object::INTEGER(10)),
+            ("let a = 5; a * 2; let b = a;", Object::INTEGER(10)),
+            (
+                "let f = fn() { 1; 2; 3 }; 4; let x = f();",
+                Object::INTEGER(4),
+            ),
+            ("if (true) { 1; 2 }", Object::INTEGER(2)),
+            ("if (false) { 1 }", Object::NULL),
+            (
+                "let i = 0; while (i < 3) { i * 10; let i = i + 1; }",
+                Object::INTEGER(20),
+            ),
+            ("7; while (false) { 1 }", Object::INTEGER(7)),
+        ];
+
+        for (input, expected) in tests {
+            let mut compiler = Compiler::new();
+            compiler.compile(parse(input)).unwrap();
+            let mut vm = VM::new(compiler.bytecode());
+            assert_eq!(vm.result().as_ref(), &Object::NULL);
+            vm.run().unwrap();
+            assert_eq!(vm.result().as_ref(), &expected, "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_last_popped_stack_element() {
+        let tests = [
+            ("1 + 2", Object::INTEGER(3)),
+            ("let a = 5; a * 2", Object::INTEGER(10)),
+            ("let a = 5;", Object::NULL),
+            ("1; let a = 2;", Object::NULL),
+            ("let i = 0; while (i < 3) { let i = i + 1; }", Object::NULL),
+            ("", Object::NULL),
+        ];
+
+        for (input, expected) in tests {
+            let mut compiler = Compiler::new();
+            compiler.compile(parse(input)).unwrap();
+            let mut vm = VM::new(compiler.bytecode());
+            vm.run().unwrap();
+            let popped = vm.last_popped_stack_element().unwrap();
+            assert_eq!(popped.as_ref(), &expected, "input: {input}");
+        }
+
+        // There is no result before the program is done, or if it failed
+        let mut compiler = Compiler::new();
+        compiler.compile(parse("1; 1 + true; 2")).unwrap();
+        let mut vm = VM::new(compiler.bytecode());
+        assert!(vm.last_popped_stack_element().is_err());
+        assert!(vm.run().is_err());
+        assert!(vm.last_popped_stack_element().is_err());
+    }
+
+    #[test]
+    fn test_compile_expression_public() {
+        let tests = vec![
+            ("1 + 1", Object::INTEGER(2)),
+            ("if (false) { 1 }", Object::NULL),
+            ("[1, 2][1]", Object::INTEGER(2)),
+        ];
+
+        for (input, expected) in tests {
+            let program = parse(input);
+            let expression = match program.statements.as_slice() {
+                [Statement::Expression(expression)] => expression.clone(),
+                _ => panic!("{input} is not a single expression"),
+            };
+            let mut compiler = Compiler::new();
+            compiler.compile_expression_public(expression).unwrap();
+            let bytecode = compiler.bytecode();
+            assert_ne!(
+                bytecode.instructions.data.last(),
+                Some(&(Opcode::Pop as u8))
+            );
+
+            let mut vm = VM::new(bytecode);
+            vm.run().unwrap();
+            assert_eq!(
+                vm.stack_top().unwrap().as_ref(),
+                &expected,
+                "input: {input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_final_pop_elision() {
+        let tests = vec![
+            ("1 + 2", Object::INTEGER(3)),
+            (
+                "let a = [1]; a + [2]",
+                Object::ARRAY(vec![Object::INTEGER(1), Object::INTEGER(2)].into()),
+            ),
+            ("let f = fn(x) { x * 2 }; f(4)", Object::INTEGER(8)),
+            ("1; 2", Object::INTEGER(2)),
+        ];
+
+        for (input, expected) in tests {
+            let mut compiler = Compiler::new();
+            compiler.compile(parse(input)).unwrap();
+            let mut vm = VM::new(compiler.bytecode());
+            vm.run().unwrap();
+            assert_eq!(vm.stack_top(), None, "input: {input}");
+            assert_eq!(
+                vm.last_popped_stack_element().unwrap().as_ref(),
+                &expected,
+                "input: {input}"
+            );
+
+            let mut compiler = Compiler::new();
+            compiler.emit_final_pop(false);
+            compiler.compile(parse(input)).unwrap();
+            let mut vm = VM::new(compiler.bytecode());
+            vm.run().unwrap();
+            assert_eq!(
+                vm.stack_top().unwrap().as_ref(),
+                &expected,
+                "input: {input}"
+            );
+        }
+
+        // Only a final expression statement is affected
+        for input in ["let a = 1;", "1; let a = 1;", "while (false) { 1 }"] {
+            let mut compiler = Compiler::new();
+            compiler.compile(parse(input)).unwrap();
+            let mut elided = Compiler::new();
+            elided.emit_final_pop(false);
+            elided.compile(parse(input)).unwrap();
+            assert_eq!(
+                compiler.bytecode().instructions,
+                elided.bytecode().instructions,
+                "input: {input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_comparison_operands_evaluation_order() {
+        let tests = vec![
+            ("1 < 2", "true"),
+            ("2 < 1", "false"),
+            ("1 <= 1", "true"),
+            ("2 <= 1", "false"),
+            ("'a' < 'b'", "true"),
+            ("'b' <= 'a'", "false"),
+        ];
+        for (input, expected) in tests {
+            assert_eq!(
+                crate::embed::eval_to_string(input),
+                expected,
+                "input: {input}"
+            );
+        }
+
+        // Both operands are evaluated from left to right, whatever the operator
+        let program = "let left = fn() { puts(\"left\"); 1 }; \
+                       let right = fn() { puts(\"right\"); 2 };";
+        for operator in ["<", "<=", ">", ">=", "==", "!="] {
+            let input = format!("{program} left() {operator} right();");
+            let output = crate::embed::eval_to_string(&input);
+            assert!(
+                output.starts_with("\"left\"\n\"right\"\n"),
+                "operator: {operator}, output: {output}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_vm_capacity() {
+        let input = "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } }; f(100)";
+        let compile = || {
+            let mut compiler = Compiler::new();
+            compiler.compile(parse(input)).unwrap();
+            compiler.bytecode()
+        };
+
+        let mut vm = VM::with_capacity(compile(), 64, 16);
+        assert_eq!(
+            vm.run(),
+            Err("Stack overflow :(, you gotta fix this".to_string())
+        );
+
+        let mut vm = VM::with_capacity(compile(), 1024, 16);
+        vm.run().unwrap();
+        assert_eq!(*vm.result(), Object::INTEGER(100));
+
+        // Defining more globals than there is room for fails instead of panicking
+        let mut compiler = Compiler::new();
+        compiler
+            .compile(parse("let a = 1; let b = 2; a + b"))
+            .unwrap();
+        let mut vm = VM::with_capacity(compiler.bytecode(), 64, 1);
+        assert_eq!(vm.run(), Err("Too many global variables".to_string()));
+    }
+
+    #[test]
+    fn test_try_catch() {
+        let tests = vec![
+            ("try { 1 / 0 } catch (e) { -1 }", Object::INTEGER(-1)),
+            (
+                "try { 1 / 0 } catch (e) { e }",
+                Object::ERROR("Division by zero".to_string()),
+            ),
+            ("try { 10 / 2 } catch (e) { -1 }", Object::INTEGER(5)),
+            ("try { let a = 1; } catch (e) { -1 }", Object::NULL),
+            // Errors of builtins are raised inside a `try`, but are values outside of it
+            ("try { len(1); 1 } catch (e) { 2 }", Object::INTEGER(2)),
+            (
+                "let a = try { len(1) } catch (e) { 2 }; [a, len(1)]",
+                Object::ARRAY(vec![
+                    Object::INTEGER(2),
+                    Object::ERROR("argument to `len` not supported, got INTEGER".to_string()),
+                ].into()),
+            ),
+            // The frames of the functions called inside the `try` are discarded
+            (
+                "let f = fn(n) { if (n == 0) { 1 / 0 } else { f(n - 1) } }; \
+                 let g = fn() { try { f(5) } catch (e) { 42 } }; \
+                 g() + 1",
+                Object::INTEGER(43),
+            ),
+            (
+                "let f = fn() { let x = 1; [x, 1 / 0] }; [1, try { f() } catch (e) { 2 }, 3]",
+                Object::ARRAY(vec![
+                    Object::INTEGER(1),
+                    Object::INTEGER(2),
+                    Object::INTEGER(3),
+                ].into()),
+            ),
+            // Nested `try`s, and errors raised by a `catch`
+            (
+                "try { try { 1 / 0 } catch (e) { 1 } } catch (e) { 2 }",
+                Object::INTEGER(1),
+            ),
+            (
+                "try { try { 1 / 0 } catch (e) { 1 / 0 } } catch (e) { 2 }",
+                Object::INTEGER(2),
+            ),
+            // Leaving a `try` with `return`, `break` or `continue` ends it
+            (
+                "let f = fn() { try { return 1; } catch (e) { 2 } }; \
+                 try { f(); 1 / 0 } catch (e) { 3 }",
+                Object::INTEGER(3),
+            ),
+            (
+                "let i = 0; \
+                 while (true) { try { i = i + 1; if (i > 2) { break; } continue; } catch (e) { 0 } } \
+                 try { 1 / 0 } catch (e) { i }",
+                Object::INTEGER(3),
+            ),
+            (
+                "repeat(2, fn() { try { 1 / 0 } catch (e) { 0 } })",
+                Object::ARRAY(vec![Object::INTEGER(0), Object::INTEGER(0)].into()),
+            ),
+            (
+                "try { repeat(2, fn() { 1 / 0 }) } catch (e) { 5 }",
+                Object::INTEGER(5),
+            ),
+            (
+                "let f = fn() { f() }; try { f() } catch (e) { e }",
+                Object::ERROR("maximum recursion depth exceeded".to_string()),
+            ),
+        ];
+
+        run_vm_tests(
+            tests
+                .into_iter()
+                .map(|(input, expected)| VmTestCase {
+                    input: input.to_string(),
+                    expected,
+                })
+                .collect(),
+        );
+
+        // Uncaught errors still stop the program
+        for input in [
+            "1 / 0",
+            "try { 1 } catch (e) { 2 }; 1 / 0",
+            "try { 1 / 0 } catch (e) { e / 0 }",
+        ] {
+            let mut compiler = Compiler::new();
+            compiler.compile(parse(input)).unwrap();
+            let mut vm = VM::new(compiler.bytecode());
+            assert!(vm.run().is_err(), "input: {input}");
+        }
+        assert_eq!(
+            crate::embed::eval_to_string("try { 1 / 0 } catch (e) { puts(e); 0 } 1 / 0"),
+            "ERROR: Division by zero\nRuntime error:\n\tDivision by zero\n"
+        );
+    }
+
+    #[test]
+    fn test_throw() {
+        let tests = vec![
+            (
+                "try { throw \"oops\"; } catch (e) { e }",
+                Object::STRING("oops".to_string()),
+            ),
+            (
+                "let check = fn(x) { if (x < 0) { throw {\"code\": x}; } x }; \
+                 try { check(1) + check(-2) } catch (e) { e[\"code\"] }",
+                Object::INTEGER(-2),
+            ),
+            (
+                "try { try { throw 1; } catch (e) { throw e + 1; } } catch (e) { e }",
+                Object::INTEGER(2),
+            ),
+            // Only the value of the last `throw` is received by a `catch`
+            (
+                "try { throw 1; } catch (e) { 0 }; try { 1 / 0 } catch (e) { e }",
+                Object::ERROR("Division by zero".to_string()),
+            ),
+        ];
+
+        run_vm_tests(
+            tests
+                .into_iter()
+                .map(|(input, expected)| VmTestCase {
+                    input: input.to_string(),
+                    expected,
+                })
+                .collect(),
+        );
+
+        let tests = [
+            ("throw \"oops\";", "Uncaught error: oops"),
+            (
+                "let f = fn() { throw [1, 2]; }; f();",
+                "Uncaught error: [1, 2]",
+            ),
+            // Throwing a caught error raises it again
+            (
+                "try { 1 / 0 } catch (e) { throw e; }",
+                "Uncaught error: Division by zero",
+            ),
+        ];
+        for (input, expected) in tests {
+            let mut compiler = Compiler::new();
+            compiler.compile(parse(input)).unwrap();
+            let mut vm = VM::new(compiler.bytecode());
+            assert_eq!(vm.run(), Err(expected.to_string()), "input: {input}");
+        }
+    }
+
+    #[test]
+    fn test_max_call_depth() {
+        let compile = |input: &str| {
+            let mut compiler = Compiler::new();
+            compiler.compile(parse(input)).unwrap();
+            compiler.bytecode()
+        };
+        let max_depth_error = Object::ERROR("maximum recursion depth exceeded".to_string());
+
+        // Unbounded recursion is stopped by the default limit, before the stack overflows
+        let mut vm = VM::new(compile("let f = fn(n) { f(n + 1) }; f(0)"));
+        vm.run().unwrap();
+        assert_eq!(*vm.result(), max_depth_error);
+
+        let countdown = "let f = fn(n) { if (n == 0) { 0 } else { f(n - 1) } };";
+        let mut vm = VM::new(compile(&format!("{countdown} f(9)"))).with_max_call_depth(10);
+        vm.run().unwrap();
+        assert_eq!(*vm.result(), Object::INTEGER(0));
+
+        let mut vm = VM::new(compile(&format!("{countdown} f(10)"))).with_max_call_depth(10);
+        vm.run().unwrap();
+        assert_eq!(*vm.result(), max_depth_error);
+
+        // The program goes on after the error
+        let input = format!("{countdown} let result = f(100); [result, f(5)]");
+        let mut vm = VM::new(compile(&input)).with_max_call_depth(10);
+        vm.run().unwrap();
+        assert_eq!(
+            *vm.result(),
+            Object::ARRAY(vec![max_depth_error, Object::INTEGER(0)].into())
+        );
+    }
+
+    #[test]
+    fn test_assignment() {
+        let tests = vec![
+            ("let x; x = 5; x", Object::INTEGER(5)),
+            ("let x; x", Object::NULL),
+            ("let x = 1; x = x + 1; x", Object::INTEGER(2)),
+            (
+                "let i = 0; while (i < 3) { i = i + 1; } i",
+                Object::INTEGER(3),
+            ),
+            (
+                "let count = 0; let inc = fn() { count = count + 1; }; inc(); inc(); count",
+                Object::INTEGER(2),
+            ),
+            (
+                "let f = fn() { let a; a = 2; a * 3 }; f()",
+                Object::INTEGER(6),
+            ),
+            ("let f = fn(a) { a = a * 2; a }; f(4)", Object::INTEGER(8)),
+            // A branch ending with an assignment or a `let` has the value `null`
+            ("let x = 1; if (true) { x = 2; }; x", Object::INTEGER(2)),
+            ("let x = 1; if (true) { x = 2; }", Object::NULL),
+            ("if (false) { 1 } else { let y = 2; }", Object::NULL),
+            ("let x = 1; { x = 2; }; x", Object::INTEGER(2)),
+            ("let x = 1; { x = x + 1 }", Object::NULL),
+        ];
+
+        run_vm_tests(
+            tests
+                .into_iter()
+                .map(|(input, expected)| VmTestCase {
+                    input: input.to_string(),
+                    expected,
+                })
+                .collect(),
+        );
+
+        let errors = vec![
+            ("x = 1;", "Undefined variable: x at 1:1"),
+            (
+                "let f = fn() { let a = 1; fn() { a = 2; } };",
+                "Cannot assign to a, a variable of an enclosing function, at 1:34",
+            ),
+            ("len = 1;", "Cannot assign to the function len at 1:1"),
+        ];
+        for (input, expected) in errors {
+            let mut compiler = Compiler::new();
+            assert_eq!(
+                compiler.compile(parse(input)),
+                Err(expected.to_string()),
+                "input: {input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_interpolated_strings() {
+        let tests = vec![
+            (r#"f"1+1=${1+1}""#, "1+1=2"),
+            (r#"f"costs \$5, not \${price}""#, "costs $5, not ${price}"),
+            (r#"let name = "world"; f"hello ${name}!""#, "hello world!"),
+            (r#"f"${'a'}${[1, "b"]}${{}}${f"${2}"}""#, "a[1, \"b\"]{}2"),
+            (r#"let str = fn(x) { "shadowed" }; f"${1}""#, "1"),
+            (r#"f"""#, ""),
+        ];
+
+        run_vm_tests(
+            tests
+                .into_iter()
+                .map(|(input, expected)| VmTestCase {
+                    input: input.to_string(),
+                    expected: Object::STRING(expected.to_string()),
+                })
+                .collect(),
+        );
+
+        let mut compiler = Compiler::new();
+        assert_eq!(
+            compiler.compile(parse("f\"a ${missing}\"")),
+            Err("Undefined variable: missing at 1:7".to_string())
+        );
+    }
+
+    #[test]
+    fn test_spread() {
+        let tests = vec![
+            (
+                "[...[1, 2], 3]",
+                Object::ARRAY(
+                    vec![Object::INTEGER(1), Object::INTEGER(2), Object::INTEGER(3)].into(),
+                ),
+            ),
+            (
+                "let a = [2, 3]; [1, ...a, 4, ...a]",
+                Object::ARRAY(
+                    [1, 2, 3, 4, 2, 3]
+                        .into_iter()
+                        .map(Object::INTEGER)
+                        .collect::<Vec<_>>()
+                        .into(),
+                ),
+            ),
+            ("[...[]]", Object::ARRAY(vec![].into())),
+            (
+                "let f = fn(a, b) { a * 10 + b }; f(...[1, 2]) == f(1, 2)",
+                Object::BOOLEAN(true),
+            ),
+            (
+                "let f = fn(a, b, c) { a * 100 + b * 10 + c }; f(1, ...[2], ...[3])",
+                Object::INTEGER(123),
+            ),
+            ("len(...[\"abc\"])", Object::INTEGER(3)),
+            ("let f = fn(a) { a }; f?.(...[1])", Object::INTEGER(1)),
+        ];
+
+        run_vm_tests(
+            tests
+                .into_iter()
+                .map(|(input, expected)| VmTestCase {
+                    input: input.to_string(),
+                    expected,
+                })
+                .collect(),
+        );
+
+        assert_eq!(
+            run_vm_with_error_output("let f = fn(a) { a }; f(...[1, 2])"),
+            Err("Wrong number of arguments: want=1, got=2".to_string())
+        );
+        assert_eq!(
+            run_vm_with_error_output("[...\"abc\"]"),
+            Err("Cannot spread STRING, only arrays can be spread".to_string())
+        );
+    }
+
+    #[test]
+    fn test_enums() {
+        let tests = vec![
+            (
+                "enum Color { Red, Green, Blue } [Red, Green, Blue]",
+                Object::ARRAY(vec![
+                    Object::INTEGER(0),
+                    Object::INTEGER(1),
+                    Object::INTEGER(2),
+                ].into()),
+            ),
+            ("enum Color { Red, Green } Red == Red", Object::BOOLEAN(true)),
+            ("enum Color { Red, Green } Red != Green", Object::BOOLEAN(true)),
+            ("enum Size { Small, Large } Small < Large", Object::BOOLEAN(true)),
+            (
+                "let f = fn() { enum Direction { Up, Down } Down }; f()",
+                Object::INTEGER(1),
+            ),
+            (
+                "enum Color { Red, Green } let names = {Red: \"red\", Green: \"green\"}; names[Green]",
+                Object::STRING("green".to_string()),
+            ),
+        ];
+
+        run_vm_tests(
+            tests
+                .into_iter()
+                .map(|(input, expected)| VmTestCase {
+                    input: input.to_string(),
+                    expected,
+                })
+                .collect(),
+        );
+
+        // Unused variants are not reported, but redefining one is
+        let mut compiler = Compiler::new();
+        compiler
+            .compile(parse("enum Color { Red, Green } let Red = 1; Red"))
+            .unwrap();
+        let warnings: Vec<String> = compiler.warnings.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            warnings,
+            vec!["Variable Red is redefined in the same scope at 1:31".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_relative_jumps() {
+        let run = |instructions: Vec<Instructions>, constants: Vec<Object>| {
+            let mut data = Instructions::default();
+            for instruction in instructions {
+                data.append(instruction);
+            }
+            let mut vm = VM::new(Bytecode {
+                instructions: data,
+                constants,
+                globals: HashMap::new(),
+            });
+            vm.run().unwrap();
+            vm.last_popped_stack_element().unwrap().as_ref().clone()
+        };
+
+        // Forward, over the second constant
+        let skipped = run(
+            vec![
+                Opcode::Constant.make(vec![0]), // 0000
+                Opcode::JumpRel.make(vec![3]),  // 0003
+                Opcode::Constant.make(vec![1]), // 0005
+                Opcode::Pop.make(vec![]),       // 0008
+            ],
+            vec![Object::INTEGER(1), Object::INTEGER(2)],
+        );
+        assert_eq!(skipped, Object::INTEGER(1));
+
+        // Backward, counting up to 3
+        let counted = run(
+            vec![
+                Opcode::Constant.make(vec![0]),       // 0000
+                Opcode::SetGlobal.make(vec![0]),      // 0003
+                Opcode::GetGlobal.make(vec![0]),      // 0006
+                Opcode::Constant.make(vec![1]),       // 0009
+                Opcode::LessThan.make(vec![]),        // 0012
+                Opcode::JumpNotTruthy.make(vec![28]), // 0013
+                Opcode::GetGlobal.make(vec![0]),      // 0016
+                Opcode::Constant.make(vec![2]),       // 0019
+                Opcode::Add.make(vec![]),             // 0022
+                Opcode::SetGlobal.make(vec![0]),      // 0023
+                Opcode::JumpRel.make(vec![-22]),      // 0026
+                Opcode::GetGlobal.make(vec![0]),      // 0028
+                Opcode::Pop.make(vec![]),             // 0031
+            ],
+            vec![Object::INTEGER(0), Object::INTEGER(3), Object::INTEGER(1)],
+        );
+        assert_eq!(counted, Object::INTEGER(3));
+    }
 }
@@ -43,6 +43,14 @@ mod tests {
                 .to_string(),
                 expected: Object::INTEGER(1),
             },
+            // The body is too long for a relative jump back to the condition
+            VmTestCase {
+                input: format!(
+                    "let i = 0; let s = 0; while (i < 3) {{ {} let i = i + 1; }} s",
+                    "let s = s + 1; ".repeat(20)
+                ),
+                expected: Object::INTEGER(60),
+            },
         ];
 
         run_vm_tests(tests);
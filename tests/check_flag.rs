@@ -0,0 +1,68 @@
+// Runs `monkey --check` on script files, which are compiled but never run.
+
+use std::{env, fs, path::PathBuf, process::Command};
+
+fn check_script(name: &str, source: &str) -> (Option<i32>, String, String) {
+    let path: PathBuf = env::temp_dir().join(format!("{name}-{}.monkey", std::process::id()));
+    fs::write(&path, source).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_monkey"))
+        .arg("--check")
+        .arg(&path)
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    (
+        output.status.code(),
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn test_check_clean_file() {
+    let (code, stdout, stderr) = check_script("check_clean", "let a = 1; puts(a); exit(3);");
+
+    assert_eq!(code, Some(0));
+    assert_eq!(stdout, "");
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn test_check_reports_warnings() {
+    let (code, stdout, stderr) = check_script("check_warning", "let unused = 1; puts(2);");
+
+    assert_eq!(code, Some(0));
+    assert_eq!(stdout, "");
+    assert!(stderr.starts_with("Warning: "), "stderr: {stderr}");
+    assert!(stderr.contains("unused"), "stderr: {stderr}");
+}
+
+#[test]
+fn test_check_compile_error() {
+    let (code, stdout, stderr) = check_script("check_compile_error", "puts(1);\nputs(b);");
+
+    assert_eq!(code, Some(1));
+    assert_eq!(stdout, "");
+    assert_eq!(
+        stderr,
+        "Error: CompilerError { error: \"Undefined variable: b at 2:6\" }\n"
+    );
+}
+
+#[test]
+fn test_check_parse_errors() {
+    let (code, _, stderr) = check_script("check_parse_error", "let = 1;\nlet b 2;");
+
+    assert_eq!(code, Some(1));
+    // Every error is reported, not only the first one
+    assert!(
+        stderr.contains("There is no prefix parser for the token ="),
+        "stderr: {stderr}"
+    );
+    assert!(
+        stderr.contains("Expected next token to be =, got 2 instead"),
+        "stderr: {stderr}"
+    );
+}
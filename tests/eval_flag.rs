@@ -0,0 +1,71 @@
+// Runs the `monkey` binary with code given through `-e`.
+
+use std::process::Command;
+
+fn run_eval(code: &[&str]) -> (Option<i32>, String, String) {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_monkey"));
+    for code in code {
+        command.arg("-e").arg(code);
+    }
+    let output = command.output().unwrap();
+
+    (
+        output.status.code(),
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn test_eval_single_expression() {
+    let (code, stdout, stderr) = run_eval(&["1 + 2"]);
+
+    assert_eq!(code, Some(0));
+    assert_eq!(stdout, "3\n");
+    assert_eq!(stderr, "");
+}
+
+#[test]
+fn test_eval_shares_state() {
+    let (code, stdout, _) = run_eval(&["let a = 2;", "puts(a * 3);", "a + 1"]);
+
+    assert_eq!(code, Some(0));
+    assert_eq!(stdout, "6\n3\n");
+}
+
+#[test]
+fn test_eval_error() {
+    let (code, stdout, stderr) = run_eval(&["1 + true", "puts(2)"]);
+
+    assert_eq!(code, Some(1));
+    assert_eq!(stdout, "");
+    assert!(stderr.contains("Unsupported types"), "stderr: {stderr}");
+
+    let (code, _, stderr) = run_eval(&["missing"]);
+    assert_eq!(code, Some(1));
+    assert!(
+        stderr.contains("Undefined variable: missing"),
+        "stderr: {stderr}"
+    );
+}
+
+#[test]
+fn test_eval_keeps_the_seed() {
+    let run = || run_eval(&["seed(1);", "[rand(), rand_int(0, 1000)]"]);
+    let (code, stdout, _) = run();
+
+    assert_eq!(code, Some(0));
+    assert_eq!(run().1, stdout);
+    assert_ne!(
+        run_eval(&["seed(2);", "[rand(), rand_int(0, 1000)]"]).1,
+        stdout
+    );
+}
+
+#[test]
+fn test_eval_exit() {
+    let (code, stdout, _) = run_eval(&["puts(1); exit(3);", "puts(2)"]);
+
+    assert_eq!(code, Some(3));
+    assert_eq!(stdout, "1\n");
+}
@@ -0,0 +1,43 @@
+// Runs the `monkey` binary on script files to check the status it exits with.
+
+use std::{env, fs, path::PathBuf, process::Command};
+
+fn run_script(name: &str, source: &str) -> (Option<i32>, String) {
+    let path: PathBuf = env::temp_dir().join(format!("{name}-{}.monkey", std::process::id()));
+    fs::write(&path, source).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_monkey"))
+        .arg(&path)
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    (
+        output.status.code(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+#[test]
+fn test_exit_code_in_file_mode() {
+    let (code, stdout) = run_script(
+        "exit_code",
+        r#"
+        puts("before");
+        let check = fn(x) { if (x > 1) { exit(2); } };
+        check(5);
+        puts("after");
+        "#,
+    );
+
+    assert_eq!(code, Some(2));
+    assert_eq!(stdout, "\"before\"\n");
+}
+
+#[test]
+fn test_no_exit_in_file_mode() {
+    let (code, stdout) = run_script("no_exit", "puts(1 + 1);");
+
+    assert_eq!(code, Some(0));
+    assert_eq!(stdout, "2\n");
+}
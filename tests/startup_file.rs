@@ -0,0 +1,73 @@
+// Runs the `monkey` REPL with a startup file, feeding the prompt through the standard input.
+
+use std::{
+    env, fs,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+fn run_repl(args: &[&str], input: &str) -> (String, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_monkey"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+fn write_rc_file(name: &str, contents: &str) -> String {
+    let path = env::temp_dir().join(format!("{name}-{}.monkeyrc", std::process::id()));
+    fs::write(&path, contents).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+#[test]
+fn test_startup_file_functions_are_callable() {
+    let rc = write_rc_file("double", "let double = fn(x) { x * 2 };\ndouble(1)\n");
+
+    let (stdout, stderr) = run_repl(&["--rc", &rc], "double(21)\n");
+    // The value of the startup file is not echoed, only the one of the prompt
+    assert!(stdout.ends_with("commands\n\n42\n"), "stdout: {stdout}");
+    assert_eq!(stderr, "");
+
+    let (stdout, stderr) = run_repl(&["--rc", &rc, "-m", "interpreter"], "double(21)\n");
+    assert!(stdout.ends_with("commands\n\n42\n"), "stdout: {stdout}");
+    assert_eq!(stderr, "");
+
+    let (_, stderr) = run_repl(&["--no-rc"], "double(21)\n");
+    assert!(
+        stderr.contains("Undefined variable: double"),
+        "stderr: {stderr}"
+    );
+    fs::remove_file(rc).unwrap();
+}
+
+#[test]
+fn test_startup_file_errors_do_not_abort() {
+    let rc = write_rc_file("broken", "let a = 1;\nlet b = a + true;\n");
+
+    let (stdout, stderr) = run_repl(&["--rc", &rc], "a + 1\n");
+    assert!(stdout.contains("2\n"), "stdout: {stdout}");
+    assert!(stderr.contains("Unsupported types"), "stderr: {stderr}");
+    assert!(stderr.contains("in the startup file"), "stderr: {stderr}");
+    fs::remove_file(rc).unwrap();
+
+    let (_, stderr) = run_repl(&["--rc", "missing.monkeyrc"], "1\n");
+    assert!(
+        stderr.contains("Unable to read the startup file missing.monkeyrc"),
+        "stderr: {stderr}"
+    );
+}